@@ -0,0 +1,57 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use plib::testing::{generate, TestDir};
+
+/// Large enough that reading records dominates the start of awk.
+const RECORDS: usize = 1_000_000;
+
+/// Runs awk with `program` over `input`, discarding its output.
+fn run_awk(program: &str, input: &Path) {
+    let status = Command::new(env!("CARGO_BIN_EXE_awk"))
+        .arg(program)
+        .arg(input)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn records(c: &mut Criterion) {
+    let dir = TestDir::new("awk-records-bench");
+    let input = dir.file("input", generate::lines(RECORDS, 1963));
+
+    let mut group = c.benchmark_group("records");
+    group
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(30))
+        .throughput(Throughput::Elements(RECORDS as u64));
+    for (name, program) in [
+        ("count fields", "{ n += NF } END { print n }"),
+        ("compare NF", "NF > 5 { n++ } END { print n + 0 }"),
+        ("match regex", "/e/ { n++ } END { print n + 0 }"),
+        ("update array", "{ a[length($1)]++ } END { print a[1] + 0 }"),
+        (
+            "assign field",
+            "{ $2 = \"q\"; n += length($0) } END { print n + 0 }",
+        ),
+    ] {
+        group.bench_function(name, |b| b.iter(|| run_awk(program, &input)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, records);
+criterion_main!(benches);
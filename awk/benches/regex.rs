@@ -0,0 +1,53 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use plib::testing::generate;
+
+// awk has no library target, so the module is built into the benchmark
+#[allow(dead_code, unused_imports)]
+#[path = "../src/regex.rs"]
+mod regex;
+
+use regex::Regex;
+
+/// Lines of lowercase words, so the patterns below match often or never.
+const HAYSTACK_LINES: usize = 20_000;
+
+fn match_locations(c: &mut Criterion) {
+    let haystack = generate::lines(HAYSTACK_LINES, 1994);
+    let mut group = c.benchmark_group("match_locations");
+    group.throughput(Throughput::Bytes(haystack.len() as u64));
+    for (name, ere) in [
+        ("no matches", "[0-9]+"),
+        ("few matches", "qu+x"),
+        ("many matches", "[aeiou][aeiou]"),
+        ("long matches", "[a-z]+ [a-z]+"),
+    ] {
+        let regex = Regex::from_awk_ere(ere).unwrap();
+        group.bench_with_input(BenchmarkId::new(name, ere), &haystack, |b, haystack| {
+            b.iter(|| regex.match_locations_bytes(haystack).count())
+        });
+    }
+    group.finish();
+}
+
+fn split_locations(c: &mut Criterion) {
+    let haystack = generate::lines(HAYSTACK_LINES, 1994);
+    let regex = Regex::from_awk_ere("[ \n]+").unwrap();
+    let mut group = c.benchmark_group("split_locations");
+    group.throughput(Throughput::Bytes(haystack.len() as u64));
+    group.bench_function("words", |b| {
+        b.iter(|| regex.split_locations(&haystack).len())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, match_locations, split_locations);
+criterion_main!(benches);
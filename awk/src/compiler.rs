@@ -21,7 +21,6 @@ use pest::pratt_parser::{Assoc, Op, PrattParser};
 use pest::Parser;
 use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use std::ffi::CString;
 use std::hash::Hash;
 use std::rc::Rc;
 use std::str::Chars;
@@ -259,12 +258,16 @@ fn is_octal_digit(c: char) -> bool {
 /// # Arguments
 /// - `iter`: a character iterator placed after the '\' character in an escape sequence.
 /// # Returns
-/// a pair containing the escaped character and the next character in the iterator
+/// the escaped character. The iterator is left right after the escape sequence
 /// # Errors
 /// returns an error if the escape sequence is invalid
-fn parse_escape_sequence(iter: &mut Chars) -> Result<(char, Option<char>), String> {
-    let mut char_after_escape_sequence = None;
-    let next_char = iter.next().ok_or("invalid escape sequence".to_string())?;
+/// Parses the escape sequence following a backslash. Returns `None`, without
+/// consuming anything, if the sequence is not one defined by POSIX.
+fn parse_escape_sequence(iter: &mut Chars) -> Result<Option<char>, String> {
+    let mut lookahead = iter.clone();
+    let next_char = lookahead
+        .next()
+        .ok_or("invalid escape sequence".to_string())?;
     let escaped_char = match next_char {
         '"' => '"',
         '/' => '/',
@@ -279,13 +282,14 @@ fn parse_escape_sequence(iter: &mut Chars) -> Result<(char, Option<char>), Strin
         n if is_octal_digit(n) => {
             let mut char_code = n.to_digit(8).unwrap();
             for _ in 0..2 {
-                if let Some(c) = iter.next() {
-                    if is_octal_digit(c) {
+                // only consume the next character if it is part of the sequence
+                let mut digit_lookahead = lookahead.clone();
+                match digit_lookahead.next() {
+                    Some(c) if is_octal_digit(c) => {
                         char_code = char_code * 8 + c.to_digit(8).unwrap();
-                    } else {
-                        char_after_escape_sequence = Some(c);
-                        break;
+                        lookahead = digit_lookahead;
                     }
+                    _ => break,
                 }
             }
             if char_code == 0 {
@@ -293,10 +297,10 @@ fn parse_escape_sequence(iter: &mut Chars) -> Result<(char, Option<char>), Strin
             }
             char::from_u32(char_code).ok_or("invalid character")?
         }
-        other => return Err(format!("invalid escape sequence: \\{}", other)),
+        _ => return Ok(None),
     };
-    let char_after_escape_sequence = char_after_escape_sequence.or_else(|| iter.next());
-    Ok((escaped_char, char_after_escape_sequence))
+    *iter = lookahead;
+    Ok(Some(escaped_char))
 }
 
 pub fn escape_string_contents(s: &str) -> Result<Rc<str>, String> {
@@ -304,13 +308,16 @@ pub fn escape_string_contents(s: &str) -> Result<Rc<str>, String> {
     let mut chars = s.chars();
     while let Some(c) = chars.next() {
         match c {
-            '\\' => {
-                let (escaped_char, next) = parse_escape_sequence(&mut chars)?;
-                result.push(escaped_char);
-                if let Some(next) = next {
-                    result.push(next);
-                }
+            // a backslash followed by a newline continues the string on the next line
+            '\\' if chars.clone().next() == Some('\n') => {
+                chars.next();
             }
+            '\\' => match parse_escape_sequence(&mut chars)? {
+                Some(escaped_char) => result.push(escaped_char),
+                // the meaning of other escape sequences is undefined. The backslash
+                // is kept, so that strings like "\." still work when used as a regex
+                None => result.push('\\'),
+            },
             other => result.push(other),
         }
     }
@@ -630,10 +637,11 @@ impl Compiler {
                 Ok(Expr::new(ExprKind::Number, instructions))
             }
             Rule::ere => {
-                let ere_c_str = CString::new(primary.as_str().trim_matches('/')).unwrap();
-                let regex = Regex::new(ere_c_str)
+                let ere = primary.as_str();
+                // remove the delimiters
+                let regex = Regex::from_awk_ere(&ere[1..ere.len() - 1])
                     .map_err(|e| pest_error_from_span(primary.as_span(), e))?;
-                let index = self.push_constant(Constant::Regex(Rc::new(regex)));
+                let index = self.push_constant(Constant::Regex(regex));
                 Ok(Expr::new(
                     ExprKind::Regex,
                     Instructions::from_instructions_and_line_col(
@@ -1125,7 +1133,7 @@ impl Compiler {
     ) -> Result<(), PestError> {
         let expr = first_child(expr);
         match expr.as_rule() {
-            Rule::assignment => {
+            Rule::assignment | Rule::print_assignment => {
                 let mut inner = expr.into_inner();
                 self.compile_lvalue(inner.next().unwrap(), instructions, locals)?;
                 lvalue_to_scalar_ref(&mut instructions.opcodes);
@@ -1209,8 +1217,8 @@ impl Compiler {
                     .get_var(name.as_str(), locals)
                     .map_err(|msg| pest_error_from_span(name.as_span(), msg))?;
                 instructions.push(get_instruction, stmt_line_col);
-                if let Some(index) = inner.next() {
-                    self.compile_expr(index, instructions, locals)?;
+                if inner.peek().is_some() {
+                    self.compile_array_index(inner, instructions, locals)?;
                     instructions.push(OpCode::DeleteElement, stmt_line_col);
                 } else {
                     instructions.push(OpCode::ClearArray, stmt_line_col);
@@ -1357,6 +1365,8 @@ impl Compiler {
         let iter_deref_location = instructions.len();
         instructions.push(OpCode::Invalid, array_var_line_col);
 
+        self.loop_stack.push(LoopStubs::default());
+
         let body = inner.next().unwrap();
         self.compile_stmt(body, instructions, locals)?;
 
@@ -1365,6 +1375,19 @@ impl Compiler {
             array_var_line_col,
         );
 
+        let loop_stubs = self.loop_stack.pop().unwrap();
+        // when the loop is left with `break` the iterator is still on the stack
+        if !loop_stubs.break_stubs.is_empty() {
+            let drop_iterator_location = instructions.len();
+            instructions.push(OpCode::DropIterator, array_var_line_col);
+            for stub in loop_stubs.break_stubs {
+                instructions.opcodes[stub] = OpCode::Jump(distance(stub, drop_iterator_location));
+            }
+        }
+        for stub in loop_stubs.continue_stubs {
+            instructions.opcodes[stub] = OpCode::Jump(distance(stub, iter_deref_location));
+        }
+
         instructions.opcodes[iter_deref_location] =
             OpCode::AdvanceIterOrJump(distance(iter_deref_location, instructions.len()));
 
@@ -1550,10 +1573,10 @@ impl Compiler {
                 let stmt_line_col = stmt.line_col();
                 if let Some(expr) = stmt.into_inner().next() {
                     self.compile_expr(expr, instructions, locals)?;
+                    instructions.push(OpCode::Exit, stmt_line_col);
                 } else {
-                    instructions.push(OpCode::PushZero, stmt_line_col);
+                    instructions.push(OpCode::ExitWithoutStatus, stmt_line_col);
                 }
-                instructions.push(OpCode::Exit, stmt_line_col);
                 Ok(())
             }
             Rule::return_stmt => {
@@ -1710,12 +1733,7 @@ impl Compiler {
         })
     }
 
-    fn declare_program_functions(
-        &mut self,
-        program: Pairs<Rule>,
-        filename: &str,
-        errors: &mut Vec<PestError>,
-    ) {
+    fn declare_program_functions(&mut self, program: Pairs<Rule>, errors: &mut Vec<PestError>) {
         for item in program {
             if item.as_rule() == Rule::function_definition {
                 let mut inner = item.into_inner();
@@ -1735,14 +1753,10 @@ impl Compiler {
                     },
                 );
                 if previous_value.is_some() {
-                    let error = improve_error(
-                        pest_error_from_span(
-                            name.as_span(),
-                            format!("function '{}' is defined multiple times", name),
-                        ),
-                        filename,
-                    );
-                    errors.push(error);
+                    errors.push(pest_error_from_span(
+                        name.as_span(),
+                        format!("function '{}' is defined multiple times", name.as_str()),
+                    ));
                 }
             }
         }
@@ -1756,16 +1770,147 @@ fn location_end(loc: &InputLocation) -> usize {
     }
 }
 
+/// An error in the program text, located at the token that caused it.
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone)]
+pub struct CompilerError {
+    file: Rc<str>,
+    line: usize,
+    column: usize,
+    line_text: String,
+    is_syntax_error: bool,
+    message: String,
+}
+
+/// Returns true if the string or regex literal starting at the
+/// beginning of `text` is closed before the end of the line.
+fn literal_is_terminated(text: &str, delimiter: char) -> bool {
+    let mut chars = text.chars().skip(1);
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\n' => return false,
+            c if c == delimiter => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn offending_token(text: &str) -> &str {
+    let end = match text.chars().next() {
+        None => 0,
+        Some('"') if literal_is_terminated(text, '"') => {
+            let mut escaped = false;
+            text.char_indices()
+                .skip(1)
+                .find(|&(_, c)| {
+                    let is_end = !escaped && c == '"';
+                    escaped = !escaped && c == '\\';
+                    is_end
+                })
+                .map(|(i, _)| i + 1)
+                .unwrap()
+        }
+        Some(c) if c.is_alphanumeric() || c == '_' => text
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(text.len()),
+        Some(c) => c.len_utf8(),
+    };
+    &text[..end]
+}
+
+impl CompilerError {
+    /// Creates an error for `source_file` from an error returned by the parser
+    /// or the compiler. `offset` is the position in the source at which the
+    /// parsed text that produced the error starts.
+    fn new(error: PestError, source_file: &SourceFile, offset: usize) -> Self {
+        let source = source_file.contents.as_str();
+        let position = offset
+            + match error.location {
+                InputLocation::Pos(p) => p,
+                InputLocation::Span((start, _)) => start,
+            };
+        let line_start = source[..position].rfind('\n').map_or(0, |p| p + 1);
+        let line_end = source[position..]
+            .find('\n')
+            .map_or(source.len(), |p| position + p);
+        let rest = &source[position..];
+        let (is_syntax_error, message) = match error.variant {
+            pest::error::ErrorVariant::CustomError { message } => (false, message),
+            pest::error::ErrorVariant::ParsingError { positives, .. } => {
+                let message = if rest.is_empty() {
+                    "unexpected end of program".to_string()
+                } else if rest.starts_with('\n') {
+                    "unexpected newline".to_string()
+                } else if rest.starts_with('"') && !literal_is_terminated(rest, '"') {
+                    "unterminated string".to_string()
+                } else if rest.starts_with('/')
+                    && positives.contains(&Rule::ere)
+                    && !literal_is_terminated(rest, '/')
+                {
+                    "unterminated regular expression".to_string()
+                } else {
+                    format!("unexpected token '{}'", offending_token(rest))
+                };
+                (true, message)
+            }
+        };
+        Self {
+            file: source_file.filename.as_str().into(),
+            line: source[..position].matches('\n').count() + 1,
+            column: source[line_start..position].chars().count() + 1,
+            line_text: source[line_start..line_end].to_string(),
+            is_syntax_error,
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for CompilerError {
+    /// Formats the error as
+    /// ```text
+    /// awk: syntax error at source line 2: unexpected token '}'
+    ///  x = 1; }
+    ///         ^
+    /// ```
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "awk: ")?;
+        if self.is_syntax_error {
+            write!(f, "syntax ")?;
+        }
+        write!(f, "error at source line {}", self.line)?;
+        if !self.file.is_empty() {
+            write!(f, " of file {}", self.file)?;
+        }
+        writeln!(f, ": {}", self.message)?;
+        writeln!(f, " {}", self.line_text)?;
+        // keep tabs so that the caret lines up with the excerpt
+        let padding: String = self
+            .line_text
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(f, " {}^", padding)
+    }
+}
+
 #[cfg_attr(test, derive(Debug))]
 #[derive(Clone)]
 pub struct CompilerErrors {
-    errors: Vec<PestError>,
+    errors: Vec<CompilerError>,
 }
 
 impl std::fmt::Display for CompilerErrors {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        for error in &self.errors {
-            writeln!(f, "{}", error)?;
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
         }
         Ok(())
     }
@@ -1781,26 +1926,35 @@ fn next_checkpoint(text: &str) -> Option<usize> {
         .min()
 }
 
-fn improve_error(error: PestError, file: &str) -> PestError {
-    if !file.is_empty() {
-        error.with_path(file)
-    } else {
-        error
-    }
-}
-
-fn gather_errors(first_error: PestError, source: &str, errors: &mut Vec<PestError>, file: &str) {
-    let first_error_end = location_end(&first_error.location);
-
-    errors.push(improve_error(first_error, file));
-    let mut parsing_start = first_error_end;
-
-    while let Some(checkpoint_offset) = next_checkpoint(&source[parsing_start..]) {
-        parsing_start += checkpoint_offset;
-        match AwkParser::parse(Rule::program, &source[parsing_start..]) {
+/// Reports `first_error` and then tries to resume parsing at the
+/// start of the following items of the program, to report any
+/// other independent syntax error.
+fn gather_errors(
+    first_error: PestError,
+    source_file: &SourceFile,
+    errors: &mut Vec<CompilerError>,
+) {
+    let source = source_file.contents.as_str();
+    let mut search_start = location_end(&first_error.location);
+    errors.push(CompilerError::new(first_error, source_file, 0));
+
+    while let Some(checkpoint_offset) = next_checkpoint(&source[search_start..]) {
+        let parsing_start = search_start + checkpoint_offset;
+        let err = match AwkParser::parse(Rule::program, &source[parsing_start..]) {
             Ok(_) => break,
-            Err(err) => errors.push(improve_error(err, file)),
+            Err(err) => err,
+        };
+        // always move past the checkpoint, so that the same error
+        // is not reported twice
+        let checkpoint_len = source[parsing_start..]
+            .chars()
+            .next()
+            .map_or(0, |c| c.len_utf8());
+        if checkpoint_len == 0 {
+            break;
         }
+        search_start = parsing_start + location_end(&err.location).max(checkpoint_len);
+        errors.push(CompilerError::new(err, source_file, parsing_start));
     }
 }
 
@@ -1826,24 +1980,30 @@ pub fn compile_program(sources: &[SourceFile]) -> Result<Program, CompilerErrors
         match AwkParser::parse(Rule::program, &source_file.contents) {
             Ok(mut program) => {
                 let program = program.next().unwrap();
-                parsed_sources.push((filename, program.into_inner()));
+                parsed_sources.push((filename, source_file, program.into_inner()));
             }
             Err(err) => {
-                gather_errors(err, &source_file.contents, &mut errors, &filename);
+                gather_errors(err, source_file, &mut errors);
             }
         };
     }
 
     let mut compiler = Compiler::default();
-    for (filename, program_iter) in &parsed_sources {
-        compiler.declare_program_functions(program_iter.clone(), filename, &mut errors);
+    for (_, source_file, program_iter) in &parsed_sources {
+        let mut declaration_errors = Vec::new();
+        compiler.declare_program_functions(program_iter.clone(), &mut declaration_errors);
+        errors.extend(
+            declaration_errors
+                .into_iter()
+                .map(|err| CompilerError::new(err, source_file, 0)),
+        );
     }
 
     let mut begin_actions = Vec::new();
     let mut rules = Vec::new();
     let mut end_actions = Vec::new();
     let mut functions = Vec::new();
-    for (filename, program_iter) in parsed_sources {
+    for (filename, source_file, program_iter) in parsed_sources {
         for item in program_iter {
             match item.as_rule() {
                 Rule::begin_action | Rule::end_action => {
@@ -1855,7 +2015,7 @@ pub fn compile_program(sources: &[SourceFile]) -> Result<Program, CompilerErrors
                         &HashMap::new(),
                     );
                     if let Err(err) = result {
-                        errors.push(improve_error(err, &filename));
+                        errors.push(CompilerError::new(err, source_file, 0));
                     }
                     if is_begin_action {
                         begin_actions.push(instructions.into_action(filename.clone()));
@@ -1865,12 +2025,12 @@ pub fn compile_program(sources: &[SourceFile]) -> Result<Program, CompilerErrors
                 }
                 Rule::rule => match compiler.compile_rule(item, filename.clone()) {
                     Ok(rule) => rules.push(rule),
-                    Err(err) => errors.push(improve_error(err, &filename)),
+                    Err(err) => errors.push(CompilerError::new(err, source_file, 0)),
                 },
                 Rule::function_definition => {
                     match compiler.compile_function_definition(item, filename.clone()) {
                         Ok(function) => functions.push(function),
-                        Err(err) => errors.push(improve_error(err, &filename)),
+                        Err(err) => errors.push(CompilerError::new(err, source_file, 0)),
                     }
                 }
                 Rule::EOI => {}
@@ -1884,8 +2044,8 @@ pub fn compile_program(sources: &[SourceFile]) -> Result<Program, CompilerErrors
         .into_inner()
         .into_iter()
         .filter_map(|(k, v)| match v {
-            GlobalName::Variable(id) => Some((k, id)),
-            _ => None,
+            GlobalName::Variable(id) | GlobalName::SpecialVar(id) => Some((k, id)),
+            GlobalName::Function { .. } => None,
         })
         .collect();
 
@@ -1904,11 +2064,6 @@ pub fn compile_program(sources: &[SourceFile]) -> Result<Program, CompilerErrors
     }
 }
 
-/// Returns true if the given string is a valid number token.
-pub fn is_valid_number(s: &str) -> bool {
-    AwkParser::parse(Rule::number, s).is_ok()
-}
-
 #[cfg(test)]
 mod test {
 
@@ -2073,6 +2228,9 @@ mod test {
         let (_, constants) = compile_expr(r#""hello\\world""#);
         assert_eq!(constants, vec![Constant::from("hello\\world")]);
 
+        let (_, constants) = compile_expr(r#""\.\+""#);
+        assert_eq!(constants, vec![Constant::from("\\.\\+")]);
+
         let (_, constants) = compile_expr(r#""hello\"world""#);
         assert_eq!(constants, vec![Constant::from(r#"hello"world"#)]);
 
@@ -2081,6 +2239,12 @@ mod test {
 
         let (_, constants) = compile_expr(r#""hello\141world""#);
         assert_eq!(constants, vec![Constant::from("helloaworld")]);
+
+        let (_, constants) = compile_expr(r#""\\\\&""#);
+        assert_eq!(constants, vec![Constant::from("\\\\&")]);
+
+        let (_, constants) = compile_expr(r#""\41\\n""#);
+        assert_eq!(constants, vec![Constant::from("!\\n")]);
     }
 
     #[test]
@@ -2217,6 +2381,38 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_exp_binds_tighter_than_unary_minus() {
+        let (instructions, _) = compile_expr("-2 ^ 2");
+        assert_eq!(
+            instructions,
+            vec![
+                OpCode::PushConstant(0),
+                OpCode::PushConstant(1),
+                OpCode::Pow,
+                OpCode::Negate,
+            ]
+        );
+
+        let (instructions, _) = compile_expr("2 ^ -1");
+        assert_eq!(
+            instructions,
+            vec![
+                OpCode::PushConstant(0),
+                OpCode::PushConstant(1),
+                OpCode::Negate,
+                OpCode::Pow,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_double_star_is_exp() {
+        assert_eq!(compile_expr("1 ** 2 ** 3"), compile_expr("1 ^ 2 ^ 3"));
+        assert_eq!(compile_expr("-2 ** 2"), compile_expr("-2 ^ 2"));
+        assert_eq!(compile_expr("a **= 2"), compile_expr("a ^= 2"));
+    }
+
     #[test]
     fn test_compile_binary_numeric_exprs_with_correct_precedence() {
         let (instructions, constants) = compile_expr("1 + 2 * 3");
@@ -2542,7 +2738,7 @@ mod test {
             constants,
             vec![
                 Constant::from("hello"),
-                Constant::Regex(Rc::new(regex_from_str("hello")))
+                Constant::Regex(regex_from_str("hello"))
             ]
         )
     }
@@ -2564,11 +2760,65 @@ mod test {
             constants,
             vec![
                 Constant::from("test"),
-                Constant::Regex(Rc::new(regex_from_str("te?s+t*")))
+                Constant::Regex(regex_from_str("te?s+t*"))
             ]
         )
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_compile_ere_with_escaped_slashes() {
+        let (_, constants) = compile_expr(r#" "a/b/" ~ /a\/b\//"#);
+        assert_eq!(
+            constants,
+            vec![
+                Constant::from("a/b/"),
+                Constant::Regex(regex_from_str("a/b/"))
+            ]
+        )
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_compile_ere_ending_with_an_escaped_backslash() {
+        let (_, constants) = compile_expr(r#" "a\\" ~ /a\\/"#);
+        assert_eq!(
+            constants,
+            vec![
+                Constant::from("a\\"),
+                Constant::Regex(regex_from_str("a\\\\"))
+            ]
+        )
+    }
+
+    #[test]
+    fn test_syntax_errors_after_recovery_are_located_in_the_whole_source() {
+        let errors = compile_program(&[SourceFile::stdin(
+            "BEGIN { print ( }\nBEGIN { x = }\n\nEND { y = 1 + }".to_string(),
+        )])
+        .expect_err("expected error compiling program")
+        .errors;
+        let locations = errors
+            .iter()
+            .map(|e| (e.line, e.column, e.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            vec![
+                (1, 17, "unexpected token '}'"),
+                (2, 13, "unexpected token '}'"),
+                (4, 15, "unexpected token '}'")
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_compile_invalid_ere_is_an_error() {
+        let result = compile_program(&[SourceFile::stdin("$0 ~ /a(/ { print }".to_string())]);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_compile_and() {
         let (instructions, constants) = compile_expr("1 && 2");
@@ -3144,7 +3394,7 @@ mod test {
     #[test]
     fn test_compile_exit() {
         let (instructions, _) = compile_stmt("exit;");
-        assert_eq!(instructions, vec![OpCode::PushZero, OpCode::Exit]);
+        assert_eq!(instructions, vec![OpCode::ExitWithoutStatus]);
 
         let (instructions, _) = compile_stmt("exit 1;");
         assert_eq!(instructions, vec![OpCode::PushConstant(0), OpCode::Exit]);
@@ -3178,6 +3428,23 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_compile_delete_multidimensional_element() {
+        let (instructions, _) = compile_stmt("delete a[1, 2];");
+        assert_eq!(
+            instructions,
+            vec![
+                OpCode::GetGlobal(FIRST_GLOBAL_VAR),
+                OpCode::PushConstant(0),
+                OpCode::GetGlobal(SpecialVar::Subsep as u32),
+                OpCode::Concat,
+                OpCode::PushConstant(1),
+                OpCode::Concat,
+                OpCode::DeleteElement,
+            ]
+        );
+    }
+
     #[test]
     fn test_compile_clear_array() {
         let (instructions, _) = compile_stmt("delete a");
@@ -3465,6 +3732,104 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_newline_after_logical_operators_continues_the_condition() {
+        assert_eq!(
+            compile_stmt("if (a &&\n  b ||\n\n  c) d = 1"),
+            compile_stmt("if (a && b || c) d = 1")
+        );
+    }
+
+    #[test]
+    fn test_newline_after_comma_continues_the_list() {
+        let split =
+            compile_correct_program("function f(a,\n  b) { return a b }\nBEGIN { f(1,\n  2) }");
+        let joined = compile_correct_program("function f(a, b) { return a b }\nBEGIN { f(1, 2) }");
+        assert_eq!(split.functions[0].parameters_count, 2);
+        assert_eq!(
+            split.begin_actions[0].instructions,
+            joined.begin_actions[0].instructions
+        );
+    }
+
+    #[test]
+    fn test_if_else_chain_with_braces_on_their_own_lines() {
+        let split = compile_stmt(
+            r#"
+            if (a)
+            {
+                b = 1
+            }
+            else if (c)
+            {
+                b = 2
+            }
+            else
+            {
+                b = 3
+            }
+            "#,
+        );
+        assert_eq!(
+            split,
+            compile_stmt("if (a) { b = 1 } else if (c) { b = 2 } else { b = 3 }")
+        );
+    }
+
+    #[test]
+    fn test_else_after_terminated_statement_in_last_if() {
+        assert_eq!(
+            compile_stmt("if (a) b = 1; else\n b = 2"),
+            compile_stmt("if (a) { b = 1 } else { b = 2 }")
+        );
+        assert_eq!(
+            compile_stmt("if (a) if (b) c = 1; else c = 2"),
+            compile_stmt("if (a) { if (b) { c = 1 } else { c = 2 } }")
+        );
+    }
+
+    #[test]
+    fn test_name_starting_with_else_is_not_an_else_branch() {
+        assert_eq!(
+            compile_stmt("if (a) b = 1; elsewhere = 2"),
+            compile_stmt("if (a) { b = 1 } elsewhere = 2")
+        );
+    }
+
+    #[test]
+    fn test_backslash_newline_is_a_continuation() {
+        assert_eq!(
+            compile_stmt("x = 1 + \\\n 2; print \\\n x"),
+            compile_stmt("x = 1 + 2; print x")
+        );
+        assert_eq!(
+            compile_correct_program("BEGIN \\\n{ x = 1 }")
+                .begin_actions
+                .len(),
+            1
+        );
+        let (_, constants) = compile_stmt("x = \"ab\\\ncd\"");
+        assert_eq!(constants, vec![Constant::from("abcd")]);
+    }
+
+    #[test]
+    fn test_minus_after_an_operand_is_binary() {
+        assert_eq!(compile_expr(r#"1 " " -1"#), compile_expr(r#"1 (" " - 1)"#));
+        assert_eq!(compile_expr("a -1"), compile_expr("(a - 1)"));
+        assert_eq!(
+            compile_expr(r#"$1" "$2-1"#),
+            compile_expr(r#"$1 " " ($2 - 1)"#)
+        );
+    }
+
+    #[test]
+    fn test_increment_applies_to_the_following_lvalue_after_a_non_lvalue() {
+        assert_eq!(compile_expr(r#"2 " " ++a"#), compile_expr(r#"2 " " (++a)"#));
+        assert_eq!(compile_expr("x --1"), compile_expr("(x--) 1"));
+        assert_eq!(compile_expr("x+++y"), compile_expr("(x++) + y"));
+        assert_eq!(compile_expr("x---y"), compile_expr("(x--) - y"));
+    }
+
     #[test]
     fn test_compile_empty_function() {
         let program = compile_correct_program(
@@ -4129,6 +4494,23 @@ mod test {
         assert_eq!(constants, vec![Constant::Number(1.0)]);
     }
 
+    #[test]
+    fn test_compile_for_each_stmt_with_break_and_continue() {
+        let (program, _) = compile_stmt("for (a in array) { continue; break; }");
+        assert_eq!(
+            program,
+            vec![
+                OpCode::GlobalScalarRef(FIRST_GLOBAL_VAR),
+                OpCode::CreateGlobalIterator(FIRST_GLOBAL_VAR + 1),
+                OpCode::AdvanceIterOrJump(5),
+                OpCode::Jump(-1),
+                OpCode::Jump(2),
+                OpCode::Jump(-3),
+                OpCode::DropIterator,
+            ]
+        );
+    }
+
     #[test]
     fn test_compile_recursive_function() {
         let program = compile_correct_program(
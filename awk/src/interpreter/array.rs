@@ -18,9 +18,10 @@ use super::AwkValue;
 #[derive(Clone, PartialEq)]
 pub struct KeyIterator {
     index: usize,
+    end: usize,
 }
 
-pub type Key = Rc<str>;
+pub type Key = Rc<[u8]>;
 
 #[cfg_attr(test, derive(Debug))]
 #[derive(Clone, Copy, PartialEq)]
@@ -30,6 +31,11 @@ pub struct ValueIndex {
 
 pub type KeyValuePair = (Key, AwkValue);
 
+/// Elements are kept in insertion order, which is also the order in which
+/// `for (k in array)` visits them. An iterator visits each element that was in
+/// the array when it was created exactly once, skipping the ones deleted before
+/// it reaches them. Elements are always inserted after the existing ones, so
+/// the ones inserted while an iterator is active are not visited by it.
 #[cfg_attr(test, derive(Debug))]
 #[derive(Clone, PartialEq, Default)]
 pub struct Array {
@@ -42,57 +48,65 @@ pub struct Array {
 impl Array {
     /// Remove the element with the given key.
     /// This is possible even if there is an active iterator.
-    pub fn delete(&mut self, key: &str) {
+    pub fn delete(&mut self, key: &[u8]) {
         if let Some(pair_index) = self.key_map.remove(key) {
-            if self.iterator_count == 0 {
-                self.pairs.swap_remove(pair_index);
-                if !self.pairs.is_empty() {
-                    let (key, _) = self.pairs[pair_index].as_ref().unwrap();
-                    *self.key_map.get_mut(key).unwrap() = pair_index;
-                }
-            } else {
-                self.pairs[pair_index] = None;
-                self.empty_slots += 1;
+            // the slot is left empty, so that the elements after it keep their
+            // position in the iteration order
+            self.pairs[pair_index] = None;
+            self.empty_slots += 1;
+            if self.iterator_count == 0 && self.empty_slots > self.pairs.len() / 2 {
+                self.compact();
             }
         }
     }
 
+    /// Remove the empty slots, preserving the order of the remaining elements.
+    fn compact(&mut self) {
+        self.pairs.retain(Option::is_some);
+        for (index, (key, _)) in self.pairs.iter().flatten().enumerate() {
+            *self.key_map.get_mut(key).unwrap() = index;
+        }
+        self.empty_slots = 0;
+    }
+
     pub fn key_iter(&mut self) -> KeyIterator {
         self.iterator_count += 1;
-        KeyIterator { index: 0 }
+        KeyIterator {
+            index: 0,
+            end: self.pairs.len(),
+        }
     }
 
     pub fn key_iter_next(&mut self, iter: &mut KeyIterator) -> Option<Key> {
-        for maybe_key in &self.pairs[iter.index..] {
+        while iter.index < iter.end {
+            let maybe_key = &self.pairs[iter.index];
             iter.index += 1;
             if let Some((key, _)) = maybe_key {
                 return Some(key.clone());
             }
         }
-        iter.index = usize::MAX;
-        self.iterator_count -= 1;
+        self.key_iter_drop(iter);
         None
     }
 
+    /// Release an iterator that has not been run to completion.
+    pub fn key_iter_drop(&mut self, iter: &mut KeyIterator) {
+        if iter.index != usize::MAX {
+            iter.index = usize::MAX;
+            self.iterator_count -= 1;
+        }
+    }
+
     /// Get the `ValueIndex` of the key in the array. If the key does not exist, it will be inserted.
-    /// # Errors
-    /// If the array has an active iterator, an error will be returned.
     pub fn get_value_index(&mut self, key: Key) -> Result<ValueIndex, String> {
         match self.key_map.entry(key.clone()) {
             Entry::Occupied(e) => Ok(ValueIndex { index: *e.get() }),
             Entry::Vacant(e) => {
-                if self.iterator_count == 0 {
-                    let pair_index = insert_pair(
-                        key,
-                        AwkValue::uninitialized_scalar(),
-                        &mut self.pairs,
-                        self.empty_slots == 0,
-                    );
-                    e.insert(pair_index);
-                    Ok(ValueIndex { index: pair_index })
-                } else {
-                    Err("cannot insert into an array with an active iterator".to_string())
-                }
+                let pair_index = self.pairs.len();
+                self.pairs
+                    .push(Some((key, AwkValue::uninitialized_scalar())));
+                e.insert(pair_index);
+                Ok(ValueIndex { index: pair_index })
             }
         }
     }
@@ -107,38 +121,39 @@ impl Array {
     }
 
     /// Set the array element at the given key to the given value
-    /// # Errors
-    /// If the array has an active iterator, an error will be returned.
     pub fn set<V: Into<AwkValue>>(&mut self, key: String, value: V) -> Result<ValueIndex, String> {
-        if self.iterator_count == 0 {
-            let key = Rc::<str>::from(key);
-            let value = value.into();
-            match self.key_map.entry(key.clone()) {
-                Entry::Occupied(e) => {
-                    let pair_index = *e.get();
-                    self.pairs[pair_index].as_mut().unwrap().1 = value;
-                    Ok(ValueIndex { index: pair_index })
-                }
-                Entry::Vacant(e) => {
-                    let pair_index =
-                        insert_pair(key, value, &mut self.pairs, self.empty_slots == 0);
-                    e.insert(pair_index);
-                    Ok(ValueIndex { index: pair_index })
-                }
+        let key = Rc::<[u8]>::from(key.into_bytes());
+        let value = value.into();
+        match self.key_map.entry(key.clone()) {
+            Entry::Occupied(e) => {
+                let pair_index = *e.get();
+                self.pairs[pair_index].as_mut().unwrap().1 = value;
+                Ok(ValueIndex { index: pair_index })
+            }
+            Entry::Vacant(e) => {
+                let pair_index = self.pairs.len();
+                self.pairs.push(Some((key, value)));
+                e.insert(pair_index);
+                Ok(ValueIndex { index: pair_index })
             }
-        } else {
-            Err("cannot insert into an array with an active iterator".to_string())
         }
     }
 
-    pub fn contains(&self, key: &str) -> bool {
+    pub fn contains(&self, key: &[u8]) -> bool {
         self.key_map.contains_key(key)
     }
 
+    /// Remove all the elements of the array.
+    /// This is possible even if there is an active iterator.
     pub fn clear(&mut self) {
         self.key_map.clear();
-        self.pairs.clear();
-        self.empty_slots = 0;
+        if self.iterator_count == 0 {
+            self.pairs.clear();
+            self.empty_slots = 0;
+        } else {
+            self.pairs.iter_mut().for_each(|pair| *pair = None);
+            self.empty_slots = self.pairs.len();
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -158,32 +173,14 @@ impl<S: Into<String>, A: Into<AwkValue>> FromIterator<(S, A)> for Array {
     }
 }
 
-fn insert_pair(
-    key: Key,
-    value: AwkValue,
-    pairs: &mut Vec<Option<KeyValuePair>>,
-    has_empty_slots: bool,
-) -> usize {
-    if has_empty_slots {
-        let index = pairs.len();
-        pairs.push(Some((key, value)));
-        index
-    } else {
-        let index = pairs
-            .iter()
-            .enumerate()
-            .filter_map(|(i, v)| v.as_ref().map(|_| i))
-            .next()
-            .expect("map has no empty key slots");
-        pairs[index] = Some((key, value));
-        index
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn key(key: &str) -> Key {
+        Rc::from(key.as_bytes())
+    }
+
     #[test]
     fn iterate_through_empty_array() {
         let mut array = Array::default();
@@ -198,9 +195,9 @@ mod tests {
         array.set("b".to_string(), 2.0).unwrap();
         array.set("c".to_string(), 3.0).unwrap();
         let mut iter = array.key_iter();
-        assert_eq!(array.key_iter_next(&mut iter), Some(Rc::from("a")));
-        assert_eq!(array.key_iter_next(&mut iter), Some(Rc::from("b")));
-        assert_eq!(array.key_iter_next(&mut iter), Some(Rc::from("c")));
+        assert_eq!(array.key_iter_next(&mut iter), Some(key("a")));
+        assert_eq!(array.key_iter_next(&mut iter), Some(key("b")));
+        assert_eq!(array.key_iter_next(&mut iter), Some(key("c")));
         assert_eq!(array.key_iter_next(&mut iter), None);
     }
 
@@ -208,10 +205,10 @@ mod tests {
     fn delete_from_array() {
         let mut array = Array::default();
         array.set("a".to_string(), 1.0).unwrap();
-        array.delete("a");
+        array.delete(b"a");
         assert_eq!(array.len(), 0);
         assert_eq!(
-            array.get_value("a".into()).cloned(),
+            array.get_value(key("a")).cloned(),
             Ok(AwkValue::uninitialized_scalar())
         );
     }
@@ -221,10 +218,7 @@ mod tests {
         let mut array = Array::default();
         array.set("a".to_string(), 1.0).unwrap();
         assert_eq!(array.len(), 1);
-        assert_eq!(
-            array.get_value("a".into()).cloned(),
-            Ok(AwkValue::from(1.0))
-        );
+        assert_eq!(array.get_value(key("a")).cloned(), Ok(AwkValue::from(1.0)));
     }
 
     #[test]
@@ -233,10 +227,7 @@ mod tests {
         array.set("a".to_string(), 1.0).unwrap();
         array.set("a".to_string(), 2.0).unwrap();
         assert_eq!(array.len(), 1);
-        assert_eq!(
-            array.get_value("a".into()).cloned(),
-            Ok(AwkValue::from(2.0))
-        );
+        assert_eq!(array.get_value(key("a")).cloned(), Ok(AwkValue::from(2.0)));
     }
 
     #[test]
@@ -247,18 +238,68 @@ mod tests {
         array.set("c".to_string(), 1.0).unwrap();
         array.set("d".to_string(), 1.0).unwrap();
         let mut iter = array.key_iter();
-        array.delete("b");
-        array.delete("d");
-        assert_eq!(array.key_iter_next(&mut iter), Some(Rc::from("a")));
-        assert_eq!(array.key_iter_next(&mut iter), Some(Rc::from("c")));
+        array.delete(b"b");
+        array.delete(b"d");
+        assert_eq!(array.key_iter_next(&mut iter), Some(key("a")));
+        assert_eq!(array.key_iter_next(&mut iter), Some(key("c")));
+        assert_eq!(array.key_iter_next(&mut iter), None);
+    }
+
+    #[test]
+    fn insert_with_active_iterator_is_not_visited() {
+        let mut array = Array::default();
+        array.set("a".to_string(), 1.0).unwrap();
+        let mut iter = array.key_iter();
+        assert_eq!(array.key_iter_next(&mut iter), Some(key("a")));
+        array.set("b".to_string(), 2.0).unwrap();
+        array.get_value(key("c")).unwrap();
+        assert_eq!(array.key_iter_next(&mut iter), None);
+        assert_eq!(array.len(), 3);
+    }
+
+    #[test]
+    fn insert_in_deleted_slot_with_active_iterator_is_not_visited() {
+        let mut array = Array::default();
+        array.set("a".to_string(), 1.0).unwrap();
+        array.set("b".to_string(), 2.0).unwrap();
+        array.set("c".to_string(), 3.0).unwrap();
+        let mut iter = array.key_iter();
+        assert_eq!(array.key_iter_next(&mut iter), Some(key("a")));
+        array.delete(b"b");
+        array.set("d".to_string(), 4.0).unwrap();
+        assert_eq!(array.key_iter_next(&mut iter), Some(key("c")));
         assert_eq!(array.key_iter_next(&mut iter), None);
     }
 
     #[test]
-    fn insert_with_active_iterator_is_error() {
+    fn iteration_follows_insertion_order_after_deletions() {
         let mut array = Array::default();
-        let _ = array.key_iter();
-        assert!(array.set("a".to_string(), 1.0).is_err());
+        for key in ["a", "b", "c", "d", "e"] {
+            array.set(key.to_string(), 1.0).unwrap();
+        }
+        array.delete(b"a");
+        array.delete(b"c");
+        array.delete(b"d");
+        array.set("f".to_string(), 1.0).unwrap();
+        let mut iter = array.key_iter();
+        assert_eq!(array.key_iter_next(&mut iter), Some(key("b")));
+        assert_eq!(array.key_iter_next(&mut iter), Some(key("e")));
+        assert_eq!(array.key_iter_next(&mut iter), Some(key("f")));
+        assert_eq!(array.key_iter_next(&mut iter), None);
+        assert_eq!(array.get_value(key("e")).cloned(), Ok(AwkValue::from(1.0)));
+    }
+
+    #[test]
+    fn dropped_iterator_is_no_longer_active() {
+        let mut array = Array::default();
+        array.set("a".to_string(), 1.0).unwrap();
+        array.set("b".to_string(), 2.0).unwrap();
+        let mut iter = array.key_iter();
+        assert_eq!(array.key_iter_next(&mut iter), Some(key("a")));
+        array.key_iter_drop(&mut iter);
+        assert_eq!(array.iterator_count, 0);
+        array.key_iter_drop(&mut iter);
+        assert_eq!(array.iterator_count, 0);
     }
 
     #[test]
@@ -266,14 +307,11 @@ mod tests {
         let mut array = Array::default();
         array.set("a".to_string(), 1.0).unwrap();
         let mut iter = array.key_iter();
-        assert_eq!(array.key_iter_next(&mut iter), Some(Rc::from("a")));
+        assert_eq!(array.key_iter_next(&mut iter), Some(key("a")));
         assert_eq!(array.key_iter_next(&mut iter), None);
         assert!(array.set("e".to_string(), 2.0).is_ok());
         assert_eq!(array.len(), 2);
-        assert_eq!(
-            array.get_value("e".into()).cloned(),
-            Ok(AwkValue::from(2.0))
-        );
+        assert_eq!(array.get_value(key("e")).cloned(), Ok(AwkValue::from(2.0)));
     }
 
     #[test]
@@ -284,13 +322,61 @@ mod tests {
         array.set("c".to_string(), 3.0).unwrap();
         let mut iter1 = array.key_iter();
         let mut iter2 = array.key_iter();
-        assert_eq!(array.key_iter_next(&mut iter1), Some(Rc::from("a")));
-        array.delete("a");
-        assert_eq!(array.key_iter_next(&mut iter2), Some(Rc::from("b")));
-        array.delete("b");
-        assert_eq!(array.key_iter_next(&mut iter1), Some(Rc::from("c")));
-        assert_eq!(array.key_iter_next(&mut iter2), Some(Rc::from("c")));
+        assert_eq!(array.key_iter_next(&mut iter1), Some(key("a")));
+        array.delete(b"a");
+        assert_eq!(array.key_iter_next(&mut iter2), Some(key("b")));
+        array.delete(b"b");
+        assert_eq!(array.key_iter_next(&mut iter1), Some(key("c")));
+        assert_eq!(array.key_iter_next(&mut iter2), Some(key("c")));
         assert_eq!(array.key_iter_next(&mut iter1), None);
         assert_eq!(array.key_iter_next(&mut iter2), None);
     }
+
+    #[test]
+    fn delete_last_element() {
+        let mut array = Array::default();
+        array.set("a".to_string(), 1.0).unwrap();
+        array.set("b".to_string(), 2.0).unwrap();
+        array.delete(b"b");
+        assert_eq!(array.len(), 1);
+        assert!(array.contains(b"a"));
+        assert!(!array.contains(b"b"));
+    }
+
+    #[test]
+    fn insert_after_deleting_with_active_iterator() {
+        let mut array = Array::default();
+        array.set("a".to_string(), 1.0).unwrap();
+        array.set("b".to_string(), 2.0).unwrap();
+        array.set("c".to_string(), 3.0).unwrap();
+        let mut iter = array.key_iter();
+        array.delete(b"a");
+        array.delete(b"b");
+        assert_eq!(array.key_iter_next(&mut iter), Some(key("c")));
+        assert_eq!(array.key_iter_next(&mut iter), None);
+
+        array.set("d".to_string(), 4.0).unwrap();
+        array.set("e".to_string(), 5.0).unwrap();
+        array.set("f".to_string(), 6.0).unwrap();
+        array.delete(b"c");
+        assert_eq!(array.len(), 3);
+        assert_eq!(array.get_value(key("d")).cloned(), Ok(AwkValue::from(4.0)));
+        assert_eq!(array.get_value(key("e")).cloned(), Ok(AwkValue::from(5.0)));
+        assert_eq!(array.get_value(key("f")).cloned(), Ok(AwkValue::from(6.0)));
+        assert!(!array.contains(b"c"));
+    }
+
+    #[test]
+    fn clear_with_active_iterator() {
+        let mut array = Array::default();
+        array.set("a".to_string(), 1.0).unwrap();
+        array.set("b".to_string(), 2.0).unwrap();
+        let mut iter = array.key_iter();
+        assert_eq!(array.key_iter_next(&mut iter), Some(key("a")));
+        array.clear();
+        assert_eq!(array.key_iter_next(&mut iter), None);
+        assert_eq!(array.len(), 0);
+        array.set("c".to_string(), 3.0).unwrap();
+        assert_eq!(array.len(), 1);
+    }
 }
@@ -7,7 +7,9 @@
 // SPDX-License-Identifier: MIT
 //
 
-use std::{fmt::Write, str::Chars};
+use std::{fmt::Write, slice::Iter};
+
+use super::string::CharacterSet;
 
 const BASE_8_DIGITS: [char; 8] = ['0', '1', '2', '3', '4', '5', '6', '7'];
 const BASE_10_DIGITS: [char; 10] = ['0', '1', '2', '3', '4', '5', '6', '7', '8', '9'];
@@ -29,13 +31,9 @@ fn integer_hex_prefix_str(integer_format: IntegerFormat, args: &FormatArgs) -> &
     ""
 }
 
-fn decimal_point() -> char {
-    if cfg!(miri) {
-        '.'
-    } else {
-        unsafe { (*(*libc::localeconv()).decimal_point) as u8 as char }
-    }
-}
+/// Numbers are always formatted with the decimal point of the C locale, as
+/// they are parsed, so that output can be read back whatever the locale.
+const DECIMAL_POINT: char = '.';
 
 fn copy_buffer_to_target(buffer: &[u8], target: &mut String) {
     for c in buffer.iter() {
@@ -142,7 +140,7 @@ fn write_exponent(
     should_add_dot_after_number: bool,
 ) {
     if should_add_dot_after_number {
-        target.push(decimal_point());
+        target.push(DECIMAL_POINT);
     }
 
     // push the exponent character
@@ -222,7 +220,7 @@ fn remove_trailing_zeros(
             }
         }
     }
-    if target.chars().last().is_some_and(|c| c == decimal_point()) {
+    if target.chars().last().is_some_and(|c| c == DECIMAL_POINT) {
         target.pop();
     }
 }
@@ -267,22 +265,25 @@ pub struct FormatArgs {
 
 /// Parse the conversion specifier arguments from the format string.
 /// # Arguments
-/// `iter` - An iterator over the characters of the format string. The iterator should be positioned
+/// `iter` - An iterator over the bytes of the format string. The iterator should be positioned
 /// after the '%' character that starts the conversion specifier.
+/// `next_arg` - Called to get the value of a width or precision given as `*`.
 /// # Returns
 /// A tuple containing the conversion specifier character and the parsed arguments.
-pub fn parse_conversion_specifier_args(iter: &mut Chars) -> Result<(char, FormatArgs), String> {
-    let iter_next = |iter: &mut Chars| iter.next().ok_or("invalid format string".to_string());
+pub fn parse_conversion_specifier_args(
+    iter: &mut Iter<u8>,
+    mut next_arg: impl FnMut() -> Result<i64, String>,
+) -> Result<(u8, FormatArgs), String> {
+    let iter_next = |iter: &mut Iter<u8>| {
+        iter.next()
+            .copied()
+            .ok_or("invalid format string".to_string())
+    };
 
-    let parse_number = |next: &mut char, iter: &mut Chars| -> Result<usize, String> {
+    let parse_number = |next: &mut u8, iter: &mut Iter<u8>| -> Result<usize, String> {
         let mut number = 0;
-        loop {
-            match *next {
-                c if c.is_ascii_digit() => {
-                    number = number * 10 + c.to_digit(10).unwrap() as usize;
-                }
-                _ => break,
-            }
+        while next.is_ascii_digit() {
+            number = number * 10 + (*next - b'0') as usize;
             *next = iter_next(iter)?;
         }
         Ok(number)
@@ -292,21 +293,37 @@ pub fn parse_conversion_specifier_args(iter: &mut Chars) -> Result<(char, Format
     let mut next = iter_next(iter)?;
     loop {
         match next {
-            '-' => result.left_justified = true,
-            '+' => result.signed = true,
-            ' ' => result.prefix_space = true,
-            '#' => result.alternative_form = true,
-            '0' => result.zero_padded = true,
+            b'-' => result.left_justified = true,
+            b'+' => result.signed = true,
+            b' ' => result.prefix_space = true,
+            b'#' => result.alternative_form = true,
+            b'0' => result.zero_padded = true,
             _ => break,
         }
         next = iter_next(iter)?;
     }
 
-    result.width = parse_number(&mut next, iter)?;
+    result.width = if next == b'*' {
+        next = iter_next(iter)?;
+        let width = next_arg()?;
+        // > A negative field width is taken as a '-' flag followed by a positive field width
+        if width < 0 {
+            result.left_justified = true;
+        }
+        width.unsigned_abs() as usize
+    } else {
+        parse_number(&mut next, iter)?
+    };
 
-    result.precision = if next == decimal_point() {
+    result.precision = if next == b'.' {
         next = iter_next(iter)?;
-        Some(parse_number(&mut next, iter)?)
+        if next == b'*' {
+            next = iter_next(iter)?;
+            // > A negative precision is taken as if the precision were omitted
+            usize::try_from(next_arg()?).ok()
+        } else {
+            Some(parse_number(&mut next, iter)?)
+        }
     } else {
         None
     };
@@ -426,7 +443,9 @@ pub fn fmt_write_signed(target: &mut String, value: i64, args: &FormatArgs) {
         pad_target(target, precision.saturating_sub(buffer_length), b'0');
         copy_buffer_to_target(&buffer[buffer_start..], target);
         pad_target(target, args.width.saturating_sub(number_length), b' ');
-    } else if args.zero_padded {
+    } else if args.precision.is_none() && args.zero_padded {
+        // > For d, i, o, u, x, and X conversion specifiers, if a precision
+        // > is specified, the '0' flag shall be ignored
         target.push_str(sign);
         pad_target(target, args.width.saturating_sub(number_length), b'0');
         pad_target(target, precision.saturating_sub(buffer_length), b'0');
@@ -506,11 +525,11 @@ pub fn fmt_write_hex_float(
 
     let copy_fraction = |target: &mut String| {
         if fraction_buffer_length != 0 {
-            target.push(decimal_point());
+            target.push(DECIMAL_POINT);
             copy_buffer_to_target(&fraction_buffer[..fraction_buffer_length], target);
             pad_target(target, extra_trailing_zeros, b'0');
         } else if args.alternative_form {
-            target.push(decimal_point());
+            target.push(DECIMAL_POINT);
         }
     };
 
@@ -580,7 +599,7 @@ pub fn fmt_write_decimal_float(
         target.push_str(sign);
         write!(target, "{:.1$}", value, precision).expect("error writing to string");
         if should_add_dot_after_number {
-            target.push(decimal_point());
+            target.push(DECIMAL_POINT);
         }
         let number_length = target.len() - write_starting_index;
         pad_target(target, args.width.saturating_sub(number_length), b' ');
@@ -594,7 +613,7 @@ pub fn fmt_write_decimal_float(
             swap_sign_in_front_of_number(target, sign, write_starting_index);
         }
         if should_add_dot_after_number {
-            target.push(decimal_point());
+            target.push(DECIMAL_POINT);
         }
     }
 }
@@ -763,7 +782,7 @@ pub fn fmt_write_float_general(
         }
 
         if should_add_dot_after_number {
-            target.push(decimal_point());
+            target.push(DECIMAL_POINT);
         }
 
         if args.left_justified {
@@ -773,16 +792,24 @@ pub fn fmt_write_float_general(
     }
 }
 
-pub fn fmt_write_string(target: &mut String, value: &str, args: &FormatArgs) {
+/// Writes `value` to `target`, with width and precision counted in the
+/// characters of `charset`.
+pub fn fmt_write_string(
+    target: &mut Vec<u8>,
+    value: &[u8],
+    args: &FormatArgs,
+    charset: CharacterSet,
+) {
     let precision = args.precision.unwrap_or(usize::MAX);
-    let str_len = value.len().min(precision);
+    let str_end = charset.byte_index(value, precision);
+    let str_len = charset.char_len(&value[..str_end]);
     let padding = args.width.saturating_sub(str_len);
     if args.left_justified {
-        target.push_str(&value[..str_len]);
-        pad_target(target, padding, b' ');
+        target.extend_from_slice(&value[..str_end]);
+        target.resize(target.len() + padding, b' ');
     } else {
-        pad_target(target, padding, b' ');
-        target.push_str(&value[..str_len]);
+        target.resize(target.len() + padding, b' ');
+        target.extend_from_slice(&value[..str_end]);
     }
 }
 
@@ -792,9 +819,10 @@ mod tests {
 
     #[test]
     fn test_parse_conversion_specifier_args() {
-        let mut iter = "-+ #0123.456d".chars();
-        let (specifier, args) = parse_conversion_specifier_args(&mut iter).unwrap();
-        assert_eq!(specifier, 'd');
+        let mut iter = b"-+ #0123.456d".iter();
+        let (specifier, args) =
+            parse_conversion_specifier_args(&mut iter, || panic!("unexpected argument")).unwrap();
+        assert_eq!(specifier, b'd');
         assert!(args.left_justified);
         assert!(args.signed);
         assert!(args.prefix_space);
@@ -1400,97 +1428,129 @@ mod tests {
 
     #[test]
     fn test_write_string() {
-        let mut target = String::new();
-        fmt_write_string(&mut target, "hello", &FormatArgs::default());
-        assert_eq!(target, "hello");
+        let mut target = Vec::new();
+        fmt_write_string(
+            &mut target,
+            b"hello",
+            &FormatArgs::default(),
+            CharacterSet::Utf8,
+        );
+        assert_eq!(target, b"hello");
     }
 
     #[test]
     fn test_write_string_with_precision_less_than_length() {
-        let mut target = String::new();
+        let mut target = Vec::new();
         fmt_write_string(
             &mut target,
-            "hello",
+            b"hello",
             &FormatArgs {
                 precision: Some(3),
                 ..Default::default()
             },
+            CharacterSet::Utf8,
         );
-        assert_eq!(target, "hel");
+        assert_eq!(target, b"hel");
     }
 
     #[test]
     fn test_write_string_with_precision_greater_than_length() {
-        let mut target = String::new();
+        let mut target = Vec::new();
         fmt_write_string(
             &mut target,
-            "hello",
+            b"hello",
             &FormatArgs {
                 precision: Some(10),
                 ..Default::default()
             },
+            CharacterSet::Utf8,
         );
-        assert_eq!(target, "hello");
+        assert_eq!(target, b"hello");
     }
 
     #[test]
     fn test_write_string_with_width() {
-        let mut target = String::new();
+        let mut target = Vec::new();
         fmt_write_string(
             &mut target,
-            "hello",
+            b"hello",
             &FormatArgs {
                 width: 10,
                 ..Default::default()
             },
+            CharacterSet::Utf8,
         );
-        assert_eq!(target, "     hello");
+        assert_eq!(target, b"     hello");
     }
 
     #[test]
     fn test_write_string_left_justified() {
-        let mut target = String::new();
+        let mut target = Vec::new();
         fmt_write_string(
             &mut target,
-            "hello",
+            b"hello",
             &FormatArgs {
                 left_justified: true,
                 width: 10,
                 ..Default::default()
             },
+            CharacterSet::Utf8,
         );
-        assert_eq!(target, "hello     ");
+        assert_eq!(target, b"hello     ");
     }
 
     #[test]
     fn test_write_string_left_justified_with_precision() {
-        let mut target = String::new();
+        let mut target = Vec::new();
         fmt_write_string(
             &mut target,
-            "hello",
+            b"hello",
             &FormatArgs {
                 left_justified: true,
                 width: 10,
                 precision: Some(3),
                 ..Default::default()
             },
+            CharacterSet::Utf8,
         );
-        assert_eq!(target, "hel       ");
+        assert_eq!(target, b"hel       ");
     }
 
     #[test]
     fn test_write_string_with_width_with_precision() {
-        let mut target = String::new();
+        let mut target = Vec::new();
         fmt_write_string(
             &mut target,
-            "hello",
+            b"hello",
             &FormatArgs {
                 width: 10,
                 precision: Some(3),
                 ..Default::default()
             },
+            CharacterSet::Utf8,
+        );
+        assert_eq!(target, b"       hel");
+    }
+
+    #[test]
+    fn test_write_string_counts_characters_of_charset() {
+        // 'é' is two bytes long
+        let args = FormatArgs {
+            width: 3,
+            precision: Some(1),
+            ..Default::default()
+        };
+        let mut target = Vec::new();
+        fmt_write_string(&mut target, "éa".as_bytes(), &args, CharacterSet::Utf8);
+        assert_eq!(target, "  é".as_bytes());
+        target.clear();
+        fmt_write_string(
+            &mut target,
+            "éa".as_bytes(),
+            &args,
+            CharacterSet::SingleByte,
         );
-        assert_eq!(target, "       hel");
+        assert_eq!(target, b"  \xC3");
     }
 
     #[test]
@@ -9,42 +9,46 @@
 
 use core::panic;
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, VecDeque},
     ffi::CString,
     fs::File,
-    io::{BufReader, Bytes, Read, Write},
+    io::{BufRead, BufReader, Read, Write},
+    os::fd::{FromRawFd, RawFd},
     rc::Rc,
 };
 
 use super::string::AwkString;
+use crate::regex::Regex;
+use plib::io::ExitOnBrokenPipe;
 
+#[derive(Clone)]
 pub enum RecordSeparator {
     Char(u8),
     Null,
+    Ere(Regex),
 }
 
 impl TryFrom<AwkString> for RecordSeparator {
     type Error = String;
 
     fn try_from(value: AwkString) -> Result<Self, Self::Error> {
-        let mut iter = value.bytes();
-        let result = match iter.next() {
-            Some(c) => RecordSeparator::Char(c),
-            None => RecordSeparator::Null,
-        };
-        if iter.next().is_some() {
-            Err("the record separator cannot contain more than one characters".to_string())
-        } else {
-            Ok(result)
+        match value.len() {
+            0 => Ok(RecordSeparator::Null),
+            1 => Ok(RecordSeparator::Char(value.as_bytes()[0])),
+            _ => Ok(RecordSeparator::Ere(Regex::from_awk_ere(&value)?)),
         }
     }
 }
 
 type ReadResult = Result<u8, String>;
 
+/// Number of bytes read at least before looking for a record separator
+/// when the separator is an extended regular expression.
+const ERE_SEPARATOR_CHUNK_SIZE: usize = 4096;
+
 macro_rules! read_iter_next {
     ($iter:expr, $ret:expr) => {
-        match $iter.next() {
+        match $iter.next_byte() {
             Some(byte_result) => byte_result?,
             None => return $ret,
         }
@@ -57,59 +61,165 @@ macro_rules! read_iter_next {
 pub trait RecordReader: Iterator<Item = ReadResult> {
     fn is_done(&self) -> bool;
 
-    fn last_byte_read(&self) -> Option<u8>;
+    /// Bytes that were read past the end of the last record and have
+    /// to be returned before reading new input.
+    fn pending_bytes(&mut self) -> &mut VecDeque<u8>;
+
+    fn next_byte(&mut self) -> Option<ReadResult> {
+        if let Some(byte) = self.pending_bytes().pop_front() {
+            Some(Ok(byte))
+        } else {
+            self.next()
+        }
+    }
 
-    fn read_next_record(&mut self, separator: &RecordSeparator) -> Result<Option<String>, String> {
-        if self.is_done() {
+    /// Appends the bytes up to the next `separator` to `bytes`, and consumes the
+    /// separator. Returns `false` if the input ended before a separator was found.
+    fn read_until(&mut self, separator: u8, bytes: &mut Vec<u8>) -> Result<bool, String> {
+        while let Some(byte) = self.next_byte() {
+            let byte = byte?;
+            if byte == separator {
+                return Ok(true);
+            }
+            bytes.push(byte);
+        }
+        Ok(false)
+    }
+
+    fn read_next_record(&mut self, separator: &RecordSeparator) -> Result<Option<Vec<u8>>, String> {
+        if self.is_done() && self.pending_bytes().is_empty() {
             return Ok(None);
         }
         match separator {
             RecordSeparator::Char(sep) => {
-                let mut str = String::new();
-                let mut next = read_iter_next!(self);
-                while next != *sep {
-                    str.push(next as char);
-                    next = read_iter_next!(self, Ok(Some(str)));
+                let mut bytes = Vec::new();
+                if !self.read_until(*sep, &mut bytes)? && bytes.is_empty() {
+                    return Ok(None);
                 }
-                Ok(Some(str))
+                Ok(Some(bytes))
             }
             RecordSeparator::Null => {
-                let mut next = if let Some(byte) = self.last_byte_read() {
-                    byte
-                } else {
-                    read_iter_next!(self)
-                };
-                while next.is_ascii_whitespace() {
+                let mut next = read_iter_next!(self);
+                while next == b'\n' {
                     next = read_iter_next!(self);
                 }
-                let mut str = String::new();
-                while next != b'\n' {
-                    str.push(next as char);
-                    next = read_iter_next!(self, Ok(Some(str)));
+                let mut bytes = Vec::new();
+                loop {
+                    if next == b'\n' {
+                        next = read_iter_next!(self, Ok(Some(bytes)));
+                        if next == b'\n' {
+                            // a blank line ends the record, together with any following one
+                            while let Some(byte) = self.next_byte() {
+                                let byte = byte?;
+                                if byte != b'\n' {
+                                    self.pending_bytes().push_front(byte);
+                                    break;
+                                }
+                            }
+                            return Ok(Some(bytes));
+                        }
+                        bytes.push(b'\n');
+                    } else {
+                        bytes.push(next);
+                        next = read_iter_next!(self, Ok(Some(bytes)));
+                    }
                 }
-                while next.is_ascii_whitespace() {
-                    next = read_iter_next!(self, Ok(Some(str)));
+            }
+            RecordSeparator::Ere(ere) => self.read_record_with_ere_separator(ere),
+        }
+    }
+
+    /// Reads the next record up to the first non empty match of `ere`.
+    ///
+    /// A separator is only accepted once some input following it has been read
+    /// (or the input is over), so that separators that straddle two reads are
+    /// matched completely. Input read after the separator is kept in
+    /// `pending_bytes` for the next record.
+    fn read_record_with_ere_separator(&mut self, ere: &Regex) -> Result<Option<Vec<u8>>, String> {
+        let mut bytes = Vec::new();
+        let mut is_input_over = false;
+        loop {
+            let read_limit = bytes.len() + bytes.len().max(ERE_SEPARATOR_CHUNK_SIZE);
+            while bytes.len() < read_limit {
+                match self.next_byte() {
+                    Some(byte) => {
+                        let byte = byte?;
+                        bytes.push(byte);
+                        // stop at newlines, so that the common case of separators ending
+                        // in a newline does not wait for more input than necessary
+                        if byte == b'\n' {
+                            break;
+                        }
+                    }
+                    None => {
+                        is_input_over = true;
+                        break;
+                    }
+                }
+            }
+            let separator = ere.match_locations_bytes(&bytes).find(|m| m.start != m.end);
+            match separator {
+                Some(separator) if separator.end < bytes.len() || is_input_over => {
+                    let pending = self.pending_bytes();
+                    for &byte in bytes[separator.end..].iter().rev() {
+                        pending.push_front(byte);
+                    }
+                    bytes.truncate(separator.start);
+                    return Ok(Some(bytes));
+                }
+                _ if is_input_over => {
+                    return if bytes.is_empty() {
+                        Ok(None)
+                    } else {
+                        Ok(Some(bytes))
+                    };
                 }
-                Ok(Some(str))
+                _ => {}
             }
         }
     }
 }
 
+/// `RecordReader::read_until` for a buffered reader, with the bytes in
+/// `pending_bytes` read first. Scanning the buffer is much faster than reading
+/// byte by byte.
+fn buffered_read_until<R: BufRead>(
+    reader: &mut R,
+    pending_bytes: &mut VecDeque<u8>,
+    separator: u8,
+    bytes: &mut Vec<u8>,
+) -> Result<bool, String> {
+    while let Some(byte) = pending_bytes.pop_front() {
+        if byte == separator {
+            return Ok(true);
+        }
+        bytes.push(byte);
+    }
+    let read = reader
+        .read_until(separator, bytes)
+        .map_err(|e| e.to_string())?;
+    if read > 0 && bytes.last() == Some(&separator) {
+        bytes.pop();
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
 pub struct FileStream {
-    bytes: Bytes<BufReader<File>>,
-    last_byte_read: Option<u8>,
+    reader: BufReader<File>,
     is_done: bool,
+    pending_bytes: VecDeque<u8>,
 }
 
 impl FileStream {
     pub fn open(path: &str) -> Result<Self, String> {
-        let file = File::open(path).map_err(|e| e.to_string())?;
-        let reader = BufReader::new(file);
+        let file = File::open(path)
+            .map_err(|e| format!("could not open '{}' for reading: {}", path, e))?;
         Ok(Self {
-            bytes: reader.bytes(),
-            last_byte_read: None,
+            reader: BufReader::new(file),
             is_done: false,
+            pending_bytes: VecDeque::new(),
         })
     }
 }
@@ -118,16 +228,17 @@ impl Iterator for FileStream {
     type Item = ReadResult;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.bytes.next() {
-            Some(Ok(byte)) => {
-                self.last_byte_read = Some(byte);
-                Some(Ok(byte))
-            }
-            Some(Err(e)) => Some(Err(e.to_string())),
-            None => {
+        match self.reader.fill_buf() {
+            Ok([]) => {
                 self.is_done = true;
                 None
             }
+            Ok(buffer) => {
+                let byte = buffer[0];
+                self.reader.consume(1);
+                Some(Ok(byte))
+            }
+            Err(e) => Some(Err(e.to_string())),
         }
     }
 }
@@ -137,14 +248,22 @@ impl RecordReader for FileStream {
         self.is_done
     }
 
-    fn last_byte_read(&self) -> Option<u8> {
-        self.last_byte_read
+    fn pending_bytes(&mut self) -> &mut VecDeque<u8> {
+        &mut self.pending_bytes
+    }
+
+    fn read_until(&mut self, separator: u8, bytes: &mut Vec<u8>) -> Result<bool, String> {
+        let found =
+            buffered_read_until(&mut self.reader, &mut self.pending_bytes, separator, bytes)?;
+        self.is_done |= !found;
+        Ok(found)
     }
 }
 
 pub struct StringRecordReader {
     string: String,
     index: usize,
+    pending_bytes: VecDeque<u8>,
 }
 
 impl<S: Into<String>> From<S> for StringRecordReader {
@@ -152,6 +271,7 @@ impl<S: Into<String>> From<S> for StringRecordReader {
         Self {
             string: value.into(),
             index: 0,
+            pending_bytes: VecDeque::new(),
         }
     }
 }
@@ -175,53 +295,106 @@ impl RecordReader for StringRecordReader {
         self.index == self.string.len()
     }
 
-    fn last_byte_read(&self) -> Option<u8> {
-        if self.index == 0 {
-            None
-        } else {
-            Some(self.string.as_bytes()[self.index - 1])
-        }
+    fn pending_bytes(&mut self) -> &mut VecDeque<u8> {
+        &mut self.pending_bytes
     }
 }
 
-pub type EmptyRecordReader = std::iter::Empty<ReadResult>;
+/// Writes `contents` to the standard output. awk ends quietly, like a C
+/// utility would, when the reader of its output has exited.
+pub fn write_stdout(contents: &[u8]) -> Result<(), String> {
+    ExitOnBrokenPipe::new(std::io::stdout())
+        .write_all(contents)
+        .map_err(|e| format!("could not write to the standard output: {}", e))
+}
 
-impl RecordReader for EmptyRecordReader {
-    fn is_done(&self) -> bool {
-        true
+/// Decode the status returned by `pclose` into the value returned by `close`:
+/// the exit status of the command, or 256 plus the signal number if the
+/// command was terminated by a signal.
+pub fn decode_wait_status(status: libc::c_int) -> i32 {
+    if status == -1 {
+        -1
+    } else if libc::WIFEXITED(status) {
+        libc::WEXITSTATUS(status)
+    } else if libc::WIFSIGNALED(status) {
+        256 + libc::WTERMSIG(status)
+    } else {
+        status
     }
+}
+
+enum OutputFile {
+    Stdout,
+    Stderr,
+    File(File),
+}
 
-    fn last_byte_read(&self) -> Option<u8> {
-        None
+impl OutputFile {
+    /// Open `filename` for writing. `/dev/stdout`, `/dev/stderr` and
+    /// `/dev/fd/N` refer to the descriptors already open in the process,
+    /// so that output written through them stays ordered with `print`.
+    fn open(filename: &str, append: bool) -> Result<Self, String> {
+        let fd = filename
+            .strip_prefix("/dev/fd/")
+            .and_then(|n| n.parse::<RawFd>().ok());
+        match (filename, fd) {
+            ("/dev/stdout", _) | (_, Some(1)) => Ok(OutputFile::Stdout),
+            ("/dev/stderr", _) | (_, Some(2)) => Ok(OutputFile::Stderr),
+            (_, Some(fd)) => {
+                // duplicate the descriptor so that closing the file in awk
+                // does not close the one inherited by the process
+                let new_fd = unsafe { libc::dup(fd) };
+                if new_fd < 0 {
+                    return Err(format!(
+                        "could not open '{}' for writing: {}",
+                        filename,
+                        std::io::Error::last_os_error()
+                    ));
+                }
+                Ok(OutputFile::File(unsafe { File::from_raw_fd(new_fd) }))
+            }
+            (_, None) => File::options()
+                .write(true)
+                .create(true)
+                .append(append)
+                .truncate(!append)
+                .open(filename)
+                .map(OutputFile::File)
+                .map_err(|e| format!("could not open '{}' for writing: {}", filename, e)),
+        }
+    }
+
+    fn write_all(&mut self, contents: &[u8]) -> std::io::Result<()> {
+        match self {
+            OutputFile::Stdout => ExitOnBrokenPipe::new(std::io::stdout()).write_all(contents),
+            OutputFile::Stderr => std::io::stderr().write_all(contents),
+            OutputFile::File(file) => file.write_all(contents),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputFile::Stdout => ExitOnBrokenPipe::new(std::io::stdout()).flush(),
+            OutputFile::Stderr => std::io::stderr().flush(),
+            OutputFile::File(file) => file.flush(),
+        }
     }
 }
 
+/// Files opened by output redirections. A file is opened (and truncated, for
+/// `>`) the first time it is written to, and stays open until it is closed.
 #[derive(Default)]
 pub struct WriteFiles {
-    files: HashMap<String, File>,
+    files: HashMap<String, OutputFile>,
 }
 
 impl WriteFiles {
-    pub fn write(&mut self, filename: &str, contents: &str, append: bool) -> Result<(), String> {
-        match self.files.entry(filename.to_string()) {
-            Entry::Occupied(mut e) => {
-                e.get_mut()
-                    .write_all(contents.as_bytes())
-                    .map_err(|e| e.to_string())?;
-            }
-            Entry::Vacant(e) => {
-                let mut file = File::options()
-                    .write(true)
-                    .create(true)
-                    .append(append)
-                    .open(filename)
-                    .map_err(|e| e.to_string())?;
-                file.write_all(contents.as_bytes())
-                    .map_err(|e| e.to_string())?;
-                e.insert(file);
-            }
-        }
-        Ok(())
+    pub fn write(&mut self, filename: &str, contents: &[u8], append: bool) -> Result<(), String> {
+        let file = match self.files.entry(filename.to_string()) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(OutputFile::open(filename, append)?),
+        };
+        file.write_all(contents).map_err(|e| e.to_string())
     }
 
     pub fn flush_file(&mut self, filename: &str) -> bool {
@@ -235,13 +408,16 @@ impl WriteFiles {
     pub fn flush_all(&mut self) -> bool {
         let mut success = true;
         for file in self.files.values_mut() {
-            success = success && file.flush().is_ok();
+            success &= file.flush().is_ok();
         }
         success
     }
 
-    pub fn close_file(&mut self, filename: &str) {
-        self.files.remove(filename);
+    /// Close the file, returning `None` if it was not open or
+    /// the status of the operation otherwise.
+    pub fn close_file(&mut self, filename: &str) -> Option<i32> {
+        let mut file = self.files.remove(filename)?;
+        Some(if file.flush().is_ok() { 0 } else { -1 })
     }
 }
 
@@ -255,8 +431,8 @@ impl ReadFiles {
         &mut self,
         filename: AwkString,
         separator: &RecordSeparator,
-    ) -> Result<Option<String>, String> {
-        let filename = Rc::<str>::from(filename);
+    ) -> Result<Option<Vec<u8>>, String> {
+        let filename = Rc::<str>::from(filename.to_string_lossy());
         match self.files.entry(filename.clone()) {
             Entry::Occupied(mut e) => e.get_mut().read_next_record(separator),
             Entry::Vacant(e) => {
@@ -268,11 +444,13 @@ impl ReadFiles {
         }
     }
 
-    pub fn close_file(&mut self, filename: &str) {
-        self.files.remove(filename);
+    pub fn close_file(&mut self, filename: &str) -> Option<i32> {
+        self.files.remove(filename).map(|_| 0)
     }
 }
 
+/// Commands opened by `|` output redirections. The command is started the
+/// first time it is written to, and runs until the pipe is closed.
 #[derive(Default)]
 pub struct WritePipes {
     pipes: HashMap<Rc<str>, *mut libc::FILE>,
@@ -280,7 +458,7 @@ pub struct WritePipes {
 
 impl WritePipes {
     pub fn write(&mut self, command: AwkString, contents: AwkString) -> Result<(), String> {
-        let key = Rc::from(command.clone());
+        let key = Rc::<str>::from(command.to_string_lossy());
         let file = match self.pipes.entry(key) {
             Entry::Occupied(e) => *e.get(),
             Entry::Vacant(e) => {
@@ -296,10 +474,10 @@ impl WritePipes {
                 file
             }
         };
-        let contents: CString = contents.try_into()?;
-        let result = unsafe { libc::fputs(contents.as_ptr(), file) };
-        if result == libc::EOF {
-            return Err("failed to write to file".to_string());
+        let contents = contents.as_bytes();
+        let written = unsafe { libc::fwrite(contents.as_ptr().cast(), 1, contents.len(), file) };
+        if written != contents.len() {
+            return Err("failed to write to pipe".to_string());
         }
         Ok(())
     }
@@ -315,17 +493,16 @@ impl WritePipes {
     pub fn flush_all(&mut self) -> bool {
         let mut success = true;
         for file in self.pipes.values() {
-            success = success && unsafe { libc::fflush(*file) == 0 };
+            success &= unsafe { libc::fflush(*file) == 0 };
         }
         success
     }
 
-    pub fn close_pipe(&mut self, filename: &str) {
-        if let Some(file) = self.pipes.remove(filename) {
-            unsafe {
-                libc::pclose(file);
-            }
-        }
+    /// Close the pipe and wait for the command to exit, returning `None` if
+    /// the pipe was not open or the exit status of the command otherwise.
+    pub fn close_pipe(&mut self, command: &str) -> Option<i32> {
+        let file = self.pipes.remove(command)?;
+        Some(decode_wait_status(unsafe { libc::pclose(file) }))
     }
 }
 
@@ -343,8 +520,8 @@ impl Drop for WritePipes {
 
 pub struct PipeRecordReader {
     pipe: *mut libc::FILE,
-    last_byte_read: Option<u8>,
     is_done: bool,
+    pending_bytes: VecDeque<u8>,
 }
 
 impl PipeRecordReader {
@@ -359,10 +536,17 @@ impl PipeRecordReader {
         };
         Ok(Self {
             pipe: file,
-            last_byte_read: None,
             is_done: false,
+            pending_bytes: VecDeque::new(),
         })
     }
+
+    /// Close the pipe and wait for the command to exit, returning its exit status.
+    pub fn close(mut self) -> i32 {
+        let status = unsafe { libc::pclose(self.pipe) };
+        self.pipe = std::ptr::null_mut();
+        decode_wait_status(status)
+    }
 }
 
 impl Iterator for PipeRecordReader {
@@ -374,7 +558,6 @@ impl Iterator for PipeRecordReader {
             self.is_done = true;
             None
         } else {
-            self.last_byte_read = Some(result as u8);
             Some(Ok(result as u8))
         }
     }
@@ -385,13 +568,16 @@ impl RecordReader for PipeRecordReader {
         self.is_done
     }
 
-    fn last_byte_read(&self) -> Option<u8> {
-        self.last_byte_read
+    fn pending_bytes(&mut self) -> &mut VecDeque<u8> {
+        &mut self.pending_bytes
     }
 }
 
 impl Drop for PipeRecordReader {
     fn drop(&mut self) {
+        if self.pipe.is_null() {
+            return;
+        }
         unsafe {
             if libc::pclose(self.pipe) == -1 {
                 panic!("failed to close pipe");
@@ -410,8 +596,8 @@ impl ReadPipes {
         &mut self,
         command: AwkString,
         separator: &RecordSeparator,
-    ) -> Result<Option<String>, String> {
-        let command = Rc::<str>::from(command);
+    ) -> Result<Option<Vec<u8>>, String> {
+        let command = Rc::<str>::from(command.to_string_lossy());
         match self.pipes.entry(command.clone()) {
             Entry::Occupied(mut e) => e.get_mut().read_next_record(separator),
             Entry::Vacant(e) => {
@@ -423,15 +609,15 @@ impl ReadPipes {
         }
     }
 
-    pub fn close_pipe(&mut self, command: &str) {
-        self.pipes.remove(command);
+    pub fn close_pipe(&mut self, command: &str) -> Option<i32> {
+        self.pipes.remove(command).map(PipeRecordReader::close)
     }
 }
 
 #[derive(Default)]
 pub struct StdinRecordReader {
-    last_byte_read: Option<u8>,
     is_done: bool,
+    pending_bytes: VecDeque<u8>,
 }
 
 impl Iterator for StdinRecordReader {
@@ -440,10 +626,7 @@ impl Iterator for StdinRecordReader {
     fn next(&mut self) -> Option<Self::Item> {
         let next = std::io::stdin().lock().bytes().next();
         match next {
-            Some(Ok(byte)) => {
-                self.last_byte_read = Some(byte);
-                Some(Ok(byte))
-            }
+            Some(Ok(byte)) => Some(Ok(byte)),
             Some(Err(e)) => Some(Err(e.to_string())),
             None => {
                 self.is_done = true;
@@ -458,8 +641,19 @@ impl RecordReader for StdinRecordReader {
         self.is_done
     }
 
-    fn last_byte_read(&self) -> Option<u8> {
-        self.last_byte_read
+    fn pending_bytes(&mut self) -> &mut VecDeque<u8> {
+        &mut self.pending_bytes
+    }
+
+    fn read_until(&mut self, separator: u8, bytes: &mut Vec<u8>) -> Result<bool, String> {
+        let found = buffered_read_until(
+            &mut std::io::stdin().lock(),
+            &mut self.pending_bytes,
+            separator,
+            bytes,
+        )?;
+        self.is_done |= !found;
+        Ok(found)
     }
 }
 
@@ -471,7 +665,7 @@ mod tests {
         let mut reader = StringRecordReader::from(file_contents);
         let mut result = Vec::new();
         while let Some(record) = reader.read_next_record(&separator).unwrap() {
-            result.push(record);
+            result.push(String::from_utf8(record).unwrap());
         }
         result
     }
@@ -482,9 +676,25 @@ mod tests {
     }
 
     #[test]
-    fn split_records_with_default_separator() {
-        let records = split_records("record1\nrecord2\n  \t\nrecord3\n", RecordSeparator::Null);
-        assert_eq!(records, vec!["record1", "record2", "record3"]);
+    fn split_records_with_null_separator() {
+        let records = split_records(
+            "record1\nrecord2\n\nrecord3\n  \t\nrecord3\n",
+            RecordSeparator::Null,
+        );
+        assert_eq!(records, vec!["record1\nrecord2", "record3\n  \t\nrecord3"]);
+    }
+
+    #[test]
+    fn split_records_with_null_separator_and_multiple_blank_lines() {
+        let records = split_records("a\n\n\n\nb\nc\n\n\nd", RecordSeparator::Null);
+        assert_eq!(records, vec!["a", "b\nc", "d"]);
+    }
+
+    #[test]
+    fn split_records_with_null_separator_skips_leading_and_trailing_blank_lines() {
+        let records = split_records("\n\n\n  a b\nc\n\n\n", RecordSeparator::Null);
+        assert_eq!(records, vec!["  a b\nc"]);
+        assert!(split_records("\n\n\n", RecordSeparator::Null).is_empty());
     }
 
     #[test]
@@ -492,4 +702,60 @@ mod tests {
         let records = split_records("record1,record2,record3", RecordSeparator::Char(b','));
         assert_eq!(records, vec!["record1", "record2", "record3"]);
     }
+
+    fn ere_separator(re: &str) -> RecordSeparator {
+        RecordSeparator::try_from(AwkString::from(re)).expect("invalid record separator")
+    }
+
+    #[test]
+    fn split_records_with_multichar_separator() {
+        let records = split_records("a;\nb;\nc;\n", ere_separator(";\n"));
+        assert_eq!(records, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn split_records_with_ere_separator_alternation() {
+        let records = split_records("a--b==c\n--d", ere_separator("--|==|\n"));
+        assert_eq!(records, vec!["a", "b", "c", "", "d"]);
+    }
+
+    #[test]
+    fn split_records_with_ere_separator_without_trailing_separator() {
+        let records = split_records("record1END\nrecord2ENDrecord3", ere_separator("END\n?"));
+        assert_eq!(records, vec!["record1", "record2", "record3"]);
+    }
+
+    #[test]
+    fn split_records_with_ere_separator_across_reads() {
+        // reads stop at newlines, so the separator is split between two reads
+        let records = split_records("x\n\nyz\n\n", ere_separator("\n\n+"));
+        assert_eq!(records, vec!["x", "yz"]);
+
+        let long_record = "a".repeat(ERE_SEPARATOR_CHUNK_SIZE * 3);
+        let input = format!("{long_record}<sep>b");
+        let records = split_records(&input, ere_separator("<sep>"));
+        assert_eq!(records, vec![long_record.as_str(), "b"]);
+    }
+
+    #[test]
+    fn split_records_with_multibyte_characters() {
+        let records = split_records("żółw\nłoś\n", RecordSeparator::Char(b'\n'));
+        assert_eq!(records, vec!["żółw", "łoś"]);
+    }
+
+    #[test]
+    fn buffered_read_until_reads_pending_bytes_first() {
+        let mut reader = std::io::Cursor::new(b"c\nd\ne".to_vec());
+        let mut pending = VecDeque::from(b"a\nb".to_vec());
+        let mut read_record = || {
+            let mut bytes = Vec::new();
+            let found = buffered_read_until(&mut reader, &mut pending, b'\n', &mut bytes).unwrap();
+            (found, String::from_utf8(bytes).unwrap())
+        };
+        assert_eq!(read_record(), (true, "a".to_string()));
+        assert_eq!(read_record(), (true, "bc".to_string()));
+        assert_eq!(read_record(), (true, "d".to_string()));
+        assert_eq!(read_record(), (false, "e".to_string()));
+        assert_eq!(read_record(), (false, "".to_string()));
+    }
 }
@@ -9,30 +9,32 @@
 
 use array::{Array, KeyIterator, ValueIndex};
 use io::{
-    EmptyRecordReader, FileStream, ReadFiles, ReadPipes, RecordReader, RecordSeparator,
-    StdinRecordReader, WriteFiles, WritePipes,
+    decode_wait_status, write_stdout, FileStream, ReadFiles, ReadPipes, RecordReader,
+    RecordSeparator, StdinRecordReader, WriteFiles, WritePipes,
 };
 use rand::rngs::SmallRng;
 use rand::{Rng, SeedableRng};
-use string::AwkString;
+use string::{AwkString, CharacterSet};
 
-use crate::compiler::{escape_string_contents, is_valid_number};
+use crate::compiler::escape_string_contents;
 use crate::program::{
     Action, BuiltinFunction, Constant, Function, OpCode, Pattern, Program, SourceLocation,
     SpecialVar,
 };
-use crate::regex::Regex;
+use crate::regex::{Regex, RegexCache};
 use format::{
     fmt_write_decimal_float, fmt_write_float_general, fmt_write_hex_float,
     fmt_write_scientific_float, fmt_write_signed, fmt_write_string, fmt_write_unsigned,
     parse_conversion_specifier_args, IntegerFormat,
 };
-use std::cell::{RefCell, UnsafeCell};
+use std::cell::{Cell, RefCell, UnsafeCell};
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::fmt::Write;
+use std::io::Write as _;
 use std::iter;
 use std::marker::PhantomData;
+use std::ops::Range;
 use std::rc::Rc;
 use std::time::SystemTime;
 
@@ -51,9 +53,13 @@ fn bool_to_f64(p: bool) -> f64 {
     }
 }
 
-fn strtod(s: &str) -> f64 {
+fn strtod(s: &[u8]) -> f64 {
+    let start = s
+        .iter()
+        .position(|b| !matches!(b, b' ' | b'\t' | b'\n' | b'\r' | b'\x0b' | b'\x0c'))
+        .unwrap_or(s.len());
     lexical::parse_partial_with_options::<f64, _, { lexical::format::C_STRING }>(
-        s,
+        &s[start..],
         &lexical::ParseFloatOptions::default(),
     )
     .map(|(val, _)| val)
@@ -68,32 +74,52 @@ fn gather_values(stack: &mut Stack, count: u16) -> Result<Vec<AwkValue>, String>
     Ok(values)
 }
 
+/// Writes the arguments of `print` to `output`, separated by `OFS` and
+/// followed by `ORS`.
+fn write_print_output(
+    stack: &mut Stack,
+    argc: u16,
+    global_env: &GlobalEnv,
+    output: &mut Vec<u8>,
+) -> Result<(), String> {
+    let values = gather_values(stack, argc)?;
+    // values are stored in reverse order
+    for (i, value) in values.iter().rev().enumerate() {
+        if i > 0 {
+            output.extend_from_slice(&global_env.ofs);
+        }
+        value.write_scalar(&global_env.ofmt, output)?;
+    }
+    output.extend_from_slice(&global_env.ors);
+    Ok(())
+}
+
 fn print_to_string(
     stack: &mut Stack,
     argc: u16,
     global_env: &GlobalEnv,
 ) -> Result<AwkString, String> {
-    let mut values = Vec::new();
-    for _ in 0..argc {
-        values.push(
-            stack
-                .pop_scalar_value()?
-                .scalar_to_string(&global_env.ofmt)?,
-        );
-    }
-    let mut output = String::new();
-    values.iter().skip(1).rev().fold(&mut output, |acc, elem| {
-        write!(acc, "{}{}", elem, &global_env.ofs).expect("error writing to string");
-        acc
-    });
-    // there has to be at least an element
-    output.push_str(values.first().expect("called print without arguments"));
-    output.push_str(&global_env.ors);
+    let mut output = Vec::new();
+    write_print_output(stack, argc, global_env, &mut output)?;
     Ok(output.into())
 }
 
+/// Largest integer such that it and all the smaller ones are exactly
+/// representable as `f64` (2^53).
+const MAX_EXACT_INTEGER: f64 = 9007199254740992.0;
+
+/// Returns whether `num` is converted to a string as an integer, as if by `%d`,
+/// instead of with `OFMT` or `CONVFMT`. Larger values are not converted as
+/// integers, because their digits past the 53 bits of precision are meaningless.
 fn is_integer(num: f64) -> bool {
-    num.is_finite() && num.fract() == 0.0
+    num.fract() == 0.0 && num.abs() <= MAX_EXACT_INTEGER
+}
+
+/// Converts an array subscript to the string that identifies the element.
+/// Numbers go through `CONVFMT` unless they are integral, so `a[1]`, `a["1"]`
+/// and `a[0.5 + 0.5]` all refer to the same element, while `a["01"]` does not.
+fn array_key(subscript: AwkValue, global_env: &GlobalEnv) -> Result<AwkString, String> {
+    subscript.scalar_to_string(&global_env.convfmt)
 }
 
 fn swap_with_default<T: Default>(value: &mut T) -> T {
@@ -102,82 +128,158 @@ fn swap_with_default<T: Default>(value: &mut T) -> T {
     result
 }
 
+/// Returns true if `s` looks like a number, that is if it consists of optional
+/// leading and trailing blanks, an optional sign and a decimal floating point constant.
+fn looks_numeric(s: &[u8]) -> bool {
+    let is_blank = |b: &u8| matches!(b, b' ' | b'\t' | b'\n');
+    let start = s.iter().position(|b| !is_blank(b)).unwrap_or(s.len());
+    let end = s
+        .iter()
+        .rposition(|b| !is_blank(b))
+        .map_or(start, |i| i + 1);
+    let bytes = match &s[start..end] {
+        [b'+' | b'-', rest @ ..] => rest,
+        s => s,
+    };
+    let mut index = 0;
+    let skip_digits = |index: &mut usize| {
+        let start = *index;
+        while bytes.get(*index).is_some_and(u8::is_ascii_digit) {
+            *index += 1;
+        }
+        *index - start
+    };
+    let mut mantissa_digits = skip_digits(&mut index);
+    if bytes.get(index) == Some(&b'.') {
+        index += 1;
+        mantissa_digits += skip_digits(&mut index);
+    }
+    if mantissa_digits == 0 {
+        return false;
+    }
+    if matches!(bytes.get(index), Some(b'e' | b'E')) {
+        index += 1;
+        if matches!(bytes.get(index), Some(b'+' | b'-')) {
+            index += 1;
+        }
+        if skip_digits(&mut index) == 0 {
+            return false;
+        }
+    }
+    index == bytes.len()
+}
+
+/// Marks a string that comes from the input as a numeric string if it looks like a number.
+/// Numeric strings compare as numbers with numbers and other numeric strings.
 fn maybe_numeric_string<S: Into<AwkString>>(str: S) -> AwkString {
     let mut str = str.into();
-    let numeric_string = is_valid_number(str.as_str().trim().trim_start_matches(['+', '-']));
-    str.is_numeric = numeric_string;
+    str.is_numeric = looks_numeric(&str);
     str
 }
 
 fn sprintf(
-    format_string: &str,
+    format_string: &[u8],
     values: &mut [AwkValue],
-    float_format: &str,
+    float_format: &[u8],
+    charset: CharacterSet,
 ) -> Result<AwkString, String> {
-    let mut result = String::with_capacity(format_string.len());
-    let mut iter = format_string.chars();
+    let mut result = Vec::with_capacity(format_string.len());
+    // numbers are formatted here, and then copied to the result
+    let mut number = String::new();
+    let mut iter = format_string.iter();
     let mut next = iter.next();
+    // values are stored in reverse order
     let mut current_arg = values.len();
-    while let Some(c) = next {
+    // missing arguments are uninitialized, so they format as "" or 0.
+    // Extra arguments are ignored
+    let mut next_value = |current_arg: &mut usize| {
+        if *current_arg == 0 {
+            return Ok::<_, String>(AwkValue::uninitialized_scalar());
+        }
+        *current_arg -= 1;
+        Ok(swap_with_default(&mut values[*current_arg]))
+    };
+    while let Some(&c) = next {
         match c {
-            '%' => {
-                let (specifier, args) = parse_conversion_specifier_args(&mut iter)?;
-                if specifier == '%' {
-                    result.push('%');
+            b'%' => {
+                let (specifier, args) = parse_conversion_specifier_args(&mut iter, || {
+                    next_value(&mut current_arg).map(|value| value.scalar_as_f64() as i64)
+                })?;
+                if specifier == b'%' {
+                    result.push(b'%');
                     next = iter.next();
                     continue;
                 }
 
-                if current_arg == 0 {
-                    return Err("not enough arguments for format string".to_string());
-                }
-                current_arg -= 1;
-                let value = swap_with_default(&mut values[current_arg]);
+                let value = next_value(&mut current_arg)?;
                 match specifier {
-                    'd' | 'i' => {
+                    b'd' | b'i' => {
                         let value = value.scalar_as_f64() as i64;
-                        fmt_write_signed(&mut result, value, &args);
+                        fmt_write_signed(&mut number, value, &args);
                     }
-                    'u' | 'o' | 'x' | 'X' => {
+                    b'u' | b'o' | b'x' | b'X' => {
                         let value = value.scalar_as_f64() as i64;
                         if value.is_negative() {
                             return Err("negative value for unsigned format specifier".to_string());
                         }
                         let format = match specifier {
-                            'u' => IntegerFormat::Decimal,
-                            'o' => IntegerFormat::Octal,
-                            'x' => IntegerFormat::HexLower,
-                            'X' => IntegerFormat::HexUpper,
+                            b'u' => IntegerFormat::Decimal,
+                            b'o' => IntegerFormat::Octal,
+                            b'x' => IntegerFormat::HexLower,
+                            b'X' => IntegerFormat::HexUpper,
                             _ => unreachable!(),
                         };
-                        fmt_write_unsigned(&mut result, value as u64, format, &args);
+                        fmt_write_unsigned(&mut number, value as u64, format, &args);
                     }
-                    'a' | 'A' => {
+                    b'a' | b'A' => {
                         let value = value.scalar_as_f64();
-                        fmt_write_hex_float(&mut result, value, specifier == 'a', &args);
+                        fmt_write_hex_float(&mut number, value, specifier == b'a', &args);
                     }
-                    'f' | 'F' => {
+                    b'f' | b'F' => {
                         let value = value.scalar_as_f64();
-                        fmt_write_decimal_float(&mut result, value, specifier == 'f', &args);
+                        fmt_write_decimal_float(&mut number, value, specifier == b'f', &args);
                     }
-                    'e' | 'E' => {
+                    b'e' | b'E' => {
                         let value = value.scalar_as_f64();
-                        fmt_write_scientific_float(&mut result, value, specifier == 'e', &args);
+                        fmt_write_scientific_float(&mut number, value, specifier == b'e', &args);
                     }
-                    'g' | 'G' => {
+                    b'g' | b'G' => {
                         let value = value.scalar_as_f64();
-                        fmt_write_float_general(&mut result, value, specifier == 'g', &args);
+                        fmt_write_float_general(&mut number, value, specifier == b'g', &args);
                     }
-                    'c' => {
-                        let value = value.scalar_as_f64() as i64 as u8;
-                        result.push(value as char);
+                    b'c' => {
+                        // a string argument prints its first character, or byte in a
+                        // single byte locale. A numeric argument prints the character
+                        // with that code point in a UTF-8 locale, and is converted to
+                        // an unsigned char like in C otherwise
+                        let char = match value.value {
+                            AwkValueVariant::String(s) if !s.is_numeric => {
+                                s.substring(0..charset.byte_index(&s, 1))
+                            }
+                            AwkValueVariant::UninitializedScalar => AwkString::default(),
+                            _ => match charset {
+                                CharacterSet::SingleByte => {
+                                    vec![value.scalar_as_f64() as i64 as u8].into()
+                                }
+                                CharacterSet::Utf8 => char::from_u32(value.scalar_as_f64() as u32)
+                                    .map_or_else(AwkString::default, |c| c.to_string().into()),
+                            },
+                        };
+                        fmt_write_string(&mut result, &char, &args, charset);
                     }
-                    's' => {
+                    b's' => {
                         let value = value.scalar_to_string(float_format)?;
-                        fmt_write_string(&mut result, &value, &args);
+                        fmt_write_string(&mut result, &value, &args, charset);
+                    }
+                    _ => {
+                        return Err(format!(
+                            "unsupported format specifier '{}'",
+                            specifier as char
+                        ))
                     }
-                    _ => return Err(format!("unsupported format specifier '{}'", specifier)),
                 }
+                result.extend_from_slice(number.as_bytes());
+                number.clear();
                 next = iter.next();
             }
             other => {
@@ -189,6 +291,17 @@ fn sprintf(
     Ok(result.into())
 }
 
+/// Formats a number that is not an integer with `OFMT` or `CONVFMT`.
+fn number_to_string(num_fmt: &[u8], num: f64) -> Result<AwkString, String> {
+    // the character set only matters for strings
+    sprintf(
+        num_fmt,
+        &mut [num.into()],
+        num_fmt,
+        CharacterSet::SingleByte,
+    )
+}
+
 fn builtin_sprintf(
     stack: &mut Stack,
     argc: u16,
@@ -198,20 +311,34 @@ fn builtin_sprintf(
     let format_string = stack
         .pop_scalar_value()?
         .scalar_to_string(&global_env.convfmt)?;
-    sprintf(&format_string, &mut values, &global_env.convfmt)
+    sprintf(
+        &format_string,
+        &mut values,
+        &global_env.convfmt,
+        global_env.charset,
+    )
 }
 
 fn builtin_match(stack: &mut Stack, global_env: &mut GlobalEnv) -> Result<(f64, f64), String> {
-    let ere = stack.pop_value().into_ere()?;
+    let ere = stack.pop_value().into_ere(global_env)?;
     let string = stack
         .pop_scalar_value()?
         .scalar_to_string(&global_env.convfmt)?;
-    let mut locations = ere.match_locations(string.try_into()?);
+    let mut locations = ere.match_locations_bytes(string.as_bytes());
     let start;
     let len;
     if let Some(first_match) = locations.next() {
-        start = first_match.start as i64 + 1;
-        len = first_match.end as i64 - start + 1;
+        // RSTART and RLENGTH are measured in characters, and the match can start
+        // or end inside of one
+        let charset = global_env.charset;
+        let first = charset.char_index(&string, first_match.start);
+        let end = if first_match.start == first_match.end {
+            first
+        } else {
+            charset.char_index(&string, first_match.end - 1) + 1
+        };
+        start = first as i64 + 1;
+        len = (end - first) as i64;
     } else {
         start = 0;
         len = -1;
@@ -220,60 +347,6 @@ fn builtin_match(stack: &mut Stack, global_env: &mut GlobalEnv) -> Result<(f64,
     Ok((start as f64, len as f64))
 }
 
-fn gsub(
-    ere: &Regex,
-    repl: &str,
-    in_str: &str,
-    only_replace_first: bool,
-) -> Result<(AwkString, usize), String> {
-    let mut result = String::with_capacity(in_str.len());
-    let mut last_match_end = 0;
-
-    let mut repl_parts = Vec::new();
-    let mut current_repl_part = String::new();
-    let mut repl_iter = repl.chars();
-    while let Some(c) = repl_iter.next() {
-        if c == '\\' {
-            match repl_iter.next() {
-                Some('\\') => current_repl_part.push('\\'),
-                Some('&') => current_repl_part.push_str(&in_str[last_match_end..]),
-                Some(c) => {
-                    current_repl_part.push('\\');
-                    current_repl_part.push(c);
-                }
-                None => {
-                    current_repl_part.push('\\');
-                    break;
-                }
-            }
-        } else if c == '&' {
-            repl_parts.push(current_repl_part);
-            current_repl_part = String::new();
-        } else {
-            current_repl_part.push(c);
-        }
-    }
-    repl_parts.push(current_repl_part);
-
-    let mut num_replacements = 0;
-    for m in ere.match_locations(AwkString::from(in_str).try_into()?) {
-        result.push_str(&in_str[last_match_end..m.start]);
-        let replaced_string = &in_str[m.start..m.end];
-        result.push_str(&repl_parts[0]);
-        for part in repl_parts.iter().skip(1) {
-            result.push_str(part);
-            result.push_str(replaced_string);
-        }
-        last_match_end = m.end;
-        num_replacements += 1;
-        if only_replace_first {
-            break;
-        }
-    }
-    result.push_str(&in_str[last_match_end..]);
-    Ok((result.into(), num_replacements))
-}
-
 fn builtin_gsub(
     stack: &mut Stack,
     global_env: &mut GlobalEnv,
@@ -282,16 +355,21 @@ fn builtin_gsub(
     let repl = stack
         .pop_scalar_value()?
         .scalar_to_string(&global_env.convfmt)?;
-    let ere = stack.pop_value().into_ere()?;
+    let ere = stack.pop_value().into_ere(global_env)?;
     let in_str = stack.pop_ref();
     in_str.ensure_value_is_scalar()?;
-    let (result, count) = gsub(
-        &ere,
-        &repl,
+    let (result, count) = ere.replace_all(
         &in_str.clone().scalar_to_string(&global_env.convfmt)?,
-        is_sub,
-    )?;
-    let result = in_str.assign(result, global_env);
+        &repl,
+        if is_sub { Some(1) } else { None },
+    );
+    // the target is only assigned when something was replaced, so that a field
+    // without matches doesn't cause `$0` to be rebuilt
+    let result = if count > 0 {
+        in_str.assign(AwkString::from(result), global_env)
+    } else {
+        Ok(FieldsState::Ok)
+    };
     stack.push_value(count as f64)?;
     result
 }
@@ -339,10 +417,14 @@ fn call_simple_builtin(
             let s = stack
                 .pop_scalar_value()?
                 .scalar_to_string(&global_env.convfmt)?;
-            let index = s
-                .as_str()
-                .find(t.as_str())
-                .map(|i| i as f64 + 1.0)
+            // positions are measured in characters, like in `match`
+            let index = if t.is_empty() {
+                Some(0)
+            } else {
+                s.windows(t.len()).position(|window| window == &t[..])
+            };
+            let index = index
+                .map(|i| global_env.charset.char_index(&s, i) as f64 + 1.0)
                 .unwrap_or(0.0);
             stack.push_value(index)?;
         }
@@ -354,7 +436,7 @@ fn call_simple_builtin(
                 }
                 _ => {
                     let value_str = value.scalar_to_string(&global_env.convfmt)?;
-                    stack.push_value(value_str.len() as f64)?;
+                    stack.push_value(global_env.charset.char_len(&value_str) as f64)?;
                 }
             }
         }
@@ -362,12 +444,22 @@ fn call_simple_builtin(
             let separator = if argc == 2 {
                 None
             } else {
-                Some(FieldSeparator::Ere(stack.pop_value().into_ere()?))
+                let separator = stack.pop_value();
+                if let AwkValueVariant::Regex { ere, .. } = separator.value {
+                    Some(FieldSeparator::Ere(ere))
+                } else {
+                    let separator = separator.scalar_to_string(&global_env.convfmt)?;
+                    if separator.len() > 1 {
+                        Some(FieldSeparator::Ere(global_env.regex_cache.get(&separator)?))
+                    } else {
+                        Some(separator.try_into()?)
+                    }
+                }
             };
             let s = stack
                 .pop_scalar_value()?
                 .scalar_to_string(&global_env.convfmt)?;
-            let array = stack.pop_ref().as_array()?;
+            let array = stack.pop_array()?;
             array.clear();
 
             split_record(
@@ -384,110 +476,165 @@ fn call_simple_builtin(
         }
         BuiltinFunction::Substr => {
             let n = if argc == 2 {
-                usize::MAX
+                f64::INFINITY
             } else {
-                stack.pop_scalar_value()?.scalar_as_f64() as usize
+                stack.pop_scalar_value()?.scalar_as_f64().round()
             };
-            // the behaviour for values < 1 is not specified. Here we follow what other
-            // implementations do
-            let m = stack.pop_scalar_value()?.scalar_as_f64().max(1.0) as usize;
+            let m = stack.pop_scalar_value()?.scalar_as_f64().round();
             let s = stack
                 .pop_scalar_value()?
                 .scalar_to_string(&global_env.convfmt)?;
-            let substr = s.chars().skip(m - 1).take(n).collect::<String>();
+            // the result contains the characters at positions m to m + n - 1, so
+            // starting before the first character shortens it
+            let first = m.max(1.0);
+            let end = m + n;
+            let substr = if end > first {
+                let charset = global_env.charset;
+                // the conversion saturates, so an infinite length takes the rest
+                let start = charset.byte_index(&s, first as usize - 1);
+                let end = charset.byte_index(&s, (end - 1.0) as usize);
+                s.substring(start..end)
+            } else {
+                AwkString::default()
+            };
             stack.push_value(substr)?;
         }
-        BuiltinFunction::ToLower => {
-            let value = stack
-                .pop_scalar_value()?
-                .scalar_to_string(&global_env.convfmt)?;
-            stack.push_value(value.to_lowercase())?;
-        }
-        BuiltinFunction::ToUpper => {
+        // In a UTF-8 locale case mapping uses the full Unicode tables, so characters
+        // whose mapping changes length are expanded (e.g. `toupper("ß")` is "SS").
+        // In any other locale only ASCII letters are mapped, and the other bytes
+        // are kept as they are.
+        BuiltinFunction::ToLower | BuiltinFunction::ToUpper => {
             let value = stack
                 .pop_scalar_value()?
                 .scalar_to_string(&global_env.convfmt)?;
-            stack.push_value(value.to_uppercase())?;
+            let unicode = match global_env.charset {
+                CharacterSet::Utf8 => std::str::from_utf8(&value).ok(),
+                CharacterSet::SingleByte => None,
+            };
+            let mapped: AwkString = match (unicode, function) {
+                (Some(value), BuiltinFunction::ToUpper) => value.to_uppercase().into(),
+                (Some(value), _) => value.to_lowercase().into(),
+                (None, BuiltinFunction::ToUpper) => value.to_ascii_uppercase().into(),
+                (None, _) => value.to_ascii_lowercase().into(),
+            };
+            stack.push_value(mapped)?;
         }
         BuiltinFunction::Gsub | BuiltinFunction::Sub => {
             return builtin_gsub(stack, global_env, function == BuiltinFunction::Sub)
         }
-        BuiltinFunction::System => {
-            let command: CString = stack
-                .pop_scalar_value()?
-                .scalar_to_string(&global_env.convfmt)?
-                .try_into()?;
-            let status = unsafe { libc::system(command.as_ptr()) };
-            if status == -1 {
-                return Err("system call failed".to_string());
-            }
-        }
         BuiltinFunction::Print => {
-            print!("{}", print_to_string(stack, argc, global_env)?);
+            // the buffer is reused, so that printing does not allocate for every record
+            let mut output = std::mem::take(&mut global_env.print_buffer);
+            output.clear();
+            let result = write_print_output(stack, argc, global_env, &mut output)
+                .and_then(|()| write_stdout(&output));
+            global_env.print_buffer = output;
+            result?;
         }
         BuiltinFunction::Printf => {
-            print!("{}", builtin_sprintf(stack, argc, global_env)?);
+            write_stdout(builtin_sprintf(stack, argc, global_env)?.as_bytes())?;
         }
         _ => unreachable!("call_simple_builtin was passed an invalid builtin function kind"),
     }
     Ok(FieldsState::Ok)
 }
 
+#[derive(Clone)]
 enum FieldSeparator {
     Default,
     Char(u8),
-    Ere(Rc<Regex>),
+    Ere(Regex),
 }
 
 /// Splits a record into fields and calls the provided closure for each field.
-/// If the record is a numeric string, fields will be numeric strings if appropriate.
+/// Fields that look like numbers are numeric strings. Fields share the contents
+/// of `record` instead of copying them.
 fn split_record<S: FnMut(usize, AwkString) -> Result<(), String>>(
     record: AwkString,
     field_separator: &FieldSeparator,
     mut store_result: S,
 ) -> Result<(), String> {
-    let is_record_numeric = record.is_numeric;
-    let string = |s: &str| -> AwkString {
-        if is_record_numeric {
-            AwkString::numeric_string(s)
-        } else {
-            s.into()
-        }
-    };
+    if record.is_empty() {
+        return Ok(());
+    }
+    let field = |range: Range<usize>| maybe_numeric_string(record.substring(range));
     match field_separator {
-        FieldSeparator::Default => record
-            .trim_start()
-            .split_ascii_whitespace()
-            .enumerate()
-            .try_for_each(|(i, s)| store_result(i, string(s))),
-        FieldSeparator::Char(c) => record
-            .split(*c as char)
-            .enumerate()
-            .try_for_each(|(i, s)| store_result(i, string(s))),
-        FieldSeparator::Ere(re) => {
-            let mut split_start = 0;
+        FieldSeparator::Default => {
+            // fields are separated by runs of blanks and newlines, ignoring leading and
+            // trailing ones. Scanning the bytes directly is a lot faster than using a regex
+            let is_separator = |b: u8| matches!(b, b' ' | b'\t' | b'\n');
+            let bytes = record.as_bytes();
             let mut index = 0;
-            for separator_range in re.match_locations(record.clone().try_into()?) {
-                store_result(index, string(&record[split_start..separator_range.start]))?;
-                split_start = separator_range.end;
+            let mut pos = 0;
+            loop {
+                while pos < bytes.len() && is_separator(bytes[pos]) {
+                    pos += 1;
+                }
+                if pos == bytes.len() {
+                    return Ok(());
+                }
+                let field_start = pos;
+                while pos < bytes.len() && !is_separator(bytes[pos]) {
+                    pos += 1;
+                }
+                store_result(index, field(field_start..pos))?;
                 index += 1;
             }
-            store_result(index, string(&record[split_start..]))
         }
+        FieldSeparator::Char(c) => {
+            let mut field_start = 0;
+            record.split(|b| b == c).enumerate().try_for_each(|(i, s)| {
+                let range = field_start..field_start + s.len();
+                field_start = range.end + 1;
+                store_result(i, field(range))
+            })
+        }
+        FieldSeparator::Ere(re) => re
+            .split_locations(&record)
+            .into_iter()
+            .enumerate()
+            .try_for_each(|(i, range)| store_result(i, field(range))),
+    }
+}
+
+/// Splits a record into fields like `split_record`. When records are separated by
+/// blank lines (`RS = ""`), newlines also separate fields, whatever the value of `FS`.
+fn split_record_fields<S: FnMut(usize, AwkString) -> Result<(), String>>(
+    record: AwkString,
+    field_separator: &FieldSeparator,
+    record_separator: &RecordSeparator,
+    mut store_result: S,
+) -> Result<(), String> {
+    if !matches!(record_separator, RecordSeparator::Null)
+        || matches!(field_separator, FieldSeparator::Default)
+    {
+        return split_record(record, field_separator, store_result);
+    }
+    let mut index = 0;
+    let mut line_start = 0;
+    for line in record.split(|&b| b == b'\n') {
+        let line_range = line_start..line_start + line.len();
+        line_start = line_range.end + 1;
+        split_record(record.substring(line_range), field_separator, |_, field| {
+            store_result(index, field)?;
+            index += 1;
+            Ok(())
+        })?;
     }
+    Ok(())
 }
 
 impl TryFrom<AwkString> for FieldSeparator {
     type Error = String;
 
     fn try_from(value: AwkString) -> Result<Self, Self::Error> {
-        if value.as_str() == " " {
+        if value.as_bytes() == b" " {
             Ok(FieldSeparator::Default)
         } else if value.len() == 1 {
             Ok(FieldSeparator::Char(*value.as_bytes().first().unwrap()))
         } else {
-            let ere = Regex::new(value.try_into()?)?;
-            Ok(FieldSeparator::Ere(Rc::from(ere)))
+            let ere = Regex::from_awk_ere(&value)?;
+            Ok(FieldSeparator::Ere(ere))
         }
     }
 }
@@ -502,6 +649,9 @@ struct GlobalEnv {
     nr: u32,
     fnr: u32,
     nf: usize,
+    regex_cache: RegexCache,
+    print_buffer: Vec<u8>,
+    charset: CharacterSet,
 }
 
 impl GlobalEnv {
@@ -516,7 +666,13 @@ impl GlobalEnv {
             SpecialVar::Rs => self.rs = as_string(value)?.try_into()?,
             SpecialVar::Nr => self.nr = value.scalar_as_f64() as u32,
             SpecialVar::Fnr => self.fnr = value.scalar_as_f64() as u32,
-            SpecialVar::Nf => self.nf = value.scalar_as_f64() as usize,
+            SpecialVar::Nf => {
+                let nf = value.scalar_as_f64();
+                if nf < 0.0 {
+                    return Err("NF cannot be set to a negative value".to_string());
+                }
+                self.nf = nf as usize
+            }
             _ => {
                 // not needed
             }
@@ -535,9 +691,12 @@ impl Default for GlobalEnv {
             ors: AwkString::from("\n"),
             ofmt: AwkString::from("%.6g"),
             rs: RecordSeparator::Char(b'\n'),
-            nr: 1,
-            fnr: 1,
+            nr: 0,
+            fnr: 0,
             nf: 0,
+            regex_cache: RegexCache::default(),
+            print_buffer: Vec::new(),
+            charset: CharacterSet::SingleByte,
         }
     }
 }
@@ -550,141 +709,205 @@ enum FieldsState {
 }
 
 struct Record {
-    record: RefCell<CString>,
-    fields: Vec<AwkValueRef>,
+    record: RefCell<AwkString>,
+    /// `$0` followed by the fields, added as they are needed. Each field is
+    /// boxed, so references to it stay valid when more fields are added.
+    #[allow(clippy::vec_box)]
+    fields: RefCell<Vec<Box<AwkValueRef>>>,
     last_field: RefCell<usize>,
+    /// Set when `$0` changed and the fields have not been split yet. Holds the
+    /// separators that were in effect when `$0` changed.
+    pending_split: RefCell<Option<(FieldSeparator, RecordSeparator)>>,
+    /// Set when a field or `NF` changed and `$0` has not been rebuilt yet.
+    record_outdated: Cell<bool>,
 }
 
 impl Record {
-    const MAX_FIELDS: usize = 1024;
+    /// Replaces the record with one read from the input. The record is split
+    /// into fields the first time a field or `NF` is read.
+    fn reset(
+        &mut self,
+        record: Vec<u8>,
+        field_separator: &FieldSeparator,
+        record_separator: &RecordSeparator,
+    ) -> Result<(), String> {
+        // the record is shared with `$0`, so it is never copied again
+        let record = maybe_numeric_string(Rc::<[u8]>::from(record));
+        *self.fields.get_mut()[0].get_mut() = AwkValue::field_ref(record.clone(), 0);
+        *self.record.get_mut() = record;
+        *self.pending_split.get_mut() = Some((field_separator.clone(), record_separator.clone()));
+        self.record_outdated.set(false);
+        Ok(())
+    }
 
-    fn reset(&mut self, record: String, field_separator: &FieldSeparator) -> Result<(), String> {
-        let previous_last_field = *self.last_field.get_mut();
+    /// Splits `$0` into fields, if it changed since the last split.
+    /// Returns `true` if the fields were split.
+    ///
+    /// # Safety
+    /// The caller has to ensure that there are no active references to the fields
+    unsafe fn split_if_outdated(&self) -> Result<bool, String> {
+        let Some((field_separator, record_separator)) = self.pending_split.borrow_mut().take()
+        else {
+            return Ok(false);
+        };
+        let previous_last_field = *self.last_field.borrow();
         let mut last_field = 0;
-        let record = maybe_numeric_string(record);
-        split_record(record.clone(), field_separator, |i, s| {
-            let field_index = i + 1;
-            last_field += 1;
-            *self.fields[field_index].get_mut() = AwkValue::field_ref(s, field_index as u16);
-            Ok(())
-        })
-        .expect("error splitting record");
-        if last_field < previous_last_field {
-            for field in &mut self.fields[last_field + 1..=previous_last_field] {
-                field.get_mut().value = AwkValueVariant::UninitializedScalar;
-            }
+        split_record_fields(
+            self.record.borrow().clone(),
+            &field_separator,
+            &record_separator,
+            |i, s| {
+                let field_index = i + 1;
+                last_field = field_index;
+                *self.field(field_index) = AwkValue::field_ref(s, field_index);
+                Ok(())
+            },
+        )?;
+        for field in self
+            .fields
+            .borrow()
+            .iter()
+            .take(previous_last_field + 1)
+            .skip(last_field + 1)
+        {
+            (*field.get()).value = AwkValueVariant::UninitializedScalar;
         }
-        *self.fields[0].get_mut() = AwkValue::field_ref(record.clone(), 0);
-        *self.record.get_mut() = record.try_into()?;
-        *self.last_field.get_mut() = last_field;
-        Ok(())
+        *self.last_field.borrow_mut() = last_field;
+        Ok(true)
     }
 
+    /// Rebuilds `$0` by joining the fields with `OFS`, if a field changed since
+    /// the last rebuild.
+    ///
     /// # Safety
     /// The caller has to ensure that there are no active references to the fields
-    unsafe fn recompute_record(
-        &self,
-        global_env: &GlobalEnv,
-        last_field: usize,
-        truncate_fields: bool,
-    ) -> Result<(), String> {
-        let last_field = if truncate_fields {
-            if last_field < *self.last_field.borrow() {
-                for field in self
-                    .fields
-                    .iter()
-                    .skip(last_field + 1)
-                    .take(*self.last_field.borrow() - last_field)
-                {
-                    (*field.get()).value = AwkValueVariant::UninitializedScalar;
-                }
+    unsafe fn rebuild_if_outdated(&self, global_env: &GlobalEnv) -> Result<(), String> {
+        if !self.record_outdated.replace(false) {
+            return Ok(());
+        }
+        let last_field = *self.last_field.borrow();
+        let mut new_record = Vec::new();
+        for (i, field) in self.fields.borrow()[1..=last_field].iter().enumerate() {
+            if i > 0 {
+                new_record.extend_from_slice(&global_env.ofs);
             }
-            last_field
-        } else {
-            self.last_field.borrow().max(last_field)
-        };
-        let mut new_record = String::new();
-        for field in self.fields.iter().skip(1).take(last_field - 1) {
-            let field_str = (*field.get())
-                .clone()
-                .scalar_to_string(&global_env.convfmt)?;
-            write!(new_record, "{}{}", field_str, &global_env.ofs)
-                .expect("error writing to string");
+            (*field.get()).write_scalar(&global_env.convfmt, &mut new_record)?;
         }
-        let last_field_str = (*self.fields[last_field].get())
-            .clone()
-            .scalar_to_string(&global_env.convfmt)?;
-        write!(new_record, "{}", last_field_str).expect("error writing to string");
         // the spec doesn't specify if a recomputed record should be a numeric string.
         // Most other implementations don't really handle this case. Here we just
         // mark it as a numeric string if appropriate
-        let record_str = maybe_numeric_string(new_record);
-        *self.fields[0].get() = AwkValue::field_ref(record_str.clone(), 0);
-        *self.record.borrow_mut() = record_str.try_into()?;
-        *self.last_field.borrow_mut() = last_field;
+        let record_str = maybe_numeric_string(Rc::<[u8]>::from(new_record));
+        *self.field(0) = AwkValue::field_ref(record_str.clone(), 0);
+        *self.record.borrow_mut() = record_str;
         Ok(())
     }
 
+    /// Called after `$0` was assigned. The fields are split with the current
+    /// separators the first time a field or `NF` is read.
+    ///
     /// # Safety
     /// The caller has to ensure that there are no active references to the fields
-    unsafe fn recompute_fields(&self, global_env: &GlobalEnv) -> Result<(), String> {
-        let mut last_field = 0;
-        let record_str = (*self.fields[0].get())
-            .to_owned()
+    unsafe fn record_changed(&self, global_env: &GlobalEnv) -> Result<(), String> {
+        let record_str = (*self.field(0))
+            .clone()
             .scalar_to_string(&global_env.convfmt)?;
-        split_record(record_str.clone(), &global_env.fs, |i, s| {
-            let field_index = i + 1;
-            last_field += 1;
-            *self.fields[field_index].get() = AwkValue::field_ref(s, field_index as u16);
-            Ok(())
-        })
-        .expect("error splitting record");
-        *self.fields[0].get() = AwkValue::field_ref(record_str.clone(), 0);
-        *self.record.borrow_mut() = record_str.try_into()?;
-        *self.last_field.borrow_mut() = last_field;
+        *self.field(0) = AwkValue::field_ref(record_str.clone(), 0);
+        *self.record.borrow_mut() = record_str;
+        *self.pending_split.borrow_mut() = Some((global_env.fs.clone(), global_env.rs.clone()));
+        self.record_outdated.set(false);
+        Ok(())
+    }
+
+    /// Called after the field at `changed_field` was assigned.
+    ///
+    /// # Safety
+    /// The caller has to ensure that there are no active references to the fields
+    unsafe fn field_changed(&self, changed_field: usize) -> Result<(), String> {
+        // `$0` can only be assigned after the field was referenced in expressions
+        // like `$2 = ($0 = "a b c")`. The new value of the field takes precedence
+        // over the one coming from the new record
+        let new_value = (*self.field(changed_field)).clone();
+        if self.split_if_outdated()? {
+            *self.field(changed_field) = new_value;
+        }
+        let mut last_field = self.last_field.borrow_mut();
+        *last_field = (*last_field).max(changed_field);
+        self.record_outdated.set(true);
+        Ok(())
+    }
+
+    /// Called after `NF` was assigned. Fields after the new last field are cleared.
+    ///
+    /// # Safety
+    /// The caller has to ensure that there are no active references to the fields
+    unsafe fn nf_changed(&self, nf: usize) -> Result<(), String> {
+        self.split_if_outdated()?;
+        // the fields up to the new last one have to exist to rebuild `$0`
+        self.field(nf);
+        let previous_last_field = *self.last_field.borrow();
+        for field in self
+            .fields
+            .borrow()
+            .iter()
+            .take(previous_last_field + 1)
+            .skip(nf + 1)
+        {
+            (*field.get()).value = AwkValueVariant::UninitializedScalar;
+        }
+        *self.last_field.borrow_mut() = nf;
+        self.record_outdated.set(true);
         Ok(())
     }
 
     fn get_last_field(&self) -> usize {
         *self.last_field.borrow()
     }
+
+    /// Returns the field at `index`, adding uninitialized fields up to it if
+    /// the record does not have that many.
+    fn field(&self, index: usize) -> *mut AwkValue {
+        let mut fields = self.fields.borrow_mut();
+        while fields.len() <= index {
+            let new_field =
+                AwkValue::uninitialized_scalar().into_ref(AwkRefType::Field(fields.len()));
+            fields.push(Box::new(AwkValueRef::new(new_field)));
+        }
+        fields[index].get()
+    }
+
+    /// Returns a copy of the field at `index`, without adding fields to the
+    /// record, so that reading a field far past the last one is cheap.
+    ///
+    /// # Safety
+    /// The caller has to ensure that there are no active mutable references to the field
+    unsafe fn field_value(&self, index: usize) -> AwkValue {
+        match self.fields.borrow().get(index) {
+            Some(field) => (*field.get()).clone(),
+            None => AwkValue::uninitialized_scalar().into_ref(AwkRefType::Field(index)),
+        }
+    }
 }
 
 impl Default for Record {
     fn default() -> Self {
-        let fields = (0..Record::MAX_FIELDS)
-            .map(|i| {
-                AwkValueRef::new(
-                    AwkValue::uninitialized_scalar().into_ref(AwkRefType::Field(i as u16)),
-                )
-            })
-            .collect();
+        let record = AwkValue::uninitialized_scalar().into_ref(AwkRefType::Field(0));
         Self {
-            record: CString::default().into(),
-            fields,
+            record: AwkString::default().into(),
+            fields: vec![Box::new(AwkValueRef::new(record))].into(),
             last_field: 0.into(),
+            pending_split: None.into(),
+            record_outdated: false.into(),
         }
     }
 }
 
-fn is_valid_record_index(index: usize) -> Result<(), String> {
-    if !(0..=Record::MAX_FIELDS).contains(&index) {
-        Err("invalid field index".to_string())
-    } else {
-        Ok(())
-    }
-}
-
 #[cfg_attr(test, derive(Debug))]
 #[derive(Clone, PartialEq)]
 enum AwkValueVariant {
     Number(f64),
     String(AwkString),
     Array(Array),
-    Regex {
-        ere: Rc<Regex>,
-        matches_record: bool,
-    },
+    Regex { ere: Regex, matches_record: bool },
     Uninitialized,
     UninitializedScalar,
 }
@@ -693,7 +916,7 @@ enum AwkValueVariant {
 #[derive(Clone, Copy, PartialEq)]
 enum AwkRefType {
     None,
-    Field(u16),
+    Field(usize),
     SpecialGlobalVar(SpecialVar),
 }
 
@@ -710,7 +933,7 @@ impl AwkValue {
     fn scalar_as_f64(&self) -> f64 {
         match &self.value {
             AwkValueVariant::Number(x) => *x,
-            AwkValueVariant::String(s) => strtod(s.as_str()),
+            AwkValueVariant::String(s) => strtod(s),
             AwkValueVariant::UninitializedScalar => 0.0,
             AwkValueVariant::Regex { matches_record, .. } => bool_to_f64(*matches_record),
             AwkValueVariant::Array(_) | AwkValueVariant::Uninitialized => {
@@ -722,6 +945,7 @@ impl AwkValue {
     fn scalar_as_bool(&self) -> bool {
         match &self.value {
             AwkValueVariant::Number(x) => *x != 0.0,
+            AwkValueVariant::String(s) if s.is_numeric => strtod(s) != 0.0,
             AwkValueVariant::String(s) => !s.is_empty(),
             AwkValueVariant::Regex { matches_record, .. } => *matches_record,
             AwkValueVariant::UninitializedScalar => false,
@@ -731,13 +955,36 @@ impl AwkValue {
         }
     }
 
-    fn scalar_to_string(self, num_fmt: &str) -> Result<AwkString, String> {
+    /// Appends the value converted to a string to `output`, without allocating
+    /// a new string for it when possible.
+    fn write_scalar(&self, num_fmt: &[u8], output: &mut Vec<u8>) -> Result<(), String> {
+        match &self.value {
+            AwkValueVariant::Number(num) => {
+                if is_integer(*num) {
+                    write!(output, "{}", *num as i64).expect("error writing to string");
+                } else {
+                    output.extend_from_slice(&number_to_string(num_fmt, *num)?);
+                }
+            }
+            AwkValueVariant::String(s) => output.extend_from_slice(s),
+            AwkValueVariant::Regex { matches_record, .. } => {
+                output.push(if *matches_record { b'1' } else { b'0' })
+            }
+            AwkValueVariant::UninitializedScalar => {}
+            AwkValueVariant::Array(_) | AwkValueVariant::Uninitialized => {
+                panic!("not a scalar")
+            }
+        }
+        Ok(())
+    }
+
+    fn scalar_to_string(self, num_fmt: &[u8]) -> Result<AwkString, String> {
         match self.value {
             AwkValueVariant::Number(num) => {
                 if is_integer(num) {
                     Ok((num as i64).to_string().into())
                 } else {
-                    sprintf(num_fmt, &mut [num.into()], num_fmt)
+                    number_to_string(num_fmt, num)
                 }
             }
             AwkValueVariant::String(s) => Ok(s),
@@ -784,6 +1031,12 @@ impl AwkValue {
     ) -> Result<FieldsState, String> {
         let rhs = rhs.into();
         self.value = rhs.value;
+        if let AwkValueVariant::String(s) = &mut self.value {
+            // variables can outlive the record their value was taken from
+            if !matches!(self.ref_type, AwkRefType::Field(_)) {
+                s.detach();
+            }
+        }
         match self.ref_type {
             AwkRefType::SpecialGlobalVar(special_var) => {
                 global_env.set(special_var, self)?;
@@ -796,7 +1049,7 @@ impl AwkValue {
                     Ok(FieldsState::RecordChanged)
                 } else {
                     Ok(FieldsState::FieldChanged {
-                        changed_field: index as usize,
+                        changed_field: index,
                     })
                 };
             }
@@ -809,10 +1062,15 @@ impl AwkValue {
         Self { ref_type, ..self }
     }
 
-    fn into_ere(self) -> Result<Rc<Regex>, String> {
+    /// Converts the value into an extended regular expression. Strings are
+    /// compiled at most once, using the regex cache in `global_env`.
+    fn into_ere(self, global_env: &mut GlobalEnv) -> Result<Regex, String> {
         match self.value {
             AwkValueVariant::Regex { ere, .. } => Ok(ere),
-            _ => Err("expected extended regular expression".to_string()),
+            _ => {
+                let source = self.scalar_to_string(&global_env.convfmt)?;
+                global_env.regex_cache.get(&source)
+            }
         }
     }
 
@@ -830,8 +1088,8 @@ impl AwkValue {
         }
     }
 
-    fn from_ere(ere: Rc<Regex>, record: &CString) -> Self {
-        let matches_record = ere.matches(record);
+    fn from_ere(ere: Regex, record: &AwkString) -> Self {
+        let matches_record = ere.matches_bytes(record.as_bytes());
         Self {
             value: AwkValueVariant::Regex {
                 ere,
@@ -841,7 +1099,7 @@ impl AwkValue {
         }
     }
 
-    fn field_ref<V: Into<AwkValue>>(value: V, field_index: u16) -> Self {
+    fn field_ref<V: Into<AwkValue>>(value: V, field_index: usize) -> Self {
         let value = value.into();
         value.into_ref(AwkRefType::Field(field_index))
     }
@@ -966,6 +1224,11 @@ impl StackValue {
             StackValue::Value(val) => val.into_inner(),
             StackValue::ValueRef(ref_val) => (*ref_val).clone().into_ref(AwkRefType::None),
             StackValue::UninitializedRef(_) => AwkValue::uninitialized_scalar(),
+            // compound assignments to array elements use the value of the element
+            StackValue::ArrayElementRef(_) => {
+                let mut value = self;
+                value.value_ref().clone().into_ref(AwkRefType::None)
+            }
             _ => unreachable!("invalid stack value"),
         }
     }
@@ -1093,6 +1356,26 @@ impl<'i, 's> Stack<'i, 's> {
         }
     }
 
+    /// returns a pointer to the local at `index`, to be used as a scalar.
+    /// Scalars are passed by value, so if the local refers to an untyped
+    /// variable of the caller, the variable becomes a scalar and the
+    /// function gets its own copy of it.
+    fn get_mut_local_scalar_ptr(&mut self, index: usize) -> Option<*mut AwkValue> {
+        if unsafe { self.sp.offset_from(self.bp) } > index as isize {
+            let local = unsafe { &mut *self.bp.add(index) };
+            if let StackValue::UninitializedRef(var) = local {
+                // safe by type invariance
+                let var = unsafe { &mut **var };
+                if !matches!(var.value, AwkValueVariant::Array(_)) {
+                    var.ensure_value_is_scalar()
+                        .expect("uninitialized value is always a valid scalar");
+                    *local = StackValue::from(AwkValue::uninitialized_scalar());
+                }
+            }
+        }
+        self.get_mut_value_ptr(index)
+    }
+
     fn pop_value(&mut self) -> AwkValue {
         // safe by type invariance
         unsafe {
@@ -1106,6 +1389,24 @@ impl<'i, 's> Stack<'i, 's> {
         unsafe { &mut *self.pop().expect("empty stack").unwrap_ptr() }
     }
 
+    /// pops a reference to an array, or to an uninitialized value
+    /// that can become one.
+    /// # Errors
+    /// returns an error if the value on top of the stack is a scalar
+    fn pop_array_ptr(&mut self) -> Result<*mut AwkValue, String> {
+        match self.pop().expect("empty stack") {
+            StackValue::Value(_) => Err("scalar used in array context".to_string()),
+            // safe by type invariance
+            value => Ok(unsafe { value.unwrap_ptr() }),
+        }
+    }
+
+    fn pop_array(&mut self) -> Result<&mut Array, String> {
+        let array = self.pop_array_ptr()?;
+        // safe by type invariance
+        unsafe { &mut *array }.as_array()
+    }
+
     fn push_value<V: Into<AwkValue>>(&mut self, value: V) -> Result<(), String> {
         // a `StackValue::Value` is always valid, so this is safe
         unsafe { self.push(StackValue::from(value.into())) }
@@ -1144,6 +1445,33 @@ impl<'i, 's> Stack<'i, 's> {
         self.source_locations = &function.debug_info.source_locations;
     }
 
+    /// releases the array iterators left on the stack from `start` onwards by
+    /// leaving a `for (k in array)` loop early with `return`, `next` or `exit`
+    fn release_iterators_from(&mut self, start: *mut StackValue) {
+        let mut value = start;
+        while value != self.sp {
+            // values in [`bp`, `sp`) of all frames are valid, and the arrays
+            // referenced by iterators outlive them
+            unsafe {
+                if let StackValue::Iterator(iter) = &mut *value {
+                    if let Ok(array) = (*iter.array).as_array() {
+                        array.key_iter_drop(&mut iter.key_iter);
+                    }
+                }
+                value = value.add(1);
+            }
+        }
+    }
+
+    fn release_frame_iterators(&mut self) {
+        self.release_iterators_from(self.bp);
+    }
+
+    fn release_all_iterators(&mut self) {
+        let stack_start = self.call_frames.first().map_or(self.bp, |frame| frame.bp);
+        self.release_iterators_from(stack_start);
+    }
+
     fn restore_caller(&mut self) {
         let caller_frame = self
             .call_frames
@@ -1152,6 +1480,9 @@ impl<'i, 's> Stack<'i, 's> {
         self.bp = caller_frame.bp;
         self.sp = caller_frame.sp;
         self.instructions = caller_frame.instructions;
+        self.source_locations = caller_frame.source_locations;
+        self.current_function_file = caller_frame.function_file;
+        self.current_function_name = caller_frame.function_name;
         self.ip = caller_frame.ip;
     }
 
@@ -1179,7 +1510,17 @@ enum ExecutionResult {
     Expression(AwkValue),
     Next,
     NextFile,
-    Exit(i32),
+    /// `exit`, with the status given to it, if any
+    Exit(Option<i32>),
+}
+
+/// Evaluates the result of running a pattern. A pattern can execute `exit`
+/// by calling a function, in which case the status is returned as an error.
+fn pattern_to_bool(result: ExecutionResult) -> Result<bool, Option<i32>> {
+    match result {
+        ExecutionResult::Exit(status) => Err(status),
+        result => Ok(result.expr_to_bool()),
+    }
 }
 
 impl ExecutionResult {
@@ -1202,7 +1543,7 @@ struct Interpreter {
     read_files: ReadFiles,
     write_pipes: WritePipes,
     read_pipes: ReadPipes,
-    rand_seed: u64,
+    rand_seed: f64,
     rng: SmallRng,
 }
 
@@ -1226,7 +1567,7 @@ macro_rules! compare_op {
               	if lhs.is_numeric && rhs.is_numeric {
 									$stack.push_value(bool_to_f64(strtod(lhs) $op strtod(rhs)))?;
               	} else {
-                	$stack.push_value(bool_to_f64(lhs.as_str() $op rhs.as_str()))?;
+                	$stack.push_value(bool_to_f64(lhs.as_bytes() $op rhs.as_bytes()))?;
               	}
             }
             (AwkValueVariant::Number(lhs), AwkValueVariant::UninitializedScalar) => {
@@ -1241,21 +1582,56 @@ macro_rules! compare_op {
             (AwkValueVariant::Number(x), AwkValueVariant::String(s)) if s.is_numeric => {
                 $stack.push_value(bool_to_f64(*x $op rhs.scalar_as_f64()))?;
             }
+            (AwkValueVariant::String(s), AwkValueVariant::UninitializedScalar) if s.is_numeric => {
+                $stack.push_value(bool_to_f64(lhs.scalar_as_f64() $op 0.0))?;
+            }
+            (AwkValueVariant::UninitializedScalar, AwkValueVariant::String(s)) if s.is_numeric => {
+                $stack.push_value(bool_to_f64(0.0 $op rhs.scalar_as_f64()))?;
+            }
             (_, _) => {
-                $stack.push_value(bool_to_f64(lhs.scalar_to_string($convfmt)?.as_str() $op rhs.scalar_to_string($convfmt)?.as_str()))?;
+                $stack.push_value(bool_to_f64(lhs.scalar_to_string($convfmt)?.as_bytes() $op rhs.scalar_to_string($convfmt)?.as_bytes()))?;
             }
         }
     };
 }
 
-fn stack_trace(error: String, stack: Stack) -> String {
-    let mut result = format!("runtime error: {}\ncall trace:\n", error);
+/// Name used in diagnostics for a program source file. Programs given on
+/// the command line have no file name.
+fn source_name(file: &str) -> &str {
+    if file.is_empty() {
+        "cmd. line"
+    } else {
+        file
+    }
+}
+
+/// Formats a runtime error as
+/// ```text
+/// awk: <file>:<line>: <error>
+///  input record number <NR>, file <FILENAME>
+/// call trace:
+/// => <function> at <file>:<line>:<column>
+/// ...
+/// ```
+/// The input record line is omitted when no record has been read yet.
+fn stack_trace(error: String, stack: Stack, input_position: Option<(u32, AwkString)>) -> String {
     let error_location = stack.source_locations[stack.ip as usize];
+    let mut result = format!(
+        "awk: {}:{}: {}\n",
+        source_name(&stack.current_function_file),
+        error_location.line,
+        error
+    );
+    if let Some((nr, filename)) = input_position {
+        writeln!(result, " input record number {}, file {}", nr, filename)
+            .expect("error writing to string");
+    }
+    result.push_str("call trace:\n");
     writeln!(
         result,
         "=> {} at {}:{}:{}",
         stack.current_function_name,
-        stack.current_function_file,
+        source_name(&stack.current_function_file),
         error_location.line,
         error_location.column
     )
@@ -1265,7 +1641,10 @@ fn stack_trace(error: String, stack: Stack) -> String {
         writeln!(
             result,
             "=> {} at {}:{}:{}",
-            frame.function_name, frame.function_file, source_location.line, source_location.column
+            frame.function_name,
+            source_name(&frame.function_file),
+            source_location.line,
+            source_location.column
         )
         .expect("error writing to string");
     }
@@ -1273,6 +1652,12 @@ fn stack_trace(error: String, stack: Stack) -> String {
     result
 }
 
+/// Creates the generator used by `rand`. The same seed always
+/// produces the same sequence of values.
+fn rng_from_seed(seed: f64) -> SmallRng {
+    SmallRng::seed_from_u64(seed.to_bits())
+}
+
 impl Interpreter {
     fn run(
         &mut self,
@@ -1281,12 +1666,26 @@ impl Interpreter {
         record: &mut Record,
         stack: &mut [StackValue],
         global_env: &mut GlobalEnv,
-        current_file: &mut dyn RecordReader,
+        main_input: &mut MainInput,
     ) -> Result<ExecutionResult, String> {
         let mut stack = Stack::new(action, stack);
-        match self.run_internal(functions, record, &mut stack, global_env, current_file) {
-            Err(err) => Err(stack_trace(err, stack)),
-            Ok(result) => Ok(result),
+        match self.run_internal(functions, record, &mut stack, global_env, main_input) {
+            Err(err) => {
+                let input_position = if global_env.nr > 0 {
+                    let filename = self.globals[SpecialVar::Filename as usize]
+                        .get_mut()
+                        .clone()
+                        .scalar_to_string(&global_env.convfmt)?;
+                    Some((global_env.nr, filename))
+                } else {
+                    None
+                };
+                Err(stack_trace(err, stack, input_position))
+            }
+            Ok(result) => {
+                stack.release_all_iterators();
+                Ok(result)
+            }
         }
     }
 
@@ -1296,7 +1695,7 @@ impl Interpreter {
         record: &Record,
         stack: &mut Stack<'a, 'a>,
         global_env: &mut GlobalEnv,
-        current_file: &mut dyn RecordReader,
+        main_input: &mut MainInput,
     ) -> Result<ExecutionResult, String> {
         // # Safety
         // To meat the requirements of stacked borrows (as checked by miri),
@@ -1317,10 +1716,20 @@ impl Interpreter {
                     numeric_op!(stack,  *);
                 }
                 OpCode::Div => {
-                    numeric_op!(stack,  /);
+                    let rhs = stack.pop_scalar_value()?.scalar_as_f64();
+                    let lhs = stack.pop_scalar_value()?.scalar_as_f64();
+                    if rhs == 0.0 {
+                        return Err("division by zero attempted".to_string());
+                    }
+                    stack.push_value(lhs / rhs)?;
                 }
                 OpCode::Mod => {
-                    numeric_op!(stack,  %);
+                    let rhs = stack.pop_scalar_value()?.scalar_as_f64();
+                    let lhs = stack.pop_scalar_value()?.scalar_as_f64();
+                    if rhs == 0.0 {
+                        return Err("division by zero attempted in `%`".to_string());
+                    }
+                    stack.push_value(lhs % rhs)?;
                 }
                 OpCode::Pow => {
                     let rhs = stack.pop_scalar_value()?.scalar_as_f64();
@@ -1346,11 +1755,11 @@ impl Interpreter {
                     compare_op!(stack, &global_env.convfmt, !=);
                 }
                 OpCode::Match => {
-                    let ere = stack.pop_value().into_ere()?;
+                    let ere = stack.pop_value().into_ere(global_env)?;
                     let string = stack
                         .pop_scalar_value()?
                         .scalar_to_string(&global_env.convfmt)?;
-                    let result = ere.matches(&string.try_into()?);
+                    let result = ere.matches_bytes(string.as_bytes());
                     stack.push_value(bool_to_f64(result))?;
                 }
                 OpCode::Concat => {
@@ -1364,10 +1773,8 @@ impl Interpreter {
                     stack.push_value(lhs)?;
                 }
                 OpCode::In => {
-                    let key = stack
-                        .pop_scalar_value()?
-                        .scalar_to_string(&global_env.convfmt)?;
-                    let array = stack.pop_ref().as_array()?;
+                    let key = array_key(stack.pop_scalar_value()?, global_env)?;
+                    let array = stack.pop_array()?;
                     let result = array.contains(&key);
                     stack.push_value(bool_to_f64(result))?;
                 }
@@ -1451,7 +1858,7 @@ impl Interpreter {
                     if let Some(key) = array.key_iter_next(&mut iter.key_iter) {
                         unsafe {
                             // `iter_var` is a valid by stack invariance
-                            *iter.iter_var = key.to_string().into();
+                            *iter.iter_var = AwkString::from(key).into();
                             // we only modified the key iterator, so `iter` is still valid
                             stack.push(StackValue::Iterator(iter))?;
                         }
@@ -1459,11 +1866,21 @@ impl Interpreter {
                         ip_increment = offset as isize;
                     }
                 }
+                OpCode::DropIterator => {
+                    let mut iter = stack.pop().expect("empty stack").unwrap_array_iterator();
+                    // The pointer value is valid by stack invariance
+                    let array = unsafe { &mut *iter.array }.as_array()?;
+                    array.key_iter_drop(&mut iter.key_iter);
+                }
                 OpCode::AsNumber => {
                     let val = stack.pop_scalar_value()?;
                     stack.push_value(val.scalar_as_f64())?;
                 }
                 OpCode::GetGlobal(index) => unsafe {
+                    if index == SpecialVar::Nf as u32 {
+                        // there are no active field references at this point, so this is safe
+                        self.split_record_if_outdated(record, global_env)?;
+                    }
                     // globals outlive the stack, so this is safe even if the global is an array
                     stack.push(StackValue::from_var(self.globals[index as usize].get()))?
                 },
@@ -1477,25 +1894,28 @@ impl Interpreter {
                 }
                 OpCode::GetField => {
                     let index = stack.pop_scalar_value()?.scalar_as_f64() as usize;
-                    is_valid_record_index(index)?;
+                    // there are no active field references at this point, so this is safe
+                    unsafe { self.sync_record_for_field(record, index, global_env)? };
                     // fields are never arrays, so this is always safe
-                    unsafe { stack.push_value((*record.fields[index].get()).clone())? };
+                    unsafe { stack.push_value(record.field_value(index))? };
                 }
                 OpCode::IndexArrayGetValue => {
-                    let key = stack
-                        .pop_scalar_value()?
-                        .scalar_to_string(&global_env.convfmt)?;
-                    let array = stack.pop_ref().as_array()?;
+                    let key = array_key(stack.pop_scalar_value()?, global_env)?;
+                    let array = stack.pop_array()?;
                     let element = array.get_value(key.into())?.clone();
                     stack.push_value(element)?
                 }
                 OpCode::GlobalScalarRef(index) => unsafe {
+                    if index == SpecialVar::Nf as u32 {
+                        // there are no active field references at this point, so this is safe
+                        self.split_record_if_outdated(record, global_env)?;
+                    }
                     // globals outlive the stack, so this is safe
                     stack.push_ref(self.globals[index as usize].get())?
                 },
                 OpCode::LocalScalarRef(index) => {
                     let value = stack
-                        .get_mut_value_ptr(index as usize)
+                        .get_mut_local_scalar_ptr(index as usize)
                         .expect("invalid local index");
                     // this value is valid until the stack value at `index` is popped
                     // so this preserves the stack invariance
@@ -1503,15 +1923,14 @@ impl Interpreter {
                 }
                 OpCode::FieldRef => {
                     let index = stack.pop_scalar_value()?.scalar_as_f64() as usize;
-                    is_valid_record_index(index)?;
-                    // fields live longer than the stack, so this is safe
-                    unsafe { stack.push_ref(record.fields[index].get())? };
+                    // there are no active field references at this point, so this is safe
+                    unsafe { self.sync_record_for_field(record, index, global_env)? };
+                    // fields live longer than the stack and are never moved, so this is safe
+                    unsafe { stack.push_ref(record.field(index))? };
                 }
                 OpCode::IndexArrayGetRef => {
-                    let key = stack
-                        .pop_scalar_value()?
-                        .scalar_to_string(&global_env.convfmt)?;
-                    let array = unsafe { stack.pop().expect("empty stack").unwrap_ptr() };
+                    let key = array_key(stack.pop_scalar_value()?, global_env)?;
+                    let array = stack.pop_array_ptr()?;
                     // safe by type invariance
                     let value_index = unsafe { &mut *array }
                         .as_array()?
@@ -1533,14 +1952,12 @@ impl Interpreter {
                     stack.push_value(value)?;
                 }
                 OpCode::DeleteElement => {
-                    let key = stack
-                        .pop_scalar_value()?
-                        .scalar_to_string(&global_env.convfmt)?;
-                    let array = stack.pop_ref().as_array()?;
+                    let key = array_key(stack.pop_scalar_value()?, global_env)?;
+                    let array = stack.pop_array()?;
                     array.delete(&key);
                 }
                 OpCode::ClearArray => {
-                    let array = stack.pop_ref().as_array()?;
+                    let array = stack.pop_array()?;
                     array.clear();
                 }
                 OpCode::JumpIfFalse(offset) => {
@@ -1594,7 +2011,8 @@ impl Interpreter {
                             BuiltinFunction::RedirectedPrintfAppend
                                 | BuiltinFunction::RedirectedPrintAppend
                         );
-                        self.write_files.write(&filename, &str, is_append)?;
+                        self.write_files
+                            .write(&filename.to_string_lossy(), &str, is_append)?;
                     }
                     BuiltinFunction::RedirectedPrintPipe
                     | BuiltinFunction::RedirectedPrintfPipe => {
@@ -1612,30 +2030,52 @@ impl Interpreter {
                         let filename = stack
                             .pop_scalar_value()?
                             .scalar_to_string(&global_env.convfmt)?;
-                        self.write_files.close_file(&filename);
-                        self.read_files.close_file(&filename);
-                        self.write_pipes.close_pipe(&filename);
-                        self.read_pipes.close_pipe(&filename);
+                        let filename = filename.to_string_lossy();
+                        let result = [
+                            self.write_files.close_file(&filename),
+                            self.read_files.close_file(&filename),
+                            self.write_pipes.close_pipe(&filename),
+                            self.read_pipes.close_pipe(&filename),
+                        ]
+                        .into_iter()
+                        .flatten()
+                        // if the name was open in more than one way, report the first failure
+                        .min_by_key(|&status| status == 0)
+                        .unwrap_or(-1);
+                        stack.push_value(result as f64)?;
                     }
                     BuiltinFunction::FFlush => {
-                        let expr_str = if argc == 1 {
-                            stack
+                        let success = if argc == 1 {
+                            let name = stack
                                 .pop_scalar_value()?
-                                .scalar_to_string(&global_env.convfmt)?
-                        } else {
-                            AwkString::default()
-                        };
-                        let result = if expr_str.is_empty() {
-                            self.write_files.flush_file(&expr_str)
-                                && self.write_pipes.flush_file(&expr_str)
+                                .scalar_to_string(&global_env.convfmt)?;
+                            let name = name.to_string_lossy();
+                            if name.is_empty() {
+                                self.flush_all_output()
+                            } else {
+                                self.write_files.flush_file(&name)
+                                    || self.write_pipes.flush_file(&name)
+                            }
                         } else {
-                            self.write_files.flush_all() && self.write_pipes.flush_all()
+                            self.flush_all_output()
                         };
-                        stack.push_value(bool_to_f64(result))?;
+                        stack.push_value(if success { 0.0 } else { -1.0 })?;
+                    }
+                    BuiltinFunction::System => {
+                        let command: CString = stack
+                            .pop_scalar_value()?
+                            .scalar_to_string(&global_env.convfmt)?
+                            .try_into()?;
+                        // output written before the call must appear before
+                        // the output of the command
+                        self.flush_all_output();
+                        let status = unsafe { libc::system(command.as_ptr()) };
+                        stack.push_value(decode_wait_status(status) as f64)?;
                     }
                     BuiltinFunction::GetLine => {
+                        let next_record = main_input.read_next_record(&self.globals, global_env)?;
                         let var = stack.pop_ref();
-                        if let Some(next_record) = current_file.read_next_record(&global_env.rs)? {
+                        if let Some(next_record) = next_record {
                             fields_state =
                                 var.assign(maybe_numeric_string(next_record), global_env)?;
                             // borrowing `self.globas` mutably here breaks the stacked borrows rules
@@ -1654,17 +2094,30 @@ impl Interpreter {
                             .pop_scalar_value()?
                             .scalar_to_string(&global_env.convfmt)?;
                         let var = stack.pop_ref();
-                        let maybe_next_record = if function == BuiltinFunction::GetLineFromFile {
-                            self.read_files.read_next_record(filename, &global_env.rs)?
+                        let maybe_next_record = if function == BuiltinFunction::GetLineFromFile
+                            && is_stdin_filename(&filename)
+                        {
+                            main_input.read_stdin_record(&global_env.rs)
+                        } else if function == BuiltinFunction::GetLineFromFile {
+                            self.read_files.read_next_record(filename, &global_env.rs)
                         } else {
-                            self.read_pipes.read_next_record(filename, &global_env.rs)?
+                            self.read_pipes.read_next_record(filename, &global_env.rs)
                         };
-                        if let Some(next_record) = maybe_next_record {
-                            fields_state =
-                                var.assign(maybe_numeric_string(next_record), global_env)?;
-                            stack.push_value(1.0)?;
-                        } else {
-                            stack.push_value(0.0)?;
+                        match maybe_next_record {
+                            Ok(Some(next_record)) => {
+                                fields_state =
+                                    var.assign(maybe_numeric_string(next_record), global_env)?;
+                                if function == BuiltinFunction::GetLineFromPipe {
+                                    let nr = unsafe {
+                                        &mut *self.globals[SpecialVar::Nr as usize].get()
+                                    };
+                                    nr.assign(global_env.nr as f64 + 1.0, global_env)?;
+                                }
+                                stack.push_value(1.0)?;
+                            }
+                            Ok(None) => stack.push_value(0.0)?,
+                            // the input could not be opened or read
+                            Err(_) => stack.push_value(-1.0)?,
                         }
                     }
                     BuiltinFunction::Rand => {
@@ -1673,16 +2126,16 @@ impl Interpreter {
                     }
                     BuiltinFunction::Srand => {
                         let seed = if argc == 1 {
-                            stack.pop_scalar_value()?.scalar_as_f64() as u64
+                            stack.pop_scalar_value()?.scalar_as_f64()
                         } else {
                             SystemTime::now()
                                 .duration_since(SystemTime::UNIX_EPOCH)
                                 .expect("time went backwards")
-                                .as_secs()
+                                .as_secs() as f64
                         };
-                        stack.push_value(self.rand_seed as f64)?;
+                        stack.push_value(self.rand_seed)?;
                         self.rand_seed = seed;
-                        self.rng = SmallRng::seed_from_u64(self.rand_seed);
+                        self.rng = rng_from_seed(seed);
                     }
                     other => fields_state = call_simple_builtin(other, argc, stack, global_env)?,
                 },
@@ -1690,6 +2143,8 @@ impl Interpreter {
                     Constant::Number(num) => stack.push_value(num)?,
                     Constant::String(s) => stack.push_value(AwkString::from(s))?,
                     Constant::Regex(ere) => {
+                        // there are no active field references at this point, so this is safe
+                        unsafe { record.rebuild_if_outdated(global_env)? };
                         stack.push_value(AwkValue::from_ere(ere, &record.record.borrow()))?
                     }
                 },
@@ -1724,10 +2179,12 @@ impl Interpreter {
                 OpCode::NextFile => return Ok(ExecutionResult::NextFile),
                 OpCode::Exit => {
                     let exit_code = stack.pop_scalar_value()?.scalar_as_f64();
-                    return Ok(ExecutionResult::Exit(exit_code as i32));
+                    return Ok(ExecutionResult::Exit(Some(exit_code as i32)));
                 }
+                OpCode::ExitWithoutStatus => return Ok(ExecutionResult::Exit(None)),
                 OpCode::Return => {
                     let return_value = stack.pop_scalar_value()?;
+                    stack.release_frame_iterators();
                     stack.restore_caller();
                     stack.push_value(return_value)?;
                 }
@@ -1737,20 +2194,20 @@ impl Interpreter {
                 FieldsState::Ok => {
                     // no need to recompute anything
                 }
+                // `$0` and the fields are only brought back in sync when the outdated
+                // part is read, so that repeated assignments don't rebuild the whole record
                 FieldsState::RecordChanged => {
                     // there are no active field references at this point, so this is safe
-                    unsafe { record.recompute_fields(global_env)? };
-                    let nf = unsafe { &mut *self.globals[SpecialVar::Nf as usize].get() };
-                    nf.assign(record.get_last_field() as f64, global_env)?;
+                    unsafe { record.record_changed(global_env)? };
                 }
                 FieldsState::FieldChanged { changed_field } => {
                     // there are no active field references at this point, so this is safe
-                    unsafe { record.recompute_record(global_env, changed_field, false)? };
+                    unsafe { record.field_changed(changed_field)? };
                     let nf = unsafe { &mut *self.globals[SpecialVar::Nf as usize].get() };
                     nf.assign(record.get_last_field() as f64, global_env)?;
                 }
                 FieldsState::NfChanged => unsafe {
-                    record.recompute_record(global_env, global_env.nf, true)?;
+                    record.nf_changed(global_env.nf)?;
                 },
             }
             fields_state = FieldsState::Ok;
@@ -1765,20 +2222,62 @@ impl Interpreter {
         ))
     }
 
-    fn new(args: Array, env: Array, constants: Vec<Constant>, program_globals: usize) -> Self {
-        let mut globals = (0..SpecialVar::Count as usize + program_globals)
-            .map(|_| AwkValueRef::new(AwkValue::uninitialized()))
-            .collect::<Vec<AwkValueRef>>();
-
-        *globals[SpecialVar::Argc as usize].get_mut() = AwkValue::from(args.len() as f64)
-            .into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Argc));
-        *globals[SpecialVar::Argv as usize].get_mut() =
-            AwkValue::from(args).into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Argv));
-        *globals[SpecialVar::Convfmt as usize].get_mut() = AwkValue::from("%.6g".to_string())
-            .into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Convfmt));
-        *globals[SpecialVar::Environ as usize].get_mut() =
+    /// Splits the record into fields if `$0` changed since the last split, and
+    /// updates `NF`.
+    ///
+    /// # Safety
+    /// The caller has to ensure that there are no active references to the fields
+    unsafe fn split_record_if_outdated(
+        &self,
+        record: &Record,
+        global_env: &mut GlobalEnv,
+    ) -> Result<(), String> {
+        if record.split_if_outdated()? {
+            let nf = &mut *self.globals[SpecialVar::Nf as usize].get();
+            nf.assign(record.get_last_field() as f64, global_env)?;
+        }
+        Ok(())
+    }
+
+    /// Makes sure the field at `index` is up to date before it is read or referenced.
+    ///
+    /// # Safety
+    /// The caller has to ensure that there are no active references to the fields
+    unsafe fn sync_record_for_field(
+        &self,
+        record: &Record,
+        index: usize,
+        global_env: &mut GlobalEnv,
+    ) -> Result<(), String> {
+        if index == 0 {
+            record.rebuild_if_outdated(global_env)
+        } else {
+            self.split_record_if_outdated(record, global_env)
+        }
+    }
+
+    /// Flush standard output and all the files and pipes opened by output redirections.
+    fn flush_all_output(&mut self) -> bool {
+        let stdout_flushed = std::io::Write::flush(&mut std::io::stdout()).is_ok();
+        let files_flushed = self.write_files.flush_all();
+        let pipes_flushed = self.write_pipes.flush_all();
+        stdout_flushed && files_flushed && pipes_flushed
+    }
+
+    fn new(args: Array, env: Array, constants: Vec<Constant>, program_globals: usize) -> Self {
+        let mut globals = (0..SpecialVar::Count as usize + program_globals)
+            .map(|_| AwkValueRef::new(AwkValue::uninitialized()))
+            .collect::<Vec<AwkValueRef>>();
+
+        *globals[SpecialVar::Argc as usize].get_mut() = AwkValue::from(args.len() as f64)
+            .into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Argc));
+        *globals[SpecialVar::Argv as usize].get_mut() =
+            AwkValue::from(args).into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Argv));
+        *globals[SpecialVar::Convfmt as usize].get_mut() = AwkValue::from("%.6g".to_string())
+            .into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Convfmt));
+        *globals[SpecialVar::Environ as usize].get_mut() =
             AwkValue::from(env).into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Environ));
-        *globals[SpecialVar::Filename as usize].get_mut() = AwkValue::from("-".to_string())
+        *globals[SpecialVar::Filename as usize].get_mut() = AwkValue::from(String::new())
             .into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Filename));
         *globals[SpecialVar::Fnr as usize].get_mut() =
             AwkValue::from(0.0).into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Fnr));
@@ -1800,7 +2299,7 @@ impl Interpreter {
             AwkValue::from("\n".to_string()).into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Rs));
         *globals[SpecialVar::Rstart as usize].get_mut() =
             AwkValue::from(0.0).into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Rstart));
-        *globals[SpecialVar::Subsep as usize].get_mut() = AwkValue::from(" ".to_string())
+        *globals[SpecialVar::Subsep as usize].get_mut() = AwkValue::from("\x1c".to_string())
             .into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Subsep));
 
         Self {
@@ -1810,8 +2309,8 @@ impl Interpreter {
             read_files: ReadFiles::default(),
             write_pipes: WritePipes::default(),
             read_pipes: ReadPipes::default(),
-            rand_seed: 0,
-            rng: SmallRng::seed_from_u64(0),
+            rand_seed: 0.0,
+            rng: rng_from_seed(0.0),
         }
     }
 }
@@ -1838,18 +2337,169 @@ fn set_globals_with_assignment_arguments(
     global_env: &mut GlobalEnv,
     assignments: &[String],
 ) -> Result<(), String> {
-    assignments
-        .iter()
-        .filter_map(|s| parse_assignment(s))
-        .filter_map(|(var, value)| globals.get(var).copied().map(|index| (index, value)))
-        .try_for_each(|(global_index, value)| {
+    for assignment in assignments {
+        let (var, value) = parse_assignment(assignment)
+            .ok_or_else(|| format!("invalid variable assignment '{}'", assignment))?;
+        if let Some(&global_index) = globals.get(var) {
             let value = escape_string_contents(value)?;
             interpreter.globals[global_index as usize]
                 .get_mut()
-                .assign(maybe_numeric_string(value), global_env)
-                .expect("failed to assign value");
-            Ok(())
-        })
+                .assign(maybe_numeric_string(value), global_env)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns `true` if `filename` refers to standard input in `getline < filename`
+/// or in the file operands.
+fn is_stdin_filename(filename: &[u8]) -> bool {
+    filename == b"-" || filename == b"/dev/stdin"
+}
+
+enum InputSource {
+    File(FileStream),
+    Stdin,
+}
+
+/// The main input of the program: the concatenation of the file operands
+/// in `ARGV`, or standard input if there are none. Shared between the main
+/// loop and plain `getline`, so that both advance through the same records.
+///
+/// Standard input is always read through `stdin`, so reading it with
+/// `getline < "-"` while it is also the main input consumes the records in
+/// the order in which they are requested.
+struct MainInput {
+    program_globals: HashMap<String, u32>,
+    reader: Option<InputSource>,
+    stdin: StdinRecordReader,
+    next_arg_index: usize,
+    input_opened: bool,
+    is_done: bool,
+}
+
+impl MainInput {
+    fn new(program_globals: HashMap<String, u32>) -> Self {
+        Self {
+            program_globals,
+            reader: None,
+            stdin: StdinRecordReader::default(),
+            next_arg_index: 1,
+            input_opened: false,
+            is_done: false,
+        }
+    }
+
+    #[cfg(test)]
+    fn exhausted() -> Self {
+        Self {
+            is_done: true,
+            ..Self::new(HashMap::new())
+        }
+    }
+
+    /// Read the next record from the main input, moving on to the next
+    /// operand in `ARGV` when the current one is exhausted.
+    /// Returns `None` when there is no more input.
+    fn read_next_record(
+        &mut self,
+        globals: &[AwkValueRef],
+        global_env: &mut GlobalEnv,
+    ) -> Result<Option<Vec<u8>>, String> {
+        loop {
+            let record = match &mut self.reader {
+                Some(InputSource::File(file)) => file.read_next_record(&global_env.rs)?,
+                Some(InputSource::Stdin) => self.stdin.read_next_record(&global_env.rs)?,
+                None => None,
+            };
+            if record.is_some() {
+                return Ok(record);
+            }
+            self.reader = None;
+            if self.is_done || !self.open_next_input(globals, global_env)? {
+                self.is_done = true;
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Read the next record from standard input, for `getline < "-"`.
+    fn read_stdin_record(
+        &mut self,
+        separator: &RecordSeparator,
+    ) -> Result<Option<Vec<u8>>, String> {
+        self.stdin.read_next_record(separator)
+    }
+
+    /// Stop reading from the current input, so that the next record will be
+    /// read from the next operand.
+    fn skip_file(&mut self) {
+        self.reader = None;
+    }
+
+    /// Process the operands in `ARGV` until one names an input, and open it.
+    /// Returns `false` if there are no more inputs.
+    fn open_next_input(
+        &mut self,
+        globals: &[AwkValueRef],
+        global_env: &mut GlobalEnv,
+    ) -> Result<bool, String> {
+        // # Safety
+        // `ARGC`, `ARGV` and `FILENAME` are only accessed through the stack
+        // while an instruction is being executed, so there cannot be any other
+        // live reference to them at this point.
+        loop {
+            let argc = unsafe { &*globals[SpecialVar::Argc as usize].get() }
+                .clone()
+                .scalar_as_f64() as usize;
+            if self.next_arg_index >= argc {
+                if self.input_opened {
+                    return Ok(false);
+                }
+                unsafe { &mut *globals[SpecialVar::Filename as usize].get() }.value =
+                    AwkValueVariant::String("-".into());
+                self.input_opened = true;
+                self.reader = Some(InputSource::Stdin);
+                return Ok(true);
+            }
+
+            let key = self.next_arg_index.to_string();
+            self.next_arg_index += 1;
+            let argv = unsafe { &mut *globals[SpecialVar::Argv as usize].get() }.as_array()?;
+            if !argv.contains(key.as_bytes()) {
+                continue;
+            }
+            let arg = argv
+                .get_value(Rc::from(key.as_bytes()))?
+                .clone()
+                .scalar_to_string(&global_env.convfmt)?;
+
+            if arg.is_empty() {
+                continue;
+            }
+
+            if let Some((var, value)) = parse_assignment(&arg.to_string_lossy()) {
+                if let Some(&global_index) = self.program_globals.get(var) {
+                    unsafe { &mut *globals[global_index as usize].get() }.assign(
+                        maybe_numeric_string(escape_string_contents(value)?),
+                        global_env,
+                    )?;
+                }
+                continue;
+            }
+
+            let reader = if is_stdin_filename(&arg) {
+                InputSource::Stdin
+            } else {
+                InputSource::File(FileStream::open(&arg.to_string_lossy())?)
+            };
+            unsafe { &mut *globals[SpecialVar::Filename as usize].get() }.value =
+                AwkValueVariant::String(maybe_numeric_string(arg));
+            global_env.fnr = 0;
+            self.input_opened = true;
+            self.reader = Some(reader);
+            return Ok(true);
+        }
+    }
 }
 
 pub fn interpret(
@@ -1876,21 +2526,33 @@ pub fn interpret(
         .collect::<Vec<StackValue>>();
     let mut current_record = Record::default();
     let mut interpreter = Interpreter::new(args, env, program.constants, program.globals_count);
-    let mut global_env = GlobalEnv::default();
+    let mut global_env = GlobalEnv {
+        charset: CharacterSet::from_locale(),
+        ..Default::default()
+    };
     let mut range_pattern_started = vec![false; program.rules.len()];
     let mut return_value = 0;
+    let mut exiting = false;
+    let mut main_input = MainInput::new(program.globals);
 
     set_globals_with_assignment_arguments(
         &mut interpreter,
-        &program.globals,
+        &main_input.program_globals,
         &mut global_env,
         assignments,
     )?;
 
     if let Some(separator) = separator {
+        // the value of `-F` is processed like a string literal, so `-F '\t'` is a tab.
+        // A lone backslash does not start an escape sequence, so it is taken literally
+        let separator = if separator == "\\" {
+            AwkString::from(separator)
+        } else {
+            AwkString::from(escape_string_contents(&separator)?.as_ref())
+        };
         interpreter.globals[SpecialVar::Fs as usize]
             .get_mut()
-            .assign(AwkString::from(separator), &mut global_env)?;
+            .assign(separator, &mut global_env)?;
     }
 
     for action in program.begin_actions {
@@ -1900,10 +2562,11 @@ pub fn interpret(
             &mut current_record,
             &mut stack,
             &mut global_env,
-            &mut EmptyRecordReader::default(),
+            &mut main_input,
         )?;
-        if let ExecutionResult::Exit(val) = begin_result {
-            return_value = val;
+        if let ExecutionResult::Exit(status) = begin_result {
+            return_value = status.unwrap_or(return_value);
+            exiting = true;
             break;
         }
     }
@@ -1912,145 +2575,96 @@ pub fn interpret(
         return Ok(return_value);
     }
 
-    let mut current_arg_index = 1;
-    let mut input_read = false;
-    'file_loop: loop {
-        let argc = interpreter.globals[SpecialVar::Argc as usize]
-            .get_mut()
-            .scalar_as_f64() as usize;
-
-        let arg = if current_arg_index >= argc {
-            if input_read {
-                break;
-            } else {
-                "-".into()
-            }
-        } else {
-            interpreter.globals[SpecialVar::Argv as usize]
-                .get_mut()
-                .as_array()
-                .expect("ARGV is not an array")
-                .get_value(current_arg_index.to_string().into())
-                // there cannot be active iterators at this point, so this is safe
-                .unwrap()
-                .clone()
-                .scalar_to_string(&global_env.convfmt)?
-        };
-
-        if arg.is_empty() {
-            current_arg_index += 1;
-            continue;
-        }
-
-        if let Some((var, value)) = parse_assignment(&arg) {
-            if let Some(&global_index) = program.globals.get(var) {
-                interpreter.globals[global_index as usize]
-                    .get_mut()
-                    .assign(
-                        maybe_numeric_string(escape_string_contents(value)?),
-                        &mut global_env,
-                    )?;
-            }
-            current_arg_index += 1;
-            continue;
-        }
+    // `exit` in a `BEGIN` action skips the input, but `END` actions still run
+    'record_loop: while let Some(record) = if exiting {
+        None
+    } else {
+        main_input.read_next_record(&interpreter.globals, &mut global_env)?
+    } {
+        global_env.nr += 1;
+        global_env.fnr += 1;
+        current_record.reset(record, &global_env.fs, &global_env.rs)?;
 
-        interpreter.globals[SpecialVar::Filename as usize]
+        interpreter.globals[SpecialVar::Fnr as usize]
             .get_mut()
-            .value = AwkValueVariant::String(maybe_numeric_string(arg.clone()));
-
-        let reader: &mut dyn RecordReader = if arg.as_str() == "-" {
-            &mut StdinRecordReader::default()
-        } else {
-            &mut FileStream::open(&arg)?
-        };
-
-        // at this point we know that some input will be read
-        input_read = true;
-
-        global_env.fnr = 1;
-        'record_loop: while let Some(record) = reader.read_next_record(&global_env.rs)? {
-            current_record.reset(record, &global_env.fs)?;
-            interpreter.globals[SpecialVar::Nf as usize].get_mut().value =
-                AwkValue::from(current_record.get_last_field() as f64).value;
-            global_env.nf = current_record.get_last_field();
-
-            interpreter.globals[SpecialVar::Fnr as usize]
-                .get_mut()
-                .value = AwkValue::from(global_env.fnr as f64).value;
-            interpreter.globals[SpecialVar::Nr as usize].get_mut().value =
-                AwkValue::from(global_env.nr as f64).value;
-
-            for (i, rule) in program.rules.iter().enumerate() {
-                let should_execute = match &rule.pattern {
-                    Pattern::All => true,
-                    Pattern::Expr(expr) => interpreter
-                        .run(
-                            expr,
+            .value = AwkValue::from(global_env.fnr as f64).value;
+        interpreter.globals[SpecialVar::Nr as usize].get_mut().value =
+            AwkValue::from(global_env.nr as f64).value;
+
+        for (i, rule) in program.rules.iter().enumerate() {
+            let should_execute = match &rule.pattern {
+                Pattern::All => Ok(true),
+                Pattern::Expr(expr) => pattern_to_bool(interpreter.run(
+                    expr,
+                    &program.functions,
+                    &mut current_record,
+                    &mut stack,
+                    &mut global_env,
+                    &mut main_input,
+                )?),
+                Pattern::Range { start, end } => {
+                    let in_range = if range_pattern_started[i] {
+                        Ok(true)
+                    } else {
+                        pattern_to_bool(interpreter.run(
+                            start,
+                            &program.functions,
+                            &mut current_record,
+                            &mut stack,
+                            &mut global_env,
+                            &mut main_input,
+                        )?)
+                    };
+                    if in_range == Ok(true) {
+                        // the range is inclusive, and the record that starts it
+                        // can also end it
+                        pattern_to_bool(interpreter.run(
+                            end,
                             &program.functions,
                             &mut current_record,
                             &mut stack,
                             &mut global_env,
-                            reader,
-                        )?
-                        .expr_to_bool(),
-                    Pattern::Range { start, end } => {
-                        if range_pattern_started[i] {
-                            let should_end = !interpreter
-                                .run(
-                                    end,
-                                    &program.functions,
-                                    &mut current_record,
-                                    &mut stack,
-                                    &mut global_env,
-                                    reader,
-                                )?
-                                .expr_to_bool();
-                            range_pattern_started[i] = should_end;
-                            // range is inclusive
+                            &mut main_input,
+                        )?)
+                        .map(|ended| {
+                            range_pattern_started[i] = !ended;
                             true
-                        } else {
-                            let should_start = interpreter
-                                .run(
-                                    start,
-                                    &program.functions,
-                                    &mut current_record,
-                                    &mut stack,
-                                    &mut global_env,
-                                    reader,
-                                )?
-                                .expr_to_bool();
-                            range_pattern_started[i] = should_start;
-                            should_start
-                        }
+                        })
+                    } else {
+                        in_range
                     }
-                };
-                if should_execute {
-                    let rule_result = interpreter.run(
-                        &rule.action,
-                        &program.functions,
-                        &mut current_record,
-                        &mut stack,
-                        &mut global_env,
-                        reader,
-                    )?;
-                    match rule_result {
-                        ExecutionResult::Next => break,
-                        ExecutionResult::NextFile => break 'record_loop,
-                        ExecutionResult::Exit(val) => {
-                            return_value = val;
-                            break 'file_loop;
-                        }
-                        ExecutionResult::Expression(_) => {}
+                }
+            };
+            let should_execute = match should_execute {
+                Ok(should_execute) => should_execute,
+                Err(status) => {
+                    return_value = status.unwrap_or(return_value);
+                    break 'record_loop;
+                }
+            };
+            if should_execute {
+                let rule_result = interpreter.run(
+                    &rule.action,
+                    &program.functions,
+                    &mut current_record,
+                    &mut stack,
+                    &mut global_env,
+                    &mut main_input,
+                )?;
+                match rule_result {
+                    ExecutionResult::Next => break,
+                    ExecutionResult::NextFile => {
+                        main_input.skip_file();
+                        break;
                     }
+                    ExecutionResult::Exit(status) => {
+                        return_value = status.unwrap_or(return_value);
+                        break 'record_loop;
+                    }
+                    ExecutionResult::Expression(_) => {}
                 }
             }
-
-            global_env.fnr += 1;
-            global_env.nr += 1;
         }
-
-        current_arg_index += 1;
     }
 
     for action in program.end_actions {
@@ -2060,10 +2674,10 @@ pub fn interpret(
             &mut current_record,
             &mut stack,
             &mut global_env,
-            &mut EmptyRecordReader::default(),
+            &mut main_input,
         )?;
-        if let ExecutionResult::Exit(val) = end_result {
-            return_value = val;
+        if let ExecutionResult::Exit(status) = end_result {
+            return_value = status.unwrap_or(return_value);
             break;
         }
     }
@@ -2091,6 +2705,7 @@ mod tests {
         functions: Vec<Function>,
         record: Record,
         globals_count: usize,
+        charset: CharacterSet,
     }
 
     impl Test {
@@ -2111,9 +2726,16 @@ mod tests {
                 functions: Default::default(),
                 record: Default::default(),
                 globals_count,
+                // tests run as in a UTF-8 locale, unless they set another one
+                charset: CharacterSet::Utf8,
             }
         }
 
+        fn with_charset(mut self, charset: CharacterSet) -> Self {
+            self.charset = charset;
+            self
+        }
+
         fn add_function(mut self, f: Function) -> Self {
             self.functions.push(f);
             self
@@ -2121,7 +2743,11 @@ mod tests {
 
         fn add_record(mut self, record_string: &str) -> Self {
             self.record
-                .reset(record_string.to_string(), &FieldSeparator::Default)
+                .reset(
+                    record_string.as_bytes().to_vec(),
+                    &FieldSeparator::Default,
+                    &RecordSeparator::Char(b'\n'),
+                )
                 .expect("could not split record");
             self
         }
@@ -2141,16 +2767,29 @@ mod tests {
                 instructions: self.instructions,
             };
 
+            let mut global_env = GlobalEnv {
+                charset: self.charset,
+                ..Default::default()
+            };
             let execution_result = interpreter
                 .run(
                     &action,
                     &self.functions,
                     &mut self.record,
                     &mut stack,
-                    &mut GlobalEnv::default(),
-                    &mut EmptyRecordReader::default(),
+                    &mut global_env,
+                    &mut MainInput::exhausted(),
                 )
                 .expect("execution generated an error");
+            // bring `$0`, the fields and `NF` in sync, so that tests can inspect them
+            unsafe {
+                interpreter
+                    .split_record_if_outdated(&self.record, &mut global_env)
+                    .expect("could not split record");
+                self.record
+                    .rebuild_if_outdated(&global_env)
+                    .expect("could not rebuild record");
+            }
 
             let globals = interpreter
                 .globals
@@ -2190,6 +2829,14 @@ mod tests {
     }
 
     fn test_sprintf(format: &str, args: Vec<Constant>) -> String {
+        test_sprintf_with_charset(format, args, CharacterSet::Utf8).to_string()
+    }
+
+    fn test_sprintf_with_charset(
+        format: &str,
+        args: Vec<Constant>,
+        charset: CharacterSet,
+    ) -> AwkString {
         let mut instructions = vec![OpCode::PushConstant(0)];
         let mut constants = vec![Constant::from(format)];
         let argc = args.len() + 1;
@@ -2201,9 +2848,13 @@ mod tests {
             function: BuiltinFunction::Sprintf,
             argc: argc as u16,
         });
-        let result = interpret_expr(instructions, constants);
+        let result = Test::new(instructions, constants)
+            .with_charset(charset)
+            .run_correct()
+            .execution_result
+            .unwrap_expr();
         if let AwkValueVariant::String(s) = result.value {
-            s.to_string()
+            s
         } else {
             panic!("expected string, got {:?}", result);
         }
@@ -2313,6 +2964,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_integral_numbers_are_converted_to_strings_as_integers() {
+        let to_string = |num: f64| AwkValue::from(num).scalar_to_string(b"%.6g").unwrap();
+        assert_eq!(to_string(2147483648.0).as_bytes(), b"2147483648");
+        assert_eq!(to_string(1e6).as_bytes(), b"1000000");
+        assert_eq!(to_string(-0.0).as_bytes(), b"0");
+        assert_eq!(
+            to_string(9007199254740991.0).as_bytes(),
+            b"9007199254740991"
+        );
+        assert_eq!(
+            to_string(9007199254740992.0).as_bytes(),
+            b"9007199254740992"
+        );
+        assert_eq!(
+            to_string(-9007199254740992.0).as_bytes(),
+            b"-9007199254740992"
+        );
+        assert_eq!(to_string(9007199254740994.0).as_bytes(), b"9.0072e+15");
+        assert_eq!(to_string(1e30).as_bytes(), b"1e+30");
+        assert_eq!(to_string(0.5).as_bytes(), b"0.5");
+        assert_eq!(to_string(f64::INFINITY).as_bytes(), b"inf");
+    }
+
+    #[test]
+    fn test_array_key_conversion() {
+        let global_env = GlobalEnv::default();
+        let key = |value: AwkValue| array_key(value, &global_env).unwrap();
+        assert_eq!(key(1.0.into()).as_bytes(), b"1");
+        assert_eq!(key((0.5 + 0.5).into()).as_bytes(), b"1");
+        assert_eq!(key(AwkValue::from("1")).as_bytes(), b"1");
+        assert_eq!(key(AwkValue::from("01")).as_bytes(), b"01");
+        assert_eq!(key((-3.0).into()).as_bytes(), b"-3");
+        assert_eq!(key((-0.0).into()).as_bytes(), b"0");
+        assert_eq!(key(0.1.into()).as_bytes(), b"0.1");
+        assert_eq!(key((1.0 / 3.0).into()).as_bytes(), b"0.333333");
+        assert_eq!(key(AwkValue::uninitialized_scalar()).as_bytes(), b"");
+    }
+
     #[test]
     fn test_compare_same_operand_type() {
         let instructions = vec![OpCode::PushConstant(0), OpCode::PushConstant(1), OpCode::Le];
@@ -2346,6 +3036,21 @@ mod tests {
         assert_eq!(interpret_expr(instructions, constant), AwkValue::from(1.0));
     }
 
+    #[test]
+    fn test_uninitialized_equals_zero_and_empty_string() {
+        for constant in [Constant::Number(0.0), Constant::from("")] {
+            let instructions = vec![
+                OpCode::GetGlobal(FIRST_GLOBAL_VAR),
+                OpCode::PushConstant(0),
+                OpCode::Eq,
+            ];
+            assert_eq!(
+                interpret_expr(instructions, vec![constant]),
+                AwkValue::from(1.0)
+            );
+        }
+    }
+
     #[test]
     fn test_interpret_in_for_global_array() {
         let instructions = vec![
@@ -2706,6 +3411,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_assigning_untyped_argument_does_not_change_caller_variable() {
+        let main = vec![OpCode::GetGlobal(FIRST_GLOBAL_VAR), OpCode::Call(0)];
+        let function = Function {
+            parameters_count: 1,
+            instructions: vec![
+                OpCode::LocalScalarRef(0),
+                OpCode::PushOne,
+                OpCode::Assign,
+                OpCode::Return,
+            ],
+            ..Default::default()
+        };
+        let result = Test::new(main, vec![]).add_function(function).run_correct();
+        assert_eq!(result.execution_result.unwrap_expr(), AwkValue::from(1.0));
+        assert_eq!(
+            result.globals[FIRST_GLOBAL_VAR as usize],
+            AwkValue::uninitialized_scalar()
+        );
+    }
+
     #[test]
     fn test_call_function_with_multiple_scalar_arguments() {
         let main = vec![
@@ -2791,6 +3517,26 @@ mod tests {
         assert_eq!(interpret_expr(instructions, constant), AwkValue::from(0.0));
     }
 
+    #[test]
+    fn test_builtin_index_counts_characters() {
+        let instructions = vec![
+            OpCode::PushConstant(0),
+            OpCode::PushConstant(1),
+            OpCode::CallBuiltin {
+                function: BuiltinFunction::Index,
+                argc: 2,
+            },
+        ];
+        let constant = vec![Constant::from("naïve"), Constant::from("ve")];
+        assert_eq!(
+            interpret_expr(instructions.clone(), constant),
+            AwkValue::from(4.0)
+        );
+
+        let constant = vec![Constant::from("€a€b"), Constant::from("b")];
+        assert_eq!(interpret_expr(instructions, constant), AwkValue::from(4.0));
+    }
+
     #[test]
     fn test_builtin_length() {
         let instructions = vec![
@@ -2854,6 +3600,135 @@ mod tests {
         assert_eq!(interpret_expr(instructions, constants), AwkValue::from(3.0));
     }
 
+    #[test]
+    fn test_builtin_length_counts_characters() {
+        let instructions = vec![
+            OpCode::PushConstant(0),
+            OpCode::CallBuiltin {
+                function: BuiltinFunction::Length,
+                argc: 1,
+            },
+        ];
+        assert_eq!(
+            interpret_expr(instructions.clone(), vec![Constant::from("héllo")]),
+            AwkValue::from(5.0)
+        );
+        assert_eq!(
+            interpret_expr(instructions, vec![Constant::from("€€x")]),
+            AwkValue::from(3.0)
+        );
+    }
+
+    #[test]
+    fn test_builtin_length_and_substr_count_bytes_in_single_byte_locale() {
+        let length = vec![
+            OpCode::PushConstant(0),
+            OpCode::CallBuiltin {
+                function: BuiltinFunction::Length,
+                argc: 1,
+            },
+        ];
+        let result = Test::new(length, vec![Constant::from("héllo")])
+            .with_charset(CharacterSet::SingleByte)
+            .run_correct();
+        assert_eq!(result.execution_result.unwrap_expr(), AwkValue::from(6.0));
+
+        let substr = vec![
+            OpCode::PushConstant(0),
+            OpCode::PushConstant(1),
+            OpCode::PushConstant(2),
+            OpCode::CallBuiltin {
+                function: BuiltinFunction::Substr,
+                argc: 3,
+            },
+        ];
+        // the second byte of 'é' is kept as it is
+        for (m, expected) in [(2.0, "é".as_bytes()), (3.0, b"\xA9l")] {
+            let constants = vec![
+                Constant::from("héllo"),
+                Constant::Number(m),
+                Constant::Number(2.0),
+            ];
+            let result = Test::new(substr.clone(), constants)
+                .with_charset(CharacterSet::SingleByte)
+                .run_correct();
+            assert_eq!(
+                result.execution_result.unwrap_expr(),
+                AwkValue::from(AwkString::from(expected))
+            );
+        }
+    }
+
+    #[test]
+    fn test_builtin_tolower() {
+        let instructions = vec![
+            OpCode::PushConstant(0),
+            OpCode::CallBuiltin {
+                function: BuiltinFunction::ToLower,
+                argc: 1,
+            },
+        ];
+        let cases = [
+            ("HeLLo, World 42", "hello, world 42"),
+            ("ÅNGSTRÖM", "ångström"),
+            ("ÉCOLE À CÔTÉ", "école à côté"),
+            ("ПРИВЕТ МИР", "привет мир"),
+            ("ABC-Ärger-ΣΟΦΙΑ", "abc-ärger-σοφια"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                interpret_expr(instructions.clone(), vec![Constant::from(input)]),
+                AwkValue::from(expected.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_builtin_toupper() {
+        let instructions = vec![
+            OpCode::PushConstant(0),
+            OpCode::CallBuiltin {
+                function: BuiltinFunction::ToUpper,
+                argc: 1,
+            },
+        ];
+        let cases = [
+            ("HeLLo, World 42", "HELLO, WORLD 42"),
+            ("ångström", "ÅNGSTRÖM"),
+            ("école à côté", "ÉCOLE À CÔTÉ"),
+            ("привет мир", "ПРИВЕТ МИР"),
+            ("abc-ärger", "ABC-ÄRGER"),
+            ("straße", "STRASSE"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                interpret_expr(instructions.clone(), vec![Constant::from(input)]),
+                AwkValue::from(expected.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_builtin_case_mapping_is_ascii_only_in_single_byte_locale() {
+        let cases = [
+            (BuiltinFunction::ToUpper, "straße, école", "STRAßE, éCOLE"),
+            (BuiltinFunction::ToLower, "ÅNGSTRÖM Abc", "ÅngstrÖm abc"),
+        ];
+        for (function, input, expected) in cases {
+            let instructions = vec![
+                OpCode::PushConstant(0),
+                OpCode::CallBuiltin { function, argc: 1 },
+            ];
+            let result = Test::new(instructions, vec![Constant::from(input)])
+                .with_charset(CharacterSet::SingleByte)
+                .run_correct();
+            assert_eq!(
+                result.execution_result.unwrap_expr(),
+                AwkValue::from(expected.to_string())
+            );
+        }
+    }
+
     #[test]
     fn test_builtin_substr() {
         let instructions = vec![
@@ -2890,6 +3765,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_builtin_substr_positions() {
+        let instructions = vec![
+            OpCode::PushConstant(0),
+            OpCode::PushConstant(1),
+            OpCode::PushConstant(2),
+            OpCode::CallBuiltin {
+                function: BuiltinFunction::Substr,
+                argc: 3,
+            },
+        ];
+        let cases = [
+            ("naïve€x", 3.0, 3.0, "ïve"),
+            ("h€llo", 0.0, 2.0, "h"),
+            ("h€llo", -1.0, 3.0, "h"),
+            ("h€llo", 1.5, 2.3, "€l"),
+            ("h€llo", 2.0, -1.0, ""),
+            ("h€llo", 10.0, 2.0, ""),
+        ];
+        for (s, m, n, expected) in cases {
+            let constant = vec![Constant::from(s), Constant::Number(m), Constant::Number(n)];
+            assert_eq!(
+                interpret_expr(instructions.clone(), constant),
+                AwkValue::from(expected.to_string())
+            );
+        }
+    }
+
     #[test]
     fn test_builtin_sprintf_with_escaped_percent_sign() {
         assert_eq!(test_sprintf("%%", vec![]), "%");
@@ -3049,7 +3952,118 @@ mod tests {
     #[test]
     fn test_builtin_sprintf_char() {
         assert_eq!(test_sprintf("%c", vec![Constant::Number(55.0)]), "7");
-        assert_eq!(test_sprintf("%c", vec![Constant::Number(548.0)]), "$");
+        assert_eq!(test_sprintf("%c", vec![Constant::Number(548.0)]), "\u{224}");
+        assert_eq!(test_sprintf("%c", vec![Constant::from("hello")]), "h");
+        assert_eq!(test_sprintf("%c", vec![Constant::from("ñu")]), "ñ");
+        assert_eq!(test_sprintf("%c", vec![Constant::from("")]), "");
+
+        let single_byte = |value| {
+            test_sprintf_with_charset(
+                "%c",
+                vec![Constant::Number(value)],
+                CharacterSet::SingleByte,
+            )
+        };
+        assert_eq!(single_byte(548.0), "$".into());
+        assert_eq!(single_byte(233.0), b"\xE9"[..].into());
+    }
+
+    #[test]
+    fn test_builtin_sprintf_with_missing_arguments() {
+        assert_eq!(test_sprintf("%s-%d", vec![]), "-0");
+        assert_eq!(test_sprintf("%s-%d", vec!["a".into()]), "a-0");
+        assert_eq!(test_sprintf("[%5s|%-3c|%.2f]", vec![]), "[     |   |0.00]");
+        assert_eq!(test_sprintf("%*d|", vec![]), "0|");
+    }
+
+    #[test]
+    fn test_builtin_sprintf_ignores_extra_arguments() {
+        assert_eq!(
+            test_sprintf(
+                "%s-%d",
+                vec![
+                    "a".into(),
+                    Constant::Number(2.0),
+                    Constant::Number(3.0),
+                    "b".into(),
+                    "c".into()
+                ]
+            ),
+            "a-2"
+        );
+        assert_eq!(
+            test_sprintf("no conversions", vec!["a".into()]),
+            "no conversions"
+        );
+    }
+
+    #[test]
+    fn test_builtin_sprintf_c_with_empty_string() {
+        assert_eq!(test_sprintf("[%c]", vec!["".into()]), "[]");
+    }
+
+    #[test]
+    fn test_builtin_sprintf_conversions_table() {
+        let n = Constant::Number;
+        let s = Constant::from;
+        let cases: Vec<(&str, Vec<Constant>, &str)> = vec![
+            ("%d", vec![n(42.0)], "42"),
+            ("%i", vec![n(42.0)], "42"),
+            ("%i", vec![n(-42.9)], "-42"),
+            ("%5d", vec![n(42.0)], "   42"),
+            ("%-5d|", vec![n(42.0)], "42   |"),
+            ("%05d", vec![n(42.0)], "00042"),
+            ("%05d", vec![n(-42.0)], "-0042"),
+            ("%+d", vec![n(42.0)], "+42"),
+            ("% d", vec![n(42.0)], " 42"),
+            ("%+ d", vec![n(42.0)], "+42"),
+            ("%+05d", vec![n(42.0)], "+0042"),
+            ("%-+5d|", vec![n(42.0)], "+42  |"),
+            ("%-05d|", vec![n(42.0)], "42   |"),
+            ("%.3d", vec![n(42.0)], "042"),
+            ("%8.3d", vec![n(42.0)], "     042"),
+            ("%08.3d", vec![n(42.0)], "     042"),
+            ("%.0d", vec![n(0.0)], ""),
+            ("%08.3x", vec![n(42.0)], "     02a"),
+            ("%#o", vec![n(8.0)], "010"),
+            ("%#x", vec![n(255.0)], "0xff"),
+            ("%#X", vec![n(255.0)], "0XFF"),
+            ("%u", vec![n(42.0)], "42"),
+            ("%*d", vec![n(5.0), n(42.0)], "   42"),
+            ("%-*d|", vec![n(5.0), n(42.0)], "42   |"),
+            ("%*d|", vec![n(-5.0), n(42.0)], "42   |"),
+            ("%.*d", vec![n(4.0), n(42.0)], "0042"),
+            ("%*.*d", vec![n(6.0), n(4.0), n(42.0)], "  0042"),
+            ("%.*f", vec![n(-1.0), n(2.5)], "2.500000"),
+            ("%*.*f", vec![n(8.0), n(2.0), n(1.23456)], "    1.23"),
+            ("%.*s", vec![n(3.0), s("abcdef")], "abc"),
+            ("%*s", vec![n(4.0), s("ab")], "  ab"),
+            ("%5s", vec![s("ab")], "   ab"),
+            ("%-5s|", vec![s("ab")], "ab   |"),
+            ("%.2s", vec![s("ñandú")], "ña"),
+            ("%5s", vec![s("ñandú")], "ñandú"),
+            ("%6.1s|", vec![s("ñandú")], "     ñ|"),
+            ("%c", vec![n(65.0)], "A"),
+            ("%3c", vec![n(65.0)], "  A"),
+            ("%-3c|", vec![s("xyz")], "x  |"),
+            ("%f", vec![n(2.5)], "2.500000"),
+            ("%.2f", vec![n(2.345)], "2.35"),
+            ("%.0f", vec![n(2.5)], "2"),
+            ("%+.1f", vec![n(2.0)], "+2.0"),
+            ("%08.2f", vec![n(-2.5)], "-0002.50"),
+            ("%-8.2f|", vec![n(2.5)], "2.50    |"),
+            ("%e", vec![n(1234.5)], "1.234500e+03"),
+            ("%.2E", vec![n(1234.5)], "1.23E+03"),
+            ("%g", vec![n(0.0001)], "0.0001"),
+            ("%g", vec![n(1e10)], "1e+10"),
+            ("%G", vec![n(1e-10)], "1E-10"),
+            ("%#.3g", vec![n(42.0)], "42.0"),
+            ("%d%%", vec![n(50.0)], "50%"),
+            ("%s and %s", vec![s("a"), n(1.5)], "a and 1.5"),
+        ];
+        for (format, args, expected) in cases {
+            assert_eq!(test_sprintf(format, args), expected, "format: {}", format);
+        }
     }
 
     #[test]
@@ -3062,10 +4076,22 @@ mod tests {
         ];
         let constant = vec![
             Constant::from("hello"),
-            Constant::Regex(Rc::new(
+            Constant::Regex(
                 Regex::new(CString::new("e").unwrap()).expect("failed to compile regex"),
-            )),
+            ),
+        ];
+        assert_eq!(interpret_expr(instructions, constant), AwkValue::from(1.0));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_match_op_with_dynamic_regex() {
+        let instructions = vec![
+            OpCode::PushConstant(0),
+            OpCode::PushConstant(1),
+            OpCode::Match,
         ];
+        let constant = vec![Constant::from("axby"), Constant::from("x.y")];
         assert_eq!(interpret_expr(instructions, constant), AwkValue::from(1.0));
     }
 
@@ -3082,7 +4108,7 @@ mod tests {
         ];
         let constants = vec![
             Constant::from("this is a test"),
-            Constant::Regex(Rc::new(regex_from_str("is* a"))),
+            Constant::Regex(regex_from_str("is* a")),
         ];
         let result = Test::new(instructions, constants).run_correct();
 
@@ -3097,6 +4123,84 @@ mod tests {
         );
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_builtin_match_on_multibyte_string() {
+        let instructions = vec![
+            OpCode::PushConstant(0),
+            OpCode::PushConstant(1),
+            OpCode::CallBuiltin {
+                function: BuiltinFunction::Match,
+                argc: 2,
+            },
+        ];
+        let constants = vec![
+            Constant::from("żółw€ab"),
+            Constant::Regex(regex_from_str("w€a")),
+        ];
+        let result = Test::new(instructions, constants).run_correct();
+
+        assert_eq!(result.execution_result.unwrap_expr(), AwkValue::from(4.0));
+        assert_eq!(
+            result.globals[SpecialVar::Rstart as usize],
+            AwkValue::from(4.0)
+        );
+        assert_eq!(
+            result.globals[SpecialVar::Rlength as usize],
+            AwkValue::from(3.0)
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_builtin_match_without_match() {
+        let instructions = vec![
+            OpCode::PushConstant(0),
+            OpCode::PushConstant(1),
+            OpCode::CallBuiltin {
+                function: BuiltinFunction::Match,
+                argc: 2,
+            },
+        ];
+        let constants = vec![Constant::from("abc"), Constant::Regex(regex_from_str("z"))];
+        let result = Test::new(instructions, constants).run_correct();
+
+        assert_eq!(result.execution_result.unwrap_expr(), AwkValue::from(0.0));
+        assert_eq!(
+            result.globals[SpecialVar::Rstart as usize],
+            AwkValue::from(0.0)
+        );
+        assert_eq!(
+            result.globals[SpecialVar::Rlength as usize],
+            AwkValue::from(-1.0)
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_builtin_match_empty_match() {
+        let instructions = vec![
+            OpCode::PushConstant(0),
+            OpCode::PushConstant(1),
+            OpCode::CallBuiltin {
+                function: BuiltinFunction::Match,
+                argc: 2,
+            },
+        ];
+        let constants = vec![Constant::from("abc"), Constant::Regex(regex_from_str("x*"))];
+        let result = Test::new(instructions, constants).run_correct();
+
+        assert_eq!(result.execution_result.unwrap_expr(), AwkValue::from(1.0));
+        assert_eq!(
+            result.globals[SpecialVar::Rstart as usize],
+            AwkValue::from(1.0)
+        );
+        assert_eq!(
+            result.globals[SpecialVar::Rlength as usize],
+            AwkValue::from(0.0)
+        );
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn test_builtin_split_with_split_ere() {
@@ -3111,7 +4215,7 @@ mod tests {
         ];
         let constants = vec![
             Constant::from("a, b, c"),
-            Constant::Regex(Rc::new(regex_from_str(","))),
+            Constant::Regex(regex_from_str(",")),
         ];
 
         let global = test_global(instructions, constants);
@@ -3140,6 +4244,99 @@ mod tests {
         );
     }
 
+    fn split_to_vec(record: &str, field_separator: &FieldSeparator) -> Vec<String> {
+        let mut fields = Vec::new();
+        split_record(record.into(), field_separator, |_, s| {
+            fields.push(s.to_string());
+            Ok(())
+        })
+        .unwrap();
+        fields
+    }
+
+    #[test]
+    fn test_split_record_with_default_fs_ignores_leading_and_trailing_blanks() {
+        assert_eq!(
+            split_to_vec("   a  b ", &FieldSeparator::Default),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            split_to_vec("\ta\t \tb\nc\n", &FieldSeparator::Default),
+            vec!["a", "b", "c"]
+        );
+        assert!(split_to_vec(" \t \n", &FieldSeparator::Default).is_empty());
+    }
+
+    #[test]
+    fn test_split_record_with_default_fs_only_splits_on_blanks_and_newlines() {
+        assert_eq!(
+            split_to_vec("a\rb\x0Cc d", &FieldSeparator::Default),
+            vec!["a\rb\x0Cc", "d"]
+        );
+        assert_eq!(
+            split_to_vec("żółw  łoś", &FieldSeparator::Default),
+            vec!["żółw", "łoś"]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_split_record_with_default_fs_matches_regex_splitting() {
+        // compare the fast path against the equivalent regex over a large generated record
+        let mut record = String::from("  ");
+        for i in 0..20_000 {
+            record.push_str(&format!("field{}", i));
+            record.push_str(match i % 4 {
+                0 => " ",
+                1 => "\t\t",
+                2 => " \t \n",
+                _ => "   ",
+            });
+        }
+        let regex_separator = FieldSeparator::Ere(regex_from_str("[ \t\n]+"));
+        let expected = split_to_vec(record.trim_matches([' ', '\t', '\n']), &regex_separator);
+        let start = std::time::Instant::now();
+        let fields = split_to_vec(&record, &FieldSeparator::Default);
+        let fast_path_time = start.elapsed();
+        let start = std::time::Instant::now();
+        split_to_vec(&record, &regex_separator);
+        let regex_time = start.elapsed();
+        assert_eq!(fields.len(), 20_000);
+        assert_eq!(fields, expected);
+        eprintln!("default FS: {fast_path_time:?}, regex FS: {regex_time:?}");
+    }
+
+    #[test]
+    fn test_split_record_marks_each_numeric_field() {
+        let mut fields = Vec::new();
+        split_record(
+            "10 abc 1e3x +2.5".into(),
+            &FieldSeparator::Default,
+            |_, s| {
+                fields.push(s.is_numeric);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(fields, vec![true, false, false, true]);
+    }
+
+    #[test]
+    fn test_looks_numeric() {
+        for s in ["10", "-0", " +1.5e3 ", ".5", "5.", "1E-2", "\t42\n"] {
+            assert!(looks_numeric(s.as_bytes()), "{:?} should look numeric", s);
+        }
+        for s in [
+            "", " ", ".", "e5", "1e", "1x", "0x1A", "--1", "1 2", "inf", "+",
+        ] {
+            assert!(
+                !looks_numeric(s.as_bytes()),
+                "{:?} should not look numeric",
+                s
+            );
+        }
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn test_builtin_sub() {
@@ -3157,7 +4354,7 @@ mod tests {
         ];
         let constants = vec![
             Constant::from("aaabbbabaabb"),
-            Constant::Regex(Rc::from(regex_from_str("ab+"))),
+            Constant::Regex(regex_from_str("ab+")),
             Constant::from("x"),
         ];
 
@@ -3178,21 +4375,27 @@ mod tests {
                 argc: 2,
             },
         ];
-        let constants = vec![
-            Constant::Regex(Rc::from(regex_from_str("ab+"))),
-            Constant::from("x"),
-        ];
+        let constants = vec![Constant::Regex(regex_from_str("ab+")), Constant::from("x")];
         let mut record = Test::new(instructions, constants)
             .add_record("aaabbb ab aabb")
             .run_correct()
             .record;
         assert_eq!(
-            *record.fields[0].get_mut(),
+            *record.fields.get_mut()[0].get_mut(),
             AwkValue::field_ref("aax ab aabb", 0)
         );
-        assert_eq!(*record.fields[1].get_mut(), AwkValue::field_ref("aax", 1));
-        assert_eq!(*record.fields[2].get_mut(), AwkValue::field_ref("ab", 2));
-        assert_eq!(*record.fields[3].get_mut(), AwkValue::field_ref("aabb", 3));
+        assert_eq!(
+            *record.fields.get_mut()[1].get_mut(),
+            AwkValue::field_ref("aax", 1)
+        );
+        assert_eq!(
+            *record.fields.get_mut()[2].get_mut(),
+            AwkValue::field_ref("ab", 2)
+        );
+        assert_eq!(
+            *record.fields.get_mut()[3].get_mut(),
+            AwkValue::field_ref("aabb", 3)
+        );
     }
 
     #[test]
@@ -3212,7 +4415,7 @@ mod tests {
         ];
         let constants = vec![
             Constant::from("aaabbbabaabb"),
-            Constant::Regex(Rc::from(regex_from_str("ab+"))),
+            Constant::Regex(regex_from_str("ab+")),
             Constant::from("x"),
         ];
 
@@ -3220,6 +4423,31 @@ mod tests {
         assert_eq!(global, "aaxxax".into());
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_builtin_gsub_with_empty_matches() {
+        let instructions = vec![
+            OpCode::GlobalScalarRef(FIRST_GLOBAL_VAR),
+            OpCode::PushConstant(0),
+            OpCode::Assign,
+            OpCode::GlobalScalarRef(FIRST_GLOBAL_VAR),
+            OpCode::PushConstant(1),
+            OpCode::PushConstant(2),
+            OpCode::CallBuiltin {
+                function: BuiltinFunction::Gsub,
+                argc: 3,
+            },
+        ];
+        let constants = vec![
+            Constant::from("abxxc"),
+            Constant::Regex(regex_from_str("x*")),
+            Constant::from("-"),
+        ];
+
+        let global = test_global(instructions, constants);
+        assert_eq!(global, "-a-b-c-".into());
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn test_builtin_gsub_on_record() {
@@ -3233,21 +4461,27 @@ mod tests {
                 argc: 2,
             },
         ];
-        let constants = vec![
-            Constant::Regex(Rc::from(regex_from_str("ab+"))),
-            Constant::from("x"),
-        ];
+        let constants = vec![Constant::Regex(regex_from_str("ab+")), Constant::from("x")];
         let mut record = Test::new(instructions, constants)
             .add_record("aaabbb ab aabb")
             .run_correct()
             .record;
         assert_eq!(
-            *record.fields[0].get_mut(),
+            *record.fields.get_mut()[0].get_mut(),
             AwkValue::field_ref("aax x ax", 0)
         );
-        assert_eq!(*record.fields[1].get_mut(), AwkValue::field_ref("aax", 1));
-        assert_eq!(*record.fields[2].get_mut(), AwkValue::field_ref("x", 2));
-        assert_eq!(*record.fields[3].get_mut(), AwkValue::field_ref("ax", 3));
+        assert_eq!(
+            *record.fields.get_mut()[1].get_mut(),
+            AwkValue::field_ref("aax", 1)
+        );
+        assert_eq!(
+            *record.fields.get_mut()[2].get_mut(),
+            AwkValue::field_ref("x", 2)
+        );
+        assert_eq!(
+            *record.fields.get_mut()[3].get_mut(),
+            AwkValue::field_ref("ax", 3)
+        );
     }
 
     #[test]
@@ -3386,13 +4620,13 @@ mod tests {
     #[cfg_attr(miri, ignore)]
     fn test_ere_outside_match_matches_record() {
         let instructions = vec![OpCode::PushConstant(0)];
-        let constants = vec![Constant::Regex(Rc::new(regex_from_str("test")))];
+        let constants = vec![Constant::Regex(regex_from_str("test"))];
         let result = Test::new(instructions, constants)
             .add_record("this is a test")
             .run_correct();
         let value = result.execution_result.unwrap_expr();
         assert_eq!(value.scalar_as_f64(), 1.0);
-        assert_eq!(value.scalar_to_string("").unwrap(), "1".to_string().into());
+        assert_eq!(value.scalar_to_string(b"").unwrap(), "1".to_string().into());
     }
 
     #[test]
@@ -3499,6 +4733,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_record_is_split_and_rebuilt_only_when_outdated() {
+        let mut record = Record::default();
+        record
+            .reset(
+                b"a b c".to_vec(),
+                &FieldSeparator::Default,
+                &RecordSeparator::Char(b'\n'),
+            )
+            .expect("could not reset record");
+        assert_eq!(record.get_last_field(), 0);
+        unsafe {
+            assert!(record.split_if_outdated().unwrap());
+            assert!(!record.split_if_outdated().unwrap());
+            assert_eq!(record.get_last_field(), 3);
+
+            *record.field(2) = AwkValue::field_ref("x", 2);
+            record.field_changed(2).unwrap();
+            assert_eq!(record.record.borrow().as_bytes(), b"a b c");
+            record.rebuild_if_outdated(&GlobalEnv::default()).unwrap();
+            assert_eq!(record.record.borrow().as_bytes(), b"a x c");
+            assert_eq!(*record.field(0), AwkValue::field_ref("a x c", 0));
+        }
+    }
+
     #[test]
     fn test_changing_a_field_recomputes_the_record() {
         let instructions = vec![
@@ -3514,11 +4773,17 @@ mod tests {
             .run_correct()
             .record;
         assert_eq!(
-            *record.fields[0].get_mut(),
+            *record.fields.get_mut()[0].get_mut(),
             AwkValue::field_ref("test b", 0)
         );
-        assert_eq!(*record.fields[1].get_mut(), AwkValue::field_ref("test", 1));
-        assert_eq!(*record.fields[2].get_mut(), AwkValue::field_ref("b", 2));
+        assert_eq!(
+            *record.fields.get_mut()[1].get_mut(),
+            AwkValue::field_ref("test", 1)
+        );
+        assert_eq!(
+            *record.fields.get_mut()[2].get_mut(),
+            AwkValue::field_ref("b", 2)
+        );
     }
 
     #[test]
@@ -3536,11 +4801,11 @@ mod tests {
             .run_correct();
         assert_eq!(*result.record.last_field.get_mut(), 1);
         assert_eq!(
-            *result.record.fields[0].get_mut(),
+            *result.record.fields.get_mut()[0].get_mut(),
             AwkValue::field_ref("test", 0)
         );
         assert_eq!(
-            *result.record.fields[1].get_mut(),
+            *result.record.fields.get_mut()[1].get_mut(),
             AwkValue::field_ref("test", 1)
         );
         assert_eq!(
@@ -3642,7 +4907,7 @@ mod tests {
         ];
         let mut result = Test::new(instructions, constants).run_correct();
         assert_eq!(
-            *result.record.fields[1].get_mut(),
+            *result.record.fields.get_mut()[1].get_mut(),
             AwkValue::field_ref("test4", 1)
         );
     }
@@ -3701,15 +4966,15 @@ mod tests {
             .run_correct();
         assert_eq!(*result.record.last_field.get_mut(), 1);
         assert_eq!(
-            *result.record.fields[0].get_mut(),
+            *result.record.fields.get_mut()[0].get_mut(),
             AwkValue::field_ref(maybe_numeric_string("1"), 0)
         );
         assert_eq!(
-            *result.record.fields[1].get_mut(),
+            *result.record.fields.get_mut()[1].get_mut(),
             AwkValue::field_ref(maybe_numeric_string("1"), 1)
         );
         assert_eq!(
-            *result.record.fields[2].get_mut(),
+            *result.record.fields.get_mut()[2].get_mut(),
             AwkValue::field_ref(AwkValue::uninitialized_scalar(), 2)
         );
 
@@ -3724,7 +4989,7 @@ mod tests {
             .run_correct();
         assert_eq!(*result.record.last_field.get_mut(), 10);
         assert_eq!(
-            *result.record.fields[0].get_mut(),
+            *result.record.fields.get_mut()[0].get_mut(),
             AwkValue::field_ref(maybe_numeric_string("1 2 3 4      "), 0)
         );
     }
@@ -3756,4 +5021,24 @@ mod tests {
         let result = Test::new(instructions, constants).run_correct();
         assert_eq!(result.execution_result.unwrap_expr(), AwkValue::from(42.0));
     }
+
+    #[test]
+    fn test_srand_returns_a_fractional_previous_seed() {
+        let constants = vec![Constant::from(2.5)];
+        let instructions = vec![
+            OpCode::PushConstant(0),
+            OpCode::CallBuiltin {
+                function: BuiltinFunction::Srand,
+                argc: 1,
+            },
+            OpCode::Pop,
+            OpCode::PushConstant(0),
+            OpCode::CallBuiltin {
+                function: BuiltinFunction::Srand,
+                argc: 1,
+            },
+        ];
+        let result = Test::new(instructions, constants).run_correct();
+        assert_eq!(result.execution_result.unwrap_expr(), AwkValue::from(2.5));
+    }
 }
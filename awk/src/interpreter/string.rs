@@ -7,14 +7,111 @@
 // SPDX-License-Identifier: MIT
 //
 
+//! Strings of the awk interpreter.
+//!
+//! The string builtins that count positions and lengths, `length`, `index`,
+//! `substr`, `match` and the `%c` and `%s` conversions of `printf`, follow
+//! gawk: in a UTF-8 locale they count characters, in any other locale,
+//! including the default C locale, they count bytes. They all convert
+//! positions with [`CharacterSet`], so they cannot disagree.
+//!
+//! Regexes are matched by the C library in the same locale, so they match
+//! characters where the builtins count them. Strings are stored as bytes,
+//! which are never decoded, so the input is passed through unchanged in any
+//! locale: `substr("é", 1, 1)` in the C locale is the first byte of 'é', and
+//! concatenating it with `substr("é", 2)` gives back the whole character.
+
 use core::fmt;
-use std::{ffi::CString, ops::Deref, rc::Rc};
+use std::{
+    borrow::Cow,
+    ffi::{CStr, CString},
+    ops::{Deref, Range},
+    rc::Rc,
+};
+
+/// The unit in which the string builtins count, see the module documentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharacterSet {
+    SingleByte,
+    Utf8,
+}
+
+/// Returns whether a character starts at `index` of `string` decoded as UTF-8.
+/// The first byte always starts one, even if the string is not valid UTF-8 and
+/// it is a continuation byte.
+fn is_char_start(string: &[u8], index: usize) -> bool {
+    index == 0 || (string[index] & 0xC0) != 0x80
+}
+
+impl CharacterSet {
+    /// Returns the character set of the `LC_CTYPE` locale, which `main` sets
+    /// from the environment.
+    pub fn from_locale() -> Self {
+        // SAFETY: the returned string is only read before any other call to
+        // `nl_langinfo` or `setlocale`, and the interpreter is single threaded
+        let codeset = unsafe { CStr::from_ptr(libc::nl_langinfo(libc::CODESET)) };
+        Self::from_codeset(&codeset.to_string_lossy())
+    }
+
+    /// Returns the character set of a codeset name like the `UTF-8` of
+    /// `en_US.UTF-8`.
+    fn from_codeset(codeset: &str) -> Self {
+        if codeset.eq_ignore_ascii_case("UTF-8") || codeset.eq_ignore_ascii_case("UTF8") {
+            CharacterSet::Utf8
+        } else {
+            CharacterSet::SingleByte
+        }
+    }
+
+    /// Returns the position, counted from 0, of the character that contains the
+    /// byte at `byte_index` in `string`, or the number of characters of `string`
+    /// when `byte_index` is its length.
+    pub fn char_index(self, string: &[u8], byte_index: usize) -> usize {
+        match self {
+            CharacterSet::SingleByte => byte_index,
+            CharacterSet::Utf8 => {
+                let char_starts = (0..byte_index)
+                    .filter(|&i| is_char_start(string, i))
+                    .count();
+                if byte_index == string.len() || is_char_start(string, byte_index) {
+                    char_starts
+                } else {
+                    char_starts - 1
+                }
+            }
+        }
+    }
+
+    /// Returns the number of characters of `string`.
+    pub fn char_len(self, string: &[u8]) -> usize {
+        self.char_index(string, string.len())
+    }
+
+    /// Returns the byte offset in `string` of the character at position
+    /// `char_index`, counted from 0, or the length of `string` when it has fewer
+    /// characters.
+    pub fn byte_index(self, string: &[u8], char_index: usize) -> usize {
+        match self {
+            CharacterSet::SingleByte => char_index.min(string.len()),
+            CharacterSet::Utf8 => (0..string.len())
+                .filter(|&i| is_char_start(string, i))
+                .nth(char_index)
+                .unwrap_or(string.len()),
+        }
+    }
+}
 
 #[cfg_attr(test, derive(Debug))]
 #[derive(Clone, PartialEq)]
 enum AwkStringVariant {
-    Owned(String),
-    Shared(Rc<str>),
+    Owned(Vec<u8>),
+    Shared(Rc<[u8]>),
+    /// A part of a shared string, like a field of the record. The contents
+    /// are only copied if the string is modified.
+    Slice {
+        string: Rc<[u8]>,
+        range: Range<usize>,
+    },
 }
 
 #[cfg_attr(test, derive(Debug))]
@@ -25,27 +122,63 @@ pub struct AwkString {
 }
 
 impl AwkString {
-    pub fn as_str(&self) -> &str {
+    pub fn as_bytes(&self) -> &[u8] {
         match &self.value {
             AwkStringVariant::Owned(value) => value,
             AwkStringVariant::Shared(value) => value,
+            AwkStringVariant::Slice { string, range } => &string[range.clone()],
         }
     }
 
+    /// Returns the string decoded as UTF-8, for the places where it names
+    /// something, like a file, rather than being data.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.as_bytes())
+    }
+
     pub fn concat(&mut self, other: &AwkString) {
-        match &mut self.value {
-            AwkStringVariant::Owned(value) => value.push_str(other.as_str()),
-            AwkStringVariant::Shared(value) => {
-                let mut new_value = value.to_string();
-                new_value.push_str(other.as_str());
-                self.value = AwkStringVariant::Owned(new_value);
-            }
+        if let AwkStringVariant::Owned(value) = &mut self.value {
+            value.extend_from_slice(other.as_bytes());
+        } else {
+            let mut new_value = Vec::with_capacity(self.len() + other.len());
+            new_value.extend_from_slice(self.as_bytes());
+            new_value.extend_from_slice(other.as_bytes());
+            self.value = AwkStringVariant::Owned(new_value);
         }
         self.is_numeric = false;
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.as_str().is_empty()
+    /// Returns the part of the string in the byte range `range`. The result shares
+    /// the contents of the string.
+    pub fn substring(&self, range: Range<usize>) -> AwkString {
+        // slicing checks the range, so `as_bytes` cannot fail on the result
+        let contents = &self.as_bytes()[range.clone()];
+        let value = match &self.value {
+            AwkStringVariant::Owned(_) => AwkStringVariant::Shared(contents.into()),
+            AwkStringVariant::Shared(string) => AwkStringVariant::Slice {
+                string: string.clone(),
+                range,
+            },
+            AwkStringVariant::Slice {
+                string,
+                range: outer,
+            } => AwkStringVariant::Slice {
+                string: string.clone(),
+                range: outer.start + range.start..outer.start + range.end,
+            },
+        };
+        AwkString {
+            value,
+            is_numeric: false,
+        }
+    }
+
+    /// Copies the contents of a string that is a part of a larger one, so that
+    /// storing it does not keep the larger string alive.
+    pub fn detach(&mut self) {
+        if let AwkStringVariant::Slice { string, range } = &self.value {
+            self.value = AwkStringVariant::Shared(string[range.clone()].into());
+        }
     }
 
     pub fn numeric_string<V: Into<AwkString>>(val: V) -> AwkString {
@@ -58,40 +191,40 @@ impl AwkString {
 impl Default for AwkString {
     fn default() -> Self {
         AwkString {
-            value: AwkStringVariant::Owned(String::new()),
+            value: AwkStringVariant::Owned(Vec::new()),
             is_numeric: false,
         }
     }
 }
 
-impl AsRef<str> for AwkString {
-    fn as_ref(&self) -> &str {
-        self.as_str()
+impl AsRef<[u8]> for AwkString {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
     }
 }
 
 impl Deref for AwkString {
-    type Target = str;
+    type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
-        self.as_str()
+        self.as_bytes()
     }
 }
 
 impl PartialEq for AwkString {
     fn eq(&self, other: &Self) -> bool {
-        self.as_str() == other.as_str() && self.is_numeric == other.is_numeric
+        self.as_bytes() == other.as_bytes() && self.is_numeric == other.is_numeric
     }
 }
 
 impl fmt::Display for AwkString {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.as_str())
+        write!(f, "{}", self.to_string_lossy())
     }
 }
 
-impl From<Rc<str>> for AwkString {
-    fn from(value: Rc<str>) -> Self {
+impl From<Rc<[u8]>> for AwkString {
+    fn from(value: Rc<[u8]>) -> Self {
         AwkString {
             value: AwkStringVariant::Shared(value),
             is_numeric: false,
@@ -99,8 +232,14 @@ impl From<Rc<str>> for AwkString {
     }
 }
 
-impl From<String> for AwkString {
-    fn from(value: String) -> Self {
+impl From<Rc<str>> for AwkString {
+    fn from(value: Rc<str>) -> Self {
+        Rc::<[u8]>::from(value).into()
+    }
+}
+
+impl From<Vec<u8>> for AwkString {
+    fn from(value: Vec<u8>) -> Self {
         AwkString {
             value: AwkStringVariant::Owned(value),
             is_numeric: false,
@@ -108,8 +247,14 @@ impl From<String> for AwkString {
     }
 }
 
-impl From<&str> for AwkString {
-    fn from(value: &str) -> Self {
+impl From<String> for AwkString {
+    fn from(value: String) -> Self {
+        value.into_bytes().into()
+    }
+}
+
+impl From<&[u8]> for AwkString {
+    fn from(value: &[u8]) -> Self {
         AwkString {
             value: AwkStringVariant::Shared(value.into()),
             is_numeric: false,
@@ -117,11 +262,19 @@ impl From<&str> for AwkString {
     }
 }
 
-impl From<AwkString> for Rc<str> {
+impl From<&str> for AwkString {
+    fn from(value: &str) -> Self {
+        value.as_bytes().into()
+    }
+}
+
+impl From<AwkString> for Rc<[u8]> {
     fn from(val: AwkString) -> Self {
         match val.value {
             AwkStringVariant::Owned(value) => value.into(),
             AwkStringVariant::Shared(value) => value,
+            AwkStringVariant::Slice { string, range } if range == (0..string.len()) => string,
+            AwkStringVariant::Slice { string, range } => string[range].into(),
         }
     }
 }
@@ -134,9 +287,99 @@ impl TryInto<CString> for AwkString {
             AwkStringVariant::Owned(value) => {
                 CString::new(value).map_err(|_| "invalid string".to_string())
             }
-            AwkStringVariant::Shared(value) => {
-                CString::new(value.as_bytes()).map_err(|_| "invalid string".to_string())
+            AwkStringVariant::Shared(_) | AwkStringVariant::Slice { .. } => {
+                CString::new(self.as_bytes()).map_err(|_| "invalid string".to_string())
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substring_of_substring() {
+        let string = AwkString::from("hello world");
+        let world = string.substring(6..11);
+        assert_eq!(world.as_bytes(), b"world");
+        assert_eq!(world.substring(1..3).as_bytes(), b"or");
+        assert_eq!(
+            AwkString::from("abc".to_string())
+                .substring(1..2)
+                .as_bytes(),
+            b"b"
+        );
+    }
+
+    #[test]
+    fn test_substring_splitting_a_character() {
+        // 'ż' is encoded as C5 BC
+        let string = AwkString::from("ażb");
+        let mut first = string.substring(0..2);
+        assert_eq!(first.as_bytes(), b"a\xC5");
+        assert_eq!(string.substring(2..4).as_bytes(), b"\xBCb");
+        first.concat(&string.substring(2..4));
+        assert_eq!(first.as_bytes(), "ażb".as_bytes());
+    }
+
+    #[test]
+    fn test_character_set_of_codeset() {
+        for codeset in ["UTF-8", "utf-8", "UTF8"] {
+            assert_eq!(CharacterSet::from_codeset(codeset), CharacterSet::Utf8);
+        }
+        // the codesets of the C locale on glibc and on macOS
+        for codeset in ["", "ANSI_X3.4-1968", "US-ASCII", "ISO-8859-1"] {
+            assert_eq!(
+                CharacterSet::from_codeset(codeset),
+                CharacterSet::SingleByte
+            );
+        }
+    }
+
+    #[test]
+    fn test_char_index_of_multibyte_characters() {
+        // 'é' is two bytes long, '€' is three bytes long
+        let string = "hé€x".as_bytes();
+        let utf8 = CharacterSet::Utf8;
+        let char_indices = (0..=string.len())
+            .map(|i| utf8.char_index(string, i))
+            .collect::<Vec<_>>();
+        assert_eq!(char_indices, [0, 1, 1, 2, 2, 2, 3, 4]);
+        assert_eq!(utf8.char_len(string), 4);
+        assert_eq!(CharacterSet::SingleByte.char_index(string, 5), 5);
+        assert_eq!(CharacterSet::SingleByte.char_len(string), 7);
+        // bytes that are not valid UTF-8 count as characters, except for
+        // continuation bytes after the first one
+        assert_eq!(utf8.char_len(b"\xA9a\xC3"), 3);
+        assert_eq!(utf8.char_len(b"a\xA9\xA9"), 1);
+    }
+
+    #[test]
+    fn test_byte_index_of_multibyte_characters() {
+        let string = "hé€x".as_bytes();
+        let utf8 = CharacterSet::Utf8;
+        let byte_indices = (0..=5)
+            .map(|i| utf8.byte_index(string, i))
+            .collect::<Vec<_>>();
+        assert_eq!(byte_indices, [0, 1, 3, 6, 7, 7]);
+        assert_eq!(CharacterSet::SingleByte.byte_index(string, 2), 2);
+        assert_eq!(CharacterSet::SingleByte.byte_index(string, 9), 7);
+        assert_eq!(utf8.byte_index(b"\xA9a", 1), 1);
+    }
+
+    #[test]
+    fn test_detached_substring_keeps_its_contents() {
+        let mut substring = AwkString::from("hello world").substring(0..5);
+        substring.detach();
+        assert_eq!(substring.as_bytes(), b"hello");
+        assert_eq!(Rc::<[u8]>::from(substring).as_ref(), b"hello");
+    }
+
+    #[test]
+    fn test_concat_to_substring() {
+        let mut substring = AwkString::from("hello world").substring(0..5);
+        substring.concat(&AwkString::from("!"));
+        assert_eq!(substring.as_bytes(), b"hello!");
+    }
+}
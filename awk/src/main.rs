@@ -44,18 +44,22 @@ struct Args {
     arguments: Vec<String>,
 }
 
-fn exit_if_error<T, U: Display>(r: Result<T, U>) -> T {
+fn exit_if_error<T, U: Display>(r: Result<T, U>, status: i32) -> T {
     match r {
         Ok(v) => v,
         Err(err) => {
             eprintln!("{err}");
-            std::process::exit(1);
+            std::process::exit(status);
         }
     }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     setlocale(LocaleCategory::LcAll, "");
+    // the character set of the locale decides what a character is for the string
+    // builtins and for regexes, which are matched by the C library
+    // SAFETY: no other thread is running yet
+    unsafe { libc::setlocale(libc::LC_CTYPE, c"".as_ptr()) };
     textdomain(env!("PROJECT_NAME"))?;
     bind_textdomain_codeset(env!("PROJECT_NAME"), "UTF-8")?;
 
@@ -74,23 +78,30 @@ fn main() -> Result<(), Box<dyn Error>> {
                 filename: source_file.clone(),
             });
         }
-        let program = exit_if_error(compile_program(&sources));
-        exit_if_error(interpret(
-            program,
-            &args.arguments,
-            &args.assignments,
-            args.separator_string,
-        ))
+        let program = exit_if_error(compile_program(&sources), 2);
+        exit_if_error(
+            interpret(
+                program,
+                &args.arguments,
+                &args.assignments,
+                args.separator_string,
+            ),
+            2,
+        )
     } else if !args.arguments.is_empty() {
-        let program = exit_if_error(compile_program(&[SourceFile::stdin(
-            args.arguments[0].clone(),
-        )]));
-        exit_if_error(interpret(
-            program,
-            &args.arguments[1..],
-            &args.assignments,
-            args.separator_string,
-        ))
+        let program = exit_if_error(
+            compile_program(&[SourceFile::stdin(args.arguments[0].clone())]),
+            2,
+        );
+        exit_if_error(
+            interpret(
+                program,
+                &args.arguments[1..],
+                &args.assignments,
+                args.separator_string,
+            ),
+            2,
+        )
     } else {
         eprintln!("{}", gettext("missing program argument"));
         1
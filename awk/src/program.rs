@@ -46,6 +46,8 @@ pub enum OpCode {
     CreateGlobalIterator(u32),
     CreateLocalIterator(u32),
     AdvanceIterOrJump(i32),
+    // pop the iterator on top of the stack before it reached the end of the array
+    DropIterator,
 
     AsNumber,
     // push the value on top of the stack
@@ -101,7 +103,10 @@ pub enum OpCode {
 
     Next,
     NextFile,
+    // exit with the status on top of the stack
     Exit,
+    // exit with the status of a previous `exit`, or 0 if there was none
+    ExitWithoutStatus,
     Return,
 
     // invalid opcode. Cannot be inside a valid program
@@ -113,7 +118,7 @@ pub enum OpCode {
 pub enum Constant {
     Number(f64),
     String(Rc<str>),
-    Regex(Rc<Regex>),
+    Regex(Regex),
 }
 
 impl From<&str> for Constant {
@@ -128,8 +133,8 @@ impl From<f64> for Constant {
     }
 }
 
-impl From<Rc<Regex>> for Constant {
-    fn from(value: Rc<Regex>) -> Self {
+impl From<Regex> for Constant {
+    fn from(value: Regex) -> Self {
         Self::Regex(value)
     }
 }
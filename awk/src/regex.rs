@@ -7,134 +7,311 @@
 // SPDX-License-Identifier: MIT
 //
 
+use std::collections::HashMap;
 use std::ffi::CString;
-use std::ptr;
-
-fn regex_compilation_result(
-    status_integer: libc::c_int,
-    regex: &libc::regex_t,
-) -> Result<(), String> {
-    if status_integer != 0 {
-        let mut error_buffer = vec![b'\0'; 128];
-        unsafe {
-            libc::regerror(
-                status_integer,
-                ptr::from_ref(regex),
-                error_buffer.as_mut_ptr() as *mut libc::c_char,
-                128,
-            )
-        };
-        let error = CString::from_vec_with_nul(error_buffer)
-            .expect("error message returned from `libc::regerror` is an invalid CString");
-        Err(error
-            .into_string()
-            .expect("error message from `libc::regerror' contains invalid utf-8"))
-    } else {
-        Ok(())
-    }
-}
+use std::ops::Range;
 
-pub struct Regex {
-    raw_regex: libc::regex_t,
-    regex_string: CString,
-}
+pub use plib::regex::MatchIter;
+
+/// A compiled regex, with the operations of the awk language on top of the
+/// ones of [`plib::regex::Regex`].
+#[derive(Clone, PartialEq)]
+pub struct Regex(plib::regex::Regex);
 
-#[cfg_attr(test, derive(Debug))]
-#[derive(Copy, Clone, Default, PartialEq, Eq)]
-pub struct RegexMatch {
-    pub start: usize,
-    pub end: usize,
+enum ReplacementPart {
+    Literal(Vec<u8>),
+    MatchedText,
 }
 
-pub struct MatchIter<'re> {
-    string: CString,
-    next_start: usize,
-    regex: &'re Regex,
+fn parse_replacement(replacement: &[u8]) -> Vec<ReplacementPart> {
+    let mut parts = Vec::new();
+    let mut literal = Vec::new();
+    let mut bytes = replacement.iter().copied();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'\\' => match bytes.next() {
+                Some(escaped @ (b'\\' | b'&')) => literal.push(escaped),
+                Some(other) => {
+                    literal.push(b'\\');
+                    literal.push(other);
+                }
+                None => literal.push(b'\\'),
+            },
+            b'&' => {
+                if !literal.is_empty() {
+                    parts.push(ReplacementPart::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(ReplacementPart::MatchedText);
+            }
+            other => literal.push(other),
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(ReplacementPart::Literal(literal));
+    }
+    parts
 }
 
-impl Iterator for MatchIter<'_> {
-    type Item = RegexMatch;
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.next_start >= self.string.as_bytes().len() {
-            return None;
+/// Translates the escape sequences that awk recognizes in EREs into the characters
+/// they stand for, so that the result can be compiled by `regcomp`.
+///
+/// The same translation is used for regex literals and for strings used as
+/// regexes, so that `/\./` and `"\\."` both match a literal dot. Following gawk:
+/// - `\/`, `\"`, `\a`, `\b`, `\f`, `\n`, `\r`, `\t`, `\v` and octal sequences
+///   (`\ddd`) are replaced by the character they represent. An octal sequence
+///   stands for a single byte, so `/\303\251/` matches the UTF-8 encoding of 'é'.
+///   Outside of bracket expressions, a character that is special in EREs is
+///   escaped, so `/\056/` matches a literal dot. Inside of them no character is
+///   special, so `/[\056]/` only matches a dot
+/// - any other escape sequence (`\.`, `\\`, `\(`, ...) is passed to `regcomp` unchanged.
+///   Inside of bracket expressions, where a backslash stands for itself, this
+///   means that `/[\.]/` matches a backslash or a dot
+/// - a trailing backslash is passed to `regcomp`, which reports it as an error
+fn translate_awk_ere(ere: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(ere.len());
+    let mut index = 0;
+    let mut in_brackets = false;
+    while index < ere.len() {
+        let b = ere[index];
+        index += 1;
+        match b {
+            b'[' if !in_brackets => {
+                result.push(b'[');
+                in_brackets = true;
+                // a `]` at the start of the list, after an optional `^`, is a
+                // literal and does not end the bracket expression
+                if ere.get(index) == Some(&b'^') {
+                    result.push(b'^');
+                    index += 1;
+                }
+                if ere.get(index) == Some(&b']') {
+                    result.push(b']');
+                    index += 1;
+                }
+                continue;
+            }
+            b'[' if matches!(ere.get(index), Some(b':' | b'=' | b'.')) => {
+                // `[:alpha:]`, `[=a=]` and `[.a.]` are copied up to their own `]`
+                let rest = &ere[index..];
+                let terminator = [rest[0], b']'];
+                let len = rest
+                    .windows(2)
+                    .skip(1)
+                    .position(|window| window == terminator)
+                    .map_or(0, |position| position + 3);
+                result.push(b'[');
+                result.extend_from_slice(&rest[..len]);
+                index += len;
+                continue;
+            }
+            b']' if in_brackets => {
+                result.push(b']');
+                in_brackets = false;
+                continue;
+            }
+            b'\\' => {}
+            other => {
+                result.push(other);
+                continue;
+            }
         }
-        let mut match_range = libc::regmatch_t {
-            rm_so: -1,
-            rm_eo: -1,
-        };
-        let exec_status = unsafe {
-            libc::regexec(
-                ptr::from_ref(&self.regex.raw_regex),
-                self.string.as_ptr().add(self.next_start),
-                1,
-                ptr::from_mut(&mut match_range),
-                0,
-            )
+        let translated = match ere.get(index) {
+            Some(b'/') => b'/',
+            Some(b'"') => b'"',
+            Some(b'a') => b'\x07',
+            Some(b'b') => b'\x08',
+            Some(b'f') => b'\x0C',
+            Some(b'n') => b'\n',
+            Some(b'r') => b'\r',
+            Some(b't') => b'\t',
+            Some(b'v') => b'\x0B',
+            Some(b'0'..=b'7') => {
+                let digits = ere[index..]
+                    .iter()
+                    .take(3)
+                    .take_while(|b| (b'0'..=b'7').contains(b))
+                    .count();
+                // like in C, the value is truncated to a byte
+                let code = ere[index..index + digits]
+                    .iter()
+                    .fold(0u32, |code, digit| code * 8 + u32::from(digit - b'0'))
+                    as u8;
+                // NUL cannot be part of the pattern passed to `regcomp`
+                if code == 0 {
+                    result.push(b'\\');
+                    continue;
+                }
+                index += digits - 1;
+                code
+            }
+            Some(&other) if !in_brackets => {
+                result.push(b'\\');
+                result.push(other);
+                index += 1;
+                continue;
+            }
+            // in a bracket expression the backslash is a literal, and the next
+            // character keeps its meaning, e.g. `]` ends the expression
+            Some(_) | None => {
+                result.push(b'\\');
+                continue;
+            }
         };
-        if exec_status == libc::REG_NOMATCH {
-            return None;
+        index += 1;
+        if !in_brackets && b"\\^$.[]|()*+?{}".contains(&translated) {
+            result.push(b'\\');
         }
-        let result = RegexMatch {
-            start: self.next_start + match_range.rm_so as usize,
-            end: self.next_start + match_range.rm_eo as usize,
-        };
-        self.next_start += match_range.rm_eo as usize;
-        Some(result)
+        result.push(translated);
     }
+    result
 }
 
 impl Regex {
     pub fn new(regex: CString) -> Result<Self, String> {
-        let mut raw = unsafe { std::mem::zeroed::<libc::regex_t>() };
-        let compilation_status =
-            unsafe { libc::regcomp(ptr::from_mut(&mut raw), regex.as_ptr(), libc::REG_EXTENDED) };
-        regex_compilation_result(compilation_status, &raw)?;
-        Ok(Self {
-            raw_regex: raw,
-            regex_string: regex,
-        })
-    }
-
-    pub fn match_locations(&self, string: CString) -> MatchIter {
-        MatchIter {
-            next_start: 0,
-            regex: self,
-            string,
-        }
+        plib::regex::Regex::new(regex).map(Self)
     }
 
-    pub fn matches(&self, string: &CString) -> bool {
-        let exec_status = unsafe {
-            libc::regexec(
-                ptr::from_ref(&self.raw_regex),
-                string.as_ptr(),
-                0,
-                ptr::null_mut(),
-                0,
-            )
-        };
-        exec_status != libc::REG_NOMATCH
+    /// Compiles an ERE written in awk syntax, see `translate_awk_ere`.
+    pub fn from_awk_ere<E: AsRef<[u8]>>(ere: E) -> Result<Self, String> {
+        let ere = CString::new(translate_awk_ere(ere.as_ref()))
+            .map_err(|_| "invalid string".to_string())?;
+        Self::new(ere)
+    }
+
+    /// Returns an iterator over all non overlapping matches in `string`.
+    pub fn match_locations_bytes<'s>(&self, string: &'s [u8]) -> MatchIter<'_, 's> {
+        self.0.match_locations_bytes(string)
     }
-}
 
-impl Drop for Regex {
-    fn drop(&mut self) {
-        unsafe {
-            libc::regfree(ptr::from_mut(&mut self.raw_regex));
+    /// Returns the byte ranges of the fields of `string` separated by matches of the
+    /// regex.
+    ///
+    /// A match at the start or at the end of the string produces an empty first or
+    /// last field, except for empty matches, which only separate characters.
+    /// An empty string has no fields.
+    pub fn split_locations(&self, string: &[u8]) -> Vec<Range<usize>> {
+        if string.is_empty() {
+            return Vec::new();
         }
+        let mut fields = Vec::new();
+        let mut field_start = 0;
+        for separator in self.match_locations_bytes(string) {
+            if separator.start == separator.end
+                && (separator.start == 0 || separator.start == string.len())
+            {
+                continue;
+            }
+            fields.push(field_start..separator.start);
+            field_start = separator.end;
+        }
+        fields.push(field_start..string.len());
+        fields
+    }
+
+    /// Replaces the matches of the regex in `subject` with `replacement`, following
+    /// the rules of the awk `sub` and `gsub` functions: `&` stands for the matched
+    /// text, `\&` for a literal ampersand and `\\` for a literal backslash.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject` - the string where matches are replaced
+    /// * `replacement` - the replacement for each match
+    /// * `max` - the maximum number of replacements, or `None` to replace all matches
+    ///
+    /// # Returns
+    ///
+    /// The resulting string and the number of replacements made.
+    pub fn replace_all(
+        &self,
+        subject: &[u8],
+        replacement: &[u8],
+        max: Option<usize>,
+    ) -> (Vec<u8>, usize) {
+        let replacement = parse_replacement(replacement);
+        let mut result = Vec::with_capacity(subject.len());
+        let mut last_match_end = 0;
+        let mut count = 0;
+        for m in self.match_locations_bytes(subject) {
+            if max.is_some_and(|max| count >= max) {
+                break;
+            }
+            result.extend_from_slice(&subject[last_match_end..m.start]);
+            for part in &replacement {
+                match part {
+                    ReplacementPart::Literal(literal) => result.extend_from_slice(literal),
+                    ReplacementPart::MatchedText => {
+                        result.extend_from_slice(&subject[m.start..m.end])
+                    }
+                }
+            }
+            last_match_end = m.end;
+            count += 1;
+        }
+        result.extend_from_slice(&subject[last_match_end..]);
+        (result, count)
+    }
+
+    /// Returns whether `string` contains a match for the regex.
+    pub fn matches_bytes(&self, string: &[u8]) -> bool {
+        self.0.matches_bytes(string)
     }
 }
 
 #[cfg(test)]
 impl core::fmt::Debug for Regex {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        writeln!(f, "/{}/", self.regex_string.to_str().unwrap())
+        self.0.fmt(f)
     }
 }
 
-impl PartialEq for Regex {
-    fn eq(&self, other: &Self) -> bool {
-        self.regex_string == other.regex_string
+/// Cache for regexes compiled from strings at runtime.
+///
+/// Holds at most `capacity` regexes, evicting the least recently used one when full.
+pub struct RegexCache {
+    capacity: usize,
+    regexes: HashMap<Vec<u8>, (Regex, u64)>,
+    time: u64,
+}
+
+impl RegexCache {
+    pub const DEFAULT_CAPACITY: usize = 64;
+
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            regexes: HashMap::with_capacity(capacity),
+            time: 0,
+        }
+    }
+
+    /// Returns the compiled regex for `source`, compiling it only if it is not
+    /// already in the cache.
+    pub fn get(&mut self, source: &[u8]) -> Result<Regex, String> {
+        self.time += 1;
+        if let Some((regex, last_used)) = self.regexes.get_mut(source) {
+            *last_used = self.time;
+            return Ok(regex.clone());
+        }
+        let regex = Regex::from_awk_ere(source)?;
+        if self.regexes.len() >= self.capacity {
+            let least_recently_used = self
+                .regexes
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = least_recently_used {
+                self.regexes.remove(&key);
+            }
+        }
+        self.regexes
+            .insert(source.to_vec(), (regex.clone(), self.time));
+        Ok(regex)
+    }
+}
+
+impl Default for RegexCache {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
     }
 }
 
@@ -150,26 +327,234 @@ mod tests {
 
     #[test]
     #[cfg_attr(miri, ignore)]
-    fn test_create_regex() {
-        regex_from_str("test");
+    fn test_regex_cache_hit_returns_the_same_regex() {
+        let mut cache = RegexCache::default();
+        let first = cache.get(b"x.y").unwrap();
+        let second = cache.get(b"x.y").unwrap();
+        assert!(first.0.same(&second.0));
+        assert_eq!(first.matches_bytes(b"axby"), second.matches_bytes(b"axby"));
+        assert!(!second.matches_bytes(b"xy"));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_cache_evicts_least_recently_used() {
+        let mut cache = RegexCache::new(2);
+        let a = cache.get(b"a").unwrap();
+        let b = cache.get(b"b").unwrap();
+        // "a" is now more recently used than "b"
+        cache.get(b"a").unwrap();
+        cache.get(b"c").unwrap();
+        assert_eq!(cache.regexes.len(), 2);
+        assert!(a.0.same(&cache.get(b"a").unwrap().0));
+        let new_b = cache.get(b"b").unwrap();
+        assert!(!b.0.same(&new_b.0));
+        assert!(new_b.matches_bytes(b"abc"));
+    }
+
+    #[test]
+    fn test_translate_awk_ere() {
+        assert_eq!(translate_awk_ere(br"a\/b"), b"a/b");
+        assert_eq!(
+            translate_awk_ere(br#"\t\n\r\v\f\a\b\""#),
+            b"\t\n\r\x0B\x0C\x07\x08\""
+        );
+        assert_eq!(translate_awk_ere(br"\41\0x"), br"!\0x");
+        assert_eq!(translate_awk_ere(br"a\056b\1411"), br"a\.ba1");
+        assert_eq!(translate_awk_ere(br"\.\\t\(\+"), br"\.\\t\(\+");
+        assert_eq!(translate_awk_ere(br"a\"), br"a\");
+        assert_eq!(translate_awk_ere("[\\t ]é".as_bytes()), "[\t ]é".as_bytes());
+    }
+
+    #[test]
+    fn test_translate_awk_ere_octal_bytes() {
+        assert_eq!(translate_awk_ere(br"\303\251"), "é".as_bytes());
+        assert_eq!(translate_awk_ere(br"\377x"), b"\xFFx");
+        assert_eq!(translate_awk_ere(br"[\351]"), b"[\xE9]");
+    }
+
+    #[test]
+    fn test_translate_awk_ere_in_brackets() {
+        assert_eq!(translate_awk_ere(br"[\056]\056"), br"[.]\.");
+        assert_eq!(translate_awk_ere(br"[^]\050]\051"), br"[^](]\)");
+        assert_eq!(translate_awk_ere(br"[]a]\056"), br"[]a]\.");
+        assert_eq!(
+            translate_awk_ere(br"[[:alpha:]\056[.-.]]\056"),
+            br"[[:alpha:].[.-.]]\."
+        );
+        // the backslash is a literal in brackets, so `\]` ends the expression
+        assert_eq!(translate_awk_ere(br"[\]\056"), br"[\]\.");
+        assert_eq!(translate_awk_ere(br"[\\]\056"), br"[\\]\.");
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_from_awk_ere() {
+        let ere = Regex::from_awk_ere(r"a\tb").unwrap();
+        assert!(ere.matches_bytes(b"a\tb"));
+        assert!(!ere.matches_bytes(b"atb"));
+        let ere = Regex::from_awk_ere(r"a\056b").unwrap();
+        assert!(ere.matches_bytes(b"a.b"));
+        assert!(!ere.matches_bytes(b"axb"));
+        assert!(Regex::from_awk_ere(r"a\").is_err());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_from_awk_ere_in_brackets() {
+        let ere = Regex::from_awk_ere(r"[\056]").unwrap();
+        assert!(ere.matches_bytes(b"."));
+        assert!(!ere.matches_bytes(b"\\"));
+        let ere = Regex::from_awk_ere(r"^\303\251$").unwrap();
+        assert!(ere.matches_bytes("é".as_bytes()));
+        assert!(!ere.matches_bytes("Ã©".as_bytes()));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_cache_reports_compilation_errors() {
+        let mut cache = RegexCache::default();
+        assert!(cache.get(b"(").is_err());
+        assert!(cache.regexes.is_empty());
+    }
+
+    fn split_str<'s>(ere: &Regex, s: &'s str) -> Vec<&'s str> {
+        ere.split_locations(s.as_bytes())
+            .into_iter()
+            .map(|range| &s[range])
+            .collect()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_split_locations_leading_and_trailing_separators() {
+        let ere = regex_from_str(":");
+        assert_eq!(
+            split_str(&ere, "::a::b::"),
+            vec!["", "", "a", "", "b", "", ""]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_split_locations_with_empty_matches() {
+        let ere = regex_from_str("x*");
+        assert_eq!(split_str(&ere, "abc"), vec!["a", "b", "c"]);
+        assert_eq!(split_str(&ere, "axxbc"), vec!["a", "b", "c"]);
+        assert_eq!(split_str(&ere, "xxa"), vec!["", "a"]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_split_locations_empty_string_has_no_fields() {
+        let ere = regex_from_str(":");
+        assert!(ere.split_locations(b"").is_empty());
+        let ere = regex_from_str("x*");
+        assert!(ere.split_locations(b"").is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_split_locations_without_separators() {
+        let ere = regex_from_str(",+");
+        assert_eq!(split_str(&ere, "abc"), vec!["abc"]);
+        assert_eq!(split_str(&ere, "a,,b,c"), vec!["a", "b", "c"]);
+    }
+
+    fn replace_all(
+        ere: &Regex,
+        subject: &str,
+        replacement: &str,
+        max: Option<usize>,
+    ) -> (Vec<u8>, usize) {
+        ere.replace_all(subject.as_bytes(), replacement.as_bytes(), max)
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_replace_all_with_matched_text() {
+        let ere = regex_from_str("fo+");
+        assert_eq!(
+            replace_all(&ere, "a foo b fooo", "<&>", None),
+            (Vec::from("a <foo> b <fooo>"), 2)
+        );
+        assert_eq!(
+            replace_all(&ere, "foo", "&-&", None),
+            (Vec::from("foo-foo"), 1)
+        );
+        assert_eq!(replace_all(&ere, "foo", "&", None), (Vec::from("foo"), 1));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_replace_all_consecutive_matches() {
+        let ere = regex_from_str("ab");
+        assert_eq!(
+            replace_all(&ere, "ababab", "[&]", None),
+            (Vec::from("[ab][ab][ab]"), 3)
+        );
+        assert_eq!(
+            replace_all(&ere, "ababab", "x", Some(1)),
+            (Vec::from("xabab"), 1)
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_replace_all_zero_length_matches() {
+        let ere = regex_from_str("x*");
+        assert_eq!(
+            replace_all(&ere, "abc", "-", None),
+            (Vec::from("-a-b-c-"), 4)
+        );
+        assert_eq!(
+            replace_all(&ere, "axxb", "<&>", None),
+            (Vec::from("<>a<xx>b<>"), 3)
+        );
     }
 
     #[test]
     #[cfg_attr(miri, ignore)]
-    fn test_regex_matches() {
-        let ere = regex_from_str("ab*c");
-        assert!(ere.matches(&CString::new("abbbbc").unwrap()));
+    fn test_replace_all_multibyte_characters() {
+        // in the C locale `.` matches one byte, so both bytes of 'ż', C5 BC,
+        // are replaced
+        let ere = regex_from_str(".");
+        assert_eq!(replace_all(&ere, "żb", "x", None), (Vec::from("xxx"), 3));
+        assert_eq!(
+            replace_all(&ere, "żb", "<&>", Some(1)),
+            (b"<\xC5>\xBCb".to_vec(), 1)
+        );
+        let ere = regex_from_str("[^ab]");
+        assert_eq!(
+            replace_all(&ere, "ażb", "X", Some(1)),
+            (b"aX\xBCb".to_vec(), 1)
+        );
+        // empty matches move forward by whole characters
+        let ere = regex_from_str("x*");
+        assert_eq!(replace_all(&ere, "ż€", "-", None), (Vec::from("-ż-€-"), 3));
+        let ere = regex_from_str("ż");
+        assert_eq!(
+            replace_all(&ere, "ażbż", "&&", None),
+            (Vec::from("ażżbżż"), 2)
+        );
     }
 
     #[test]
     #[cfg_attr(miri, ignore)]
-    fn test_regex_match_locations() {
-        let ere = regex_from_str("match");
-        let mut iter = ere.match_locations(CString::new("match 12345 match2 matchmatch").unwrap());
-        assert_eq!(iter.next(), Some(RegexMatch { start: 0, end: 5 }));
-        assert_eq!(iter.next(), Some(RegexMatch { start: 12, end: 17 }));
-        assert_eq!(iter.next(), Some(RegexMatch { start: 19, end: 24 }));
-        assert_eq!(iter.next(), Some(RegexMatch { start: 24, end: 29 }));
-        assert_eq!(iter.next(), None);
+    fn test_replace_all_with_backslashes() {
+        let ere = regex_from_str("b");
+        assert_eq!(replace_all(&ere, "abc", "\\&", None), (Vec::from("a&c"), 1));
+        assert_eq!(
+            replace_all(&ere, "abc", "\\\\&", None),
+            (Vec::from("a\\bc"), 1)
+        );
+        assert_eq!(
+            replace_all(&ere, "abc", "\\n", None),
+            (Vec::from("a\\nc"), 1)
+        );
+        assert_eq!(
+            replace_all(&ere, "abc", "x\\", None),
+            (Vec::from("ax\\c"), 1)
+        );
     }
 }
@@ -0,0 +1,4 @@
+function double(x) {
+    return x * 2
+}
+# no newline after this comment
\ No newline at end of file
@@ -0,0 +1 @@
+{ print double($1) }
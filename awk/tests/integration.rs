@@ -1,4 +1,8 @@
-use plib::testing::{run_test, run_test_with_checker, TestPlan};
+use plib::testing::{
+    generate, run_pipeline, run_test, run_test_with_checker, ExpectedExitCode, ExpectedOutput,
+    RunOptions, StdinSource, TestDir, TestFixture, TestPlan,
+};
+use std::time::Duration;
 
 fn test_awk(args: Vec<String>, expected_output: &str) {
     run_test(TestPlan {
@@ -567,3 +571,2643 @@ fn test_awk_modifying_nf_recomputes_the_record() {
         "tests/awk/test_data.txt"
     );
 }
+
+#[test]
+fn test_awk_match_records_with_embedded_nul() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["/b/ { n++; print } END { print n }".to_string()],
+        stdin_data: String::from("a\0b\nc\nd\0\n"),
+        expected_out: String::from("a\0b\n1\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_dynamic_regex() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-v".to_string(),
+            "pat=x.y".to_string(),
+            "$0 ~ pat { n = split($0, parts, \"[xy]\"); sub(pat, \"-\"); print n, $0 }".to_string(),
+        ],
+        stdin_data: String::from("axbyc\nxy\nbxzyd\n"),
+        expected_out: String::from("3 a-c\n3 b-d\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_split_with_ere() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-F".to_string(),
+            ",".to_string(),
+            concat!(
+                "BEGIN { n = split(\"::a::b::\", arr, /:/); print n, arr[3], arr[5], arr[7] \"|\";",
+                " print split(\"abc\", chars, /x*/), chars[2]; print split(\"\", none, /:/) }",
+                " { print NF }"
+            )
+            .to_string(),
+        ],
+        stdin_data: String::from("a,b\n\n"),
+        expected_out: String::from("7 a b |\n3 b\n0\n2\n0\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_split_with_multibyte_characters() {
+    // the regex matches bytes, so `[^a]` matches each byte of "ż"
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-F".to_string(),
+            "[^a]".to_string(),
+            concat!(
+                "BEGIN { n = split(\"ażb\", arr, /[^ab]/); print n, arr[1], arr[3];",
+                " print split(\"xżyżz\", arr, \"ż\"), arr[2] }",
+                " { print NF, $1 }"
+            )
+            .to_string(),
+        ],
+        stdin_data: String::from("ażb\n"),
+        expected_out: String::from("3 a b\n3 y\n4 a\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_sub_and_gsub_replacement_escapes() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "{ n = gsub(/o+/, \"<&>\\\\&\"); print n, $0; sub(/b/, \"[\\\\\\\\&]\"); print }"
+                .to_string(),
+        ],
+        stdin_data: String::from("foo boo\n"),
+        expected_out: String::from("2 f<oo>& b<oo>&\nf<oo>& [\\b]<oo>&\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_regex_record_separator() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["BEGIN { RS = \";\\n|[|]\" } { print NR \": \" $0 }".to_string()],
+        stdin_data: String::from("a;\nb|c;\nd"),
+        expected_out: String::from("1: a\n2: b\n3: c\n4: d\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_paragraph_mode() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-F".to_string(),
+            ":".to_string(),
+            "BEGIN { RS = \"\" } { printf \"%d %d\", NR, NF; for (i = 1; i <= NF; i++) printf \" [%s]\", $i; print \"\" }"
+                .to_string(),
+        ],
+        stdin_data: String::from("\n\n  a b\nc:d\n\n\n\ne\nf:g:\n\n"),
+        expected_out: String::from("1 3 [  a b] [c] [d]\n2 4 [e] [f] [g] []\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_default_field_separator_ignores_surrounding_blanks() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["{ print NF; print $1 \"/\" $2 }".to_string()],
+        stdin_data: String::from("   a  b \n\ta\t\tb\t\n"),
+        expected_out: String::from("2\na/b\n2\na/b\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_getline_continues_into_next_file() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "FNR == 3 { r = getline; print r, NR, FNR, FILENAME, $0 }".to_string(),
+            "tests/awk/test_data4.txt".to_string(),
+            "tests/awk/test_data4.txt".to_string(),
+        ],
+        stdin_data: String::from(""),
+        expected_out: String::from(
+            "1 4 1 tests/awk/test_data4.txt 1:1;1\n0 6 3 tests/awk/test_data4.txt 3;3:3\n",
+        ),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_getline_return_values() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "BEGIN { while ((getline line < \"tests/awk/test_data4.txt\") > 0) n++; ",
+            "print n, (getline line < \"tests/awk/test_data4.txt\"), ",
+            "(getline line < \"tests/awk/missing_file.txt\"); ",
+            "\"echo piped\" | getline; print $0, NR }"
+        )
+        .to_string()],
+        stdin_data: String::from(""),
+        expected_out: String::from("3 0 -1\npiped 1\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_output_redirection_truncates_once() {
+    let dir = TestDir::new("awk-truncates-once");
+    let path = dir.file("out", "previous contents that are longer than the output\n");
+    let path = path.to_str().unwrap().to_string();
+
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-v".to_string(),
+            format!("f={}", path),
+            concat!(
+                "{ print > f } END { print \"last\" > f; print close(f), close(f); ",
+                "while ((getline line < f) > 0) print \"read:\", line }"
+            )
+            .to_string(),
+        ],
+        stdin_data: String::from("a\nb\n"),
+        expected_out: String::from("0 -1\nread: a\nread: b\nread: last\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_print_to_pipe_and_close() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "{ print | \"sort -u\" } END { r = close(\"sort -u\"); ",
+            "print \"x\" | \"cat; exit 3\"; print r, close(\"cat; exit 3\"), close(\"nope\") }"
+        )
+        .to_string()],
+        stdin_data: String::from("c\na\nc\nb\n"),
+        expected_out: String::from("a\nb\nc\nx\n0 3 -1\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_redirection_to_standard_streams() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "{ print \"out\", $0 > \"/dev/stdout\"; print \"err\", $0 > \"/dev/stderr\"; print }"
+                .to_string(),
+        ],
+        stdin_data: String::from("1\n2\n"),
+        expected_out: String::from("out 1\n1\nout 2\n2\n"),
+        expected_err: String::from("err 1\nerr 2\n"),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_runtime_error_reports_source_line_and_record() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            concat!("BEGIN { x = 0 }\n", "{ print }\n", "NR == 2 { y = $1 / x }").to_string(),
+        ],
+        stdin_data: String::from("1\n2\n3\n"),
+        expected_out: String::from("1\n2\n"),
+        expected_err: String::from(concat!(
+            "awk: cmd. line:3: division by zero attempted\n",
+            " input record number 2, file -\n",
+            "call trace:\n",
+            "=> <start> at cmd. line:3:18\n",
+            "\n"
+        )),
+        expected_exit_code: 2,
+    })
+}
+
+#[test]
+fn test_awk_runtime_error_for_array_in_scalar_context() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "function f(a) {\n",
+            "  return a + 1\n",
+            "}\n",
+            "BEGIN { x[1] = 1; f(x) }"
+        )
+        .to_string()],
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::from(concat!(
+            "awk: cmd. line:2: array used in scalar context\n",
+            "call trace:\n",
+            "=> f at cmd. line:2:12\n",
+            "=> <start> at cmd. line:4:19\n",
+            "\n"
+        )),
+        expected_exit_code: 2,
+    })
+}
+
+#[test]
+fn test_awk_redirection_to_fd_special_files() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "{ print \"a\" $0; print \"b\" $0 > \"/dev/fd/1\"; print \"c\" $0 }\n",
+            "{ print \"d\" $0 > \"/dev/fd/2\"; print \"e\" $0 > \"/dev/stderr\" }\n",
+            "END { close(\"/dev/stdout\"); close(\"/dev/fd/2\"); print \"end\" > \"/dev/stdout\" }"
+        )
+        .to_string()],
+        stdin_data: String::from("1\n2\n"),
+        expected_out: String::from("a1\nb1\nc1\na2\nb2\nc2\nend\n"),
+        expected_err: String::from("d1\ne1\nd2\ne2\n"),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_printf_dynamic_width_and_precision() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["{ printf \"%*d|%-*.*s|%c%c\\n\", $2, $3, $2, $4, $1, 65, $1 }".to_string()],
+        stdin_data: String::from("word 5 42 2\n"),
+        expected_out: String::from("   42|wo   |Aw\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_environ_contains_the_environment() {
+    let path = std::env::var("PATH").expect("PATH is not set");
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["BEGIN { print ENVIRON[\"PATH\"]; print (\"PATH\" in ENVIRON) }".to_string()],
+        stdin_data: String::from(""),
+        expected_out: format!("{}\n1\n", path),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_skip_operand_by_clearing_argv_element() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "BEGIN { ARGV[2] = \"\" } { print FILENAME \": \" $0 }".to_string(),
+            "tests/awk/test_data4.txt".to_string(),
+            "tests/awk/test_data.txt".to_string(),
+        ],
+        stdin_data: String::from(""),
+        expected_out: String::from(
+            "tests/awk/test_data4.txt: 1:1;1\ntests/awk/test_data4.txt: 2,2:2\ntests/awk/test_data4.txt: 3;3:3\n",
+        ),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_append_operand_to_argv() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "BEGIN { ARGV[ARGC++] = \"tests/awk/test_data4.txt\" } { print ARGC, FNR, $0 }"
+                .to_string(),
+        ],
+        stdin_data: String::from("ignored\n"),
+        expected_out: String::from("2 1 1:1;1\n2 2 2,2:2\n2 3 3;3:3\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_field_separator_assignment_between_operands() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "{ print $1 }".to_string(),
+            "tests/awk/test_data4.txt".to_string(),
+            "FS=:".to_string(),
+            "tests/awk/test_data4.txt".to_string(),
+        ],
+        stdin_data: String::from(""),
+        expected_out: String::from("1:1;1\n2,2:2\n3;3:3\n1\n2,2\n3;3\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_assignment_values_are_unescaped() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-v".to_string(),
+            "s=a\\tb\\nc".to_string(),
+            "-v".to_string(),
+            "OFS=\\t".to_string(),
+            "{ print s, $0, t + 1 }".to_string(),
+            "t=41".to_string(),
+            "-".to_string(),
+        ],
+        stdin_data: String::from("x\n"),
+        expected_out: String::from("a\tb\nc\tx\t42\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_invalid_assignment_name() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-v".to_string(),
+            "1x=3".to_string(),
+            "BEGIN { print \"unreachable\" }".to_string(),
+        ],
+        stdin_data: String::from(""),
+        expected_out: String::from(""),
+        expected_err: String::from("invalid variable assignment '1x=3'\n"),
+        expected_exit_code: 2,
+    })
+}
+
+#[test]
+fn test_awk_rand_sequence_depends_only_on_seed() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "BEGIN { srand(7); for (i = 0; i < 5; i++) a = a \" \" rand(); ",
+            "srand(8); b = rand(); ",
+            "srand(7); for (i = 0; i < 5; i++) c = c \" \" rand(); ",
+            "print (a == c), (a != b), srand() }"
+        )
+        .to_string()],
+        stdin_data: String::from(""),
+        expected_out: String::from("1 1 7\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_rand_values_are_in_range() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "BEGIN { srand(1); for (i = 0; i < 100000; i++) { r = rand(); ",
+            "if (r < 0 || r >= 1) bad++; if (r < 0.5) low++ } ",
+            "print bad + 0, (low > 45000 && low < 55000) }"
+        )
+        .to_string()],
+        stdin_data: String::from(""),
+        expected_out: String::from("0 1\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_numeric_string_comparisons() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "{ print ($1 == 10), ($1 == $2), (\"10.0\" == \"10\"), ($1 == \"10\"), ",
+            "($3 < 2), ($4 == 100), (x == $5), ($1 < $3), ($5 ? \"t\" : \"f\") }"
+        )
+        .to_string()],
+        stdin_data: String::from("10.0 1e1 abc  +1e2  0.0\n"),
+        expected_out: String::from("1 1 0 0 0 1 1 1 f\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_numeric_strings_from_other_sources() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-v".to_string(),
+            "v= 3.0".to_string(),
+            concat!(
+                "BEGIN { split(\"2.0 x\", a); print (v == 3), (a[1] == 2), (a[2] == 0), ",
+                "(ARGV[1] == 5), (ARGV[1] < 10) }"
+            )
+            .to_string(),
+            "5.0".to_string(),
+        ],
+        stdin_data: String::from(""),
+        expected_out: String::from("1 1 0 1 1\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_numeric_string_subscripts_keep_their_text() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["{ a[$1]; a[$2]; a[$2 + 0] } END { n = 0; for (k in a) n++; print n, (\"01\" in a), (1 in a) }".to_string()],
+        stdin_data: String::from("01 1.0\n"),
+        expected_out: String::from("3 1 1\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_delete_and_in() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "BEGIN { for (i = 0; i < 5; i++) a[i] = i; delete a[4]; delete a[0]; delete a[9]; ",
+            "print length(a), (0 in a), (1 in a), (9 in a), length(a); ",
+            "b[1, 2] = 3; print ((1, 2) in b), ((2, 1) in b), length(b); ",
+            "for (k in a) delete a[k]; print length(a); a[\"x\"]; print length(a); ",
+            "for (k in a) print \"left:\", k }"
+        )
+        .to_string()],
+        stdin_data: String::from(""),
+        expected_out: String::from("3 0 1 0 3\n1 0 1\n0\n1\nleft: x\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_delete_whole_array() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "BEGIN { for (i = 0; i < 200000; i++) a[i] = i; delete a; n = 0; ",
+            "for (k in a) n++; print n, length(a); ",
+            "for (i = 0; i < 3; i++) a[i]; for (k in a) { delete a; c++ } print c, length(a) }"
+        )
+        .to_string()],
+        stdin_data: String::from(""),
+        expected_out: String::from("0 0\n1 0\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_system_returns_exit_status() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "BEGIN { print system(\"exit 7\"), system(\"true\"), system(\"kill -9 $$\") }"
+                .to_string(),
+        ],
+        stdin_data: String::from(""),
+        expected_out: String::from("7 0 265\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_system_flushes_pending_output() {
+    let dir = TestDir::new("awk-system-flush");
+    let path = dir.path().join("out").to_str().unwrap().to_string();
+
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-v".to_string(),
+            format!("f={}", path),
+            concat!(
+                "BEGIN { printf \"before \"; system(\"echo during\"); print \"after\"; ",
+                "print \"in file\" > f; print fflush(f), fflush(), fflush(\"\"), fflush(\"nope\"); ",
+                "system(\"cat \" f) }"
+            )
+            .to_string(),
+        ],
+        stdin_data: String::from(""),
+        expected_out: String::from("before during\nafter\n0 0 0 -1\nin file\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_multiple_program_files() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-f".to_string(),
+            "tests/awk/multiple_program_files_lib.awk".to_string(),
+            "-f".to_string(),
+            "tests/awk/multiple_program_files_main.awk".to_string(),
+        ],
+        stdin_data: String::from("21\n4\n"),
+        expected_out: String::from("42\n8\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_program_operand_with_program_file_is_an_input_file() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-f".to_string(),
+            "tests/awk/multiple_program_files_lib.awk".to_string(),
+            "-f".to_string(),
+            "tests/awk/multiple_program_files_main.awk".to_string(),
+            "{ print }".to_string(),
+        ],
+        stdin_data: String::from(""),
+        expected_out: String::from(""),
+        expected_err: String::from(
+            "could not open '{ print }' for reading: No such file or directory (os error 2)\n",
+        ),
+        // POSIX only requires a nonzero status for an input file that cannot be opened
+        expected_exit_code: ExpectedExitCode::AnyNonZero,
+    })
+}
+
+#[test]
+fn test_awk_operand_starting_with_dash_after_double_dash() {
+    let dir = TestDir::new("awk-double-dash");
+    let input = dir.file("-x", "data\n");
+
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "--".to_string(),
+            "{ print FILENAME, $0 }".to_string(),
+            input.to_str().unwrap().to_string(),
+            "-".to_string(),
+        ],
+        stdin_data: String::from("stdin\n"),
+        expected_out: format!("{}/-x data\n- stdin\n", dir.path().to_str().unwrap()),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_statements_continue_across_newlines() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "function describe(name,\n",
+            "                  value) {\n",
+            "    return name \"=\" \\\n",
+            "        value\n",
+            "}\n",
+            "{\n",
+            "    if ($1 > 1 &&\n",
+            "        $2 != \"\")\n",
+            "    {\n",
+            "        print describe($1,\n",
+            "                       $2)\n",
+            "    }\n",
+            "    else if ($1 == 1 ||\n",
+            "             $2 == \"x\")\n",
+            "        print \"first\"; else\n",
+            "        print \"other\"\n",
+            "}"
+        )
+        .to_string()],
+        stdin_data: String::from("1 a\n2 b\n3\n"),
+        expected_out: String::from("first\n2=b\nother\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_concatenation_and_minus() {
+    let cases = [
+        (r#"1 " " -1"#, "1-1"),
+        (r#"1 " " (-1)"#, "1 -1"),
+        ("a -1", "1"),
+        (r#"$1" "$2-1"#, "3 4"),
+        ("$1 -1", "2"),
+        ("$1 - -1", "4"),
+        (r#"-1 " " -1"#, "-1-1"),
+        (r#"- 1 " " 2"#, "-1 2"),
+        (r#"1 - 1 " " 2"#, "0 2"),
+        (r#"1 " " 2 + 3"#, "1 5"),
+        (r#"1 " " +1"#, "11"),
+        (r#"1 " " !0"#, "1 1"),
+        ("-2^2", "-4"),
+        ("2^-1", "0.5"),
+        (r#"2 " " ++b"#, "2 1"),
+        (r#"2 " " --c"#, "2 -1"),
+        ("d --1", "01"),
+        ("e+++a", "2"),
+    ];
+    let program = cases
+        .iter()
+        .map(|(expr, _)| format!("print {}", expr))
+        .collect::<Vec<_>>()
+        .join("; ");
+    let expected = cases
+        .iter()
+        .map(|(_, result)| format!("{}\n", result))
+        .collect::<String>();
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![format!("{{ a = 2; {} }}", program)],
+        stdin_data: String::from("3 5\n"),
+        expected_out: expected,
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_arrays_are_passed_by_reference() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "function fill(a) { a[1] = \"x\" }\n",
+            "function fill_deep(a) { fill(a); a[2] = \"y\" }\n",
+            "function drop(a) { delete a[1] }\n",
+            "BEGIN {\n",
+            "    fill(arr); print arr[1]\n",
+            "    fill_deep(created); print created[1], created[2], length(created)\n",
+            "    drop(created); print length(created), (1 in created)\n",
+            "}"
+        )
+        .to_string()],
+        stdin_data: String::new(),
+        expected_out: String::from("x\nx y 2\n1 0\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_scalars_are_passed_by_value() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "function set(s) { s = 5; s++; return s }\n",
+            "BEGIN {\n",
+            "    v = 1; print set(v), v\n",
+            "    print set(untyped), \"[\" untyped \"]\", length(untyped)\n",
+            "    $0 = \"a b\"; print set($1), $1\n",
+            "}"
+        )
+        .to_string()],
+        stdin_data: String::new(),
+        expected_out: String::from("6 1\n6 [] 0\n6 a\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_recursion_builds_an_array_through_several_calls() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "function squares(a, n) {\n",
+            "    if (n == 0)\n",
+            "        return 0\n",
+            "    a[n] = n * n\n",
+            "    return a[n] + squares(a, n - 1)\n",
+            "}\n",
+            "BEGIN {\n",
+            "    total = squares(sq, 5)\n",
+            "    for (i = 1; i <= 5; i++) printf \"%s \", sq[i]\n",
+            "    print length(sq), total\n",
+            "}"
+        )
+        .to_string()],
+        stdin_data: String::new(),
+        expected_out: String::from("1 4 9 16 25 5 55\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_scalar_argument_used_as_array_is_an_error() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["function f(a) {\n  a[1] = 1\n}\nBEGIN { x = 1; f(x) }".to_string()],
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::from(concat!(
+            "awk: cmd. line:2: scalar used in array context\n",
+            "call trace:\n",
+            "=> f at cmd. line:2:3\n",
+            "=> <start> at cmd. line:4:16\n",
+            "\n"
+        )),
+        expected_exit_code: 2,
+    })
+}
+
+#[test]
+fn test_awk_variable_made_an_array_by_a_function_cannot_be_used_as_scalar() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["function f(a) { a[1] = 1 }\nBEGIN { f(u)\n  print u }".to_string()],
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::from(concat!(
+            "awk: cmd. line:3: array used in scalar context\n",
+            "call trace:\n",
+            "=> <start> at cmd. line:3:3\n",
+            "\n"
+        )),
+        expected_exit_code: 2,
+    })
+}
+
+#[test]
+fn test_awk_recursive_function_with_local_accumulator() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "function fact(n,    acc) {\n",
+            "    acc = n\n",
+            "    if (n > 1)\n",
+            "        acc *= fact(n - 1)\n",
+            "    return acc\n",
+            "}\n",
+            "function calls(n,    count) {\n",
+            "    count++\n",
+            "    if (n > 0)\n",
+            "        calls(n - 1)\n",
+            "    return count\n",
+            "}\n",
+            "BEGIN { print fact(10), \"[\" acc \"]\", calls(5) }"
+        )
+        .to_string()],
+        stdin_data: String::new(),
+        expected_out: String::from("3628800 [] 1\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_function_locals_do_not_clobber_globals() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "function collect(x,    seen, k, n) {\n",
+            "    seen[x] = 1\n",
+            "    for (k in seen) n++\n",
+            "    total = total + n\n",
+            "    return n\n",
+            "}\n",
+            "function fill(a) { a[\"k\"] = 1 }\n",
+            "function local_array(    arr) { fill(arr); return length(arr) }\n",
+            "BEGIN {\n",
+            "    seen = \"global\"\n",
+            "    print collect(1), collect(2), seen, total\n",
+            "    print local_array(), local_array(), length(arr)\n",
+            "}"
+        )
+        .to_string()],
+        stdin_data: String::new(),
+        expected_out: String::from("1 1 global 2\n1 1 0\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_recursive_walk_with_locals() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "function walk(node, indent,    i, child) {\n",
+            "    print indent node\n",
+            "    for (i = 1; i <= children[node]; i++) {\n",
+            "        child = node \".\" i\n",
+            "        walk(child, indent \"  \")\n",
+            "    }\n",
+            "}\n",
+            "BEGIN {\n",
+            "    children[\"r\"] = 2; children[\"r.1\"] = 2; children[\"r.1.2\"] = 1\n",
+            "    walk(\"r\")\n",
+            "}"
+        )
+        .to_string()],
+        stdin_data: String::new(),
+        expected_out: String::from("r\n  r.1\n    r.1.1\n    r.1.2\n      r.1.2.1\n  r.2\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_exit_in_begin_skips_input_but_runs_end() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "BEGIN { print \"begin\"; exit 3; print \"after exit\" }\n",
+            "BEGIN { print \"second begin\" }\n",
+            "{ print \"main\", $0 }\n",
+            "END { print \"end\", NR }"
+        )
+        .to_string()],
+        stdin_data: String::from("1\n2\n"),
+        expected_out: String::from("begin\nend 0\n"),
+        expected_err: String::new(),
+        expected_exit_code: 3,
+    })
+}
+
+#[test]
+fn test_awk_exit_in_rule_skips_input_but_runs_end() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "{ print \"main\", $0 }\n",
+            "NR == 2 { exit 4 }\n",
+            "{ print \"after\", $0 }\n",
+            "END { print \"end\", NR }"
+        )
+        .to_string()],
+        stdin_data: String::from("1\n2\n3\n"),
+        expected_out: String::from("main 1\nafter 1\nmain 2\nend 2\n"),
+        expected_err: String::new(),
+        expected_exit_code: 4,
+    })
+}
+
+#[test]
+fn test_awk_bare_exit_in_end_keeps_previous_status() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "{ exit 3 }\n",
+            "END { print \"end\"; exit; print \"after exit\" }\n",
+            "END { print \"second end\" }"
+        )
+        .to_string()],
+        stdin_data: String::from("1\n"),
+        expected_out: String::from("end\n"),
+        expected_err: String::new(),
+        expected_exit_code: 3,
+    })
+}
+
+#[test]
+fn test_awk_exit_in_end_overrides_previous_status() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["BEGIN { exit 3 } END { print \"end\"; exit 5 }".to_string()],
+        stdin_data: String::new(),
+        expected_out: String::from("end\n"),
+        expected_err: String::new(),
+        expected_exit_code: 5,
+    })
+}
+
+#[test]
+fn test_awk_exit_in_function_called_by_pattern() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "function stop(n) { if (n == 2) exit 6; return 1 }\n",
+            "stop(NR) { print }\n",
+            "END { print \"end\" }"
+        )
+        .to_string()],
+        stdin_data: String::from("1\n2\n3\n"),
+        expected_out: String::from("1\nend\n"),
+        expected_err: String::new(),
+        expected_exit_code: 6,
+    })
+}
+
+#[test]
+fn test_awk_assigning_nr_and_fnr_renumbers_the_following_records() {
+    let dir = TestDir::new("awk-assign-nr");
+    let file = dir.file("input.txt", "x\ny\n");
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "NR == 1 { NR = 100 } FNR == 1 { FNR = 10 } { print NR, FNR } END { print NR }"
+                .to_string(),
+            "-".to_string(),
+            file.to_str().unwrap().to_string(),
+        ],
+        stdin_data: String::from("a\nb\n"),
+        expected_out: String::from("100 10\n101 11\n102 10\n103 11\n103\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_assigning_nf_truncates_and_extends_the_record() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "BEGIN { OFS = \":\" }\n",
+            "NR == 1 { NF = 1; print; print NF }\n",
+            "NR == 2 { NF = 4; print; print NF, \"[\" $4 \"]\", length($0) }"
+        )
+        .to_string()],
+        stdin_data: String::from("a b c\nd e\n"),
+        expected_out: String::from("a\n1\nd:e::\n4:[]:5\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_multidimensional_subscripts() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "BEGIN {\n",
+            "    for (i = 1; i <= 2; i++)\n",
+            "        for (j = 1; j <= 3; j++)\n",
+            "            table[i, j] = i * j\n",
+            "    print length(table), table[2, 3], table[2 SUBSEP 3]\n",
+            "    print ((1, 3) in table), ((3, 1) in table), length(table)\n",
+            "    delete table[1, 2]\n",
+            "    print ((1, 2) in table), length(table)\n",
+            "    n = 0\n",
+            "    for (k in table) {\n",
+            "        split(k, parts, SUBSEP)\n",
+            "        if (table[parts[1], parts[2]] == parts[1] * parts[2]) n++\n",
+            "    }\n",
+            "    print n, (SUBSEP == \"\\034\")\n",
+            "}"
+        )
+        .to_string()],
+        stdin_data: String::new(),
+        expected_out: String::from("6 6 6\n1 0 6\n0 5\n5 1\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_subscript_containing_subsep() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "BEGIN {\n",
+            "    SUBSEP = \":\"\n",
+            "    a[\"x:y\", \"z\"] = 1\n",
+            "    print ((\"x\", \"y\", \"z\") in a), ((\"x:y\", \"z\") in a)\n",
+            "    for (k in a) print k, split(k, parts, SUBSEP)\n",
+            "}"
+        )
+        .to_string()],
+        stdin_data: String::new(),
+        expected_out: String::from("1 1\nx:y:z 3\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_for_in_visits_each_element_once() {
+    run_test_with_checker(
+        TestPlan {
+            cmd: String::from("awk"),
+            args: vec![String::from(
+                "BEGIN { for (i = 1; i <= 100; i++) a[i]; for (k in a) print k }",
+            )],
+            ..Default::default()
+        },
+        |_: &TestPlan, output| {
+            let mut keys = String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(|key| key.parse::<u32>().unwrap())
+                .collect::<Vec<_>>();
+            keys.sort();
+            assert_eq!(keys, (1..=100).collect::<Vec<_>>());
+            assert!(output.stderr.is_empty());
+            assert!(output.status.success());
+        },
+    );
+}
+
+#[test]
+fn test_awk_for_in_order_is_insertion_order() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(concat!(
+            "BEGIN { a[\"z\"]; a[3]; a[\"b\"]; a[1]; delete a[3]; a[2]; ",
+            "for (k in a) printf \"%s \", k; print \"\" }"
+        ))],
+        stdin_data: String::new(),
+        expected_out: String::from("z b 1 2 \n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_delete_current_element_during_for_in() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(concat!(
+            "BEGIN { for (i = 1; i <= 10; i++) a[i]; ",
+            "for (k in a) { delete a[k]; n++ } ",
+            "print n, length(a) }"
+        ))],
+        stdin_data: String::new(),
+        expected_out: String::from("10 0\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_delete_unvisited_element_during_for_in() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(concat!(
+            "BEGIN { for (i = 1; i <= 6; i++) a[i]; ",
+            "for (k in a) { printf \"%s \", k; delete a[k + 1] } print \"\" }"
+        ))],
+        stdin_data: String::new(),
+        expected_out: String::from("1 3 5 \n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_insert_during_for_in() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(concat!(
+            "BEGIN { a[1]; a[2]; a[3]; ",
+            "for (k in a) { a[k + 10] = k; n++ } ",
+            "print n, length(a) }"
+        ))],
+        stdin_data: String::new(),
+        expected_out: String::from("3 6\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_break_and_continue_in_for_in() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(concat!(
+            "BEGIN { for (i = 1; i <= 5; i++) a[i]; ",
+            "for (k in a) { if (k == 2) continue; if (k == 4) break; printf \"%s \", k } ",
+            "a[6]; for (k in a) n++; print n }"
+        ))],
+        stdin_data: String::new(),
+        expected_out: String::from("1 3 6\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_return_from_for_in() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(concat!(
+            "function first(arr, k) { for (k in arr) return k } ",
+            "BEGIN { for (i = 1; i <= 5; i++) q[i]; ",
+            "while (length(q)) { k = first(q); printf \"%s \", k; delete q[k] } print \"\" }"
+        ))],
+        stdin_data: String::new(),
+        expected_out: String::from("1 2 3 4 5 \n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_filename_fnr_and_nr_across_input_files() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            concat!(
+                "BEGIN { print \"[\" FILENAME \"]\", FNR, NR } ",
+                "FNR == 1 { print \"==> \" FILENAME \" <==\" } ",
+                "{ print FILENAME, FNR, NR, v } ",
+                "END { print FILENAME, FNR, NR, v }"
+            )
+            .to_string(),
+            "tests/awk/test_data4.txt".to_string(),
+            "v=1".to_string(),
+            "-".to_string(),
+            "v=2".to_string(),
+            "tests/awk/test_data4.txt".to_string(),
+        ],
+        stdin_data: String::from("a\nb\n"),
+        expected_out: String::from(concat!(
+            "[] 0 0\n",
+            "==> tests/awk/test_data4.txt <==\n",
+            "tests/awk/test_data4.txt 1 1 \n",
+            "tests/awk/test_data4.txt 2 2 \n",
+            "tests/awk/test_data4.txt 3 3 \n",
+            "==> - <==\n",
+            "- 1 4 1\n",
+            "- 2 5 1\n",
+            "==> tests/awk/test_data4.txt <==\n",
+            "tests/awk/test_data4.txt 1 6 2\n",
+            "tests/awk/test_data4.txt 2 7 2\n",
+            "tests/awk/test_data4.txt 3 8 2\n",
+            "tests/awk/test_data4.txt 3 8 2\n"
+        )),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_filename_of_standard_input_without_operands() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "BEGIN { printf \"[%s]\", FILENAME } { printf \" [%s] %d\", FILENAME, FNR } END { print \"\" }"
+                .to_string(),
+        ],
+        stdin_data: String::from("a\nb\n"),
+        expected_out: String::from("[] [-] 1 [-] 2\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_exponent_precedence_and_associativity() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(concat!(
+            "BEGIN { x = 3; x ^= 2; y = 2; y **= 3; ",
+            "print -2^2, 2^-1, 2^3^2, x, y, 2**3**2, -2^-2, 2^3*2 }"
+        ))],
+        stdin_data: String::new(),
+        expected_out: String::from("-4 0.5 512 9 8 512 -0.25 16\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_tab_field_separator_escape() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            String::from("-F"),
+            String::from("\\t"),
+            String::from("{ print NF; for (i = 1; i <= NF; i++) print \"[\" $i \"]\" }"),
+        ],
+        stdin_data: String::from("a\tb c\t\td\n"),
+        expected_out: String::from("4\n[a]\n[b c]\n[]\n[d]\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_single_character_field_separator_is_literal() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            String::from("-F"),
+            String::from("."),
+            String::from("{ print NF, $2; FS = \"|\" }"),
+        ],
+        stdin_data: String::from("a.b.c\nx|y|z\n"),
+        expected_out: String::from("3 b\n3 y\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_backslash_field_separator() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            String::from("-F"),
+            String::from("\\"),
+            String::from("{ print NF, $2 }"),
+        ],
+        stdin_data: String::from("a\\b\\c\n"),
+        expected_out: String::from("3 b\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_blank_field_separator_is_default_splitting() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            String::from("-F"),
+            String::from(" "),
+            String::from("{ print NF, $1 }"),
+        ],
+        stdin_data: String::from("  a \t b  \n"),
+        expected_out: String::from("2 a\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_multi_character_field_separator_is_regex() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            String::from("-F"),
+            String::from(", *"),
+            String::from("{ print NF; for (i = 1; i <= NF; i++) print \"[\" $i \"]\" }"),
+        ],
+        stdin_data: String::from("a,  b,c\n"),
+        expected_out: String::from("3\n[a]\n[b]\n[c]\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_print_uses_ofs_and_ors_changed_mid_stream() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(
+            "NR == 2 { OFS = \",\"; ORS = \";\" } { print $1, $2; print }",
+        )],
+        stdin_data: String::from("a b c\nd e f\ng h i\n"),
+        expected_out: String::from("a b\na b c\nd,e;d e f;g,h;g h i;"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_print_with_multi_character_and_empty_ofs_and_ors() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(concat!(
+            "BEGIN { OFS = \"--\"; ORS = \"<>\"; print 1, 2; ",
+            "OFS = \"\"; ORS = \"\"; print 3, 4; print \"\\n\" }"
+        ))],
+        stdin_data: String::new(),
+        expected_out: String::from("1--2<>34\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_changing_ofs_only_affects_record_after_field_assignment() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(
+            "BEGIN { OFS = \"-\" } { print; $1 = $1; print; OFS = \":\"; print; $3 = \"x\"; print }",
+        )],
+        stdin_data: String::from("a  b\n"),
+        expected_out: String::from("a  b\na-b\na-b\na:b:x\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_field_values_outlive_their_record() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(concat!(
+            "{ first[NR] = $1; last = $NF; n = split($0, parts); p[NR] = parts[n]; ",
+            "x = $2; sub(/b/, \"B\", $2); $0 = $3 } ",
+            "END { print first[1], first[2], last, p[1], p[2], x, $0, NF }"
+        ))],
+        stdin_data: String::from("a1 b1 c1\na2 b2 c2 d2\n"),
+        expected_out: String::from("a1 a2 d2 c1 d2 b2 c2 1\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_fields_of_multi_line_records_with_character_separator() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            String::from("-F"),
+            String::from(":"),
+            String::from(
+                "BEGIN { RS = \"\" } { for (i = 1; i <= NF; i++) printf \"[%s]\", $i; print \"\" }",
+            ),
+        ],
+        stdin_data: String::from("a:b\nc:d:e\n\nf\n"),
+        expected_out: String::from("[a][b][c][d][e]\n[f]\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_compound_assignment_to_array_element() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(
+            "{ a[$1] += $2; a[$1] *= 2; b[$1]++ } END { print a[\"x\"], a[\"y\"], b[\"x\"], (a[\"x\"] ^= 2) }",
+        )],
+        stdin_data: String::from("x 1\ny 2\nx 3\n"),
+        expected_out: String::from("10 4 2 100\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_integral_values_are_printed_as_integers() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(concat!(
+            "BEGIN { OFMT = CONVFMT = \"%.2f\"; print 2^31, 1e6, 0.5 + 0.5, -0, 2^53, 2^53 - 1, -2^53, 0.25; ",
+            "print (2^31) \"\", (2^53 - 1) \"\", 0.25 \"\"; a[2^31] = 1; for (k in a) print k; ",
+            "OFMT = CONVFMT = \"%.6g\"; print 2^53 + 2, 2^64, 1e30; print x = 2^40 }"
+        ))],
+        stdin_data: String::new(),
+        expected_out: String::from(concat!(
+            "2147483648 1000000 1 0 9007199254740992 9007199254740991 -9007199254740992 0.25\n",
+            "2147483648 9007199254740991 0.25\n",
+            "2147483648\n",
+            "9.0072e+15 1.84467e+19 1e+30\n",
+            "1099511627776\n"
+        )),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_numeric_and_string_subscripts_address_the_same_elements() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(concat!(
+            "{ n = split($0, f); for (i = 1; i <= n; i++) a[f[i]] = i } ",
+            "END { ",
+            "a[0.5 + 0.5] = a[0.5 + 0.5] \"x\"; print a[1], a[\"1\"], (\"01\" in a), a[\"01\"]; ",
+            "print a[-2], a[\"-2\"], a[-4 / 2]; ",
+            "a[0.1] = \"tenth\"; print a[\"0.1\"], a[1 / 10]; ",
+            "d = \"1\" \"2\"; a[12] = \"twelve\"; print a[d], (1 \"2\" in a); ",
+            "delete a[2 / 2]; print (1 in a), (\"1\" in a), (\"01\" in a); ",
+            "b[2^31] = 1; for (k in b) print k, (k in b), (2147483648 in b) ",
+            "}"
+        ))],
+        stdin_data: String::from("1 01 -2\n"),
+        expected_out: String::from(concat!(
+            "1x 1x 1 2\n",
+            "3 3 3\n",
+            "tenth tenth\n",
+            "twelve 1\n",
+            "0 0 1\n",
+            "2147483648 1 1\n"
+        )),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_division_by_zero_is_a_fatal_error() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(
+            "BEGIN { print \"before\"; print 1 / 0; print \"after\" }",
+        )],
+        stdin_data: String::new(),
+        expected_out: String::from("before\n"),
+        expected_err: String::from(concat!(
+            "awk: cmd. line:1: division by zero attempted\n",
+            "call trace:\n",
+            "=> <start> at cmd. line:1:33\n",
+            "\n"
+        )),
+        expected_exit_code: 2,
+    });
+}
+
+#[test]
+fn test_awk_modulo_by_zero_is_a_fatal_error() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(
+            "BEGIN { print \"before\"; print 5 % 0; print \"after\" }",
+        )],
+        stdin_data: String::new(),
+        expected_out: String::from("before\n"),
+        expected_err: String::from(concat!(
+            "awk: cmd. line:1: division by zero attempted in `%`\n",
+            "call trace:\n",
+            "=> <start> at cmd. line:1:33\n",
+            "\n"
+        )),
+        expected_exit_code: 2,
+    });
+}
+
+#[test]
+fn test_awk_divide_assign_by_zero_is_a_fatal_error() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(
+            "BEGIN { print \"before\"; x = 1; x /= 0; print \"after\" }",
+        )],
+        stdin_data: String::new(),
+        expected_out: String::from("before\n"),
+        expected_err: String::from(concat!(
+            "awk: cmd. line:1: division by zero attempted\n",
+            "call trace:\n",
+            "=> <start> at cmd. line:1:34\n",
+            "\n"
+        )),
+        expected_exit_code: 2,
+    });
+}
+
+#[test]
+fn test_awk_modulo_assign_by_zero_is_a_fatal_error() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(
+            "BEGIN { print \"before\"; x = 3; x %= 0; print \"after\" }",
+        )],
+        stdin_data: String::new(),
+        expected_out: String::from("before\n"),
+        expected_err: String::from(concat!(
+            "awk: cmd. line:1: division by zero attempted in `%`\n",
+            "call trace:\n",
+            "=> <start> at cmd. line:1:34\n",
+            "\n"
+        )),
+        expected_exit_code: 2,
+    });
+}
+
+#[test]
+fn test_awk_zero_divided_by_zero_is_a_fatal_error() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(
+            "BEGIN { print \"before\"; print 0 / 0; print \"after\" }",
+        )],
+        stdin_data: String::new(),
+        expected_out: String::from("before\n"),
+        expected_err: String::from(concat!(
+            "awk: cmd. line:1: division by zero attempted\n",
+            "call trace:\n",
+            "=> <start> at cmd. line:1:33\n",
+            "\n"
+        )),
+        expected_exit_code: 2,
+    });
+}
+
+#[test]
+fn test_awk_exponentiation_follows_c_pow() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(
+            "BEGIN { print 0 ^ 0, 0 ^ -1, -(0 ^ -1), (-2) ^ 3, (-2) ^ -2, 2 ^ -1 }",
+        )],
+        stdin_data: String::new(),
+        expected_out: String::from("1 inf -inf -8 0.25 0.5\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_bare_regex_in_boolean_expression_matches_the_record() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(
+            "/foo/ && NR > 2 { print } !/foo/ || NR == 1 { print \"not\", $0 }",
+        )],
+        stdin_data: String::from("foo1\nbar\nfoo2\nbaz\n"),
+        expected_out: String::from("not foo1\nnot bar\nfoo2\nnot baz\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_bare_regex_assigned_to_a_variable_is_the_match_result() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(
+            "{ x = /foo/; y = /foo/ + 1; z = (/foo/) \"\"; print x, y, z, /o/ ~ 1 }",
+        )],
+        stdin_data: String::from("foo\nbar\n"),
+        expected_out: String::from("1 2 1 1\n0 1 0 0\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_bare_regex_as_ternary_condition() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from(
+            "function f(v) { return v } { print /foo/ ? \"y\" : \"n\", f(/foo/) }",
+        )],
+        stdin_data: String::from("foo\nbar\n"),
+        expected_out: String::from("y 1\nn 0\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_runtime_error_in_program_file_names_the_file() {
+    let dir = TestDir::new("awk-error-file");
+    let path = dir.file("error.awk", "BEGIN {\n  x = 1 / 0\n}\n");
+    let path = path.to_str().unwrap().to_string();
+
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from("-f"), path.clone()],
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: ExpectedOutput::Regex(String::from(
+            "^awk: /.*/error\\.awk:2: division by zero attempted\n",
+        )),
+        expected_exit_code: 2,
+    });
+}
+
+#[test]
+fn test_awk_output_redirection_writes_and_appends_files() {
+    TestFixture::new("awk-redirection")
+        .with_file("numbers", "1\n2\n3\n4\n")
+        .with_file("log", "previous\n")
+        .with_file("truncated", "previous\n")
+        .expect_file("odd", "1\n3\n")
+        .expect_file("even", "2\n4\n")
+        .expect_file("log", "previous\n4 records\n")
+        .expect_file("truncated", "")
+        .expect_no_file("unused")
+        .run_test(TestPlan {
+            cmd: String::from("awk"),
+            args: vec![
+                String::from(concat!(
+                    "BEGIN { printf \"\" > \"truncated\" } ",
+                    "{ print > ($1 % 2 ? \"odd\" : \"even\") } ",
+                    "END { print NR, \"records\" >> \"${log}\"; if (0) print > \"unused\" }"
+                )),
+                String::from("${numbers}"),
+            ],
+            stdin_data: String::new(),
+            expected_out: String::new(),
+            expected_err: String::new(),
+            expected_exit_code: 0,
+        });
+}
+
+#[test]
+fn test_awk_output_to_a_pipe_reads_and_writes_files() {
+    TestFixture::new("awk-pipe-output")
+        .expect_file("sorted", "a\nb\nc\n")
+        .run_test(TestPlan {
+            cmd: String::from("awk"),
+            args: vec![String::from(
+                "{ print | \"sort > sorted\" } END { close(\"sort > sorted\"); while ((getline l < \"sorted\") > 0) n++; print n }",
+            )],
+            stdin_data: String::from("c\na\nb\n"),
+            expected_out: String::from("3\n"),
+            expected_err: String::new(),
+            expected_exit_code: 0,
+        }
+        .with_options(RunOptions {
+            timeout: Some(std::time::Duration::from_secs(10)),
+            ..Default::default()
+        }));
+}
+
+#[test]
+fn test_awk_number_formatting_in_c_and_utf8_locales() {
+    for locale in ["C", "C.UTF-8"] {
+        run_test(TestPlan {
+            cmd: String::from("awk"),
+            args: vec![String::from(
+                "BEGIN { x = 3.14159; print x, x \"\", 1e6, 0.1 + 0.2; printf \"%.2f %d\\n\", x, x }",
+            )],
+            stdin_data: String::new(),
+            expected_out: String::from("3.14159 3.14159 1000000 0.3\n3.14 3\n"),
+            expected_err: String::new(),
+            expected_exit_code: 0,
+        }
+        .with_env("LC_ALL", locale));
+    }
+}
+
+#[test]
+fn test_awk_environ_with_a_cleared_environment() {
+    run_test(
+        TestPlan {
+            cmd: String::from("awk"),
+            args: vec![String::from(
+                "BEGIN { n = 0; for (k in ENVIRON) n++; print n, ENVIRON[\"AWK_TEST_VAR\"] }",
+            )],
+            stdin_data: String::new(),
+            expected_out: String::from("1 a value\n"),
+            expected_err: String::new(),
+            expected_exit_code: 0,
+        }
+        .with_options(RunOptions {
+            env: vec![(String::from("AWK_TEST_VAR"), String::from("a value"))],
+            clear_env: true,
+            ..Default::default()
+        }),
+    );
+}
+
+#[test]
+fn test_awk_syntax_error_for_missing_open_brace() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["BEGIN { x = 1 }\nEND print x }".to_string()],
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::from(concat!(
+            "awk: syntax error at source line 2: unexpected token 'print'\n",
+            " END print x }\n",
+            "     ^\n"
+        )),
+        expected_exit_code: 2,
+    })
+}
+
+#[test]
+fn test_awk_syntax_error_for_unterminated_string() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["{\n\tprint \"abc\n}".to_string()],
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::from(concat!(
+            "awk: syntax error at source line 2: unterminated string\n",
+            " \tprint \"abc\n",
+            " \t      ^\n"
+        )),
+        expected_exit_code: 2,
+    })
+}
+
+#[test]
+fn test_awk_syntax_error_for_unterminated_regex() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["$1 ~ /a\\/b { print }".to_string()],
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::from(concat!(
+            "awk: syntax error at source line 1: unterminated regular expression\n",
+            " $1 ~ /a\\/b { print }\n",
+            "      ^\n"
+        )),
+        expected_exit_code: 2,
+    })
+}
+
+#[test]
+fn test_awk_reports_independent_syntax_errors() {
+    let dir = TestDir::new("awk-syntax-errors");
+    let program_path = dir.file(
+        "syntax_errors.awk",
+        "BEGIN { print ( }\n{ print $1 }\nEND { x = }\n",
+    );
+    let file = program_path.to_str().unwrap().to_string();
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["-f".to_string(), file.clone()],
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: format!(
+            concat!(
+                "awk: syntax error at source line 1 of file {0}: unexpected token '}}'\n",
+                " BEGIN {{ print ( }}\n",
+                "                 ^\n",
+                "awk: syntax error at source line 3 of file {0}: unexpected token '}}'\n",
+                " END {{ x = }}\n",
+                "           ^\n"
+            ),
+            file
+        ),
+        expected_exit_code: 2,
+    });
+}
+
+#[test]
+fn test_awk_invalid_regex_literal_is_reported_before_reading_input() {
+    run_test_with_checker(
+        TestPlan {
+            cmd: String::from("awk"),
+            args: vec!["BEGIN { print \"started\" } /a(/ { print }".to_string()],
+            stdin_data: String::from("a(\n"),
+            ..Default::default()
+        },
+        |_: &TestPlan, output| {
+            // the description of the error comes from the C library
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.starts_with("awk: error at source line 1: regex error in /a(/: "));
+            assert!(stderr.ends_with(concat!(
+                " BEGIN { print \"started\" } /a(/ { print }\n",
+                "                           ^\n"
+            )));
+            assert!(output.stdout.is_empty());
+            assert_eq!(output.status.code(), Some(2));
+        },
+    );
+}
+
+#[test]
+fn test_awk_case_insensitive_join_with_non_ascii_keys() {
+    run_test(
+        TestPlan {
+            cmd: String::from("awk"),
+            args: vec![
+                "-F,".to_string(),
+                "NR <= 2 { price[tolower($1)] = $2; next } { print toupper($1), price[tolower($1)] }"
+                    .to_string(),
+            ],
+            stdin_data: String::from("Ölfilter,12\nПЕЧЕНЬЕ,3\nölfilter\nпеченье\n"),
+            expected_out: String::from("ÖLFILTER 12\nПЕЧЕНЬЕ 3\n"),
+            expected_err: String::from(""),
+            expected_exit_code: 0,
+        }
+        // only ASCII letters are mapped in the C locale
+        .with_env("LC_ALL", "C.UTF-8"),
+    )
+}
+
+#[test]
+fn test_awk_record_is_rebuilt_with_ofs_when_read_after_a_field_assignment() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "{ $2 = \"x\"; print; OFS = \"-\"; $1 = $1; print; ",
+            "$0 = \"d e\"; print NF, $2; $5 = \"z\"; print; print NF }"
+        )
+        .to_string()],
+        stdin_data: String::from("a   b\tc\n"),
+        expected_out: String::from("a x c\na-x-c\n2-e\nd-e---z\n5\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_record_is_rebuilt_before_matching_a_regex_literal() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["{ $2 = \"x\" } /x/ { print \"match:\", $0 } NF = 1".to_string()],
+        stdin_data: String::from("a b c\n"),
+        expected_out: String::from("match: a x c\na\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_assigned_record_is_split_with_the_fs_in_effect_at_assignment() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["{ $0 = \"p,q r\"; FS = \",\"; print $1; $0 = $0; print $1 }".to_string()],
+        stdin_data: String::from("a b\n"),
+        expected_out: String::from("p,q\np\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_long_record_with_every_field_assigned() {
+    // a single record with 500000 short fields. The input is passed as a
+    // file, because feeding it through stdin in the test harness is slow
+    let fields = (0..500_000)
+        .map(|i| format!("f{}", i % 10))
+        .collect::<Vec<_>>();
+    let dir = TestDir::new("awk-long-record");
+    let input_path = dir.file("input", fields.join(" ") + "\n");
+    let start = std::time::Instant::now();
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "{ for (i = 1; i <= NF; i++) $i = $i \"x\"; print length($0), NF, $NF }".to_string(),
+            input_path.to_str().unwrap().to_string(),
+        ],
+        stdin_data: String::from(""),
+        expected_out: String::from("1999999 500000 f9x\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    });
+    let elapsed = start.elapsed();
+    // the record is only rebuilt once, when it is printed. Rebuilding it after
+    // each assignment would take minutes
+    assert!(
+        elapsed < std::time::Duration::from_secs(10),
+        "took {elapsed:?}"
+    );
+}
+
+#[test]
+fn test_awk_typical_programs_over_generated_input() {
+    // The programs measured by `benches/records.rs`, over a smaller input
+    const LINES: usize = 2000;
+    let input = String::from_utf8(generate::lines(LINES, 1963)).unwrap();
+    let (mut fields, mut long_records, mut with_e, mut one_letter, mut modified_len) =
+        (0, 0, 0, 0, 0);
+    for line in input.lines() {
+        let words: Vec<&str> = line.split(' ').collect();
+        fields += words.len();
+        if words.len() > 5 {
+            long_records += 1;
+        }
+        if line.contains('e') {
+            with_e += 1;
+        }
+        if words[0].len() == 1 {
+            one_letter += 1;
+        }
+        modified_len += if words.len() < 2 {
+            line.len() + 2
+        } else {
+            line.len() - words[1].len() + 1
+        };
+    }
+    let dir = TestDir::new("awk-typical-programs");
+    let input_path = dir.file("input", input);
+    for (program, expected_out) in [
+        ("{ n += NF } END { print n }", fields),
+        ("NF > 5 { n++ } END { print n + 0 }", long_records),
+        ("/e/ { n++ } END { print n + 0 }", with_e),
+        ("{ a[length($1)]++ } END { print a[1] + 0 }", one_letter),
+        (
+            "{ $2 = \"q\"; n += length($0) } END { print n + 0 }",
+            modified_len,
+        ),
+    ] {
+        run_test(TestPlan {
+            cmd: String::from("awk"),
+            args: vec![
+                program.to_string(),
+                input_path.to_str().unwrap().to_string(),
+            ],
+            stdin_data: String::new(),
+            expected_out: format!("{}\n", expected_out),
+            expected_err: String::new(),
+            expected_exit_code: 0,
+        });
+    }
+}
+
+#[test]
+fn test_awk_record_with_many_fields() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        // `$1` is referenced before `$3000` adds the fields after the last one
+        args: vec![
+            "{ print NF; $1 = ($3000 = 1); print NF, length($0), $1 $2999 \"|\" $3000 }"
+                .to_string(),
+        ],
+        stdin_data: "x ".repeat(2000) + "\n",
+        expected_out: String::from("2000\n3000 5000 1|1\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_decreasing_nf_truncates_the_record() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-F:".to_string(),
+            "{ NF = 2; print; print NF, \"[\" $3 \"]\" }".to_string(),
+        ],
+        stdin_data: String::from("a:b:c\n"),
+        expected_out: String::from("a b\n2 []\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_increasing_nf_adds_empty_fields() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-F:".to_string(),
+            "-v".to_string(),
+            "OFS=-".to_string(),
+            "{ NF = 5; print; print NF, \"[\" $4 \"]\" }".to_string(),
+        ],
+        stdin_data: String::from("a:b\n"),
+        expected_out: String::from("a-b---\n5-[]\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_assigning_a_field_after_nf_extends_the_record() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-v".to_string(),
+            "OFS=,".to_string(),
+            "{ $7 = \"x\"; print; print NF }".to_string(),
+        ],
+        stdin_data: String::from("a  b\tc\n"),
+        expected_out: String::from("a,b,c,,,,x\n7\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_referencing_a_field_after_nf_does_not_change_the_record() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["{ x = $9; print NF, \"[\" x \"]\", length($9); print }".to_string()],
+        stdin_data: String::from("a  b c\n"),
+        expected_out: String::from("3 [] 0\na  b c\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_assigning_the_first_field_normalizes_whitespace() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["{ $1 = $1; print }".to_string()],
+        stdin_data: String::from("  a    b\tc \n\t\nd\n"),
+        expected_out: String::from("a b c\n\nd\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_setting_nf_to_zero_empties_the_record() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["{ NF = 0; print \"[\" $0 \"]\", NF; NF++; $NF = \"z\"; print }".to_string()],
+        stdin_data: String::from("a b c\n"),
+        expected_out: String::from("[] 0\nz\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_negative_nf_is_an_error() {
+    run_test_with_checker(
+        TestPlan {
+            cmd: String::from("awk"),
+            args: vec!["{ NF = -1 }".to_string()],
+            stdin_data: String::from("a b c\n"),
+            expected_out: String::from(""),
+            expected_err: String::from(""),
+            expected_exit_code: 2,
+        },
+        |plan, output| {
+            assert!(output.stdout.is_empty());
+            assert!(String::from_utf8_lossy(&output.stderr)
+                .contains("NF cannot be set to a negative value"));
+            assert_eq!(output.status.code(), Some(plan.expected_exit_code));
+        },
+    )
+}
+
+#[test]
+fn test_awk_dynamic_regexes_match_like_regex_literals() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "{ print ($0 ~ /a\\.b/) ($0 ~ \"a\\\\.b\") ($0 ~ \"a\\.b\"), ",
+            "($0 ~ /\\t/) ($0 ~ \"\\t\") ($0 ~ \"\\\\t\"), ",
+            "($0 ~ /\\//) ($0 ~ \"\\\\/\") ($0 ~ \"/\"), ",
+            "($0 ~ /\\\\/) ($0 ~ \"\\\\\\\\\") }"
+        )
+        .to_string()],
+        stdin_data: String::from("a.b\naxb\n\t\na/b\na\\b\n"),
+        expected_out: String::from(
+            "111 000 000 00\n000 000 000 00\n000 111 000 00\n000 000 111 00\n000 000 000 11\n",
+        ),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_dynamic_regexes_with_octal_escapes_match_like_regex_literals() {
+    // a dot in a bracket expression doesn't need a backslash, and an octal
+    // escape stands for a single byte
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "{ s = t = $0; print gsub(/[\\056]/, \"X\", s), gsub(\"[\\\\056]\", \"X\", t), s, t; ",
+            "s = t = $0; print gsub(/\\303\\251/, \"e\", s), gsub(\"\\\\303\\\\251\", \"e\", t), s, t }"
+        )
+        .to_string()],
+        stdin_data: String::from("a\\b.\ncafé\n"),
+        expected_out: String::from(
+            "1 1 a\\bX a\\bX\n0 0 a\\b. a\\b.\n0 0 café café\n1 1 cafe cafe\n",
+        ),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_dynamic_regexes_in_builtins_and_separators() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "BEGIN { FS = \"\\t\" } ",
+            "{ n = split($2, parts, \"\\\\.\"); s = $1; sub(\"\\\\.\", \"-\", s); ",
+            "print $1, n, parts[1], s }"
+        )
+        .to_string()],
+        stdin_data: String::from("a.b.c\tx.y\n"),
+        expected_out: String::from("a.b.c 2 x a-b.c\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_dynamic_regex_with_trailing_backslash_is_an_error() {
+    run_test_with_checker(
+        TestPlan {
+            cmd: String::from("awk"),
+            args: vec!["{ s = \"\\\\\"; print ($0 ~ s) }".to_string()],
+            stdin_data: String::from("a\\b\n"),
+            expected_out: String::from(""),
+            expected_err: String::from(""),
+            expected_exit_code: 2,
+        },
+        |plan, output| {
+            assert!(output.stdout.is_empty());
+            assert!(String::from_utf8_lossy(&output.stderr).contains("regex error in /\\/"));
+            assert_eq!(output.status.code(), Some(plan.expected_exit_code));
+        },
+    )
+}
+
+#[test]
+fn test_awk_range_pattern_retriggers_and_runs_to_end_of_input() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["/BEGIN/, /END/ { print NR \": \" $0 }".to_string()],
+        stdin_data: String::from("a\nBEGIN\nb\nEND\nc\nBEGIN\nd\nBEGIN\ne\n"),
+        expected_out: String::from("2: BEGIN\n3: b\n4: END\n6: BEGIN\n7: d\n8: BEGIN\n9: e\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_range_pattern_where_both_patterns_match_the_same_record() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["/x/, /x/ { printf \"%s \", $0 } END { print \"\" }".to_string()],
+        stdin_data: String::from("1\nx2\n3\nx4\nx5\n6\n"),
+        expected_out: String::from("x2 x4 x5 \n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_range_pattern_with_expressions() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "NR == 3, NR == 5 { printf \"a%s \", $0 } ",
+            "$0 % 3 == 0, $0 % 2 == 0 { printf \"b%s \", $0 } ",
+            "END { print \"\" }"
+        )
+        .to_string()],
+        stdin_data: String::from("1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n"),
+        expected_out: String::from("a3 b3 a4 b4 a5 b6 b9 b10 \n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_uninitialized_values_are_both_empty_string_and_zero() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "function f(p) { print (p == 0 && p == \"\"), \"[\" p \"]\", length(p) } ",
+            "{ if (x == 0 && x == \"\") print \"x is 0 and empty\"; ",
+            "print x \"\"; print \"[\" x \"]\", length(x), (x < 1), (x < \"a\"); ",
+            "print (a[\"k\"] == 0 && a[\"k\"] == \"\"), length(a[\"k\"]), (\"k\" in a); ",
+            "print ($5 == 0 && $5 == \"\"), length($5), NF; ",
+            "f(); f(u); ",
+            "n++; s = s $1; print n, s }"
+        )
+        .to_string()],
+        stdin_data: String::from("word\n"),
+        expected_out: String::from(concat!(
+            "x is 0 and empty\n",
+            "\n",
+            "[] 0 1 1\n",
+            "1 0 1\n",
+            "1 0 1\n",
+            "1 [] 0\n",
+            "1 [] 0\n",
+            "1 word\n"
+        )),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_gsub_on_a_field_rebuilds_the_record() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-v".to_string(),
+            "OFS=-".to_string(),
+            "{ n = gsub(/o/, \"0\", $2); print $0; print n, NF }".to_string(),
+        ],
+        stdin_data: String::from("foo  boo\tzoo\n"),
+        expected_out: String::from("foo-b00-zoo\n2-3\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_gsub_without_matches_leaves_the_record_unchanged() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["{ n = gsub(/x/, \"y\", $2); print n, $0 }".to_string()],
+        stdin_data: String::from("a   b\tc\n"),
+        expected_out: String::from("0 a   b\tc\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_sub_on_the_record_splits_the_fields_again() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "{ sub(/x/, \"y z\"); print NF, $2, $3; gsub(/ /, \":\"); print NF, $1 }".to_string(),
+        ],
+        stdin_data: String::from("ax bx\n"),
+        expected_out: String::from("3 z bx\n1 ay:z:bx\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_assigning_the_record_splits_the_fields_again() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["{ $0 = \"a b c\"; print NF, $2; $0 = $3; print NF, $1 }".to_string()],
+        stdin_data: String::from("x\n"),
+        expected_out: String::from("3 b\n1 c\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_match_sets_rstart_and_rlength() {
+    run_test(
+        TestPlan {
+            cmd: String::from("awk"),
+            args: vec![concat!(
+            "{ r = match($0, /[0-9]+/); print r, RSTART, RLENGTH, substr($0, RSTART, RLENGTH); ",
+            "r = match($0, \"z\"); print r, RSTART, RLENGTH; ",
+            "r = match($0, /x*/); print r, RSTART, RLENGTH }"
+        )
+            .to_string()],
+            stdin_data: String::from("wörd 1234 end\n"),
+            expected_out: String::from("6 6 4 1234\n0 0 -1\n1 1 0\n"),
+            expected_err: String::from(""),
+            expected_exit_code: 0,
+        }
+        .with_env("LC_ALL", "C.UTF-8"),
+    )
+}
+
+/// Runs `program` on `stdin` in a UTF-8 locale, where the string builtins count
+/// characters, and in the C locale, where they count bytes.
+/// The output in the C locale is given as bytes, because it can contain
+/// parts of characters.
+fn test_awk_in_utf8_and_c_locales(
+    program: &str,
+    stdin: &str,
+    utf8_out: &str,
+    c_out: impl AsRef<[u8]>,
+) {
+    for (locale, expected_out) in [("C.UTF-8", utf8_out.as_bytes()), ("C", c_out.as_ref())] {
+        run_test_with_checker(
+            TestPlan {
+                cmd: String::from("awk"),
+                args: vec![program.to_string()],
+                stdin_data: String::from(stdin),
+                expected_out: String::new(),
+                expected_err: String::new(),
+                expected_exit_code: 0,
+            }
+            .with_env("LC_ALL", locale),
+            |_, output| {
+                assert_eq!(
+                    output.stdout,
+                    expected_out,
+                    "unexpected stdout in the {} locale: {:?}",
+                    locale,
+                    String::from_utf8_lossy(&output.stdout)
+                );
+                assert!(output.stderr.is_empty());
+                assert_eq!(output.status.code(), Some(0));
+            },
+        );
+    }
+}
+
+// 'é' is two bytes long and '€' is three bytes long in UTF-8
+
+#[test]
+fn test_awk_length_of_multibyte_strings() {
+    test_awk_in_utf8_and_c_locales(
+        "{ print length($0), length }",
+        "héllo\n€€x\n",
+        "5 5\n3 3\n",
+        "6 6\n7 7\n",
+    );
+}
+
+#[test]
+fn test_awk_index_in_multibyte_strings() {
+    test_awk_in_utf8_and_c_locales(
+        "{ print index($0, \"l\"), index($0, \"x\"), index($0, \"€x\") }",
+        "héllo\n€€x\n",
+        "3 0 0\n0 3 2\n",
+        "4 0 0\n0 7 4\n",
+    );
+}
+
+#[test]
+fn test_awk_substr_of_multibyte_strings() {
+    // in the C locale, the bytes of a split character are output as they are
+    test_awk_in_utf8_and_c_locales(
+        "{ print substr($0, 2, 2) \"|\" substr($0, 3) }",
+        "héllo\n€€x\n",
+        "él|llo\n€x|x\n",
+        b"\xC3\xA9|\xA9llo\n\x82\xAC|\xAC\xE2\x82\xACx\n",
+    );
+}
+
+#[test]
+fn test_awk_substr_halves_of_a_character_join_back() {
+    test_awk_in_utf8_and_c_locales(
+        "{ print substr($0, 1, 1) substr($0, 2) }",
+        "é\n",
+        "é\n",
+        "é\n",
+    );
+}
+
+#[test]
+fn test_awk_match_in_multibyte_strings() {
+    test_awk_in_utf8_and_c_locales(
+        "{ print match($0, /é?l+|€x/), RSTART, RLENGTH; print match($0, /o|x/), RLENGTH }",
+        "héllo\n€€x\n",
+        "2 2 3\n5 1\n2 2 2\n3 1\n",
+        "2 2 4\n6 1\n4 4 4\n7 1\n",
+    );
+}
+
+#[test]
+fn test_awk_gsub_in_multibyte_strings() {
+    // regexes match characters where the builtins count them
+    test_awk_in_utf8_and_c_locales(
+        "{ s = $0; print gsub(/./, \"x\", s), s, match($0, /^.a/) }",
+        "éa\n",
+        "2 xx 1\n",
+        "3 xxx 0\n",
+    );
+}
+
+#[test]
+fn test_awk_printf_c_of_multibyte_strings() {
+    // numbers are code points in a UTF-8 locale, and are converted to a byte
+    // in the C locale, so 8364 (0x20AC) is 0xAC
+    test_awk_in_utf8_and_c_locales(
+        "{ printf \"%c|%c|%3c|\\n\", $0, substr($0, 2), 8364 }",
+        "héllo\n€€x\n",
+        "h|é|  €|\n€|€|  €|\n",
+        b"h|\xC3|  \xAC|\n\xE2|\x82|  \xAC|\n",
+    );
+}
+
+#[test]
+fn test_awk_bare_length_as_a_pattern() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["length > 3".to_string()],
+        stdin_data: String::from("ab\nabcd\n\nabcdef\nabc\n"),
+        expected_out: String::from("abcd\nabcdef\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_bare_length_in_expressions() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "{ print length, length - 1, length + 1, length * 2, -length, length $0, ",
+            "(length), length(), length ? \"y\" : \"n\"; x = length\n",
+            "print x }"
+        )
+        .to_string()],
+        stdin_data: String::from("abc\n"),
+        expected_out: String::from("3 2 4 6 -3 3abc 3 3 y\n3\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_length_of_an_array() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "function count(arr) { return length(arr) } ",
+            "BEGIN { a[1]; a[2] = 2; a[\"x\"] = 3; print length(a); ",
+            "delete a[2]; print length(a), count(a); delete a; print length(a) }"
+        )
+        .to_string()],
+        stdin_data: String::from(""),
+        expected_out: String::from("3\n2 2\n0\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_getline_from_stdin_in_begin_then_process_records() {
+    for name in ["-", "/dev/stdin"] {
+        run_test(TestPlan {
+            cmd: String::from("awk"),
+            args: vec![format!(
+                "BEGIN {{ getline header < \"{}\"; print \"header:\", header }} {{ print NR \": \" $0 }}",
+                name
+            )],
+            stdin_data: String::from("name\nalice\nbob\n"),
+            expected_out: String::from("header: name\n1: alice\n2: bob\n"),
+            expected_err: String::from(""),
+            expected_exit_code: 0,
+        })
+    }
+}
+
+#[test]
+fn test_awk_getline_from_stdin_interleaves_with_the_main_input() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["{ r = getline x < \"-\"; print $0, r, x }".to_string()],
+        stdin_data: String::from("1\n2\n3\n"),
+        expected_out: String::from("1 1 2\n3 0 2\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_getline_from_stdin_while_reading_file_operands() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "{ r = getline x < \"-\"; print $0, r, x }".to_string(),
+            "tests/awk/test_data4.txt".to_string(),
+        ],
+        stdin_data: String::from("a\nb\n"),
+        expected_out: String::from("1:1;1 1 a\n2,2:2 1 b\n3;3:3 0 b\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_dev_stdin_file_operand_reads_standard_input() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "{ print FILENAME, FNR, $0 }".to_string(),
+            "tests/awk/test_data4.txt".to_string(),
+            "/dev/stdin".to_string(),
+        ],
+        stdin_data: String::from("a\n"),
+        expected_out: String::from(concat!(
+            "tests/awk/test_data4.txt 1 1:1;1\n",
+            "tests/awk/test_data4.txt 2 2,2:2\n",
+            "tests/awk/test_data4.txt 3 3;3:3\n",
+            "/dev/stdin 1 a\n"
+        )),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_printf_with_missing_and_extra_arguments() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "BEGIN { printf \"%s-%d\\n\"; printf \"%s-%d\\n\", \"a\"; ",
+            "printf \"%s-%d\\n\", \"a\", 2, 3, 4, 5; printf \"[%c]\\n\", \"\" }"
+        )
+        .to_string()],
+        stdin_data: String::from(""),
+        expected_out: String::from("-0\na-0\na-2\n[]\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_output_file_is_truncated_again_after_close() {
+    let dir = TestDir::new("awk-reopen-after-close");
+    let path = dir.path().join("out").to_str().unwrap().to_string();
+
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-v".to_string(),
+            format!("f={}", path),
+            concat!(
+                "BEGIN { print \"first\" > f; r1 = close(f); print \"second\" > f; r2 = close(f); ",
+                "while ((getline line < f) > 0) print \"read:\", line; print r1, r2, close(f) }"
+            )
+            .to_string(),
+        ],
+        stdin_data: String::from(""),
+        expected_out: String::from("read: second\n0 0 0\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_close_returns_the_status_of_input_pipes() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![concat!(
+            "BEGIN { \"echo hi; exit 2\" | getline v; r = close(\"echo hi; exit 2\"); ",
+            "\"echo again\" | getline w; print v, r, w, close(\"echo again\"); ",
+            "print \"x\" | \"kill -9 $$\"; print close(\"kill -9 $$\") }"
+        )
+        .to_string()],
+        stdin_data: String::from(""),
+        expected_out: String::from("hi 2 again 0\n265\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_numbers_use_the_c_decimal_point_in_any_locale() {
+    // the harness can't set the environment, so run the binary directly
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_awk"))
+        .env("LC_ALL", "de_DE.UTF-8")
+        .args([concat!(
+            "BEGIN { print 3.5 * 2, 1.5, 0.1 + 0.2; printf \"%.2f %e %g|\", 3.5, 1234.5, 0.000125 } ",
+            "{ print $1 + 0, $2 + 0, $1 \"\" }"
+        )])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::io::Write::write_all(child.stdin.as_mut().unwrap(), b"3.25 1,5\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "7 1.5 0.3\n3.50 1.234500e+03 0.000125|3.25 1 3.25\n"
+    );
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_awk_large_csv_input() {
+    const ROWS: usize = 50_000;
+    const COLS: usize = 8;
+    // the sum of the numeric fields, which awk computes from every record
+    let table = String::from_utf8(generate::csv(ROWS, COLS, 1990)).unwrap();
+    let sum = table
+        .lines()
+        .flat_map(|row| row.split(','))
+        .filter_map(|field| field.parse::<u64>().ok())
+        .sum::<u64>();
+
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![String::from("-F,"), String::from(
+            "{ n += NF; for (i = 1; i <= NF; i++) if ($i ~ /^[0-9]+$/) s += $i } END { print NR, n, s }",
+        )],
+        stdin_data: String::new(),
+        expected_out: format!("{ROWS} {} {sum}\n", ROWS * COLS),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    }
+    .with_options(RunOptions {
+        timeout: Some(Duration::from_secs(60)),
+        stdin: Some(StdinSource::Generator {
+            generate: Box::new(|out| generate::write_csv(out, ROWS, COLS, 1990)),
+            chunk_size: plib::BUFSZ,
+            delay: None,
+        }),
+        ..Default::default()
+    }))
+}
+
+#[test]
+fn test_awk_ends_quietly_when_its_reader_exits() {
+    let first_line = String::from_utf8(generate::lines(1, 1993)).unwrap();
+    run_pipeline(vec![
+        TestPlan {
+            cmd: String::from("awk"),
+            args: vec![String::from("{ print; printf \"%s\\n\", $1 }")],
+            stdin_data: String::new(),
+            expected_out: String::new(),
+            expected_err: String::from(""),
+            expected_exit_code: 0,
+        }
+        .with_options(RunOptions {
+            // far more than a pipe holds, so awk is still writing when head exits
+            stdin: Some(StdinSource::Generator {
+                generate: Box::new(|out| generate::write_lines(out, 100_000, 1993)),
+                chunk_size: plib::BUFSZ,
+                delay: None,
+            }),
+            expect_signal: Some(libc::SIGPIPE),
+            ..Default::default()
+        }),
+        TestPlan {
+            cmd: String::from("head"),
+            args: vec![String::from("-n"), String::from("1")],
+            stdin_data: String::new(),
+            expected_out: first_line,
+            expected_err: String::from(""),
+            expected_exit_code: 0,
+        }
+        .into(),
+    ])
+}
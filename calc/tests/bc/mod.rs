@@ -12,7 +12,7 @@ use plib::testing::{run_test, TestPlan};
 fn test_bc(program: &str, expected_output: &str) {
     run_test(TestPlan {
         cmd: String::from("bc"),
-        args: vec![],
+        args: Vec::<String>::new(),
         stdin_data: program.to_string(),
         expected_out: String::from(expected_output),
         expected_err: String::from(""),
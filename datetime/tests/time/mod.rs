@@ -7,87 +7,42 @@
 // SPDX-License-Identifier: MIT
 //
 
-use std::{
-    io::Write,
-    process::{Command, Output, Stdio},
-};
+use plib::testing::{run, TestPlan};
 
-use plib::testing::TestPlan;
-
-fn run_test_base(cmd: &str, args: &Vec<String>, stdin_data: &[u8]) -> Output {
-    let relpath = if cfg!(debug_assertions) {
-        format!("target/debug/{}", cmd)
-    } else {
-        format!("target/release/{}", cmd)
-    };
-    let test_bin_path = std::env::current_dir()
-        .unwrap()
-        .parent()
-        .unwrap() // Move up to the workspace root from the current package directory
-        .join(relpath); // Adjust the path to the binary
-
-    let mut command = Command::new(test_bin_path);
-    let mut child = command
-        .args(args)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .expect("failed to spawn head");
-
-    let stdin = child.stdin.as_mut().expect("failed to get stdin");
-    stdin
-        .write_all(stdin_data)
-        .expect("failed to write to stdin");
-
-    let output = child.wait_with_output().expect("failed to wait for child");
-    output
-}
-
-fn get_output(plan: TestPlan) -> Output {
-    let output = run_test_base(&plan.cmd, &plan.args, plan.stdin_data.as_bytes());
-
-    output
-}
-
-fn run_test_time(
-    args: &[&str],
-    expected_output: &str,
-    expected_error: &str,
-    expected_exit_code: i32,
-) {
-    let str_args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
-
-    let output = get_output(TestPlan {
+/// Runs time with `args` and checks that its standard error contains
+/// `expected_error`, whatever its exit status and standard output.
+fn run_test_time(args: &[&str], expected_error: &str) {
+    let plan: TestPlan = TestPlan {
         cmd: String::from("time"),
-        args: str_args,
-        stdin_data: String::new(),
-        expected_out: String::from(expected_output),
-        expected_err: String::from(expected_error),
-        expected_exit_code,
-    });
+        args: args.iter().map(|s| String::from(*s)).collect(),
+        ..Default::default()
+    };
+    let output = run(plan);
 
     let stderr = String::from_utf8_lossy(&output.stderr);
 
-    assert!(stderr.contains(expected_error));
+    assert!(
+        stderr.contains(expected_error),
+        "stderr does not contain {expected_error:?}\nstderr: {stderr:?}"
+    );
 }
 
 #[test]
 fn simple_test() {
-    run_test_time(&["--", "ls", "-l"], "", "User time", 0);
+    run_test_time(&["--", "ls", "-l"], "User time");
 }
 
 #[test]
 fn p_test() {
-    run_test_time(&["-p", "--", "ls", "-l"], "", "user", 0);
+    run_test_time(&["-p", "--", "ls", "-l"], "user");
 }
 
 #[test]
 fn parse_error_test() {
-    run_test_time(&[], "", "not provided", 0);
+    run_test_time(&[], "not provided");
 }
 
 #[test]
 fn command_error_test() {
-    run_test_time(&["-s", "ls", "-l"], "", "unexpected argument '-s' found", 0);
+    run_test_time(&["-s", "ls", "-l"], "unexpected argument '-s' found");
 }
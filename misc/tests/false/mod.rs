@@ -12,7 +12,7 @@ use plib::testing::{run_test, TestPlan};
 fn truefalse_test(cmd: &str, expected_exit_code: i32) {
     run_test(TestPlan {
         cmd: cmd.to_string(),
-        args: Vec::new(),
+        args: Vec::<String>::new(),
         stdin_data: String::new(),
         expected_out: String::new(),
         expected_err: String::new(),
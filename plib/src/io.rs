@@ -7,8 +7,9 @@
 // SPDX-License-Identifier: MIT
 //
 
+use std::fmt;
 use std::fs;
-use std::io::{self, Read};
+use std::io::{self, BufRead, Read, Write};
 use std::path::PathBuf;
 
 /// open file, or stdin
@@ -38,3 +39,199 @@ pub fn input_reader(
     let file = input_stream(pathname, dashed_stdin)?;
     Ok(io::BufReader::new(file))
 }
+
+/// Name of an input operand of a utility.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SourceName {
+    /// The operand `-`, which stands for the standard input.
+    Stdin,
+    File(PathBuf),
+}
+
+impl fmt::Display for SourceName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceName::Stdin => write!(f, "stdin"),
+            SourceName::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// The input operands of a utility, opened in order.
+///
+/// Each `-` operand reads the standard input, and no operands at all read it
+/// once. A file that cannot be opened is returned with its error, so that the
+/// utility can report it and go on with the next operand.
+pub struct InputSources {
+    operands: std::vec::IntoIter<PathBuf>,
+}
+
+impl InputSources {
+    pub fn new(operands: Vec<PathBuf>) -> Self {
+        let operands = if operands.is_empty() {
+            vec![PathBuf::from("-")]
+        } else {
+            operands
+        };
+        InputSources {
+            operands: operands.into_iter(),
+        }
+    }
+}
+
+impl Iterator for InputSources {
+    type Item = (SourceName, io::Result<Box<dyn BufRead>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let operand = self.operands.next()?;
+        if operand.as_os_str() == "-" {
+            let reader: Box<dyn BufRead> = Box::new(io::stdin().lock());
+            return Some((SourceName::Stdin, Ok(reader)));
+        }
+        let reader = fs::File::open(&operand)
+            .map(|file| Box::new(io::BufReader::new(file)) as Box<dyn BufRead>);
+        Some((SourceName::File(operand), reader))
+    }
+}
+
+/// Reports the errors of the input operands of a utility, and remembers
+/// whether there were any for its exit code.
+pub struct InputErrors {
+    util: &'static str,
+    failed: bool,
+}
+
+impl InputErrors {
+    pub fn new(util: &'static str) -> Self {
+        InputErrors {
+            util,
+            failed: false,
+        }
+    }
+
+    /// Prints `error` for the operand `name` on standard error.
+    pub fn report(&mut self, name: &SourceName, error: impl fmt::Display) {
+        eprintln!("{}: {}: {}", self.util, name, error);
+        self.failed = true;
+    }
+
+    pub fn any(&self) -> bool {
+        self.failed
+    }
+}
+
+/// Ends the process like utilities written in C do when the reader of their
+/// output has exited: by the default action of `SIGPIPE`, without a message.
+/// Rust programs ignore `SIGPIPE`, so without this, writing to a closed pipe
+/// is just an error.
+pub fn exit_on_epipe() -> ! {
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+        libc::raise(libc::SIGPIPE);
+    }
+    // only reached when SIGPIPE is blocked
+    std::process::exit(128 + libc::SIGPIPE)
+}
+
+/// A writer that ends the process with [`exit_on_epipe`] when the reader of
+/// its pipe has exited, instead of returning [`io::ErrorKind::BrokenPipe`].
+/// Other errors are returned as they are.
+pub struct ExitOnBrokenPipe<W>(W);
+
+impl<W: Write> ExitOnBrokenPipe<W> {
+    pub fn new(inner: W) -> Self {
+        ExitOnBrokenPipe(inner)
+    }
+}
+
+fn exit_if_broken_pipe<T>(result: io::Result<T>) -> io::Result<T> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => exit_on_epipe(),
+        result => result,
+    }
+}
+
+impl<W: Write> Write for ExitOnBrokenPipe<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        exit_if_broken_pipe(self.0.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        exit_if_broken_pipe(self.0.flush())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestDir;
+
+    #[test]
+    fn test_input_sources() {
+        let dir = TestDir::new("plib-input-sources");
+        let file = dir.file("a", "a\n");
+        let missing = dir.path().join("missing");
+
+        let sources =
+            InputSources::new(vec![file.clone(), "-".into(), missing.clone()]).collect::<Vec<_>>();
+        let names = sources
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                SourceName::File(file),
+                SourceName::Stdin,
+                SourceName::File(missing)
+            ]
+        );
+        let mut results = sources.into_iter().map(|(_, reader)| reader);
+        let mut contents = String::new();
+        results
+            .next()
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "a\n");
+        results.next().unwrap().unwrap();
+        let error = results.next().unwrap().err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+
+        let names = InputSources::new(Vec::new())
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, [SourceName::Stdin]);
+        assert_eq!(SourceName::Stdin.to_string(), "stdin");
+    }
+
+    #[test]
+    fn test_exit_on_broken_pipe() {
+        use std::os::fd::FromRawFd;
+        use std::os::unix::process::ExitStatusExt;
+
+        // the test runs in a child, which the broken pipe ends
+        if std::env::var_os("PLIB_TEST_BROKEN_PIPE").is_some() {
+            let mut fds = [0; 2];
+            assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+            unsafe { libc::close(fds[0]) };
+            let writer = unsafe { fs::File::from_raw_fd(fds[1]) };
+            let mut out = ExitOnBrokenPipe::new(writer);
+            let _ = out.write_all(b"lost\n");
+            unreachable!("the write should have ended the process");
+        }
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "io::tests::test_exit_on_broken_pipe"])
+            .env("PLIB_TEST_BROKEN_PIPE", "1")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.signal(), Some(libc::SIGPIPE), "{output:?}");
+
+        // other errors are returned
+        let mut buf = [0u8; 2];
+        let mut out = ExitOnBrokenPipe::new(&mut buf[..]);
+        let error = out.write_all(b"abc").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WriteZero);
+    }
+}
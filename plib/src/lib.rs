@@ -14,7 +14,9 @@ pub mod lzw;
 pub mod modestr;
 pub mod platform;
 pub mod priority;
+pub mod regex;
 pub mod sccsfile;
+pub mod signals;
 pub mod testing;
 pub mod utmpx;
 
@@ -0,0 +1,683 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Safe wrapper around the POSIX `regcomp` and `regexec` functions.
+
+use std::borrow::Cow;
+use std::ffi::CString;
+use std::ops::{BitOr, Range};
+use std::ptr;
+use std::sync::Arc;
+
+#[cfg(any(
+    all(target_os = "linux", target_env = "gnu"),
+    target_os = "android",
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+const REG_STARTEND: libc::c_int = libc::REG_STARTEND;
+
+/// `REG_STARTEND` is not available on this platform, so strings have to be
+/// copied and NUL terminated before they are matched.
+#[cfg(not(any(
+    all(target_os = "linux", target_env = "gnu"),
+    target_os = "android",
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+const REG_STARTEND: libc::c_int = 0;
+
+/// Prepares `string` to be passed to `regexec`.
+///
+/// When `REG_STARTEND` is supported the bytes are used as they are. Otherwise
+/// they are copied up to the first NUL and a terminator is appended, so the
+/// returned slice is one byte longer than the part that can be matched.
+fn exec_subject(string: &[u8]) -> Cow<'_, [u8]> {
+    if REG_STARTEND != 0 {
+        Cow::Borrowed(string)
+    } else {
+        let end = string.iter().position(|&b| b == 0).unwrap_or(string.len());
+        let mut subject = Vec::with_capacity(end + 1);
+        subject.extend_from_slice(&string[..end]);
+        subject.push(b'\0');
+        Cow::Owned(subject)
+    }
+}
+
+/// Returns the number of bytes of a subject returned by `exec_subject` that can
+/// be matched.
+fn subject_len(subject: &[u8]) -> usize {
+    if REG_STARTEND != 0 {
+        subject.len()
+    } else {
+        subject.len() - 1
+    }
+}
+
+fn regex_compilation_result(
+    status_integer: libc::c_int,
+    regex: &libc::regex_t,
+    regex_string: &CString,
+) -> Result<(), String> {
+    if status_integer != 0 {
+        // a first call with an empty buffer returns the size needed for the whole message
+        let error_size =
+            unsafe { libc::regerror(status_integer, ptr::from_ref(regex), ptr::null_mut(), 0) };
+        let mut error_buffer = vec![b'\0'; error_size.max(1)];
+        unsafe {
+            libc::regerror(
+                status_integer,
+                ptr::from_ref(regex),
+                error_buffer.as_mut_ptr() as *mut libc::c_char,
+                error_buffer.len(),
+            )
+        };
+        let message_len = error_buffer
+            .iter()
+            .position(|&b| b == b'\0')
+            .unwrap_or(error_buffer.len());
+        Err(format!(
+            "regex error in /{}/: {}",
+            regex_string.to_string_lossy(),
+            String::from_utf8_lossy(&error_buffer[..message_len])
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Flags used to compile a regex.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RegexFlags(libc::c_int);
+
+impl RegexFlags {
+    /// Use POSIX basic regular expressions, the default without `EXTENDED`.
+    pub const BASIC: RegexFlags = RegexFlags(0);
+    /// Use POSIX extended regular expressions.
+    pub const EXTENDED: RegexFlags = RegexFlags(libc::REG_EXTENDED);
+    /// Ignore case when matching.
+    pub const ICASE: RegexFlags = RegexFlags(libc::REG_ICASE);
+    /// Newlines are not matched by `.` or non-matching bracket expressions, and
+    /// `^` and `$` also match right after and before a newline.
+    pub const NEWLINE: RegexFlags = RegexFlags(libc::REG_NEWLINE);
+
+    pub fn contains(self, other: RegexFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for RegexFlags {
+    type Output = RegexFlags;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        RegexFlags(self.0 | rhs.0)
+    }
+}
+
+struct RegexInner {
+    raw_regex: libc::regex_t,
+    regex_string: CString,
+    flags: RegexFlags,
+}
+
+// SAFETY: the compiled `regex_t` is only read after `regcomp` and until
+// `regfree`, which runs once no reference is left. POSIX requires `regexec` to
+// be thread-safe, so a compiled regex can be matched from several threads at
+// once.
+unsafe impl Send for RegexInner {}
+unsafe impl Sync for RegexInner {}
+
+impl Drop for RegexInner {
+    fn drop(&mut self) {
+        unsafe {
+            libc::regfree(ptr::from_mut(&mut self.raw_regex));
+        }
+    }
+}
+
+/// A compiled regex. Cloning only increments a reference count, the compiled
+/// state is freed when the last clone is dropped.
+#[derive(Clone)]
+pub struct Regex(Arc<RegexInner>);
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RegexMatch {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl RegexMatch {
+    /// Converts a match expressed in byte offsets into `string` into one expressed in
+    /// character offsets.
+    ///
+    /// Regexes match bytes, so a match can start or end inside a multibyte
+    /// character, e.g. when `.` matches its first byte. Such a character is
+    /// counted as part of a non-empty match.
+    pub fn to_char_offsets(self, string: &str) -> RegexMatch {
+        let bytes = string.as_bytes();
+        let is_char_start = |b: &&u8| (**b & 0xC0) != 0x80;
+        let mut start = bytes[..self.start].iter().filter(is_char_start).count();
+        if bytes.get(self.start).is_some_and(|b| !is_char_start(&b)) {
+            // the first byte of the character is before the match
+            start -= 1;
+        }
+        if self.start == self.end {
+            return RegexMatch { start, end: start };
+        }
+        let end = bytes[..self.end].iter().filter(is_char_start).count();
+        RegexMatch { start, end }
+    }
+}
+
+pub struct MatchIter<'re, 's> {
+    string: Cow<'s, [u8]>,
+    len: usize,
+    next_start: usize,
+    last_match_end: Option<usize>,
+    regex: &'re Regex,
+}
+
+impl MatchIter<'_, '_> {
+    /// Returns the byte offset of the character following the one at `index`.
+    fn next_char_boundary(&self, index: usize) -> usize {
+        let mut next = index + 1;
+        while next < self.len && (self.string[next] & 0xC0) == 0x80 {
+            next += 1;
+        }
+        next
+    }
+}
+
+impl Iterator for MatchIter<'_, '_> {
+    type Item = RegexMatch;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.next_start > self.len {
+                return None;
+            }
+            let Some(RegexMatch { start, end }) =
+                self.regex
+                    .exec_first(&self.string, self.len, self.next_start)
+            else {
+                self.next_start = self.len + 1;
+                return None;
+            };
+            if start == end {
+                // an empty match is never allowed right after the previous match,
+                // and the search has to move forward by at least one character
+                self.next_start = self.next_char_boundary(start);
+                if self.last_match_end == Some(start) {
+                    continue;
+                }
+            } else {
+                self.next_start = end;
+            }
+            self.last_match_end = Some(end);
+            return Some(RegexMatch { start, end });
+        }
+    }
+}
+
+impl Regex {
+    const MAX_GROUPS: usize = 9;
+
+    pub fn new(regex: CString) -> Result<Self, String> {
+        Self::with_flags(regex, RegexFlags::EXTENDED)
+    }
+
+    pub fn with_flags(regex: CString, flags: RegexFlags) -> Result<Self, String> {
+        let mut raw = unsafe { std::mem::zeroed::<libc::regex_t>() };
+        let compilation_status =
+            unsafe { libc::regcomp(ptr::from_mut(&mut raw), regex.as_ptr(), flags.0) };
+        regex_compilation_result(compilation_status, &raw, &regex)?;
+        Ok(Self(Arc::new(RegexInner {
+            raw_regex: raw,
+            regex_string: regex,
+            flags,
+        })))
+    }
+
+    /// Returns whether `self` and `other` are clones of the same compiled regex.
+    pub fn same(&self, other: &Regex) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Finds the first match in `subject[start..len]`, where `subject` was
+    /// produced by `exec_subject`, and stores it and the matches of the groups
+    /// in `matches`. The stored offsets are relative to the beginning of `subject`.
+    fn exec(
+        &self,
+        subject: &[u8],
+        len: usize,
+        start: usize,
+        matches: &mut [libc::regmatch_t],
+    ) -> bool {
+        // `^` must only match at the real beginning of the string
+        let mut flags = if start == 0 { 0 } else { libc::REG_NOTBOL };
+        let string_ptr = if REG_STARTEND != 0 {
+            matches[0].rm_so = start as libc::regoff_t;
+            matches[0].rm_eo = len as libc::regoff_t;
+            flags |= REG_STARTEND;
+            if subject.is_empty() {
+                c"".as_ptr()
+            } else {
+                subject.as_ptr() as *const libc::c_char
+            }
+        } else {
+            unsafe { subject.as_ptr().add(start) as *const libc::c_char }
+        };
+        let exec_status = unsafe {
+            libc::regexec(
+                ptr::from_ref(&self.0.raw_regex),
+                string_ptr,
+                matches.len(),
+                matches.as_mut_ptr(),
+                flags,
+            )
+        };
+        if exec_status != 0 {
+            return false;
+        }
+        if REG_STARTEND == 0 {
+            for m in matches.iter_mut().filter(|m| m.rm_so >= 0) {
+                m.rm_so += start as libc::regoff_t;
+                m.rm_eo += start as libc::regoff_t;
+            }
+        }
+        true
+    }
+
+    /// Finds the first match in `subject[start..len]`, see `exec`.
+    fn exec_first(&self, subject: &[u8], len: usize, start: usize) -> Option<RegexMatch> {
+        let mut matches = [libc::regmatch_t {
+            rm_so: -1,
+            rm_eo: -1,
+        }];
+        self.exec(subject, len, start, &mut matches)
+            .then(|| RegexMatch {
+                start: matches[0].rm_so as usize,
+                end: matches[0].rm_eo as usize,
+            })
+    }
+
+    /// Returns the byte ranges of the first match in `string` at or after
+    /// `start`, followed by those of the first nine parenthesized groups of
+    /// the regex, or `None` for the groups that did not take part in the match
+    /// or that the regex does not have. The ranges are offsets into `string`,
+    /// and `^` only matches at its beginning.
+    pub fn captures_at(&self, string: &[u8], start: usize) -> Option<Vec<Option<Range<usize>>>> {
+        let subject = exec_subject(string);
+        let len = subject_len(&subject);
+        if start > len {
+            return None;
+        }
+        let mut matches = vec![
+            libc::regmatch_t {
+                rm_so: -1,
+                rm_eo: -1,
+            };
+            Self::MAX_GROUPS + 1
+        ];
+        if !self.exec(&subject, len, start, &mut matches) {
+            return None;
+        }
+        Some(
+            matches
+                .iter()
+                .map(|m| (m.rm_so >= 0).then_some(m.rm_so as usize..m.rm_eo as usize))
+                .collect(),
+        )
+    }
+
+    /// Returns an iterator over all non overlapping matches in `string`.
+    pub fn match_locations_bytes<'s>(&self, string: &'s [u8]) -> MatchIter<'_, 's> {
+        let string = exec_subject(string);
+        MatchIter {
+            len: subject_len(&string),
+            string,
+            next_start: 0,
+            last_match_end: None,
+            regex: self,
+        }
+    }
+
+    /// Returns whether `string` contains a match for the regex.
+    pub fn matches_bytes(&self, string: &[u8]) -> bool {
+        let subject = exec_subject(string);
+        self.exec_first(&subject, subject_len(&subject), 0)
+            .is_some()
+    }
+}
+
+impl core::fmt::Debug for Regex {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "/{}/", self.0.regex_string.to_string_lossy())?;
+        if self.0.flags.contains(RegexFlags::ICASE) {
+            write!(f, "i")?;
+        }
+        if self.0.flags.contains(RegexFlags::NEWLINE) {
+            write!(f, "n")?;
+        }
+        writeln!(f)
+    }
+}
+
+impl PartialEq for Regex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.regex_string == other.0.regex_string && self.0.flags == other.0.flags
+    }
+}
+
+/// utility function for writing tests
+#[cfg(test)]
+fn regex_from_str(re: &str) -> Regex {
+    Regex::new(CString::new(re).unwrap()).expect("error compiling ere")
+}
+
+#[cfg(test)]
+fn regex_with_flags(re: &str, flags: RegexFlags) -> Regex {
+    Regex::with_flags(CString::new(re).unwrap(), flags).expect("error compiling ere")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_create_regex() {
+        regex_from_str("test");
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_matches() {
+        let ere = regex_from_str("ab*c");
+        assert!(ere.matches_bytes(b"abbbbc"));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_match_locations() {
+        let ere = regex_from_str("match");
+        let mut iter = ere.match_locations_bytes(b"match 12345 match2 matchmatch");
+        assert_eq!(iter.next(), Some(RegexMatch { start: 0, end: 5 }));
+        assert_eq!(iter.next(), Some(RegexMatch { start: 12, end: 17 }));
+        assert_eq!(iter.next(), Some(RegexMatch { start: 19, end: 24 }));
+        assert_eq!(iter.next(), Some(RegexMatch { start: 24, end: 29 }));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_match_locations_with_empty_matches() {
+        let ere = regex_from_str("x*");
+        let matches = ere.match_locations_bytes(b"abc").collect::<Vec<_>>();
+        assert_eq!(
+            matches,
+            vec![
+                RegexMatch { start: 0, end: 0 },
+                RegexMatch { start: 1, end: 1 },
+                RegexMatch { start: 2, end: 2 },
+                RegexMatch { start: 3, end: 3 },
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_match_locations_no_empty_match_after_match() {
+        let ere = regex_from_str("x*");
+        let matches = ere.match_locations_bytes(b"xxaxb").collect::<Vec<_>>();
+        assert_eq!(
+            matches,
+            vec![
+                RegexMatch { start: 0, end: 2 },
+                RegexMatch { start: 3, end: 4 },
+                RegexMatch { start: 5, end: 5 },
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_match_locations_on_empty_string() {
+        let ere = regex_from_str("a?");
+        let mut iter = ere.match_locations_bytes(b"");
+        assert_eq!(iter.next(), Some(RegexMatch { start: 0, end: 0 }));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_match_locations_anchor_matches_only_at_start() {
+        let ere = regex_from_str("^a");
+        let matches = ere.match_locations_bytes(b"aaa").collect::<Vec<_>>();
+        assert_eq!(matches, vec![RegexMatch { start: 0, end: 1 }]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_match_locations_empty_matches_skip_whole_characters() {
+        let ere = regex_from_str("x*");
+        let matches = ere
+            .match_locations_bytes("żb".as_bytes())
+            .map(|m| m.start)
+            .collect::<Vec<_>>();
+        assert_eq!(matches, vec![0, 2, 3]);
+    }
+
+    #[test]
+    fn test_match_to_char_offsets_ascii() {
+        let m = RegexMatch { start: 2, end: 4 }.to_char_offsets("abcdef");
+        assert_eq!(m, RegexMatch { start: 2, end: 4 });
+    }
+
+    #[test]
+    fn test_match_to_char_offsets_multibyte_before_match() {
+        // 'ż' and 'ó' are two bytes long, '€' is three bytes long
+        let m = RegexMatch { start: 4, end: 6 }.to_char_offsets("żółw");
+        assert_eq!(m, RegexMatch { start: 2, end: 3 });
+        let m = RegexMatch { start: 6, end: 7 }.to_char_offsets("€€x");
+        assert_eq!(m, RegexMatch { start: 2, end: 3 });
+    }
+
+    #[test]
+    fn test_match_to_char_offsets_multibyte_inside_match() {
+        let m = RegexMatch { start: 1, end: 6 }.to_char_offsets("aż€b");
+        assert_eq!(m, RegexMatch { start: 1, end: 3 });
+        let m = RegexMatch { start: 0, end: 4 }.to_char_offsets("żółw");
+        assert_eq!(m, RegexMatch { start: 0, end: 2 });
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_match_to_char_offsets_partial_characters() {
+        // `.` matches a single byte, which can be a part of a character
+        let first_char_offsets = |re: &str, string: &str| {
+            let ere = regex_from_str(re);
+            let m = ere.match_locations_bytes(string.as_bytes()).next().unwrap();
+            m.to_char_offsets(string)
+        };
+        assert_eq!(
+            first_char_offsets(".", "żb"),
+            RegexMatch { start: 0, end: 1 }
+        );
+        assert_eq!(
+            first_char_offsets(".b", "ażb"),
+            RegexMatch { start: 1, end: 3 }
+        );
+        assert_eq!(
+            first_char_offsets("a..", "a€b"),
+            RegexMatch { start: 0, end: 2 }
+        );
+        assert_eq!(
+            first_char_offsets("x.*", "€xż€"),
+            RegexMatch { start: 1, end: 4 }
+        );
+        let m = RegexMatch { start: 2, end: 2 }.to_char_offsets("€b");
+        assert_eq!(m, RegexMatch { start: 0, end: 0 });
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_matches_bytes_with_embedded_nul() {
+        let ere = regex_from_str("b+");
+        assert!(ere.matches_bytes(b"a\0b"));
+        assert!(!ere.matches_bytes(b"a\0c"));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_match_locations_bytes_with_embedded_nul() {
+        let ere = regex_from_str("b");
+        let matches = ere.match_locations_bytes(b"ab\0ab\0").collect::<Vec<_>>();
+        assert_eq!(
+            matches,
+            vec![
+                RegexMatch { start: 1, end: 2 },
+                RegexMatch { start: 4, end: 5 }
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_case_insensitive() {
+        let ere = regex_with_flags("ab+c", RegexFlags::EXTENDED | RegexFlags::ICASE);
+        assert!(ere.matches_bytes(b"xABbC"));
+        assert!(!regex_from_str("ab+c").matches_bytes(b"xABbC"));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_anchors_without_newline_flag() {
+        let ere = regex_from_str("^b$");
+        assert!(!ere.matches_bytes(b"a\nb\nc"));
+        let ere = regex_from_str("a.b");
+        assert!(ere.matches_bytes(b"a\nb"));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_anchors_with_newline_flag() {
+        let ere = regex_with_flags("^b$", RegexFlags::EXTENDED | RegexFlags::NEWLINE);
+        assert!(ere.matches_bytes(b"a\nb\nc"));
+        let matches = ere.match_locations_bytes(b"b\nb").collect::<Vec<_>>();
+        assert_eq!(
+            matches,
+            vec![
+                RegexMatch { start: 0, end: 1 },
+                RegexMatch { start: 2, end: 3 }
+            ]
+        );
+        let ere = regex_with_flags("a.b", RegexFlags::EXTENDED | RegexFlags::NEWLINE);
+        assert!(!ere.matches_bytes(b"a\nb"));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regexes_with_different_flags_are_not_equal() {
+        let ere = regex_from_str("abc");
+        assert_eq!(ere, regex_from_str("abc"));
+        assert_ne!(
+            ere,
+            regex_with_flags("abc", RegexFlags::EXTENDED | RegexFlags::ICASE)
+        );
+    }
+
+    fn compilation_error(re: &str) -> String {
+        Regex::new(CString::new(re).unwrap()).expect_err("regex should be invalid")
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_error_unbalanced_parentheses() {
+        let error = compilation_error("(ab");
+        assert!(error.starts_with("regex error in /(ab/: "), "{error}");
+        assert!(error.len() > "regex error in /(ab/: ".len());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_error_bad_bracket_expression() {
+        let error = compilation_error("[a");
+        assert!(error.starts_with("regex error in /[a/: "), "{error}");
+        assert!(error.len() > "regex error in /[a/: ".len());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_error_bad_interval() {
+        let error = compilation_error("a{2,1}");
+        assert!(error.starts_with("regex error in /a{2,1}/: "), "{error}");
+        assert!(error.len() > "regex error in /a{2,1}/: ".len());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_cloned_regex_outlives_original() {
+        let ere = regex_from_str("ab+c");
+        let clone = ere.clone();
+        assert!(ere.same(&clone));
+        drop(ere);
+        assert!(clone.matches_bytes(b"xabbc"));
+        assert_eq!(clone, regex_from_str("ab+c"));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_is_matched_from_several_threads() {
+        let ere = regex_from_str("ab+c");
+        let threads = (0..4)
+            .map(|_| {
+                let ere = ere.clone();
+                std::thread::spawn(move || (0..1000).all(|_| ere.matches_bytes(b"xabbc")))
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            assert!(thread.join().unwrap());
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_basic_regex() {
+        let bre = regex_with_flags("a+(b)", RegexFlags::BASIC);
+        assert!(bre.matches_bytes(b"a+(b)"));
+        assert!(!bre.matches_bytes(b"aab"));
+        let bre = regex_with_flags("a\\{2\\}", RegexFlags::BASIC | RegexFlags::ICASE);
+        assert!(bre.matches_bytes(b"xAa"));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_captures_at() {
+        let ere = regex_from_str("([a-z]+)=([0-9]+)?");
+        let groups = ere.captures_at(b"x=1 y=", 0).unwrap();
+        assert_eq!(groups.len(), 10);
+        assert_eq!(groups[..4], [Some(0..3), Some(0..1), Some(2..3), None]);
+        let groups = ere.captures_at(b"x=1 y=", 1).unwrap();
+        assert_eq!(groups[..3], [Some(4..6), Some(4..5), None]);
+        assert_eq!(ere.captures_at(b"x=1 y=", 7), None);
+        assert_eq!(ere.captures_at(b"x=1", 3), None);
+
+        let ere = regex_from_str("^a");
+        assert_eq!(ere.captures_at(b"aa", 1), None);
+        let ere = regex_from_str("b");
+        assert_eq!(ere.captures_at(b"a\0b", 0).unwrap()[0], Some(2..3));
+    }
+}
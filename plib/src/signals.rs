@@ -0,0 +1,289 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Names and numbers of the signals of the platform.
+
+/// Returns the signal names, without the `SIG` prefix, in the order of their
+/// numbers. Numbers with several names, like `ABRT` and `IOT`, appear once
+/// per name.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    SIGLIST.iter().map(|(name, _)| *name)
+}
+
+/// Returns the number of the signal `name`, which is case insensitive and may
+/// start with `SIG`. Real-time signals are not included, see [`parse`].
+pub fn name_to_num(name: &str) -> Option<i32> {
+    let normalized = name.to_uppercase();
+    let normalized = normalized.strip_prefix("SIG").unwrap_or(&normalized);
+    SIGLIST
+        .iter()
+        .find(|(signame, _)| *signame == normalized)
+        .map(|(_, num)| *num)
+}
+
+/// Returns the name of the signal `num`, without the `SIG` prefix.
+pub fn num_to_name(num: i32) -> Option<&'static str> {
+    SIGLIST
+        .iter()
+        .find(|(_, signo)| *signo == num)
+        .map(|(name, _)| *name)
+}
+
+/// Parses [str] into a signal number.
+///
+/// # Arguments
+///
+/// * `s` - [str] that represents the signal name or decimal signal number.
+///   Names are case insensitive and may start with `SIG`; on Linux, real-time
+///   signals are written `RTMIN`, `RTMAX`, `RTMIN+n` or `RTMAX-n`.
+///
+/// # Returns
+///
+/// Returns the parsed signal number.
+///
+/// # Errors
+///
+/// Returns an [String] error if passed invalid signal name or number.
+pub fn parse(s: &str) -> Result<i32, String> {
+    let trimmed = s.trim();
+    if !trimmed.is_empty() && trimmed.bytes().all(|b| b.is_ascii_digit()) {
+        return match trimmed.parse::<i32>() {
+            Ok(num) if (1..NSIG).contains(&num) => Ok(num),
+            _ => Err(format!("invalid signal number '{s}'")),
+        };
+    }
+
+    if let Some(num) = name_to_num(trimmed) {
+        return Ok(num);
+    }
+
+    let normalized = trimmed.to_uppercase();
+    let normalized = normalized.strip_prefix("SIG").unwrap_or(&normalized);
+    if normalized.starts_with("RTMIN") || normalized.starts_with("RTMAX") {
+        return parse_rt_signal(normalized).map_err(|err| format!("{err} '{s}'"));
+    }
+
+    Err(format!("invalid signal name '{s}'"))
+}
+
+/// Parses real-time signal name (`RTMIN`, `RTMAX`, `RTMIN+n` or `RTMAX-n`) into signal number.
+#[cfg(target_os = "linux")]
+fn parse_rt_signal(name: &str) -> Result<i32, &'static str> {
+    rt_signal_number(name, libc::SIGRTMIN(), libc::SIGRTMAX())
+}
+
+/// Parses real-time signal name into signal number.
+#[cfg(not(target_os = "linux"))]
+fn parse_rt_signal(_name: &str) -> Result<i32, &'static str> {
+    Err("real-time signals are not supported on this platform:")
+}
+
+/// Resolves real-time signal name against the given `rtmin..=rtmax` range.
+///
+/// # Arguments
+///
+/// * `name` - uppercase signal name without `SIG` prefix.
+/// * `rtmin` - lowest real-time signal number.
+/// * `rtmax` - highest real-time signal number.
+///
+/// # Errors
+///
+/// Returns an error if the name is malformed or the resulting signal is out of range.
+fn rt_signal_number(name: &str, rtmin: i32, rtmax: i32) -> Result<i32, &'static str> {
+    let (base, sign, rest) = if let Some(rest) = name.strip_prefix("RTMIN") {
+        (rtmin, '+', rest)
+    } else if let Some(rest) = name.strip_prefix("RTMAX") {
+        (rtmax, '-', rest)
+    } else {
+        return Err("invalid signal name");
+    };
+
+    let signal = if rest.is_empty() {
+        base
+    } else {
+        let n = rest
+            .strip_prefix(sign)
+            .filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+            .ok_or("invalid signal name")?;
+        let n: i32 = n.parse().map_err(|_| "real-time signal out of range")?;
+        if sign == '+' {
+            base + n
+        } else {
+            base - n
+        }
+    };
+
+    if (rtmin..=rtmax).contains(&signal) {
+        Ok(signal)
+    } else {
+        Err("real-time signal out of range")
+    }
+}
+
+/// One past the highest signal number supported by the platform.
+#[cfg(target_os = "macos")]
+pub const NSIG: i32 = 32;
+
+/// One past the highest signal number supported by the platform.
+#[cfg(target_os = "linux")]
+pub const NSIG: i32 = 65;
+
+#[cfg(target_os = "macos")]
+const SIGLIST: [(&str, i32); 31] = [
+    ("HUP", 1),
+    ("INT", 2),
+    ("QUIT", 3),
+    ("ILL", 4),
+    ("TRAP", 5),
+    ("ABRT", 6),
+    ("EMT", 7),
+    ("FPE", 8),
+    ("KILL", 9),
+    ("BUS", 10),
+    ("SEGV", 11),
+    ("SYS", 12),
+    ("PIPE", 13),
+    ("ALRM", 14),
+    ("TERM", 15),
+    ("URG", 16),
+    ("STOP", 17),
+    ("TSTP", 18),
+    ("CONT", 19),
+    ("CHLD", 20),
+    ("TTIN", 21),
+    ("TTOU", 22),
+    ("IO", 23),
+    ("XCPU", 24),
+    ("XFSZ", 25),
+    ("VTALRM", 26),
+    ("PROF", 27),
+    ("WINCH", 28),
+    ("INFO", 29),
+    ("USR1", 30),
+    ("USR2", 31),
+];
+
+#[cfg(target_os = "linux")]
+const SIGLIST: [(&str, i32); 32] = [
+    ("HUP", 1),
+    ("INT", 2),
+    ("QUIT", 3),
+    ("ILL", 4),
+    ("TRAP", 5),
+    ("ABRT", 6),
+    ("IOT", 6),
+    ("BUS", 7),
+    ("FPE", 8),
+    ("KILL", 9),
+    ("USR1", 10),
+    ("SEGV", 11),
+    ("USR2", 12),
+    ("PIPE", 13),
+    ("ALRM", 14),
+    ("TERM", 15),
+    ("STKFLT", 16),
+    ("CHLD", 17),
+    ("CONT", 18),
+    ("STOP", 19),
+    ("TSTP", 20),
+    ("TTIN", 21),
+    ("TTOU", 22),
+    ("URG", 23),
+    ("XCPU", 24),
+    ("XFSZ", 25),
+    ("VTALRM", 26),
+    ("PROF", 27),
+    ("WINCH", 28),
+    ("IO", 29),
+    ("PWR", 30),
+    ("SYS", 31),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rt_signal_bounds() {
+        assert_eq!(rt_signal_number("RTMIN", 34, 64), Ok(34));
+        assert_eq!(rt_signal_number("RTMAX", 34, 64), Ok(64));
+    }
+
+    #[test]
+    fn test_rt_signal_arithmetic() {
+        assert_eq!(rt_signal_number("RTMIN+3", 34, 64), Ok(37));
+        assert_eq!(rt_signal_number("RTMAX-2", 34, 64), Ok(62));
+        assert_eq!(rt_signal_number("RTMIN+30", 34, 64), Ok(64));
+        assert_eq!(rt_signal_number("RTMAX-30", 34, 64), Ok(34));
+        assert_eq!(rt_signal_number("RTMIN+0", 34, 64), Ok(34));
+    }
+
+    #[test]
+    fn test_rt_signal_out_of_range() {
+        assert!(rt_signal_number("RTMIN+31", 34, 64).is_err());
+        assert!(rt_signal_number("RTMAX-31", 34, 64).is_err());
+        assert!(rt_signal_number("RTMIN+99999999999", 34, 64).is_err());
+    }
+
+    #[test]
+    fn test_rt_signal_malformed() {
+        assert!(rt_signal_number("RTMIN-1", 34, 64).is_err());
+        assert!(rt_signal_number("RTMAX+1", 34, 64).is_err());
+        assert!(rt_signal_number("RTMIN+", 34, 64).is_err());
+        assert!(rt_signal_number("RTMIN+-1", 34, 64).is_err());
+        assert!(rt_signal_number("RTMINX", 34, 64).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_signal_rt() {
+        assert_eq!(parse("RTMIN"), Ok(libc::SIGRTMIN()));
+        assert_eq!(parse("sigrtmin+3"), Ok(libc::SIGRTMIN() + 3));
+        assert_eq!(parse("SIGRTMAX-1"), Ok(libc::SIGRTMAX() - 1));
+        assert!(parse("RTMAX+1").is_err());
+    }
+
+    #[test]
+    fn test_every_signal_round_trips() {
+        for (name, num) in SIGLIST {
+            assert_eq!(name_to_num(name), Some(num), "{name}");
+            assert_eq!(
+                name_to_num(&format!("sig{}", name.to_lowercase())),
+                Some(num)
+            );
+            assert_eq!(parse(name), Ok(num), "{name}");
+            assert_eq!(parse(&num.to_string()), Ok(num));
+            let canonical = num_to_name(num).unwrap();
+            assert_eq!(name_to_num(canonical), Some(num), "{name}");
+        }
+        assert_eq!(num_to_name(libc::SIGTERM), Some("TERM"));
+        assert_eq!(num_to_name(libc::SIGABRT), Some("ABRT"));
+        assert_eq!(num_to_name(0), None);
+        assert_eq!(num_to_name(NSIG), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("TERM"), Ok(libc::SIGTERM));
+        assert_eq!(parse("term"), Ok(libc::SIGTERM));
+        assert_eq!(parse("SIGTERM"), Ok(libc::SIGTERM));
+        assert_eq!(parse("SigTerm"), Ok(libc::SIGTERM));
+        assert_eq!(parse(" 9 "), Ok(libc::SIGKILL));
+        assert_eq!(parse("SIG"), Err(String::from("invalid signal name 'SIG'")));
+        assert_eq!(parse("FOO"), Err(String::from("invalid signal name 'FOO'")));
+        assert_eq!(parse("0"), Err(String::from("invalid signal number '0'")));
+        assert_eq!(
+            parse(&NSIG.to_string()),
+            Err(format!("invalid signal number '{NSIG}'"))
+        );
+        assert!(parse("-1").is_err());
+        assert!(parse("SIG9").is_err());
+        assert!(parse("").is_err());
+    }
+}
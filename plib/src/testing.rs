@@ -7,18 +7,450 @@
 // SPDX-License-Identifier: MIT
 //
 
-use std::io::Write;
-use std::process::{Command, Output, Stdio};
-use std::thread;
-use std::time::Duration;
+use std::ffi::{CString, OsStr, OsString};
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{ChildStdin, ChildStdout, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use std::{fs, thread};
 
-pub struct TestPlan {
+use crate::regex::Regex;
+use crate::signals;
+
+pub mod generate;
+
+/// A test run of `cmd`. The expected outputs are anything that converts into
+/// an [`ExpectedOutput`], so plain strings are compared exactly, and the
+/// expected exit code anything that converts into an [`ExpectedExitCode`].
+/// Options that most tests leave alone are given with
+/// [`TestPlan::with_options`] or [`TestPlan::with_env`].
+#[derive(Default)]
+pub struct TestPlan<Out = String, Err = String, Code = i32, Arg = String> {
     pub cmd: String,
-    pub args: Vec<String>,
+    /// Arguments of the command, passed as they are. They are usually
+    /// strings, but can be [`OsString`]s to test arguments that are not valid
+    /// UTF-8.
+    pub args: Vec<Arg>,
     pub stdin_data: String,
-    pub expected_out: String,
-    pub expected_err: String,
-    pub expected_exit_code: i32,
+    pub expected_out: Out,
+    pub expected_err: Err,
+    pub expected_exit_code: Code,
+}
+
+impl<Out, Err, Code, Arg> TestPlan<Out, Err, Code, Arg> {
+    /// Returns a run of this plan with `options`.
+    pub fn with_options(self, options: RunOptions) -> TestRun<Out, Err, Code, Arg> {
+        TestRun {
+            plan: self,
+            options,
+        }
+    }
+
+    /// Returns a run of this plan with the variable `name` set to `value` in
+    /// the environment of the command.
+    pub fn with_env(self, name: &str, value: &str) -> TestRun<Out, Err, Code, Arg> {
+        TestRun::from(self).with_env(name, value)
+    }
+}
+
+/// Options of a run of a [`TestPlan`] besides its input and expectations.
+#[derive(Default)]
+pub struct RunOptions {
+    /// Variables added to the environment of the command, e.g. `LC_ALL`.
+    pub env: Vec<(String, String)>,
+    /// Whether the command starts with an empty environment instead of
+    /// inheriting the one of the test.
+    pub clear_env: bool,
+    /// How long the command may run before it is killed and the test fails.
+    /// There is no limit by default.
+    pub timeout: Option<Duration>,
+    /// Input of the command, used instead of `stdin_data` when set.
+    pub stdin: Option<StdinSource>,
+    /// Applied to the standard output before it is checked.
+    pub stdout_normalizers: Vec<Normalizer>,
+    /// Applied to the standard error before it is checked.
+    pub stderr_normalizers: Vec<Normalizer>,
+    /// Working directory of the command, instead of the one of the test.
+    pub cwd: Option<PathBuf>,
+    /// Directories searched for executables before the ones in `PATH`.
+    pub path_prepend: Vec<PathBuf>,
+    /// Golden file that the standard output is compared with, instead of
+    /// `expected_out`. Relative paths are relative to the package of the test.
+    /// When the test runs with `POSIXUTILS_REGEN_GOLDEN=1` in its environment,
+    /// the file is rewritten with the actual output instead.
+    pub expected_out_file: Option<PathBuf>,
+    /// Signals sent to the command, each at its offset from the start of the
+    /// command, unless the command has exited by then.
+    pub signals: Vec<(Duration, i32)>,
+    /// Signal that must terminate the command, checked instead of
+    /// `expected_exit_code` when set.
+    pub expect_signal: Option<i32>,
+    /// Whether the test fails when processes started by the command are
+    /// still running after it exits. They are killed either way. Only
+    /// checked on Linux.
+    pub check_orphans: bool,
+}
+
+/// A [`TestPlan`] with the [`RunOptions`] of its run.
+pub struct TestRun<Out = String, Err = String, Code = i32, Arg = String> {
+    pub plan: TestPlan<Out, Err, Code, Arg>,
+    pub options: RunOptions,
+}
+
+impl<Out, Err, Code, Arg> From<TestPlan<Out, Err, Code, Arg>> for TestRun<Out, Err, Code, Arg> {
+    fn from(plan: TestPlan<Out, Err, Code, Arg>) -> Self {
+        plan.with_options(RunOptions::default())
+    }
+}
+
+impl<Out, Err, Code, Arg> TestRun<Out, Err, Code, Arg> {
+    /// Sets the variable `name` to `value` in the environment of the command.
+    pub fn with_env(mut self, name: &str, value: &str) -> Self {
+        self.options.env.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn command(&self) -> Command
+    where
+        Arg: AsRef<OsStr>,
+    {
+        let options = &self.options;
+        let mut command = test_command(&self.plan.cmd);
+        command.args(&self.plan.args);
+        if options.clear_env {
+            command.env_clear();
+        }
+        command.envs(options.env.iter().map(|(name, value)| (name, value)));
+        if !options.path_prepend.is_empty() {
+            let path = match options.env.iter().rev().find(|(name, _)| name == "PATH") {
+                Some((_, path)) => Some(OsString::from(path)),
+                None if options.clear_env => None,
+                None => std::env::var_os("PATH"),
+            };
+            let dirs = options
+                .path_prepend
+                .iter()
+                .cloned()
+                .chain(path.iter().flat_map(std::env::split_paths));
+            command.env(
+                "PATH",
+                std::env::join_paths(dirs).expect("invalid PATH entry"),
+            );
+        }
+        if let Some(dir) = &options.cwd {
+            command.current_dir(dir);
+        }
+        command
+    }
+
+    fn expected_status(&self) -> ExpectedExitCode
+    where
+        Code: Clone + Into<ExpectedExitCode>,
+    {
+        match self.options.expect_signal {
+            Some(signal) => ExpectedExitCode::Signaled(signal),
+            None => self.plan.expected_exit_code.clone().into(),
+        }
+    }
+
+    fn take_stdin(&mut self) -> StdinSource {
+        self.options
+            .stdin
+            .take()
+            .unwrap_or_else(|| StdinSource::Inline(self.plan.stdin_data.as_bytes().to_vec()))
+    }
+}
+
+/// Something that the test runners accept: a [`TestPlan`], or a [`TestRun`]
+/// of one with options.
+pub trait IntoTestRun {
+    type Out;
+    type Err;
+    type Code;
+    type Arg;
+
+    fn into_test_run(self) -> TestRun<Self::Out, Self::Err, Self::Code, Self::Arg>;
+}
+
+impl<Out, Err, Code, Arg> IntoTestRun for TestPlan<Out, Err, Code, Arg> {
+    type Out = Out;
+    type Err = Err;
+    type Code = Code;
+    type Arg = Arg;
+
+    fn into_test_run(self) -> TestRun<Out, Err, Code, Arg> {
+        self.into()
+    }
+}
+
+impl<Out, Err, Code, Arg> IntoTestRun for TestRun<Out, Err, Code, Arg> {
+    type Out = Out;
+    type Err = Err;
+    type Code = Code;
+    type Arg = Arg;
+
+    fn into_test_run(self) -> TestRun<Out, Err, Code, Arg> {
+        self
+    }
+}
+
+/// How the output of a command is checked.
+#[derive(Clone, Debug)]
+pub enum ExpectedOutput {
+    /// The output must be exactly this string.
+    Exact(String),
+    /// The output must contain this string.
+    Contains(String),
+    /// The output must match this POSIX extended regular expression. The
+    /// whole output is the subject, so `^` and `$` anchor at its start and end.
+    Regex(String),
+    /// The output must satisfy this predicate.
+    Predicate(fn(&str) -> bool),
+}
+
+impl Default for ExpectedOutput {
+    fn default() -> Self {
+        ExpectedOutput::Exact(String::new())
+    }
+}
+
+impl From<String> for ExpectedOutput {
+    fn from(value: String) -> Self {
+        ExpectedOutput::Exact(value)
+    }
+}
+
+impl From<&str> for ExpectedOutput {
+    fn from(value: &str) -> Self {
+        ExpectedOutput::Exact(value.to_string())
+    }
+}
+
+impl ExpectedOutput {
+    fn assert_matches(&self, stream: &str, actual: &[u8]) {
+        let text = String::from_utf8_lossy(actual);
+        match self {
+            ExpectedOutput::Exact(expected) => {
+                assert_output_eq(stream, actual, expected.as_bytes())
+            }
+            ExpectedOutput::Contains(needle) => assert!(
+                text.contains(needle.as_str()),
+                "{stream} does not contain {needle:?}\n{stream}: {text:?}"
+            ),
+            ExpectedOutput::Regex(pattern) => assert!(
+                regex_is_match(pattern, &text),
+                "{stream} does not match the regex {pattern:?}\n{stream}: {text:?}"
+            ),
+            ExpectedOutput::Predicate(predicate) => assert!(
+                predicate(&text),
+                "{stream} does not satisfy the predicate\n{stream}: {text:?}"
+            ),
+        }
+    }
+}
+
+/// The exit status expected from a command.
+#[derive(Clone, Debug)]
+pub enum ExpectedExitCode {
+    /// The command must exit with this code.
+    Exactly(i32),
+    /// The command must exit with any code but 0.
+    AnyNonZero,
+    /// The command must exit with one of these codes.
+    OneOf(Vec<i32>),
+    /// The command must be terminated by this signal.
+    Signaled(i32),
+}
+
+impl Default for ExpectedExitCode {
+    fn default() -> Self {
+        ExpectedExitCode::Exactly(0)
+    }
+}
+
+impl From<i32> for ExpectedExitCode {
+    fn from(value: i32) -> Self {
+        ExpectedExitCode::Exactly(value)
+    }
+}
+
+impl ExpectedExitCode {
+    fn assert_matches(&self, status: ExitStatus) {
+        let code = status.code();
+        match self {
+            ExpectedExitCode::Exactly(expected) => {
+                assert!(
+                    code == Some(*expected),
+                    "expected exit code {expected}, but the command {}",
+                    describe_status(status)
+                );
+                if *expected == 0 {
+                    assert!(status.success());
+                }
+            }
+            ExpectedExitCode::AnyNonZero => assert!(
+                code.is_some_and(|code| code != 0),
+                "expected a nonzero exit code, but the command {}",
+                describe_status(status)
+            ),
+            ExpectedExitCode::OneOf(expected) => assert!(
+                code.is_some_and(|code| expected.contains(&code)),
+                "expected an exit code in {expected:?}, but the command {}",
+                describe_status(status)
+            ),
+            ExpectedExitCode::Signaled(expected) => assert!(
+                status.signal() == Some(*expected),
+                "expected termination by {}, but the command {}",
+                describe_signal(*expected),
+                describe_status(status)
+            ),
+        }
+    }
+}
+
+/// Returns how a command ended, e.g. "exited with code 1" or "was
+/// terminated by SIGTERM (15)".
+fn describe_status(status: ExitStatus) -> String {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => format!("exited with code {code}"),
+        (None, Some(signal)) if status.core_dumped() => format!(
+            "was terminated by {} and dumped core",
+            describe_signal(signal)
+        ),
+        (None, Some(signal)) => format!("was terminated by {}", describe_signal(signal)),
+        (None, None) => format!("ended with {status}"),
+    }
+}
+
+fn describe_signal(signal: i32) -> String {
+    match signals::num_to_name(signal) {
+        Some(name) => format!("SIG{name} ({signal})"),
+        None => format!("signal {signal}"),
+    }
+}
+
+/// Compiles the extended regular expression `pattern` of a test.
+fn test_regex(pattern: &str) -> Regex {
+    let pattern_c = CString::new(pattern).expect("regex contains a NUL byte");
+    Regex::new(pattern_c).unwrap_or_else(|e| panic!("invalid regex {pattern:?}: {e}"))
+}
+
+/// Returns whether the extended regular expression `pattern` matches `text`.
+fn regex_is_match(pattern: &str, text: &str) -> bool {
+    // NUL bytes end the string on platforms without `REG_STARTEND`, so they
+    // are matched as spaces
+    test_regex(pattern).matches_bytes(text.replace('\0', " ").as_bytes())
+}
+
+/// Replaces the parts of an output that change from run to run, such as
+/// temporary paths, before it is checked.
+#[derive(Clone, Debug)]
+pub struct Normalizer {
+    pattern: String,
+    replacement: String,
+}
+
+impl Normalizer {
+    /// Replaces every match of the extended regular expression `pattern`.
+    /// `\1` to `\9` in `replacement` stand for the groups of the match, and
+    /// `\\` for a backslash.
+    pub fn new(pattern: &str, replacement: &str) -> Self {
+        Normalizer {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+        }
+    }
+
+    /// Replaces every occurrence of `text` with `replacement`, both taken literally.
+    pub fn literal(text: &str, replacement: &str) -> Self {
+        let mut pattern = String::new();
+        for c in text.chars() {
+            if "\\.[]()*+?{}|^$".contains(c) {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        Normalizer::new(&pattern, &replacement.replace('\\', "\\\\"))
+    }
+
+    /// Replaces the temporary directory of the system with `$TMPDIR`.
+    pub fn temp_dir() -> Self {
+        let dir = std::env::temp_dir();
+        let dir = dir.to_str().expect("non UTF-8 temporary directory");
+        Normalizer::literal(dir.trim_end_matches('/'), "$TMPDIR")
+    }
+
+    /// Replaces the process ID of the test, which tests use to name their
+    /// temporary files, with `$PID`.
+    pub fn pid() -> Self {
+        Normalizer::new(
+            &format!("(^|[^0-9]){}([^0-9]|$)", std::process::id()),
+            "\\1$PID\\2",
+        )
+    }
+
+    fn apply(&self, text: &[u8]) -> Vec<u8> {
+        let regex = test_regex(&self.pattern);
+        // `regexec` stops at NUL bytes, so the parts between them are replaced separately
+        let parts = text
+            .split(|&b| b == b'\0')
+            .map(|part| self.replace_all(&regex, part))
+            .collect::<Vec<_>>();
+        parts.join(&b'\0')
+    }
+
+    fn replace_all(&self, regex: &Regex, text: &[u8]) -> Vec<u8> {
+        let mut result = Vec::new();
+        let mut start = 0;
+        while start <= text.len() {
+            let Some(groups) = regex.captures_at(text, start) else {
+                break;
+            };
+            let matched = groups[0].clone().unwrap();
+            result.extend_from_slice(&text[start..matched.start]);
+            let mut replacement = self.replacement.bytes();
+            while let Some(b) = replacement.next() {
+                match (b, replacement.clone().next()) {
+                    (b'\\', Some(digit @ b'0'..=b'9')) => {
+                        replacement.next();
+                        if let Some(Some(group)) = groups.get((digit - b'0') as usize) {
+                            result.extend_from_slice(&text[group.clone()]);
+                        }
+                    }
+                    (b'\\', Some(b'\\')) => {
+                        replacement.next();
+                        result.push(b'\\');
+                    }
+                    _ => result.push(b),
+                }
+            }
+            if matched.is_empty() {
+                // an empty match cannot match again at the same position
+                if let Some(&b) = text.get(matched.end) {
+                    result.push(b);
+                }
+                start = matched.end + 1;
+            } else {
+                start = matched.end;
+            }
+        }
+        if start < text.len() {
+            result.extend_from_slice(&text[start..]);
+        }
+        result
+    }
+}
+
+/// Applies `normalizers` in order to `output`.
+fn normalize(normalizers: &[Normalizer], output: &mut Vec<u8>) {
+    for normalizer in normalizers {
+        *output = normalizer.apply(output);
+    }
 }
 
 pub struct TestPlanU8 {
@@ -30,7 +462,24 @@ pub struct TestPlanU8 {
     pub expected_exit_code: i32,
 }
 
-pub fn run_test_base(cmd: &str, args: &Vec<String>, stdin_data: &[u8]) -> Output {
+pub fn run_test_base<I, S>(cmd: &str, args: I, stdin_data: &[u8]) -> Output
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = test_command(cmd);
+    command.args(args);
+    run_command(
+        command,
+        StdinSource::Inline(stdin_data.to_vec()),
+        None,
+        &[],
+        false,
+    )
+}
+
+/// Returns a command that runs the utility `cmd` built in this workspace.
+fn test_command(cmd: &str) -> Command {
     let relpath = if cfg!(debug_assertions) {
         format!("target/debug/{}", cmd)
     } else {
@@ -42,63 +491,528 @@ pub fn run_test_base(cmd: &str, args: &Vec<String>, stdin_data: &[u8]) -> Output
         .unwrap() // Move up to the workspace root from the current package directory
         .join(relpath); // Adjust the path to the binary
 
-    let mut command = Command::new(test_bin_path);
+    Command::new(test_bin_path)
+}
+
+/// Writes the input of a command under test.
+pub type InputGenerator = Box<dyn FnMut(&mut dyn Write) -> io::Result<()> + Send>;
+
+/// Input of a command under test.
+pub enum StdinSource {
+    /// These bytes, written in small chunks.
+    Inline(Vec<u8>),
+    /// The contents of this file, which becomes the standard input of the command.
+    File(PathBuf),
+    /// Whatever this function writes. Each write is split into chunks of at most
+    /// `chunk_size` bytes, and the writer sleeps for `delay`, if there is one,
+    /// before each chunk but the first, so the command sees the input arrive
+    /// over time. A write error, such as the command exiting before reading
+    /// all its input, can be returned to stop generating.
+    Generator {
+        generate: InputGenerator,
+        chunk_size: usize,
+        delay: Option<Duration>,
+    },
+}
+
+impl StdinSource {
+    /// Returns what the command reads from: the file, or a pipe for `write_to`.
+    fn stdio(&self) -> Stdio {
+        match self {
+            StdinSource::File(path) => fs::File::open(path)
+                .unwrap_or_else(|e| panic!("cannot open {}: {e}", path.display()))
+                .into(),
+            _ => Stdio::piped(),
+        }
+    }
+
+    fn write_to(self, pipe: ChildStdin) {
+        match self {
+            StdinSource::Inline(data) => write_stdin(pipe, &data),
+            StdinSource::File(_) => unreachable!("files are not written through a pipe"),
+            StdinSource::Generator {
+                mut generate,
+                chunk_size,
+                delay,
+            } => {
+                let mut writer = ChunkedWriter {
+                    pipe,
+                    chunk_size,
+                    delay,
+                    started: false,
+                };
+                // the command does not have to read all of its input
+                let _ = generate(&mut writer);
+            }
+        }
+    }
+}
+
+/// Splits writes into chunks, with an optional delay before each chunk but the first.
+struct ChunkedWriter {
+    pipe: ChildStdin,
+    chunk_size: usize,
+    delay: Option<Duration>,
+    started: bool,
+}
+
+impl Write for ChunkedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if let Some(delay) = self.delay.filter(|_| self.started) {
+            thread::sleep(delay);
+        }
+        self.started = true;
+        let len = buf.len().min(self.chunk_size.max(1));
+        self.pipe.write_all(&buf[..len])?;
+        self.pipe.flush()?;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.pipe.flush()
+    }
+}
+
+/// Runs `command` with `stdin` as its input, sending it `signals`, and
+/// returns its output. With `check_orphans`, it panics when processes
+/// started by the command outlive it.
+fn run_command(
+    mut command: Command,
+    stdin: StdinSource,
+    timeout: Option<Duration>,
+    signals: &[(Duration, i32)],
+    check_orphans: bool,
+) -> Output {
+    if check_orphans {
+        // the descendants of the command stay in its session even when they
+        // move to process groups of their own, like the utility of timeout
+        unsafe {
+            command.pre_exec(|| match libc::setsid() {
+                -1 => Err(io::Error::last_os_error()),
+                _ => Ok(()),
+            })
+        };
+    } else if timeout.is_some() {
+        // a process group of its own lets the command be killed with its children
+        command.process_group(0);
+    }
     let mut child = command
-        .args(args)
-        .stdin(Stdio::piped())
+        .stdin(stdin.stdio())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
-        .unwrap_or_else(|_| panic!("failed to spawn command {cmd}"));
-
-    // Separate the mutable borrow of stdin from the child process
-    if let Some(mut stdin) = child.stdin.take() {
-        let chunk_size = 1024; // Arbitrary chunk size, adjust if needed
-        for chunk in stdin_data.chunks(chunk_size) {
-            // Write each chunk
-            if let Err(e) = stdin.write_all(chunk) {
-                eprintln!("Error writing to stdin: {}", e);
-                break;
+        .unwrap_or_else(|_| panic!("failed to spawn command {:?}", command.get_program()));
+
+    // the input is written on its own thread, so the command can produce output
+    // before it has read all of its input
+    let writer = child
+        .stdin
+        .take()
+        .map(|pipe| thread::spawn(move || stdin.write_to(pipe)));
+
+    if timeout.is_none() && signals.is_empty() && !check_orphans {
+        // Ensure we wait for the process to complete after writing to stdin
+        let output = child.wait_with_output().expect("failed to wait for child");
+        if let Some(writer) = writer {
+            writer.join().unwrap();
+        }
+        return output;
+    }
+
+    // the command may never read its input or exit, so nothing can block this thread
+    let stdout = read_in_background(child.stdout.take().unwrap());
+    let stderr = read_in_background(child.stderr.take().unwrap());
+
+    let exited = Arc::new((Mutex::new(false), Condvar::new()));
+    let signaler = (!signals.is_empty())
+        .then(|| send_signals_in_background(child.id(), signals.to_vec(), exited.clone()));
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let status = loop {
+        // the command is reaped with the lock held, so that it is not signaled
+        // after its pid is freed
+        let mut reaped = exited.0.lock().unwrap();
+        if let Some(status) = child.try_wait().expect("failed to wait for child") {
+            *reaped = true;
+            break Some(status);
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+            child.wait().expect("failed to wait for child");
+            *reaped = true;
+            break None;
+        }
+        drop(reaped);
+        thread::sleep(Duration::from_millis(10));
+    };
+    exited.1.notify_all();
+    if let Some(signaler) = signaler {
+        signaler.join().unwrap();
+    }
+    // the orphans are killed before the output is read, since they may hold
+    // the pipes open
+    let orphans = if check_orphans {
+        kill_orphans(child.id())
+    } else {
+        Vec::new()
+    };
+
+    if let Some(writer) = writer {
+        writer.join().unwrap();
+    }
+    let stdout = stdout.join().unwrap();
+    let stderr = stderr.join().unwrap();
+    let Some(status) = status else {
+        panic!(
+            "{:?} timed out after {:?}\nstdout so far: {:?}\nstderr so far: {:?}",
+            command.get_program(),
+            timeout.unwrap(),
+            String::from_utf8_lossy(&stdout),
+            String::from_utf8_lossy(&stderr)
+        );
+    };
+    assert!(
+        orphans.is_empty(),
+        "{:?} left processes running: {orphans:?}",
+        command.get_program()
+    );
+    Output {
+        status,
+        stdout,
+        stderr,
+    }
+}
+
+/// Returns the processes of the session `sid` that have not exited, as pairs
+/// of their pid and name.
+#[cfg(target_os = "linux")]
+fn session_members(sid: u32) -> Vec<(i32, String)> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| {
+            let pid = entry.ok()?.file_name().to_str()?.parse::<i32>().ok()?;
+            let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+            // the name may contain anything, so the fields after it are
+            // found from its closing parenthesis
+            let (name, fields) = stat.split_once(" (")?.1.rsplit_once(") ")?;
+            let fields = fields.split(' ').collect::<Vec<_>>();
+            let (state, session) = (fields[0], fields.get(3)?.parse::<u32>().ok()?);
+            (session == sid && state != "Z").then(|| (pid, name.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn session_members(_sid: u32) -> Vec<(i32, String)> {
+    Vec::new()
+}
+
+/// Kills the processes left in the session `sid` once its leader has exited,
+/// and returns them. Processes that exit within a short grace period, for
+/// example because the leader signaled them just before exiting, are not
+/// returned.
+fn kill_orphans(sid: u32) -> Vec<(i32, String)> {
+    let deadline = Instant::now() + Duration::from_millis(500);
+    loop {
+        let members = session_members(sid);
+        if members.is_empty() {
+            return members;
+        }
+        if Instant::now() >= deadline {
+            for (pid, _) in &members {
+                unsafe { libc::kill(*pid, libc::SIGKILL) };
             }
-            // Flush after writing each chunk
-            if let Err(e) = stdin.flush() {
-                eprintln!("Error flushing stdin: {}", e);
-                break;
+            return members;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Sends each of `signals` to the process `pid` at its offset from now, and
+/// stops once the flag in `exited` is set.
+fn send_signals_in_background(
+    pid: u32,
+    mut signals: Vec<(Duration, i32)>,
+    exited: Arc<(Mutex<bool>, Condvar)>,
+) -> JoinHandle<()> {
+    let start = Instant::now();
+    signals.sort_by_key(|(offset, _)| *offset);
+    thread::spawn(move || {
+        let (lock, condvar) = &*exited;
+        let mut exited = lock.lock().unwrap();
+        for (offset, signal) in signals {
+            let due = start + offset;
+            loop {
+                if *exited {
+                    return;
+                }
+                let now = Instant::now();
+                if now >= due {
+                    break;
+                }
+                exited = condvar.wait_timeout(exited, due - now).unwrap().0;
             }
+            unsafe { libc::kill(pid as libc::pid_t, signal) };
+        }
+    })
+}
 
-            // Sleep briefly to avoid CPU spinning
-            thread::sleep(Duration::from_millis(10));
+fn write_stdin(mut stdin: ChildStdin, stdin_data: &[u8]) {
+    let chunk_size = 1024; // Arbitrary chunk size, adjust if needed
+    for chunk in stdin_data.chunks(chunk_size) {
+        // Write each chunk
+        if let Err(e) = stdin.write_all(chunk) {
+            eprintln!("Error writing to stdin: {}", e);
+            break;
+        }
+        // Flush after writing each chunk
+        if let Err(e) = stdin.flush() {
+            eprintln!("Error flushing stdin: {}", e);
+            break;
         }
-        // Explicitly drop stdin to close the pipe
-        drop(stdin);
+
+        // Sleep briefly to avoid CPU spinning
+        thread::sleep(Duration::from_millis(10));
     }
+    // Explicitly drop stdin to close the pipe
+    drop(stdin);
+}
 
-    // Ensure we wait for the process to complete after writing to stdin
-    let output = child.wait_with_output().expect("failed to wait for child");
-    output
+/// Reads `pipe` to its end on another thread.
+fn read_in_background<R: Read + Send + 'static>(mut pipe: R) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut contents = Vec::new();
+        // a read error ends the output like the end of file would
+        let _ = pipe.read_to_end(&mut contents);
+        contents
+    })
 }
 
-pub fn run_test(plan: TestPlan) {
-    let output = run_test_base(&plan.cmd, &plan.args, plan.stdin_data.as_bytes());
+/// Compares the bytes of an output stream, and fails with a report of the
+/// first difference rather than both outputs in full.
+fn assert_output_eq(stream: &str, actual: &[u8], expected: &[u8]) {
+    if actual != expected {
+        panic!(
+            "{}",
+            mismatch_report(stream, actual, expected, verbose_diffs())
+        );
+    }
+}
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    assert_eq!(stdout, plan.expected_out);
+/// Environment variable that makes failing tests print whole outputs.
+const VERBOSE_DIFF_VAR: &str = "POSIXUTILS_VERBOSE_DIFF";
 
-    let stderr = String::from_utf8_lossy(&output.stderr);
-    assert_eq!(stderr, plan.expected_err);
+fn verbose_diffs() -> bool {
+    std::env::var_os(VERBOSE_DIFF_VAR).is_some_and(|value| value == "1")
+}
 
-    assert_eq!(output.status.code(), Some(plan.expected_exit_code));
-    if plan.expected_exit_code == 0 {
-        assert!(output.status.success());
+/// Describes where `actual` first differs from `expected`, with the hunk
+/// around it, and with both outputs in full when `verbose` is set.
+fn mismatch_report(stream: &str, actual: &[u8], expected: &[u8], verbose: bool) -> String {
+    let offset = actual
+        .iter()
+        .zip(expected)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let before = &expected[..offset];
+    let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+    let line_start = before
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    let mut report = format!(
+        "unexpected {stream}: first difference on line {line} at byte {} (byte {} of the output)\n\
+         expected {} bytes, got {}\n",
+        offset - line_start + 1,
+        offset + 1,
+        expected.len(),
+        actual.len()
+    );
+    report.push_str(&first_diff_hunk(
+        &format!("expected {stream}"),
+        &format!("actual {stream}"),
+        expected,
+        actual,
+    ));
+    if verbose {
+        report.push_str(&format!(
+            "expected {stream}:\n{}\nactual {stream}:\n{}\n",
+            show_bytes(expected),
+            show_bytes(actual)
+        ));
+    } else {
+        report.push_str(&format!(
+            "run the test with {VERBOSE_DIFF_VAR}=1 to print both in full\n"
+        ));
+    }
+    report
+}
+
+/// Shows `bytes` as text when they are valid UTF-8, and with escaped
+/// non-ASCII bytes otherwise.
+fn show_bytes(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.escape_ascii().to_string(),
+    }
+}
+
+/// Runs `plan` and checks its output, exit status and golden file.
+pub fn run_test<P>(plan: P)
+where
+    P: IntoTestRun,
+    P::Out: Clone + Into<ExpectedOutput>,
+    P::Err: Clone + Into<ExpectedOutput>,
+    P::Code: Clone + Into<ExpectedExitCode>,
+    P::Arg: AsRef<OsStr>,
+{
+    let mut run = plan.into_test_run();
+    let output = run_plan(&mut run);
+    check_output(&run, &output);
+}
+
+/// Checks the output of `run` against the expectations of its plan.
+fn check_output<Out, Err, Code, Arg>(run: &TestRun<Out, Err, Code, Arg>, output: &Output)
+where
+    Out: Clone + Into<ExpectedOutput>,
+    Err: Clone + Into<ExpectedOutput>,
+    Code: Clone + Into<ExpectedExitCode>,
+{
+    match &run.options.expected_out_file {
+        Some(path) => check_golden_file(path, &output.stdout, regenerate_golden_files()),
+        None => run
+            .plan
+            .expected_out
+            .clone()
+            .into()
+            .assert_matches("stdout", &output.stdout),
+    }
+
+    run.plan
+        .expected_err
+        .clone()
+        .into()
+        .assert_matches("stderr", &output.stderr);
+
+    run.expected_status().assert_matches(output.status);
+}
+
+/// Environment variable that makes tests rewrite their golden files.
+const REGEN_GOLDEN_VAR: &str = "POSIXUTILS_REGEN_GOLDEN";
+
+fn regenerate_golden_files() -> bool {
+    std::env::var_os(REGEN_GOLDEN_VAR).is_some_and(|value| value == "1")
+}
+
+/// Compares `actual` with the contents of the golden file at `path`, or
+/// writes it to the file when `regenerate` is set.
+fn check_golden_file(path: &Path, actual: &[u8], regenerate: bool) {
+    if regenerate {
+        if let Some(parent) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(path, actual)
+            .unwrap_or_else(|e| panic!("failed to write golden file {}: {e}", path.display()));
+        return;
+    }
+    let expected = fs::read(path).unwrap_or_else(|e| {
+        panic!(
+            "failed to read golden file {}: {e}\nrun the test with {REGEN_GOLDEN_VAR}=1 to create it",
+            path.display()
+        )
+    });
+    if actual != expected {
+        panic!(
+            "stdout differs from the golden file {0}\n{1}run the test with {REGEN_GOLDEN_VAR}=1 to update it",
+            path.display(),
+            first_diff_hunk(
+                &path.display().to_string(),
+                "actual stdout",
+                &expected,
+                actual
+            )
+        );
+    }
+}
+
+/// Returns the first hunk in which `actual` differs from `expected`, in the
+/// unified diff format.
+fn first_diff_hunk(
+    expected_name: &str,
+    actual_name: &str,
+    expected: &[u8],
+    actual: &[u8],
+) -> String {
+    const CONTEXT: usize = 3;
+
+    let old = expected
+        .split_inclusive(|&b| b == b'\n')
+        .collect::<Vec<_>>();
+    let new = actual.split_inclusive(|&b| b == b'\n').collect::<Vec<_>>();
+    let start = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+
+    // the hunk ends at the nearest pair of equal lines
+    let (old_rest, new_rest) = (&old[start..], &new[start..]);
+    let (removed, added) = (0..=old_rest.len() + new_rest.len())
+        .flat_map(|distance| (0..=distance).map(move |i| (i, distance - i)))
+        .find(|&(i, j)| i < old_rest.len() && j < new_rest.len() && old_rest[i] == new_rest[j])
+        .unwrap_or((old_rest.len(), new_rest.len()));
+    let after = old_rest[removed..]
+        .iter()
+        .zip(&new_rest[added..])
+        .take_while(|(a, b)| a == b)
+        .take(CONTEXT)
+        .count();
+    let before = start.min(CONTEXT);
+
+    // an empty range starts at the line before it
+    let range = |count: usize| {
+        let first = start - before;
+        match count {
+            0 => format!("{first},0"),
+            _ => format!("{},{count}", first + 1),
+        }
+    };
+    let mut hunk = format!(
+        "--- {expected_name}\n+++ {actual_name}\n@@ -{} +{} @@\n",
+        range(before + removed + after),
+        range(before + added + after)
+    );
+    let lines = old[start - before..start]
+        .iter()
+        .map(|line| (' ', line))
+        .chain(old_rest[..removed].iter().map(|line| ('-', line)))
+        .chain(new_rest[..added].iter().map(|line| ('+', line)))
+        .chain(
+            old_rest[removed..removed + after]
+                .iter()
+                .map(|line| (' ', line)),
+        );
+    for (prefix, line) in lines {
+        hunk.push(prefix);
+        match line.strip_suffix(b"\n") {
+            Some(line) => hunk.push_str(&show_bytes(line)),
+            None => {
+                hunk.push_str(&show_bytes(line));
+                hunk.push_str("\n\\ No newline at end of file");
+            }
+        }
+        hunk.push('\n');
     }
+    hunk
 }
 
 pub fn run_test_u8(plan: TestPlanU8) {
     let output = run_test_base(&plan.cmd, &plan.args, &plan.stdin_data);
 
-    assert_eq!(output.stdout, plan.expected_out);
+    assert_output_eq("stdout", &output.stdout, &plan.expected_out);
 
-    assert_eq!(output.stderr, plan.expected_err);
+    assert_output_eq("stderr", &output.stderr, &plan.expected_err);
 
     assert_eq!(output.status.code(), Some(plan.expected_exit_code));
     if plan.expected_exit_code == 0 {
@@ -106,7 +1020,885 @@ pub fn run_test_u8(plan: TestPlanU8) {
     }
 }
 
-pub fn run_test_with_checker<F: FnMut(&TestPlan, &Output)>(plan: TestPlan, mut checker: F) {
-    let output = run_test_base(&plan.cmd, &plan.args, plan.stdin_data.as_bytes());
-    checker(&plan, &output);
+/// Runs `plan` like [`run_test`], but leaves all the assertions about the
+/// output, including the exit status, to `checker`.
+pub fn run_test_with_checker<P, F>(plan: P, mut checker: F)
+where
+    P: IntoTestRun,
+    P::Arg: AsRef<OsStr>,
+    F: FnMut(&TestPlan<P::Out, P::Err, P::Code, P::Arg>, &Output),
+{
+    let mut run = plan.into_test_run();
+    let output = run_plan(&mut run);
+    checker(&run.plan, &output);
+}
+
+/// Runs `plan` and returns its normalized output without checking it, for
+/// tests that compare runs or feed the output of one run to another. The
+/// expected outputs and exit code of `plan` are not used, but the checks for
+/// timeouts and leaks still fail the test.
+pub fn run<P>(plan: P) -> Output
+where
+    P: IntoTestRun,
+    P::Arg: AsRef<OsStr>,
+{
+    run_plan(&mut plan.into_test_run())
+}
+
+fn run_plan<Out, Err, Code, Arg>(run: &mut TestRun<Out, Err, Code, Arg>) -> Output
+where
+    Arg: AsRef<OsStr>,
+{
+    let mut output = run_command(
+        run.command(),
+        run.take_stdin(),
+        run.options.timeout,
+        &run.options.signals,
+        run.options.check_orphans,
+    );
+    normalize(&run.options.stdout_normalizers, &mut output.stdout);
+    normalize(&run.options.stderr_normalizers, &mut output.stderr);
+    output
+}
+
+/// Runs the commands of `plans` as a pipeline, with the standard output of
+/// each connected to the standard input of the next.
+///
+/// The first plan gives the input of the pipeline, and the last one its
+/// expected output; the input and expected output of the other plans are not
+/// used. The standard error and the exit code of every command are checked.
+/// Timeouts and signals are not supported.
+pub fn run_pipeline<P>(plans: Vec<P>)
+where
+    P: IntoTestRun,
+    P::Out: Clone + Into<ExpectedOutput>,
+    P::Err: Clone + Into<ExpectedOutput>,
+    P::Code: Clone + Into<ExpectedExitCode>,
+    P::Arg: AsRef<OsStr>,
+{
+    assert!(!plans.is_empty(), "a pipeline needs at least one command");
+    let mut runs = plans
+        .into_iter()
+        .map(IntoTestRun::into_test_run)
+        .collect::<Vec<_>>();
+    let stdin = runs[0].take_stdin();
+    let mut children = Vec::new();
+    let mut previous_stdout = None;
+    for run in &runs {
+        let mut command = run.command();
+        command.stdin(match previous_stdout.take() {
+            Some(stdout) => Stdio::from(stdout),
+            None => stdin.stdio(),
+        });
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap_or_else(|_| panic!("failed to spawn command {:?}", command.get_program()));
+        previous_stdout = child.stdout.take();
+        children.push(child);
+    }
+
+    let writer = children[0]
+        .stdin
+        .take()
+        .map(|pipe| thread::spawn(move || stdin.write_to(pipe)));
+    let stdout = read_in_background(previous_stdout.unwrap());
+    let stderrs = children
+        .iter_mut()
+        .map(|child| read_in_background(child.stderr.take().unwrap()))
+        .collect::<Vec<_>>();
+    let statuses = children
+        .iter_mut()
+        .map(|child| child.wait().expect("failed to wait for child"))
+        .collect::<Vec<_>>();
+    if let Some(writer) = writer {
+        writer.join().unwrap();
+    }
+    let mut stdout = stdout.join().unwrap();
+
+    let last = runs.len() - 1;
+    for (i, ((run, status), stderr)) in runs.iter().zip(statuses).zip(stderrs).enumerate() {
+        let mut output = Output {
+            status,
+            stdout: if i == last {
+                std::mem::take(&mut stdout)
+            } else {
+                Vec::new()
+            },
+            stderr: stderr.join().unwrap(),
+        };
+        normalize(&run.options.stderr_normalizers, &mut output.stderr);
+        if i == last {
+            normalize(&run.options.stdout_normalizers, &mut output.stdout);
+            check_output(run, &output);
+        } else {
+            run.plan
+                .expected_err
+                .clone()
+                .into()
+                .assert_matches(&format!("stderr of {}", run.plan.cmd), &output.stderr);
+            run.expected_status().assert_matches(output.status);
+        }
+    }
+}
+
+/// A step of [`run_streaming_test`].
+#[derive(Clone, Debug)]
+pub enum StreamStep {
+    /// Writes these bytes to the standard input of the command, which is kept
+    /// open afterwards.
+    Write(Vec<u8>),
+    /// Waits until the standard output read so far starts with `prefix`, and
+    /// fails the test if it does not `within` this time.
+    Expect { prefix: String, within: Duration },
+}
+
+/// Progress of the standard output of a streaming command towards an
+/// expected prefix.
+#[derive(Debug, PartialEq)]
+enum StreamState {
+    /// The output read so far is a part of the prefix.
+    Waiting,
+    /// The output read so far starts with the prefix.
+    Ready,
+    /// The output read so far can no longer become the prefix.
+    Diverged,
+}
+
+impl StreamState {
+    fn of(output: &[u8], prefix: &[u8]) -> Self {
+        if output.starts_with(prefix) {
+            StreamState::Ready
+        } else if prefix.starts_with(output) {
+            StreamState::Waiting
+        } else {
+            StreamState::Diverged
+        }
+    }
+}
+
+/// Runs `plan` with its input given by `steps`, to check when the output
+/// appears and not just what it is.
+///
+/// The standard input of the command stays open while the steps run, and is
+/// closed after the last one. The whole output is then checked against the
+/// expectations of `plan`, like [`run_test`] does. `stdin_data` of `plan`,
+/// and the input, timeout and signals of its [`RunOptions`], are not used.
+pub fn run_streaming_test<P>(plan: P, steps: Vec<StreamStep>)
+where
+    P: IntoTestRun,
+    P::Out: Clone + Into<ExpectedOutput>,
+    P::Err: Clone + Into<ExpectedOutput>,
+    P::Code: Clone + Into<ExpectedExitCode>,
+    P::Arg: AsRef<OsStr>,
+{
+    let run = plan.into_test_run();
+    let mut output = run_streaming_command(run.command(), &steps).unwrap_or_else(|e| panic!("{e}"));
+    normalize(&run.options.stdout_normalizers, &mut output.stdout);
+    normalize(&run.options.stderr_normalizers, &mut output.stderr);
+    check_output(&run, &output);
+}
+
+/// Runs `command` through `steps` and returns its output, or a description
+/// of the first step that failed. The command is killed when a step fails.
+fn run_streaming_command(mut command: Command, steps: &[StreamStep]) -> Result<Output, String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|_| panic!("failed to spawn command {:?}", command.get_program()));
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let stderr = read_in_background(child.stderr.take().unwrap());
+
+    // the output is read without blocking, so that a missing prefix is
+    // noticed when its deadline passes
+    set_nonblocking(&stdout, true);
+    let mut read = Vec::new();
+    let result = steps.iter().try_for_each(|step| match step {
+        StreamStep::Write(data) => {
+            stdin
+                .write_all(data)
+                .and_then(|()| stdin.flush())
+                .map_err(|e| {
+                    format!(
+                        "cannot write {:?} to stdin: {e}",
+                        data.escape_ascii().to_string()
+                    )
+                })
+        }
+        StreamStep::Expect { prefix, within } => {
+            read_until_prefix(&mut stdout, &mut read, prefix.as_bytes(), *within)
+        }
+    });
+    drop(stdin);
+    if let Err(e) = result {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(format!("{:?}: {e}", command.get_program()));
+    }
+
+    set_nonblocking(&stdout, false);
+    stdout
+        .read_to_end(&mut read)
+        .expect("failed to read stdout");
+    let status = child.wait().expect("failed to wait for child");
+    Ok(Output {
+        status,
+        stdout: read,
+        stderr: stderr.join().unwrap(),
+    })
+}
+
+/// Reads the non-blocking `pipe` into `output` until `output` starts with
+/// `prefix`, for at most `within`.
+fn read_until_prefix(
+    pipe: &mut ChildStdout,
+    output: &mut Vec<u8>,
+    prefix: &[u8],
+    within: Duration,
+) -> Result<(), String> {
+    let deadline = Instant::now() + within;
+    let mut buf = [0; 4096];
+    loop {
+        let text = || String::from_utf8_lossy(output).into_owned();
+        match StreamState::of(output, prefix) {
+            StreamState::Ready => return Ok(()),
+            StreamState::Diverged => {
+                return Err(format!(
+                    "stdout cannot start with {:?} anymore\nstdout so far: {:?}",
+                    String::from_utf8_lossy(prefix),
+                    text()
+                ))
+            }
+            StreamState::Waiting => {}
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(format!(
+                "stdout did not start with {:?} within {within:?}\nstdout so far: {:?}",
+                String::from_utf8_lossy(prefix),
+                text()
+            ));
+        }
+        let mut fds = libc::pollfd {
+            fd: pipe.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = (deadline - now).as_millis().clamp(1, i32::MAX as u128) as i32;
+        unsafe { libc::poll(&mut fds, 1, timeout) };
+        match pipe.read(&mut buf) {
+            Ok(0) => {
+                return Err(format!(
+                    "stdout was closed before it started with {:?}\nstdout: {:?}",
+                    String::from_utf8_lossy(prefix),
+                    text()
+                ))
+            }
+            Ok(n) => output.extend_from_slice(&buf[..n]),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+                ) => {}
+            Err(e) => return Err(format!("cannot read stdout: {e}")),
+        }
+    }
+}
+
+/// Sets or clears `O_NONBLOCK` on `pipe`.
+fn set_nonblocking(pipe: &impl AsRawFd, nonblocking: bool) {
+    let fd = pipe.as_raw_fd();
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        let flags = if nonblocking {
+            flags | libc::O_NONBLOCK
+        } else {
+            flags & !libc::O_NONBLOCK
+        };
+        assert_eq!(libc::fcntl(fd, libc::F_SETFL, flags), 0, "fcntl failed");
+    }
+}
+
+/// Files for a test that runs a utility in its own temporary directory.
+///
+/// Arguments of the form `${name}`, where `name` is a file given to
+/// [`TestFixture::with_file`] or one of the `expect_` methods, are replaced
+/// with the absolute path of that file. After the run, the expected files are
+/// checked. The directory is removed when the fixture is dropped, unless the
+/// test failed, in which case it is kept and its path is printed.
+pub struct TestFixture {
+    dir: TestDir,
+    names: Vec<String>,
+    expected_files: Vec<(String, Option<Vec<u8>>)>,
+}
+
+impl TestFixture {
+    /// Creates a fixture in a new [`TestDir`] whose name starts with `prefix`.
+    pub fn new(prefix: &str) -> Self {
+        TestFixture {
+            dir: TestDir::new(prefix),
+            names: Vec::new(),
+            expected_files: Vec::new(),
+        }
+    }
+
+    /// Creates the file `name` with `contents` before the run.
+    pub fn with_file(mut self, name: &str, contents: impl AsRef<[u8]>) -> Self {
+        self.dir.file(name, contents);
+        self.names.push(name.to_string());
+        self
+    }
+
+    /// Checks that the file `name` contains exactly `contents` after the run.
+    pub fn expect_file(mut self, name: &str, contents: impl AsRef<[u8]>) -> Self {
+        self.names.push(name.to_string());
+        self.expected_files
+            .push((name.to_string(), Some(contents.as_ref().to_vec())));
+        self
+    }
+
+    /// Checks that the file `name` does not exist after the run.
+    pub fn expect_no_file(mut self, name: &str) -> Self {
+        self.names.push(name.to_string());
+        self.expected_files.push((name.to_string(), None));
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    fn expand_placeholders(&self, arg: &str) -> String {
+        self.names.iter().fold(arg.to_string(), |arg, name| {
+            arg.replace(
+                &format!("${{{name}}}"),
+                self.path(name).to_str().expect("non UTF-8 temporary path"),
+            )
+        })
+    }
+
+    /// Runs `plan` in the fixture directory, then checks its output and the
+    /// expected files.
+    pub fn run_test<P>(&self, plan: P)
+    where
+        P: IntoTestRun,
+        P::Out: Clone + Into<ExpectedOutput>,
+        P::Err: Clone + Into<ExpectedOutput>,
+        P::Code: Clone + Into<ExpectedExitCode>,
+        P::Arg: AsRef<OsStr> + From<String>,
+    {
+        let mut run = plan.into_test_run();
+        // arguments that are not valid UTF-8 have no placeholders
+        run.plan.args = std::mem::take(&mut run.plan.args)
+            .into_iter()
+            .map(|arg| match arg.as_ref().to_str() {
+                Some(text) => P::Arg::from(self.expand_placeholders(text)),
+                None => arg,
+            })
+            .collect();
+        run.options.cwd = Some(self.dir().to_path_buf());
+        let output = run_plan(&mut run);
+        check_output(&run, &output);
+        self.check_files();
+    }
+
+    fn check_files(&self) {
+        for (name, expected) in &self.expected_files {
+            let path = self.path(name);
+            match expected {
+                Some(contents) => {
+                    let actual = fs::read(&path)
+                        .unwrap_or_else(|e| panic!("cannot read {}: {e}", path.display()));
+                    assert_output_eq(&format!("contents of {name}"), &actual, contents);
+                }
+                None => assert!(!path.exists(), "{name} should not exist"),
+            }
+        }
+    }
+}
+
+/// Environment variable that makes tests keep their directories.
+const KEEP_TEST_DIRS_VAR: &str = "POSIXUTILS_KEEP_TEST_DIRS";
+
+/// A temporary directory of its own for a test.
+///
+/// The directory is created in `target/tmp` of the workspace, or in the
+/// system temporary directory when there is no target directory, and its name
+/// is unique within the run of the tests. It is removed when dropped, unless
+/// the test failed or `POSIXUTILS_KEEP_TEST_DIRS=1` is set, in which case it
+/// is kept and its path is printed.
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// Creates an empty directory whose name starts with `prefix`.
+    pub fn new(prefix: &str) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let target_dir = std::env::current_dir()
+            .unwrap()
+            .parent()
+            .unwrap() // Move up to the workspace root from the current package directory
+            .join("target");
+        let parent = if target_dir.is_dir() {
+            target_dir.join("tmp")
+        } else {
+            std::env::temp_dir()
+        };
+        let path = parent.join(format!(
+            "{prefix}-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        // left over by an earlier run with the same pid
+        if path.exists() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        fs::create_dir_all(&path).unwrap();
+        TestDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Creates the file `name`, and its parent directories, with `contents`
+    /// and returns its path.
+    pub fn file(&self, name: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.path.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        if thread::panicking() || std::env::var_os(KEEP_TEST_DIRS_VAR).is_some_and(|v| v == "1") {
+            eprintln!("test files kept in {}", self.path.display());
+        } else {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_output_is_compared_byte_for_byte() {
+        ExpectedOutput::from("a\n").assert_matches("stdout", b"a\n");
+        let result = std::panic::catch_unwind(|| {
+            ExpectedOutput::from("a\n").assert_matches("stdout", b"a\xff\n")
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_contains_matches_substrings() {
+        let expected = ExpectedOutput::Contains(String::from("No such file"));
+        expected.assert_matches("stderr", b"cat: /tmp/x: No such file or directory\n");
+        let result = std::panic::catch_unwind(|| expected.assert_matches("stderr", b"ok\n"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_regex_matches_the_whole_output() {
+        assert!(regex_is_match("^version [0-9]+\\.[0-9]+$", "version 1.23"));
+        assert!(!regex_is_match("^version [0-9]+$", "version 1\nmore"));
+        assert!(regex_is_match("b+", "a\0bb"));
+    }
+
+    #[test]
+    fn test_predicate_is_called_with_the_output() {
+        let expected = ExpectedOutput::Predicate(|out| out.lines().count() == 2);
+        expected.assert_matches("stdout", b"1\n2\n");
+        let result = std::panic::catch_unwind(|| expected.assert_matches("stdout", b"1\n"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_fixture_expands_placeholders_and_cleans_up() {
+        let fixture = TestFixture::new("plib-fixture")
+            .with_file("in.txt", "a")
+            .expect_file("sub/out.txt", "b");
+        let dir = fixture.dir().to_path_buf();
+        assert_eq!(
+            fixture.expand_placeholders("x=${in.txt} ${sub/out.txt} ${other}"),
+            format!(
+                "x={0}/in.txt {0}/sub/out.txt ${{other}}",
+                dir.to_str().unwrap()
+            )
+        );
+        assert_eq!(fs::read(dir.join("in.txt")).unwrap(), b"a");
+        drop(fixture);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_command_finishing_in_time_is_not_killed() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "cat; echo done >&2"]);
+        let output = run_command(
+            command,
+            StdinSource::Inline(b"input\n".to_vec()),
+            Some(Duration::from_secs(10)),
+            &[],
+            false,
+        );
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"input\n");
+        assert_eq!(output.stderr, b"done\n");
+    }
+
+    #[test]
+    fn test_command_and_its_children_are_killed_on_timeout() {
+        let start = Instant::now();
+        let result = std::panic::catch_unwind(|| {
+            let mut command = Command::new("sh");
+            command.args(["-c", "echo partial; sleep 30 & sleep 30"]);
+            run_command(
+                command,
+                StdinSource::Inline(Vec::new()),
+                Some(Duration::from_millis(200)),
+                &[],
+                false,
+            )
+        });
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.contains("timed out after 200ms"), "{message}");
+        assert!(
+            message.contains("stdout so far: \"partial\\n\""),
+            "{message}"
+        );
+        // the background sleep keeps the pipes open unless it is killed as well
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_generated_input_is_written_in_delayed_chunks() {
+        let start = Instant::now();
+        let stdin = StdinSource::Generator {
+            generate: Box::new(|out| {
+                out.write_all(b"abcdef")?;
+                out.write_all(b"\n")
+            }),
+            chunk_size: 2,
+            delay: Some(Duration::from_millis(30)),
+        };
+        let output = run_command(Command::new("cat"), stdin, None, &[], false);
+        assert_eq!(output.stdout, b"abcdef\n");
+        // three delays between the four chunks
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[test]
+    fn test_generator_stops_when_the_command_exits() {
+        let stdin = StdinSource::Generator {
+            generate: Box::new(|out| loop {
+                out.write_all(&[b'x'; 4096])?;
+            }),
+            chunk_size: 4096,
+            delay: None,
+        };
+        let mut command = Command::new("head");
+        command.args(["-c", "3"]);
+        let output = run_command(command, stdin, Some(Duration::from_secs(10)), &[], false);
+        assert_eq!(output.stdout, b"xxx");
+    }
+
+    #[test]
+    fn test_file_input() {
+        let fixture = TestFixture::new("plib-stdin-file").with_file("input", "from a file\n");
+        let output = run_command(
+            Command::new("cat"),
+            StdinSource::File(fixture.path("input")),
+            None,
+            &[],
+            false,
+        );
+        assert_eq!(output.stdout, b"from a file\n");
+    }
+
+    #[test]
+    fn test_expected_exit_codes() {
+        let status = |script: &str| Command::new("sh").args(["-c", script]).status().unwrap();
+        let fails = |expected: ExpectedExitCode, script: &str| {
+            let status = status(script);
+            std::panic::catch_unwind(|| expected.assert_matches(status)).is_err()
+        };
+        ExpectedExitCode::from(0).assert_matches(status("exit 0"));
+        ExpectedExitCode::AnyNonZero.assert_matches(status("exit 2"));
+        ExpectedExitCode::OneOf(vec![1, 2]).assert_matches(status("exit 1"));
+        assert!(fails(ExpectedExitCode::from(0), "exit 1"));
+        assert!(fails(ExpectedExitCode::AnyNonZero, "exit 0"));
+        // termination by a signal is not an exit code
+        assert!(fails(ExpectedExitCode::AnyNonZero, "kill -9 $$"));
+        assert!(fails(ExpectedExitCode::OneOf(vec![1, 2]), "exit 3"));
+
+        ExpectedExitCode::Signaled(libc::SIGTERM).assert_matches(status("kill $$"));
+        let message = |expected: ExpectedExitCode, script: &str| {
+            let status = status(script);
+            let result = std::panic::catch_unwind(|| expected.assert_matches(status));
+            *result.unwrap_err().downcast::<String>().unwrap()
+        };
+        assert_eq!(
+            message(ExpectedExitCode::Signaled(libc::SIGPIPE), "exit 141"),
+            "expected termination by SIGPIPE (13), but the command exited with code 141"
+        );
+        assert_eq!(
+            message(ExpectedExitCode::from(0), "kill -9 $$"),
+            "expected exit code 0, but the command was terminated by SIGKILL (9)"
+        );
+    }
+
+    #[test]
+    fn test_normalizers() {
+        let apply = |normalizer: Normalizer, text: &[u8]| normalizer.apply(text);
+        assert_eq!(
+            apply(Normalizer::new("line [0-9]+", "line N"), b"line 12, line 3"),
+            b"line N, line N"
+        );
+        assert_eq!(
+            apply(Normalizer::new("([a-z]+)=([0-9]+)", "\\2=\\1"), b"x=1 y=22"),
+            b"1=x 22=y"
+        );
+        assert_eq!(apply(Normalizer::new("x*", "-"), b"abxc"), b"-a-b--c-");
+        assert_eq!(apply(Normalizer::new("^a", "b"), b"aa\0aa"), b"ba\0ba");
+        assert_eq!(
+            apply(Normalizer::literal("a.b(c)", "\\1"), b"a.b(c) axb(c)"),
+            b"\\1 axb(c)"
+        );
+
+        let dir = std::env::temp_dir().join(format!("file-{}.txt", std::process::id()));
+        let mut output = format!("cannot open {}\n", dir.display()).into_bytes();
+        normalize(&[Normalizer::temp_dir(), Normalizer::pid()], &mut output);
+        assert_eq!(output, b"cannot open $TMPDIR/file-$PID.txt\n");
+        assert_eq!(apply(Normalizer::pid(), b"1234567890"), b"1234567890");
+    }
+
+    #[test]
+    fn test_path_prepend_and_cwd() {
+        let path_of = |options: RunOptions| {
+            TestPlan::<String>::default()
+                .with_options(options)
+                .command()
+                .get_envs()
+                .find(|(name, _)| *name == "PATH")
+                .and_then(|(_, value)| value.map(|value| value.to_os_string()))
+        };
+        let options = RunOptions {
+            path_prepend: vec![PathBuf::from("/stubs"), PathBuf::from("/more")],
+            env: vec![(String::from("PATH"), String::from("/bin:/usr/bin"))],
+            ..Default::default()
+        };
+        assert_eq!(path_of(options), Some("/stubs:/more:/bin:/usr/bin".into()));
+        let options = RunOptions {
+            path_prepend: vec![PathBuf::from("/stubs")],
+            clear_env: true,
+            ..Default::default()
+        };
+        assert_eq!(path_of(options), Some("/stubs".into()));
+        assert_eq!(path_of(RunOptions::default()), None);
+
+        let run: TestRun = TestPlan::default().with_options(RunOptions {
+            cwd: Some(PathBuf::from("/")),
+            ..Default::default()
+        });
+        assert_eq!(run.command().get_current_dir(), Some(Path::new("/")));
+    }
+
+    #[test]
+    fn test_first_diff_hunk() {
+        assert_eq!(
+            first_diff_hunk(
+                "golden.out",
+                "actual stdout",
+                b"a\nb\nc\nd\ne\nf\ng\nh\n",
+                b"a\nb\nc\nd\nX\nY\nf\ng\nh\ni\n"
+            ),
+            "--- golden.out\n+++ actual stdout\n@@ -2,7 +2,8 @@\n b\n c\n d\n-e\n+X\n+Y\n f\n g\n h\n"
+        );
+        assert_eq!(
+            first_diff_hunk("golden.out", "actual stdout", b"a\nb\n", b"b\n"),
+            "--- golden.out\n+++ actual stdout\n@@ -1,2 +1,1 @@\n-a\n b\n"
+        );
+        assert_eq!(
+            first_diff_hunk("golden.out", "actual stdout", b"", b"a"),
+            "--- golden.out\n+++ actual stdout\n@@ -0,0 +1,1 @@\n+a\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn test_mismatch_report() {
+        let expected = (1..=1000)
+            .map(|i| format!("line {i}\n"))
+            .collect::<String>();
+        let actual = expected.replace("line 500\n", "line 5O0\n");
+        let result = std::panic::catch_unwind(|| {
+            assert_output_eq("stdout", actual.as_bytes(), expected.as_bytes())
+        });
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(
+            message,
+            "unexpected stdout: first difference on line 500 at byte 7 (byte 4390 of the output)\n\
+             expected 8893 bytes, got 8893\n\
+             --- expected stdout\n\
+             +++ actual stdout\n\
+             @@ -497,7 +497,7 @@\n line 497\n line 498\n line 499\n-line 500\n+line 5O0\n line 501\n line 502\n line 503\n\
+             run the test with POSIXUTILS_VERBOSE_DIFF=1 to print both in full\n"
+        );
+
+        let report = mismatch_report("stderr", b"a\xff\n", b"ab\n", true);
+        assert!(report.starts_with(
+            "unexpected stderr: first difference on line 1 at byte 2 (byte 2 of the output)\n\
+             expected 3 bytes, got 3\n"
+        ));
+        assert!(report.contains("\n-ab\n+a\\xff\n"), "{report}");
+        assert!(report.ends_with("expected stderr:\nab\n\nactual stderr:\na\\xff\\n\n"));
+    }
+
+    #[test]
+    fn test_golden_file_is_compared_and_regenerated() {
+        let fixture = TestFixture::new("plib-golden");
+        let path = fixture.path("golden/out.txt");
+        let message = |actual: &'static [u8]| {
+            let path = path.clone();
+            let result = std::panic::catch_unwind(move || check_golden_file(&path, actual, false));
+            *result.unwrap_err().downcast::<String>().unwrap()
+        };
+        assert!(message(b"1\n").contains("POSIXUTILS_REGEN_GOLDEN=1 to create it"));
+
+        check_golden_file(&path, b"1\n2\n", true);
+        assert_eq!(fs::read(&path).unwrap(), b"1\n2\n");
+        check_golden_file(&path, b"1\n2\n", false);
+        let message = message(b"1\n3\n");
+        assert!(
+            message.contains("@@ -1,2 +1,2 @@\n 1\n-2\n+3\n"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn test_signals_are_sent_at_their_offsets() {
+        let mut command = Command::new("sh");
+        command.args([
+            "-c",
+            "trap 'echo usr1' USR1; sleep 2 >/dev/null 2>&1 & wait; wait",
+        ]);
+        let start = Instant::now();
+        let output = run_command(
+            command,
+            StdinSource::Inline(Vec::new()),
+            None,
+            &[
+                (Duration::from_millis(300), libc::SIGTERM),
+                (Duration::from_millis(100), libc::SIGUSR1),
+            ],
+            false,
+        );
+        assert_eq!(output.stdout, b"usr1\n");
+        ExpectedExitCode::Signaled(libc::SIGTERM).assert_matches(output.status);
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        // signals due after the command exits are not sent
+        let output = run_command(
+            Command::new("true"),
+            StdinSource::Inline(Vec::new()),
+            None,
+            &[(Duration::from_secs(60), libc::SIGKILL)],
+            false,
+        );
+        assert!(output.status.success());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_orphans_are_killed_and_reported() {
+        let start = Instant::now();
+        let result = std::panic::catch_unwind(|| {
+            let mut command = Command::new("sh");
+            // the sleep keeps the output pipe open until it is killed
+            command.args(["-c", "sleep 30 & echo started"]);
+            run_command(command, StdinSource::Inline(Vec::new()), None, &[], true)
+        });
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.contains("left processes running"), "{message}");
+        assert!(message.contains("\"sleep\""), "{message}");
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        // children that exit with the command are not orphans
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 0.1 & wait"]);
+        let output = run_command(command, StdinSource::Inline(Vec::new()), None, &[], true);
+        assert!(output.status.success());
+    }
+
+    #[test]
+    fn test_dirs_are_unique_and_removed() {
+        let first = TestDir::new("plib-dir");
+        let second = TestDir::new("plib-dir");
+        assert_ne!(first.path(), second.path());
+        let name = first.path().file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with(&format!("plib-dir-{}-", std::process::id())));
+
+        let file = first.file("sub/file.txt", "contents");
+        assert_eq!(file, first.path().join("sub/file.txt"));
+        assert_eq!(fs::read(&file).unwrap(), b"contents");
+        let path = first.path().to_path_buf();
+        drop(first);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_streaming_output_is_read_before_stdin_is_closed() {
+        let output = run_streaming_command(
+            Command::new("cat"),
+            &[
+                StreamStep::Write(b"first\n".to_vec()),
+                StreamStep::Expect {
+                    prefix: "first\n".to_string(),
+                    within: Duration::from_secs(5),
+                },
+                StreamStep::Write(b"second\n".to_vec()),
+            ],
+        )
+        .unwrap();
+        assert_eq!(output.stdout, b"first\nsecond\n");
+        assert!(output.status.success());
+
+        // sort writes nothing before the end of its input
+        let start = Instant::now();
+        let e = run_streaming_command(
+            Command::new("sort"),
+            &[
+                StreamStep::Write(b"b\na\n".to_vec()),
+                StreamStep::Expect {
+                    prefix: "b\n".to_string(),
+                    within: Duration::from_millis(200),
+                },
+            ],
+        )
+        .unwrap_err();
+        assert!(
+            e.contains("did not start with \"b\\n\" within 200ms"),
+            "{e}"
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        assert_eq!(StreamState::of(b"a", b"ab"), StreamState::Waiting);
+        assert_eq!(StreamState::of(b"abc", b"ab"), StreamState::Ready);
+        assert_eq!(StreamState::of(b"ax", b"ab"), StreamState::Diverged);
+    }
 }
@@ -0,0 +1,191 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Large inputs for tests, generated from a seed instead of checked in.
+//!
+//! The same arguments always give the same input, on every platform. Each
+//! kind of input comes as a buffer, e.g. [`lines`], and as a function that
+//! writes it, e.g. [`write_lines`], which suits [`StdinSource::Generator`]:
+//!
+//! ```ignore
+//! StdinSource::Generator {
+//!     generate: Box::new(|out| generate::write_lines(out, 100_000, 1)),
+//!     chunk_size: plib::BUFSZ,
+//!     delay: None,
+//! }
+//! ```
+//!
+//! [`StdinSource::Generator`]: super::StdinSource::Generator
+
+use std::io::{self, Write};
+
+use crate::BUFSZ;
+
+/// A SplitMix64 generator, which is small and good enough for test inputs.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `low..=high`.
+    fn between(&mut self, low: usize, high: usize) -> usize {
+        low + (self.next() % (high - low + 1) as u64) as usize
+    }
+
+    fn push_word(&mut self, buf: &mut Vec<u8>) {
+        for _ in 0..self.between(1, 8) {
+            buf.push(b'a' + self.between(0, 25) as u8);
+        }
+    }
+}
+
+/// Collects the output of `write` into a buffer.
+fn collect(write: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write(&mut buf).unwrap();
+    buf
+}
+
+/// Writes `buf` to `out` once it is full, or whatever is left when `last` is set.
+fn flush_full(out: &mut dyn Write, buf: &mut Vec<u8>, last: bool) -> io::Result<()> {
+    if last || buf.len() >= BUFSZ {
+        out.write_all(buf)?;
+        buf.clear();
+    }
+    Ok(())
+}
+
+/// Returns `n` lines of one to ten lowercase words separated by spaces.
+pub fn lines(n: usize, seed: u64) -> Vec<u8> {
+    collect(|out| write_lines(out, n, seed))
+}
+
+/// Writes the lines of [`lines`] to `out`.
+pub fn write_lines(out: &mut dyn Write, n: usize, seed: u64) -> io::Result<()> {
+    let mut rng = Rng(seed);
+    let mut buf = Vec::with_capacity(BUFSZ + 128);
+    for _ in 0..n {
+        for i in 0..rng.between(1, 10) {
+            if i > 0 {
+                buf.push(b' ');
+            }
+            rng.push_word(&mut buf);
+        }
+        buf.push(b'\n');
+        flush_full(out, &mut buf, false)?;
+    }
+    flush_full(out, &mut buf, true)
+}
+
+/// Returns `n` bytes of any value.
+pub fn bytes(n: usize, seed: u64) -> Vec<u8> {
+    collect(|out| write_bytes(out, n, seed))
+}
+
+/// Writes the bytes of [`bytes`] to `out`.
+pub fn write_bytes(out: &mut dyn Write, n: usize, seed: u64) -> io::Result<()> {
+    let mut rng = Rng(seed);
+    let mut buf = Vec::with_capacity(BUFSZ + 8);
+    let mut left = n;
+    while left > 0 {
+        let chunk = rng.next().to_le_bytes();
+        let len = left.min(chunk.len());
+        buf.extend_from_slice(&chunk[..len]);
+        left -= len;
+        flush_full(out, &mut buf, false)?;
+    }
+    flush_full(out, &mut buf, true)
+}
+
+/// Returns `rows` lines of `cols` comma-separated fields, each either a
+/// lowercase word or a number below 100000.
+pub fn csv(rows: usize, cols: usize, seed: u64) -> Vec<u8> {
+    collect(|out| write_csv(out, rows, cols, seed))
+}
+
+/// Writes the rows of [`csv`] to `out`.
+pub fn write_csv(out: &mut dyn Write, rows: usize, cols: usize, seed: u64) -> io::Result<()> {
+    let mut rng = Rng(seed);
+    let mut buf = Vec::with_capacity(BUFSZ + 16 * cols);
+    for _ in 0..rows {
+        for col in 0..cols {
+            if col > 0 {
+                buf.push(b',');
+            }
+            if rng.next() % 2 == 0 {
+                rng.push_word(&mut buf);
+            } else {
+                write!(buf, "{}", rng.next() % 100_000)?;
+            }
+        }
+        buf.push(b'\n');
+        flush_full(out, &mut buf, false)?;
+    }
+    flush_full(out, &mut buf, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_input() {
+        assert_eq!(lines(100, 7), lines(100, 7));
+        assert_ne!(lines(100, 7), lines(100, 8));
+        assert_eq!(bytes(1000, 7), bytes(1000, 7));
+        assert_ne!(bytes(1000, 7), bytes(1000, 8));
+        assert_eq!(csv(10, 3, 7), csv(10, 3, 7));
+        assert_ne!(csv(10, 3, 7), csv(10, 3, 8));
+    }
+
+    #[test]
+    fn test_shapes() {
+        let text = String::from_utf8(lines(1000, 1)).unwrap();
+        assert_eq!(text.lines().count(), 1000);
+        for line in text.lines() {
+            let words = line.split(' ').collect::<Vec<_>>();
+            assert!((1..=10).contains(&words.len()), "{line:?}");
+            assert!(words.iter().all(|word| (1..=8).contains(&word.len())
+                && word.bytes().all(|b| b.is_ascii_lowercase())));
+        }
+
+        let data = bytes(100_003, 1);
+        assert_eq!(data.len(), 100_003);
+        assert!(data.contains(&0) && data.contains(&0xff));
+
+        let table = String::from_utf8(csv(500, 4, 1)).unwrap();
+        assert_eq!(table.lines().count(), 500);
+        assert!(table.lines().all(|row| row.split(',').count() == 4));
+    }
+
+    #[test]
+    fn test_buffers_match_writers() {
+        // a small chunked writer, like the one of the streaming input
+        struct Chunks(Vec<u8>);
+        impl Write for Chunks {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                let len = buf.len().min(100);
+                self.0.extend_from_slice(&buf[..len]);
+                Ok(len)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut out = Chunks(Vec::new());
+        write_lines(&mut out, 2000, 3).unwrap();
+        assert_eq!(out.0, lines(2000, 3));
+    }
+}
@@ -7,11 +7,8 @@
 // SPDX-License-Identifier: MIT
 //
 
-mod signal;
-
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
-
-use crate::signal::{list_signals, lookup_signum};
+use plib::signals;
 
 enum ConfigMode {
     Signal(i32),
@@ -23,6 +20,24 @@ struct Config {
     pids: Vec<u32>,
 }
 
+fn list_signals() {
+    let mut output = String::new();
+    for name in signals::names() {
+        output.push_str(name);
+        output.push(' ');
+    }
+
+    println!("{}", output);
+}
+
+fn lookup_signum(signame: &str) -> Result<i32, &'static str> {
+    if signame == "0" {
+        Ok(0)
+    } else {
+        signals::name_to_num(signame).ok_or("Unknown signal name")
+    }
+}
+
 fn parse_cmdline() -> Result<Config, &'static str> {
     let mut pids = Vec::new();
     let mut mode = ConfigMode::Signal(libc::SIGTERM);
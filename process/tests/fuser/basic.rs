@@ -1,6 +1,7 @@
 mod basic {
     use crate::fuser::fuser_test;
-    use std::{fs::File, path::PathBuf, process::Command, str};
+    use plib::testing::TestDir;
+    use std::{process::Command, str};
 
     /// Tests the basic functionality of `fuser` by ensuring it can find the PID of a process.
     ///
@@ -11,16 +12,9 @@ mod basic {
     /// - Verifies that the PID of the process is included in the output of `fuser`.
     #[test]
     fn test_fuser_basic() {
-        fn get_temp_file_path() -> PathBuf {
-            let mut path = std::env::temp_dir();
-
-            path.push("test_file");
-
-            path
-        }
-        let binding = get_temp_file_path();
+        let dir = TestDir::new("fuser-basic");
+        let binding = dir.file("test_file", "");
         let temp_file_path = binding.to_str().unwrap();
-        File::create(temp_file_path).expect("Failed to create temporary file");
 
         let mut process = Command::new("tail")
             .arg("-f")
@@ -41,6 +35,5 @@ mod basic {
         });
 
         process.kill().expect("Failed to kill the process");
-        std::fs::remove_file(temp_file_path).expect("Failed to remove temporary file");
     }
 }
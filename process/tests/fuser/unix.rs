@@ -1,5 +1,6 @@
 mod unix {
     use crate::fuser::fuser_test;
+    use plib::testing::TestDir;
     use std::{
         fs,
         os::unix::net::{UnixListener, UnixStream},
@@ -56,8 +57,9 @@ mod unix {
     /// - Verifies that the `fuser` command can find the process associated with the Unix socket.
     #[test]
     fn test_fuser_unixsocket() {
-        let socket_path = "/tmp/test.sock";
-        let _unix_socket = match start_unix_socket(socket_path) {
+        let dir = TestDir::new("fuser-unix");
+        let socket_path = dir.path().join("test.sock").to_str().unwrap().to_string();
+        let _unix_socket = match start_unix_socket(&socket_path) {
             Ok(socket) => socket,
             Err(e) => {
                 eprintln!("Failed to start Unix socket: {}", e);
@@ -65,11 +67,11 @@ mod unix {
             }
         };
 
-        wait_for_unix_socket(socket_path);
+        wait_for_unix_socket(&socket_path);
 
         let handle = thread::spawn(move || {
-            fuser_test(vec![socket_path.to_string()], "", 0, |_, _output| {
-                let manual_output = Command::new("fuser").arg(socket_path).output();
+            fuser_test(vec![socket_path.clone()], "", 0, |_, _output| {
+                let manual_output = Command::new("fuser").arg(&socket_path).output();
 
                 match manual_output {
                     Ok(output) => {
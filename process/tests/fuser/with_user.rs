@@ -1,6 +1,7 @@
 mod with_user {
     use crate::fuser::fuser_test;
     use libc::uid_t;
+    use plib::testing::TestDir;
     use std::{ffi::CStr, fs::File, io, process::Command, str};
 
     /// Retrieves the user name of the process owner by process ID on Linux.
@@ -73,10 +74,8 @@ mod with_user {
     /// - Verifies that the owner printed in stderr.
     #[test]
     fn test_fuser_with_user() {
-        let temp_file_path = std::env::temp_dir().join("test_file_with_user");
-        let temp_file_path_clone = temp_file_path.clone();
-
-        File::create(&temp_file_path_clone).expect("Failed to create temporary file");
+        let dir = TestDir::new("fuser-with-user");
+        let temp_file_path_clone = dir.file("test_file_with_user", "");
 
         let mut process = Command::new("tail")
             .arg("-f")
@@ -106,6 +105,5 @@ mod with_user {
         );
 
         process.kill().expect("Failed to kill the process");
-        std::fs::remove_file(temp_file_path).expect("Failed to remove temporary file");
     }
 }
@@ -8,97 +8,46 @@
 //
 
 use std::{
-    io::Write,
-    process::{Command, Output, Stdio},
+    fs,
+    io::BufRead,
+    os::unix::fs::PermissionsExt,
+    process::{Command, Stdio},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use sysinfo::System;
+use plib::testing::{run_test, run_test_base, Normalizer, RunOptions, TestFixture, TestPlan};
 
-pub struct TestPlan {
-    pub cmd: String,
-    pub args: Vec<String>,
-    pub stdin_data: String,
-    pub expected_out: String,
-    pub expected_err: String,
-    pub expected_exit_code: Option<i32>,
-    pub has_subprocesses: bool,
-}
-
-fn run_test_base(cmd: &str, args: &Vec<String>, stdin_data: &[u8]) -> (Output, i32) {
+fn test_bin_path(cmd: &str) -> std::path::PathBuf {
     let relpath = if cfg!(debug_assertions) {
         format!("target/debug/{}", cmd)
     } else {
         format!("target/release/{}", cmd)
     };
-    let test_bin_path = std::env::current_dir()
+    std::env::current_dir()
         .unwrap()
         .parent()
         .unwrap() // Move up to the workspace root from the current package directory
-        .join(relpath); // Adjust the path to the binary
-
-    let mut command = Command::new(test_bin_path);
-    let mut child = command
-        .args(args)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .expect(format!("failed to spawn command {}", cmd).as_str());
-
-    let pgid = unsafe { libc::getpgid(child.id() as i32) };
-
-    // Separate the mutable borrow of stdin from the child process
-    if let Some(mut stdin) = child.stdin.take() {
-        let chunk_size = 1024; // Arbitrary chunk size, adjust if needed
-        for chunk in stdin_data.chunks(chunk_size) {
-            // Write each chunk
-            if let Err(e) = stdin.write_all(chunk) {
-                eprintln!("Error writing to stdin: {}", e);
-                break;
-            }
-            // Flush after writing each chunk
-            if let Err(e) = stdin.flush() {
-                eprintln!("Error flushing stdin: {}", e);
-                break;
-            }
-
-            // Sleep briefly to avoid CPU spinning
-            thread::sleep(Duration::from_millis(10));
-        }
-        // Explicitly drop stdin to close the pipe
-        drop(stdin);
-    }
-
-    // Ensure we wait for the process to complete after writing to stdin
-    let output = child.wait_with_output().expect("failed to wait for child");
-    (output, pgid)
-}
-
-pub fn run_test(plan: TestPlan) {
-    let (output, pgid) = run_test_base(&plan.cmd, &plan.args, plan.stdin_data.as_bytes());
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    assert_eq!(stdout, plan.expected_out);
-
-    let stderr = String::from_utf8_lossy(&output.stderr);
-    assert_eq!(stderr, plan.expected_err);
-
-    assert_eq!(output.status.code(), plan.expected_exit_code);
-    if let Some(0) = plan.expected_exit_code {
-        assert!(output.status.success());
-    }
-
-    let mut system = System::new_all();
-    system.refresh_all();
-    for (_, process) in system.processes() {
-        if let Some(gid) = process.group_id() {
-            if *gid == pgid as u32 {
-                assert!(plan.has_subprocesses)
-            }
+        .join(relpath) // Adjust the path to the binary
+}
+
+/// Runs timeout with `args`, sends it `signal` after `delay`, and checks that
+/// the utility printed `expected_out` and exited successfully.
+fn timeout_test_with_signal(args: &[&str], signal: i32, delay: Duration, expected_out: &str) {
+    run_test(
+        TestPlan {
+            cmd: String::from("timeout"),
+            args: args.iter().map(|s| String::from(*s)).collect(),
+            stdin_data: String::from(""),
+            expected_out: String::from(expected_out),
+            expected_err: String::from(""),
+            expected_exit_code: 0,
         }
-    }
+        .with_options(RunOptions {
+            signals: vec![(delay, signal)],
+            ..Default::default()
+        }),
+    );
 }
 
 fn timeout_test(args: &[&str], expected_err: &str, expected_exit_code: i32) {
@@ -108,33 +57,31 @@ fn timeout_test(args: &[&str], expected_err: &str, expected_exit_code: i32) {
         stdin_data: String::from(""),
         expected_out: String::from(""),
         expected_err: String::from(expected_err),
-        expected_exit_code: Some(expected_exit_code),
-        has_subprocesses: false,
+        expected_exit_code,
     });
 }
 
-fn timeout_test_extended(
+/// Same as [timeout_test], but also checks that timeout finished within `min..max` wall-clock time.
+fn timeout_test_timed(
     args: &[&str],
-    expected_err: &str,
-    expected_exit_code: Option<i32>,
-    has_subprocesses: bool,
+    expected_exit_code: i32,
+    min: Duration,
+    max: Duration,
 ) {
-    run_test(TestPlan {
-        cmd: String::from("timeout"),
-        args: args.iter().map(|s| String::from(*s)).collect(),
-        stdin_data: String::from(""),
-        expected_out: String::from(""),
-        expected_err: String::from(expected_err),
-        expected_exit_code,
-        has_subprocesses,
-    });
+    let start = Instant::now();
+    timeout_test(args, "", expected_exit_code);
+    let elapsed = start.elapsed();
+    assert!(
+        elapsed >= min && elapsed < max,
+        "timeout finished after {elapsed:?}, expected {min:?}..{max:?}"
+    );
 }
 
-const TRUE: &'static str = "true";
-const SLEEP: &'static str = "sleep";
-const NON_EXECUTABLE: &'static str = "tests/timeout/non_executable.sh";
-const WITH_ARGUMENT: &'static str = "tests/timeout/with_argument.sh";
-const SPAWN_CHILD: &'static str = "tests/timeout/spawn_child.sh";
+const TRUE: &str = "true";
+const SLEEP: &str = "sleep";
+const NON_EXECUTABLE: &str = "tests/timeout/non_executable.sh";
+const WITH_ARGUMENT: &str = "tests/timeout/with_argument.sh";
+const SPAWN_CHILD: &str = "tests/timeout/spawn_child.sh";
 
 #[test]
 fn test_absent_duration() {
@@ -191,6 +138,51 @@ fn test_signal_parsing_lowercase_with_prefix() {
     timeout_test(&["-s", "sigstop", "1", TRUE], "", 0);
 }
 
+#[test]
+fn test_signal_parsing_numeric() {
+    timeout_test(&["-s", "15", "1", TRUE], "", 0);
+    timeout_test(&["-s", "9", "1", TRUE], "", 0);
+}
+
+#[test]
+fn test_signal_parsing_numeric_invalid() {
+    timeout_test(
+        &["-s", "0", "1", TRUE],
+        "timeout: invalid signal number '0'\n",
+        125,
+    );
+    timeout_test(
+        &["-s", "1000", "1", TRUE],
+        "timeout: invalid signal number '1000'\n",
+        125,
+    );
+}
+
+#[test]
+fn test_signal_parsing_realtime() {
+    if cfg!(target_os = "linux") {
+        timeout_test(&["-s", "RTMIN", "1", TRUE], "", 0);
+        timeout_test(&["-s", "RTMIN+3", "1", TRUE], "", 0);
+        timeout_test(&["-s", "SIGRTMAX-1", "1", TRUE], "", 0);
+        timeout_test(
+            &["-s", "RTMAX+1", "1", TRUE],
+            "timeout: invalid signal name 'RTMAX+1'\n",
+            125,
+        );
+    } else {
+        timeout_test(
+            &["-s", "RTMIN", "1", TRUE],
+            "timeout: real-time signals are not supported on this platform: 'RTMIN'\n",
+            125,
+        );
+    }
+}
+
+#[test]
+fn test_send_kill_numeric() {
+    timeout_test(&["-s", "9", "1", SLEEP, "2"], "", 137);
+}
+
 #[test]
 fn test_multiple_signals() {
     timeout_test(
@@ -211,6 +203,30 @@ fn test_invalid_duration_empty_float() {
     timeout_test(&[".", TRUE], "timeout: invalid duration format '.'\n", 125);
 }
 
+#[test]
+fn test_invalid_duration_rejected_before_fork() {
+    timeout_test(
+        &["--", "-5", "inexistent_utility"],
+        "timeout: invalid duration format '-5'\n",
+        125,
+    );
+    timeout_test(
+        &[".", "inexistent_utility"],
+        "timeout: invalid duration format '.'\n",
+        125,
+    );
+    timeout_test(
+        &["1..2", "inexistent_utility"],
+        "timeout: invalid duration format '1..2'\n",
+        125,
+    );
+    timeout_test(
+        &["1.", "inexistent_utility"],
+        "timeout: invalid duration format '1.'\n",
+        125,
+    );
+}
+
 #[test]
 fn test_invalid_duration_format_invalid_suffix() {
     timeout_test(
@@ -245,6 +261,24 @@ fn test_utility_cound_not_execute() {
     );
 }
 
+#[test]
+fn test_utility_is_directory() {
+    timeout_test(
+        &["1", "tests/timeout"],
+        "timeout: unable to run the utility 'tests/timeout'\n",
+        126,
+    );
+}
+
+#[test]
+fn test_utility_not_found_with_path() {
+    timeout_test(
+        &["1", "tests/timeout/inexistent_utility"],
+        "timeout: utility 'tests/timeout/inexistent_utility' not found\n",
+        127,
+    );
+}
+
 #[test]
 fn test_utility_not_found() {
     timeout_test(
@@ -266,7 +300,7 @@ fn test_basic() {
 
 #[test]
 fn test_send_kill() {
-    timeout_test_extended(&["-s", "KILL", "1", SLEEP, "2"], "", None, false);
+    timeout_test(&["-s", "KILL", "1", SLEEP, "2"], "", 137);
 }
 
 #[test]
@@ -274,6 +308,27 @@ fn test_zero_duration() {
     timeout_test(&["0", SLEEP, "2"], "", 0);
 }
 
+#[test]
+fn test_zero_duration_exit_status() {
+    timeout_test(&["0", "sh", "-c", "exit 3"], "", 3);
+    timeout_test(&["-s", "INT", "0", SLEEP, "0.2"], "", 0);
+}
+
+#[test]
+fn test_zero_duration_forwards_signals() {
+    timeout_test_with_signal(
+        &["0", "sh", "-c", "trap 'echo term; exit 0' TERM; sleep 3 & wait"],
+        libc::SIGTERM,
+        Duration::from_millis(500),
+        "term\n",
+    );
+}
+
+#[test]
+fn test_subsecond_timeout_reached() {
+    timeout_test(&["0.5", SLEEP, "2"], "", 124);
+}
+
 #[test]
 fn test_timeout_reached() {
     timeout_test(&["1", SLEEP, "2"], "", 124);
@@ -293,12 +348,7 @@ fn test_preserve_status_with_sigterm() {
 #[test]
 fn test_preserve_status_sigcont_with_sigkill() {
     // 137 = 128 + 9 (SIGKILL after second timeout)
-    timeout_test_extended(
-        &["-p", "-s", "CONT", "-k", "1", "1", SLEEP, "3"],
-        "",
-        None,
-        false,
-    );
+    timeout_test(&["-p", "-s", "CONT", "-k", "1", "1", SLEEP, "3"], "", 137);
 }
 
 #[test]
@@ -307,22 +357,459 @@ fn test_preserve_status_cont() {
     timeout_test(&["-p", "-s", "CONT", "-k", "1", "0", SLEEP, "3"], "", 0);
 }
 
+#[test]
+fn test_preserve_status_utility_killed_by_signal() {
+    // timeout terminates itself with the signal that terminated the utility
+    run_test(
+        TestPlan {
+            cmd: String::from("timeout"),
+            args: ["-p", "5", "sh", "-c", "kill -TERM $$"]
+                .iter()
+                .map(|s| String::from(*s))
+                .collect(),
+            stdin_data: String::from(""),
+            expected_out: String::from(""),
+            expected_err: String::from(""),
+            expected_exit_code: 0,
+        }
+        .with_options(RunOptions {
+            expect_signal: Some(libc::SIGTERM),
+            ..Default::default()
+        }),
+    );
+}
+
 #[test]
 fn test_not_foreground_timeout() {
-    timeout_test_extended(&["1", SPAWN_CHILD], "", Some(124), true);
+    timeout_test(&["1", SPAWN_CHILD], "", 124);
 }
 
 #[test]
 fn test_foreground_timeout() {
-    timeout_test_extended(&["-f", "1", SPAWN_CHILD], "", Some(124), false);
+    timeout_test(&["-f", "1", SPAWN_CHILD], "", 124);
 }
 
 #[test]
 fn test_not_foreground_ok() {
-    timeout_test_extended(&["5", SPAWN_CHILD], "", Some(0), true);
+    timeout_test(&["5", SPAWN_CHILD], "", 0);
 }
 
 #[test]
 fn test_foreground_ok() {
-    timeout_test_extended(&["-f", "5", SPAWN_CHILD], "", Some(0), false);
+    timeout_test(&["-f", "5", SPAWN_CHILD], "", 0);
+}
+
+#[test]
+fn test_forward_usr1() {
+    timeout_test_with_signal(
+        &["5", "sh", "-c", "trap 'echo usr1; exit 0' USR1; sleep 3 & wait"],
+        libc::SIGUSR1,
+        Duration::from_millis(500),
+        "usr1\n",
+    );
+}
+
+#[test]
+fn test_forward_int() {
+    timeout_test_with_signal(
+        &["5", "sh", "-c", "trap 'echo int; exit 0' INT; sleep 3 >/dev/null 2>&1 & wait"],
+        libc::SIGINT,
+        Duration::from_millis(100),
+        "int\n",
+    );
+}
+
+/// Spawns timeout with `args`, whose utility must print its PID as the first line of output,
+/// and returns the timeout process with the PID of the utility.
+fn spawn_timeout_reporting_pid(args: &[&str]) -> (std::process::Child, i32) {
+    let mut child = Command::new(test_bin_path("timeout"))
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn command timeout");
+
+    let mut line = String::new();
+    std::io::BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    let pid = line.trim().parse().unwrap();
+
+    (child, pid)
+}
+
+/// Returns the state of the process `pid`, as shown in `/proc/<pid>/stat`.
+#[cfg(target_os = "linux")]
+fn process_state(pid: i32) -> Option<String> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // the name may contain anything, so the state is found after its closing parenthesis
+    let (_, fields) = stat.rsplit_once(") ")?;
+    fields.split(' ').next().map(String::from)
+}
+
+/// Waits until the process `pid` is in `state`, and fails the test if it is not `within`
+/// this time.
+#[cfg(target_os = "linux")]
+fn wait_for_state(pid: i32, state: &str, within: Duration) {
+    let deadline = Instant::now() + within;
+    loop {
+        let current = process_state(pid);
+        if current.as_deref() == Some(state) {
+            return;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "process {pid} is in state {current:?} instead of {state:?} after {within:?}"
+        );
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_stop_signal_leaves_child_stopped() {
+    let (mut child, pid) =
+        spawn_timeout_reporting_pid(&["-s", "STOP", "1", "sh", "-c", "echo $$; exec sleep 3"]);
+
+    wait_for_state(pid, "T", Duration::from_secs(10));
+    assert!(child.try_wait().unwrap().is_none());
+
+    unsafe { libc::kill(pid, libc::SIGCONT) };
+    assert_eq!(child.wait().unwrap().code(), Some(124));
+}
+
+#[test]
+fn test_stop_signal_with_kill_after() {
+    timeout_test(&["-f", "-s", "STOP", "-k", "1", "1", SLEEP, "5"], "", 137);
+}
+
+fn stop_and_continue_child(args: &[&str]) {
+    let (mut child, pid) = spawn_timeout_reporting_pid(args);
+
+    thread::sleep(Duration::from_millis(200));
+    unsafe { libc::kill(pid, libc::SIGSTOP) };
+    thread::sleep(Duration::from_millis(300));
+    unsafe { libc::kill(pid, libc::SIGCONT) };
+
+    assert_eq!(child.wait().unwrap().code(), Some(3));
+}
+
+#[test]
+fn test_child_stopped_externally() {
+    stop_and_continue_child(&["5", "sh", "-c", "echo $$; sleep 1; exit 3"]);
+}
+
+#[test]
+fn test_foreground_child_stopped_externally() {
+    stop_and_continue_child(&["-f", "5", "sh", "-c", "echo $$; sleep 1; exit 3"]);
+}
+
+#[test]
+fn test_deadline_kept_when_timeout_stopped() {
+    let start = Instant::now();
+    let child = Command::new(test_bin_path("timeout"))
+        .args(["4", SLEEP, "10"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn command timeout");
+    let pid = child.id() as i32;
+
+    // a deadline restarted when timeout is continued would pass after 7.5s
+    thread::sleep(Duration::from_millis(300));
+    unsafe { libc::kill(pid, libc::SIGSTOP) };
+    thread::sleep(Duration::from_millis(3200));
+    unsafe { libc::kill(pid, libc::SIGCONT) };
+
+    let output = child.wait_with_output().unwrap();
+    let elapsed = start.elapsed();
+    assert_eq!(output.status.code(), Some(124));
+    assert!(
+        elapsed >= Duration::from_millis(3900) && elapsed < Duration::from_millis(6500),
+        "child was signaled after {elapsed:?}"
+    );
+}
+
+#[test]
+fn test_deadline_passed_while_timeout_stopped() {
+    let child = Command::new(test_bin_path("timeout"))
+        .args([
+            "-k",
+            "5",
+            "1",
+            "sh",
+            "-c",
+            "trap 'echo term; exit 3' TERM; sleep 10 & wait",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn command timeout");
+    let pid = child.id() as i32;
+
+    thread::sleep(Duration::from_millis(300));
+    unsafe { libc::kill(pid, libc::SIGSTOP) };
+    thread::sleep(Duration::from_millis(1500));
+    unsafe { libc::kill(pid, libc::SIGCONT) };
+
+    // the utility gets the grace period of -k to handle the SIGTERM
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "term\n");
+    assert_eq!(output.status.code(), Some(124));
+}
+
+#[test]
+fn test_descendants_killed() {
+    run_test(
+        TestPlan {
+            cmd: String::from("timeout"),
+            args: ["1", "sh", "-c", "sleep 100 >/dev/null & wait"]
+                .iter()
+                .map(|s| String::from(*s))
+                .collect(),
+            stdin_data: String::from(""),
+            expected_out: String::from(""),
+            expected_err: String::from(""),
+            expected_exit_code: 124,
+        }
+        .with_options(RunOptions {
+            check_orphans: true,
+            ..Default::default()
+        }),
+    );
+}
+
+#[test]
+fn test_kill_after_escalation() {
+    timeout_test(
+        &["-k", "1", "1", "sh", "-c", "trap '' TERM; sleep 10"],
+        "",
+        137,
+    );
+}
+
+#[test]
+fn test_kill_after_cancelled_on_exit() {
+    let start = Instant::now();
+    timeout_test(
+        &[
+            "-k",
+            "5",
+            "1",
+            "sh",
+            "-c",
+            "trap 'sleep 0.1; exit 0' TERM; sleep 10 & wait",
+        ],
+        "",
+        124,
+    );
+    assert!(start.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn test_utility_not_found_single_diagnostic() {
+    let output = run_test_base(
+        "timeout",
+        &vec![
+            String::from("-k"),
+            String::from("1"),
+            String::from("5"),
+            String::from("inexistent_utility"),
+        ],
+        b"",
+    );
+
+    assert_eq!(output.status.code(), Some(127));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "timeout: utility 'inexistent_utility' not found\n"
+    );
+}
+
+#[test]
+fn test_signal_right_after_spawn() {
+    // Varying delay hits different points of timeout startup. Before its handlers are set
+    // up, timeout is terminated by the signal directly, and afterwards it forwards the
+    // signal to the utility and then terminates itself with it.
+    for i in 0..20 {
+        run_test(
+            TestPlan {
+                cmd: String::from("timeout"),
+                args: vec![String::from("5"), String::from(SLEEP), String::from("5")],
+                stdin_data: String::from(""),
+                expected_out: String::from(""),
+                expected_err: String::from(""),
+                expected_exit_code: 0,
+            }
+            .with_options(RunOptions {
+                signals: vec![(Duration::from_micros(i * 100), libc::SIGTERM)],
+                expect_signal: Some(libc::SIGTERM),
+                check_orphans: true,
+                ..Default::default()
+            }),
+        );
+    }
+}
+
+#[test]
+fn test_status_passthrough_before_limit() {
+    timeout_test_timed(
+        &["5", "sh", "-c", "exit 7"],
+        7,
+        Duration::ZERO,
+        Duration::from_secs(1),
+    );
+}
+
+#[test]
+fn test_limit_expiry_timed() {
+    timeout_test_timed(
+        &["1", SLEEP, "5"],
+        124,
+        Duration::from_secs(1),
+        Duration::from_secs(2),
+    );
+}
+
+#[test]
+fn test_preserve_status_kill_after_escalation() {
+    timeout_test_timed(
+        &["-p", "-k", "1", "1", "sh", "-c", "trap '' TERM; sleep 10"],
+        137,
+        Duration::from_secs(2),
+        Duration::from_secs(3),
+    );
+}
+
+#[test]
+fn test_ignored_signal() {
+    timeout_test_timed(
+        &["-s", "USR1", "1", "sh", "-c", "trap '' USR1; sleep 2"],
+        124,
+        Duration::from_secs(2),
+        Duration::from_secs(3),
+    );
+}
+
+/// Kills the utility externally with SIGKILL after the time limit, while it ignores SIGTERM.
+fn kill_child_externally(args: &[&str]) -> Option<i32> {
+    let (mut child, pid) = spawn_timeout_reporting_pid(args);
+
+    thread::sleep(Duration::from_millis(1500));
+    unsafe { libc::kill(pid, libc::SIGKILL) };
+
+    child.wait().unwrap().code()
+}
+
+#[test]
+fn test_external_kill_after_timeout() {
+    assert_eq!(
+        kill_child_externally(&[
+            "-k",
+            "5",
+            "1",
+            "sh",
+            "-c",
+            "echo $$; trap '' TERM; exec sleep 10"
+        ]),
+        Some(124)
+    );
+}
+
+#[test]
+fn test_external_kill_after_timeout_preserve_status() {
+    assert_eq!(
+        kill_child_externally(&[
+            "-p",
+            "-k",
+            "5",
+            "1",
+            "sh",
+            "-c",
+            "echo $$; trap '' TERM; exec sleep 10"
+        ]),
+        Some(137)
+    );
+}
+
+#[test]
+fn test_foreground_kill_after_escalation() {
+    timeout_test(
+        &["-f", "-k", "1", "1", "sh", "-c", "trap '' TERM; exec sleep 10"],
+        "",
+        137,
+    );
+}
+
+#[test]
+fn test_zero_kill_after() {
+    timeout_test_timed(
+        &[
+            "-k",
+            "0",
+            "-s",
+            "TERM",
+            "0.2",
+            "sh",
+            "-c",
+            "trap '' TERM; sleep 10",
+        ],
+        137,
+        Duration::from_millis(200),
+        // well below the 10s the utility would take without SIGKILL
+        Duration::from_secs(5),
+    );
+}
+
+/// Returns a fixture with executable stub scripts in its `bin` directory.
+fn fixture_with_stubs(stubs: &[(&str, &str)]) -> TestFixture {
+    let mut fixture = TestFixture::new("timeout-stubs");
+    for (name, script) in stubs {
+        let name = format!("bin/{name}");
+        fixture = fixture.with_file(&name, format!("#!/bin/sh\n{script}\n"));
+        fs::set_permissions(fixture.path(&name), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    fixture
+}
+
+#[test]
+fn test_utility_found_in_path() {
+    let fixture = fixture_with_stubs(&[("slow", "sleep 10"), ("report", "echo \"$0 in $PWD\"")]);
+    let start = Instant::now();
+    run_test(
+        TestPlan {
+            cmd: String::from("timeout"),
+            args: vec![String::from("0.5"), String::from("slow")],
+            stdin_data: String::new(),
+            expected_out: String::new(),
+            expected_err: String::new(),
+            expected_exit_code: 124,
+        }
+        .with_options(RunOptions {
+            path_prepend: vec![fixture.path("bin")],
+            ..Default::default()
+        }),
+    );
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    let dir = fixture.dir().to_str().unwrap();
+    fixture.run_test(
+        TestPlan {
+            cmd: String::from("timeout"),
+            args: vec![String::from("5"), String::from("report")],
+            stdin_data: String::new(),
+            expected_out: String::from("$DIR/bin/report in $DIR\n"),
+            expected_err: String::new(),
+            expected_exit_code: 0,
+        }
+        .with_options(RunOptions {
+            path_prepend: vec![fixture.path("bin")],
+            stdout_normalizers: vec![Normalizer::literal(dir, "$DIR")],
+            ..Default::default()
+        }),
+    );
 }
@@ -7,27 +7,29 @@
 // SPDX-License-Identifier: MIT
 //
 
-mod signal;
-
 use std::error::Error;
-use std::os::unix::fs::PermissionsExt;
-use std::os::unix::process::{CommandExt, ExitStatusExt};
-use std::path::Path;
-use std::process::{Command, ExitStatus};
-use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
-use std::sync::Mutex;
+use std::ffi::CString;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicI32, Ordering};
 use std::time::Duration;
 
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::signals::{self, NSIG};
+
+// libc doesn't expose setitimer() for all supported platforms
+const ITIMER_REAL: libc::c_int = 0;
+extern "C" {
+    fn setitimer(
+        which: libc::c_int,
+        new_value: *const libc::itimerval,
+        old_value: *mut libc::itimerval,
+    ) -> libc::c_int;
+}
 
-use crate::signal::parse_signal;
-
-static FOREGROUND: AtomicBool = AtomicBool::new(false);
-static FIRST_SIGNAL: AtomicI32 = AtomicI32::new(libc::SIGTERM);
-static KILL_AFTER: Mutex<Option<Duration>> = Mutex::new(None);
-static MONITORED_PID: AtomicI32 = AtomicI32::new(0);
-static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+/// Write end of the self-pipe through which [handler] passes incoming signals to the main loop.
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
 
 #[derive(Parser)]
 #[command(version, about = gettext("timeout — execute a utility with a time limit"))]
@@ -41,7 +43,7 @@ struct Args {
     #[arg(short = 'k', long, value_parser = parse_duration, help=gettext("Send a SIGKILL signal if the child process has not terminated after the time period."))]
     kill_after: Option<Duration>,
 
-    #[arg(short = 's', long, default_value = "TERM", value_parser = parse_signal, help=gettext("Specify the signal to send when the time limit is reached."))]
+    #[arg(short = 's', long, default_value = "TERM", value_parser = signals::parse, help=gettext("Specify the signal to send when the time limit is reached."))]
     signal_name: i32,
 
     #[arg(name = "DURATION", value_parser = parse_duration, help=gettext("The maximum amount of time to allow the utility to run, specified as a decimal number with an optional decimal fraction and an optional suffix."))]
@@ -50,12 +52,14 @@ struct Args {
     #[arg(name = "UTILITY", help=gettext("The utility to execute."))]
     utility: String,
 
-    #[arg(name = "ARGUMENT", trailing_var_arg = true, help=gettext("Arguments to pass to the utility."))]
+    #[arg(name = "ARGUMENT", trailing_var_arg = true, allow_hyphen_values = true, help=gettext("Arguments to pass to the utility."))]
     arguments: Vec<String>,
 }
 
 /// Parses string slice into [Duration].
 ///
+/// Zero duration is valid and means that the utility runs without time limit.
+///
 /// # Arguments
 ///
 /// * `s` - [str] that represents duration.
@@ -68,87 +72,309 @@ struct Args {
 ///
 /// Returns a [String] error if passed invalid duration string.
 fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration format '{s}'");
+
     let (value, suffix) = s.split_at(
         s.find(|c: char| !c.is_ascii_digit() && c != '.')
             .unwrap_or(s.len()),
     );
 
-    let value: f64 = value
-        .parse()
-        .map_err(|_| format!("invalid duration format '{s}'"))?;
+    // Value must be digits with at most one decimal point, followed by at least one digit
+    let valid = match value.split_once('.') {
+        Some((_, fraction)) => !fraction.is_empty() && !fraction.contains('.'),
+        None => !value.is_empty(),
+    };
+    if !valid {
+        return Err(invalid());
+    }
+
+    let value: f64 = value.parse().map_err(|_| invalid())?;
 
     let multiplier = match suffix {
         "s" | "" => 1.0,
         "m" => 60.0,
         "h" => 3600.0,
         "d" => 86400.0,
-        _ => return Err(format!("invalid duration format '{s}'")),
+        _ => return Err(invalid()),
+    };
+
+    Duration::try_from_secs_f64(value * multiplier)
+        .map_err(|_| format!("duration is out of range '{s}'"))
+}
+
+/// Returns current time of [libc::CLOCK_MONOTONIC] clock.
+///
+/// # Returns
+///
+/// [Duration] since some unspecified point in the past.
+fn monotonic_now() -> Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
     };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
 
-    Ok(Duration::from_secs_f64(value * multiplier))
+/// Sets value of the real-time timer, zero value disarms it.
+///
+/// # Arguments
+///
+/// * `value` - [libc::timeval] time until alarm.
+fn set_real_timer(value: libc::timeval) {
+    let timer = libc::itimerval {
+        it_interval: libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        },
+        it_value: value,
+    };
+    unsafe { setitimer(ITIMER_REAL, &timer, std::ptr::null_mut()) };
 }
 
-/// Starts the timeout after which [libc::SIGALRM] will be send.
+/// Arms real-time timer after which [libc::SIGALRM] will be send.
 ///
 /// # Arguments
 ///
 /// * `duration` - [Duration] value of time until alarm.
-fn set_timeout(duration: Duration) {
-    if !duration.is_zero() {
-        unsafe { libc::alarm(duration.as_secs() as libc::c_uint) };
-    }
+fn arm_timer(duration: Duration) {
+    // Timer with zero value is disarmed, so sub-microsecond durations are rounded up
+    let usecs = match (duration.as_secs(), duration.subsec_micros()) {
+        (0, 0) => 1,
+        (_, usecs) => usecs,
+    };
+    set_real_timer(libc::timeval {
+        tv_sec: duration.as_secs() as libc::time_t,
+        tv_usec: usecs as libc::suseconds_t,
+    });
 }
 
-/// Sends a signal to the process or process group.
+/// Checks whether signal stops the process by default.
 ///
-/// # Arguments:
+/// # Arguments
 ///
-/// * `pid` - [i32] value of PID.
-/// * `signal` - [i32] value of signal tat must be sent.
-fn send_signal(pid: i32, signal: i32) {
-    if pid == 0 {
-        unsafe { libc::signal(signal, libc::SIG_IGN) };
+/// * `signal` - [i32] value of signal.
+fn is_stop_signal(signal: i32) -> bool {
+    matches!(
+        signal,
+        libc::SIGSTOP | libc::SIGTSTP | libc::SIGTTIN | libc::SIGTTOU
+    )
+}
+
+/// State of the monitored utility. It is owned by the main loop, signal handler never
+/// touches it.
+struct Monitor {
+    /// PID of the utility, which is also its process group ID unless running in foreground.
+    pid: i32,
+    /// Only the utility itself is signaled, not its process group.
+    foreground: bool,
+    /// Signal sent when the timer expires.
+    alarm_signal: i32,
+    /// Time after which [libc::SIGKILL] is sent, armed on the first delivered signal.
+    kill_after: Option<Duration>,
+    /// [libc::CLOCK_MONOTONIC] time at which the armed timer expires.
+    deadline: Option<Duration>,
+    /// Time limit was reached.
+    timed_out: bool,
+    /// [libc::SIGKILL] was sent by timeout itself.
+    sent_kill: bool,
+}
+
+impl Monitor {
+    /// Sends a signal to the monitored process. Unless timeout runs in foreground, signal is
+    /// sent to the whole process group of the monitored process, so its descendants receive
+    /// it too.
+    ///
+    /// # Arguments:
+    ///
+    /// * `signal` - [i32] value of signal tat must be sent.
+    fn send_signal(&mut self, signal: i32) {
+        if signal == libc::SIGKILL {
+            self.sent_kill = true;
+        }
+
+        let target = if self.foreground { self.pid } else { -self.pid };
+        unsafe {
+            libc::kill(target, signal);
+        }
     }
-    unsafe {
-        libc::kill(pid, signal);
+
+    /// Starts the timeout after which [libc::SIGALRM] will be send.
+    ///
+    /// Zero [Duration] means no time limit, so the timer is not started at all.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - [Duration] value of time until alarm.
+    fn set_timeout(&mut self, duration: Duration) {
+        if duration.is_zero() {
+            return;
+        }
+
+        self.deadline = Some(monotonic_now() + duration);
+        arm_timer(duration);
     }
-}
 
-/// Signal [libc::SIGCHLD] handler.
-extern "C" fn chld_handler(_signal: i32) {}
+    /// Disarms the timer, so no stale [libc::SIGALRM] arrives after the child is reaped.
+    fn cancel_timeout(&mut self) {
+        if self.deadline.take().is_some() {
+            set_real_timer(libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            });
+        }
+    }
+
+    /// Re-arms the timer with the time remaining until the current deadline, so the deadline
+    /// stays accurate after timeout itself was stopped and continued.
+    ///
+    /// A deadline that passed while timeout was stopped is left to the [libc::SIGALRM] that
+    /// the timer already raised. Re-arming the timer then would replace the kill-after timer
+    /// set up when that [libc::SIGALRM] is handled.
+    fn resume_timeout(&self) {
+        if let Some(deadline) = self.deadline {
+            let now = monotonic_now();
+            if deadline > now {
+                arm_timer(deadline - now);
+            }
+        }
+    }
+
+    /// Handles signal received by timeout.
+    ///
+    /// # Arguments
+    ///
+    /// * `signal` - [i32] value of incoming signal.
+    fn handle_signal(&mut self, mut signal: i32) {
+        match signal {
+            // Only wakes up the main loop to check the state of the child
+            libc::SIGCHLD => return,
+            libc::SIGCONT => {
+                self.resume_timeout();
+                if self.alarm_signal != libc::SIGCONT {
+                    return;
+                }
+            }
+            // When timeout receives [libc::SIGALRM], this will be considered as timeout reached
+            // and timeout will send prepared signal.
+            libc::SIGALRM => {
+                self.deadline = None;
+                self.timed_out = true;
+                signal = self.alarm_signal;
+            }
+            _ => {}
+        }
+
+        // Zero kill-after means no grace period, SIGKILL follows the signal immediately
+        let kill_now = match self.kill_after.take() {
+            Some(duration) if duration.is_zero() => true,
+            Some(duration) => {
+                self.alarm_signal = libc::SIGKILL;
+                self.set_timeout(duration);
+                false
+            }
+            None => false,
+        };
 
-/// Timeout signal handler.
+        // Propagating incoming signal
+        self.send_signal(signal);
+
+        // Stopped process needs to be continued to handle the signal, unless the signal
+        // itself is meant to stop or continue it
+        if signal != libc::SIGKILL && signal != libc::SIGCONT && !is_stop_signal(signal) {
+            self.send_signal(libc::SIGCONT);
+        }
+
+        if kill_now && signal != libc::SIGKILL {
+            self.send_signal(libc::SIGKILL);
+        }
+    }
+}
+
+/// Timeout signal handler. Only passes the signal to the main loop through the self-pipe, as
+/// the rest of signal handling is not async-signal-safe.
 ///
 /// # Arguments
 ///
 /// * `signal` - integer value of incoming signal.
-extern "C" fn handler(mut signal: i32) {
-    // When timeout receives [libc::SIGALRM], this will be considered as timeout reached and
-    // timeout will send prepared signal.
-    if signal == libc::SIGALRM {
-        TIMED_OUT.store(true, Ordering::SeqCst);
-        signal = FIRST_SIGNAL.load(Ordering::SeqCst);
+extern "C" fn handler(signal: i32) {
+    let saved_errno = errno::errno();
+    let byte = signal as u8;
+    unsafe {
+        libc::write(
+            SIGNAL_PIPE.load(Ordering::Relaxed),
+            &byte as *const u8 as *const libc::c_void,
+            1,
+        );
     }
-    match MONITORED_PID.load(Ordering::SeqCst).cmp(&0) {
-        std::cmp::Ordering::Less => {}
-        std::cmp::Ordering::Equal => std::process::exit(128 + signal),
-        std::cmp::Ordering::Greater => {
-            let mut kill_after = KILL_AFTER.lock().unwrap();
-            if let Some(duration) = *kill_after {
-                FIRST_SIGNAL.store(libc::SIGKILL, Ordering::SeqCst);
-                set_timeout(duration);
-                *kill_after = None;
-            }
+    errno::set_errno(saved_errno);
+}
 
-            // Propagating incoming signal
-            send_signal(MONITORED_PID.load(Ordering::SeqCst), signal);
+/// Creates non-blocking self-pipe for [handler] and stores its write end.
+///
+/// # Returns
+///
+/// [i32] - read end of the pipe.
+///
+/// # Errors
+///
+/// Returns [std::io::Error] if pipe couldn't be created.
+fn create_signal_pipe() -> std::io::Result<i32> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    for fd in fds {
+        unsafe {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK);
+        }
+    }
 
-            if !FOREGROUND.load(Ordering::SeqCst) {
-                send_signal(0, signal);
-                if signal != libc::SIGKILL && signal != libc::SIGCONT {
-                    send_signal(MONITORED_PID.load(Ordering::SeqCst), libc::SIGCONT);
-                    send_signal(0, libc::SIGCONT);
-                }
+    SIGNAL_PIPE.store(fds[1], Ordering::Relaxed);
+    Ok(fds[0])
+}
+
+/// Waits until at least one signal is passed through the self-pipe and reads received signals.
+///
+/// # Arguments
+///
+/// * `fd` - read end of the self-pipe.
+/// * `buf` - buffer for received signals.
+///
+/// # Returns
+///
+/// Slice of `buf` with received signals.
+///
+/// # Errors
+///
+/// Returns [std::io::Error] if polling or reading the pipe failed, unless it was only
+/// interrupted by a signal or found the pipe empty.
+fn read_signals(fd: i32, buf: &mut [u8]) -> std::io::Result<&[u8]> {
+    let retry =
+        |err: &std::io::Error| matches!(err.raw_os_error(), Some(libc::EINTR) | Some(libc::EAGAIN));
+    loop {
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut pollfd, 1, -1) } < 0 {
+            let err = std::io::Error::last_os_error();
+            if retry(&err) {
+                continue;
+            }
+            return Err(err);
+        }
+
+        let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if n > 0 {
+            return Ok(&buf[..n as usize]);
+        }
+        if n < 0 {
+            let err = std::io::Error::last_os_error();
+            if !retry(&err) {
+                return Err(err);
             }
         }
     }
@@ -187,31 +413,62 @@ fn unblock_signal(signal: i32) {
     }
 }
 
-/// Installs handler for [libc::SIGCHLD] signal to receive child's exit status code from parent (timeout).
-fn set_chld() {
-    unsafe {
-        let mut sig_action = std::mem::MaybeUninit::<libc::sigaction>::uninit();
-        let p_sa = sig_action.as_mut_ptr();
-        (*p_sa).sa_sigaction = chld_handler as *const extern "C" fn(libc::c_int) as usize;
-        (*p_sa).sa_flags = libc::SA_RESTART;
-        libc::sigemptyset(&mut (*p_sa).sa_mask);
-        let sig_action = sig_action.assume_init();
+/// Checks whether incoming signal must be forwarded to the monitored process.
+///
+/// Signals that can't be caught, job control signals, [libc::SIGCHLD] and synchronous
+/// fault signals have dedicated handling and are never forwarded.
+///
+/// # Arguments
+///
+/// `signal` - [i32] value of signal.
+fn is_forwarded(signal: i32) -> bool {
+    if matches!(
+        signal,
+        libc::SIGKILL
+            | libc::SIGSTOP
+            | libc::SIGCHLD
+            | libc::SIGTTIN
+            | libc::SIGTTOU
+            | libc::SIGTSTP
+            | libc::SIGCONT
+            | libc::SIGSEGV
+            | libc::SIGBUS
+            | libc::SIGFPE
+            | libc::SIGILL
+            | libc::SIGTRAP
+            | libc::SIGSYS
+    ) {
+        return false;
+    }
 
-        libc::sigaction(
-            libc::SIGCHLD,
-            &sig_action,
-            std::ptr::null_mut::<libc::sigaction>(),
-        );
+    // Signals between the last standard signal and SIGRTMIN are reserved by libc.
+    #[cfg(target_os = "linux")]
+    if signal > libc::SIGSYS && signal < libc::SIGRTMIN() {
+        return false;
     }
 
-    unblock_signal(libc::SIGCHLD);
+    true
+}
+
+/// Returns iterator over all signals that are forwarded to the monitored process.
+fn forwarded_signals() -> impl Iterator<Item = i32> {
+    (1..NSIG).filter(|signal| is_forwarded(*signal))
 }
 
-/// Installs handler ([handler]) for incoming signal and other signals.
+/// Returns iterator over all signals handled by timeout.
 ///
 /// # Arguments
 ///
-/// `signal` - [i32] value of signal that needs to be handled.
+/// `signal` - [i32] value of signal sent on timeout.
+fn handled_signals(signal: i32) -> impl Iterator<Item = i32> {
+    forwarded_signals().chain([libc::SIGCHLD, libc::SIGCONT, signal])
+}
+
+/// Installs handler ([handler]) for all signals handled by timeout.
+///
+/// # Arguments
+///
+/// `signal` - [i32] value of signal sent on timeout.
 fn set_handler(signal: i32) {
     unsafe {
         let mut sig_action = std::mem::MaybeUninit::<libc::sigaction>::uninit();
@@ -221,56 +478,29 @@ fn set_handler(signal: i32) {
         libc::sigemptyset(&mut (*p_sa).sa_mask);
         let sig_action = sig_action.assume_init();
 
-        libc::sigaction(
-            libc::SIGALRM,
-            &sig_action,
-            std::ptr::null_mut::<libc::sigaction>(),
-        );
-        libc::sigaction(
-            libc::SIGINT,
-            &sig_action,
-            std::ptr::null_mut::<libc::sigaction>(),
-        );
-        libc::sigaction(
-            libc::SIGQUIT,
-            &sig_action,
-            std::ptr::null_mut::<libc::sigaction>(),
-        );
-        libc::sigaction(
-            libc::SIGHUP,
-            &sig_action,
-            std::ptr::null_mut::<libc::sigaction>(),
-        );
-        libc::sigaction(
-            libc::SIGTERM,
-            &sig_action,
-            std::ptr::null_mut::<libc::sigaction>(),
-        );
-        libc::sigaction(signal, &sig_action, std::ptr::null_mut::<libc::sigaction>());
+        for handled in handled_signals(signal) {
+            libc::sigaction(handled, &sig_action, std::ptr::null_mut::<libc::sigaction>());
+        }
     }
 }
 
-/// Blocks incoming signal and stores previous signals mask.
+/// Changes mask of signals handled by timeout.
 ///
 /// # Arguments
 ///
-/// `signal` - [i32] value of signal that needs to be handled.
+/// `how` - [libc::SIG_BLOCK] or [libc::SIG_UNBLOCK].
+/// `signal` - [i32] value of signal sent on timeout.
 /// `old_set` - [libc::sigset_t] mutable reference to set of signals into which will be placed previous mask.
-fn block_handler_and_chld(signal: i32, old_set: &mut libc::sigset_t) {
+fn mask_handled_signals(how: i32, signal: i32, old_set: &mut libc::sigset_t) {
     unsafe {
-        let mut block_set = get_empty_sig_set();
+        let mut set = get_empty_sig_set();
 
-        libc::sigaddset(&mut block_set, libc::SIGALRM);
-        libc::sigaddset(&mut block_set, libc::SIGINT);
-        libc::sigaddset(&mut block_set, libc::SIGQUIT);
-        libc::sigaddset(&mut block_set, libc::SIGHUP);
-        libc::sigaddset(&mut block_set, libc::SIGTERM);
-        libc::sigaddset(&mut block_set, signal);
-
-        libc::sigaddset(&mut block_set, libc::SIGCHLD);
+        for handled in handled_signals(signal) {
+            libc::sigaddset(&mut set, handled);
+        }
 
-        if libc::sigprocmask(libc::SIG_BLOCK, &block_set, old_set) != 0 {
-            eprintln!("timeout: failed to set block signals mask");
+        if libc::sigprocmask(how, &set, old_set) != 0 {
+            eprintln!("timeout: failed to set signals mask");
             std::process::exit(125)
         }
     }
@@ -293,30 +523,84 @@ fn disable_core_dumps() -> bool {
     (unsafe { libc::setrlimit(libc::RLIMIT_CORE, &rlim) } == 0)
 }
 
-/// Searches for the executable utility in the directories specified by the `PATH` environment variable.
-///
-/// # Arguments
+/// Diagnostics written by the child if the utility couldn't be executed. They are prepared
+/// before fork(), so the child doesn't need to run any Rust code after a failed exec.
+struct ExecDiagnostics {
+    not_found: String,
+    not_executable: String,
+    failed: String,
+}
+
+impl ExecDiagnostics {
+    fn new(utility: &str) -> Self {
+        Self {
+            not_found: format!("timeout: utility '{utility}' not found\n"),
+            not_executable: format!("timeout: unable to run the utility '{utility}'\n"),
+            failed: format!("timeout: failed to run the utility '{utility}'\n"),
+        }
+    }
+}
+
+/// Executes the utility in the child process, never returns.
 ///
-/// * `utility` - name of the utility to search for.
+/// If the utility couldn't be executed, writes the diagnostic and immediately exits with:
+///     126 - The utility was found but could not be executed.
+///     127 - The utility could not be found.
+///     125 - Any other error.
 ///
-/// # Returns
+/// # Arguments
 ///
-/// `Option<String>` - full path to the utility if found, or `None` if not found.
-fn search_in_path(utility: &str) -> Option<String> {
-    if let Ok(paths) = std::env::var("PATH") {
-        for path in paths.split(':') {
-            let full_path = std::path::Path::new(path).join(utility);
-            if full_path.is_file() {
-                if let Ok(metadata) = std::fs::metadata(&full_path) {
-                    // Check if the file is executable
-                    if metadata.permissions().mode() & 0o111 != 0 {
-                        return Some(full_path.to_string_lossy().into_owned());
-                    }
-                }
-            }
+/// `argv` - null-terminated arguments of the utility, including its name.
+/// `diagnostics` - [ExecDiagnostics] messages for exec failures.
+/// `signal` - [i32] value of signal sent on timeout.
+/// `original_set` - [libc::sigset_t] signals mask that must be restored before exec.
+/// `foreground` - if `false`, the utility is placed into its own process group.
+fn exec_utility(
+    argv: &[*const libc::c_char],
+    diagnostics: &ExecDiagnostics,
+    signal: i32,
+    original_set: &libc::sigset_t,
+    foreground: bool,
+) -> ! {
+    unsafe {
+        if !foreground {
+            libc::setpgid(0, 0);
+        }
+
+        // Pending signals must be handled by the utility, not passed to timeout
+        for handled in handled_signals(signal) {
+            libc::signal(handled, libc::SIG_DFL);
         }
+        libc::signal(libc::SIGTTIN, libc::SIG_DFL);
+        libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+
+        libc::sigprocmask(
+            libc::SIG_SETMASK,
+            original_set,
+            std::ptr::null_mut::<libc::sigset_t>(),
+        );
+
+        libc::execvp(argv[0], argv.as_ptr());
+
+        let (message, exit_code) = match errno::errno().0 {
+            libc::ENOENT => (&diagnostics.not_found, 127),
+            libc::EACCES
+            | libc::EPERM
+            | libc::EISDIR
+            | libc::ENOEXEC
+            | libc::ENOTDIR
+            | libc::ELOOP
+            | libc::ENAMETOOLONG
+            | libc::ETXTBSY => (&diagnostics.not_executable, 126),
+            _ => (&diagnostics.failed, 125),
+        };
+        libc::write(
+            libc::STDERR_FILENO,
+            message.as_ptr() as *const libc::c_void,
+            message.len(),
+        );
+        libc::_exit(exit_code)
     }
-    None
 }
 
 /// Main timeout function that creates child and processes its return exit status.
@@ -339,26 +623,28 @@ fn timeout(args: Args) -> i32 {
         arguments,
     } = args;
 
-    let utility_path = if Path::new(&utility).is_file() {
-        utility.clone()
-    } else {
-        match search_in_path(&utility) {
-            Some(path) => path,
-            None => {
-                eprintln!("timeout: utility '{utility}' not found");
-                return 127;
+    let mut argv = Vec::with_capacity(arguments.len() + 1);
+    for arg in std::iter::once(&utility).chain(arguments.iter()) {
+        match CString::new(arg.as_bytes()) {
+            Ok(arg) => argv.push(arg),
+            Err(_) => {
+                eprintln!("timeout: invalid argument '{arg}'");
+                return 125;
             }
         }
-    };
+    }
 
-    FOREGROUND.store(foreground, Ordering::SeqCst);
-    FIRST_SIGNAL.store(signal_name, Ordering::SeqCst);
-    *KILL_AFTER.lock().unwrap() = kill_after;
+    let mut argv_ptrs: Vec<*const libc::c_char> = argv.iter().map(|arg| arg.as_ptr()).collect();
+    argv_ptrs.push(std::ptr::null());
+    let diagnostics = ExecDiagnostics::new(&utility);
 
-    // Ensures, this process is process leader so all subprocesses can be killed.s
-    if !foreground {
-        unsafe { libc::setpgid(0, 0) };
-    }
+    let signal_pipe = match create_signal_pipe() {
+        Ok(fd) => fd,
+        Err(err) => {
+            eprintln!("timeout: failed to create signal pipe: {err}");
+            return 125;
+        }
+    };
 
     // Setup handlers before to catch signals before fork()
     set_handler(signal_name);
@@ -366,104 +652,126 @@ fn timeout(args: Args) -> i32 {
         libc::signal(libc::SIGTTIN, libc::SIG_IGN);
         libc::signal(libc::SIGTTOU, libc::SIG_IGN);
     }
-    set_chld();
-
-    // To be able to handle SIGALRM (will be send after timeout)
-    unblock_signal(libc::SIGALRM);
 
     let mut original_set = get_empty_sig_set();
-    block_handler_and_chld(signal_name, &mut original_set);
-
-    let spawn_result = unsafe {
-        Command::new(&utility_path)
-            .args(arguments)
-            .pre_exec(move || {
-                libc::sigprocmask(
-                    libc::SIG_SETMASK,
-                    &original_set,
-                    std::ptr::null_mut::<libc::sigset_t>(),
-                );
-
-                libc::signal(libc::SIGTTIN, libc::SIG_DFL);
-                libc::signal(libc::SIGTTOU, libc::SIG_DFL);
-                Ok(())
-            })
-            .spawn()
-    };
-    let child = match spawn_result {
-        Ok(child) => child,
-        Err(err) => match err.kind() {
-            std::io::ErrorKind::NotFound => {
-                eprintln!("timeout: utility '{utility}' not found");
-                return 127;
-            }
-            std::io::ErrorKind::PermissionDenied => {
-                eprintln!("timeout: unable to run the utility '{utility}'");
-                return 126;
-            }
-            _ => return 125,
-        },
-    };
+    mask_handled_signals(libc::SIG_BLOCK, signal_name, &mut original_set);
+
+    let child_pid = unsafe { libc::fork() };
+    match child_pid.cmp(&0) {
+        std::cmp::Ordering::Less => {
+            eprintln!("timeout: failed to create child process");
+            return 125;
+        }
+        std::cmp::Ordering::Equal => exec_utility(
+            &argv_ptrs,
+            &diagnostics,
+            signal_name,
+            &original_set,
+            foreground,
+        ),
+        std::cmp::Ordering::Greater => {}
+    }
 
-    MONITORED_PID.store(child.id() as i32, Ordering::SeqCst);
+    // Utility becomes a process group leader, so all its descendants can be signaled.
+    // It is also done by the child itself, whichever happens first.
+    if !foreground {
+        unsafe { libc::setpgid(child_pid, child_pid) };
+    }
+
+    let mut monitor = Monitor {
+        pid: child_pid,
+        foreground,
+        alarm_signal: signal_name,
+        kill_after,
+        deadline: None,
+        timed_out: false,
+        sent_kill: false,
+    };
+    monitor.set_timeout(duration);
 
-    set_timeout(duration);
+    // Signals are unblocked only once the PID of the child is known. Signals received earlier
+    // are either pending or already queued in the self-pipe, so none of them is lost and all
+    // are forwarded to the child by the main loop.
+    mask_handled_signals(libc::SIG_UNBLOCK, signal_name, &mut get_empty_sig_set());
 
-    let mut wait_status;
     let mut status = 0;
+    let mut signals = [0u8; 64];
     let options = libc::WNOHANG | libc::WCONTINUED | libc::WUNTRACED;
 
     loop {
-        wait_status =
-            unsafe { libc::waitpid(MONITORED_PID.load(Ordering::SeqCst), &mut status, options) };
-
-        let es = ExitStatus::from_raw(status);
-        if wait_status == 0 || es.continued() {
-            unsafe { libc::sigsuspend(&original_set) };
-        } else if es.stopped_signal().is_some() {
-            send_signal(MONITORED_PID.load(Ordering::SeqCst), libc::SIGCONT);
-            TIMED_OUT.store(true, Ordering::SeqCst);
-        } else {
-            break;
+        let wait_status = unsafe { libc::waitpid(child_pid, &mut status, options) };
+        if wait_status < 0 {
+            if std::io::Error::last_os_error().raw_os_error() == Some(libc::EINTR) {
+                continue;
+            }
+            eprintln!("timeout: failed to wait for child");
+            return 125;
         }
-    }
 
-    if wait_status < 0 {
-        eprintln!("timeout: failed to wait for child");
-        125
-    } else {
-        status = if libc::WIFEXITED(status) {
-            libc::WEXITSTATUS(status)
-        } else if libc::WIFSIGNALED(status) {
-            let signal = libc::WTERMSIG(status);
-            if libc::WCOREDUMP(status) {
-                eprintln!("timeout: monitored command dumped core");
-                return 125;
-            }
-            if !TIMED_OUT.load(Ordering::SeqCst) && disable_core_dumps() {
-                unsafe { libc::signal(signal, libc::SIG_DFL) };
-                unblock_signal(signal);
-                unsafe { libc::raise(signal) };
+        if wait_status > 0 {
+            let es = ExitStatus::from_raw(status);
+            if es.stopped_signal().is_some() {
+                // Child stopped by timeout on purpose is left stopped until it is continued
+                // externally or killed after kill-after. Child stopped by job control in
+                // foreground shares process group with timeout and is stopped and continued
+                // along with it.
+                let stopped_on_purpose = monitor.timed_out && is_stop_signal(signal_name);
+                if !stopped_on_purpose && !foreground {
+                    unsafe { libc::kill(child_pid, libc::SIGCONT) };
+                }
+            } else if !es.continued() {
+                break;
             }
-            if TIMED_OUT.load(Ordering::SeqCst) && signal as i32 == libc::SIGKILL {
-                preserve_status = true;
+        }
+
+        let received = match read_signals(signal_pipe, &mut signals) {
+            Ok(received) => received,
+            Err(err) => {
+                eprintln!("timeout: failed to read signals: {err}");
+                return 125;
             }
-            128 + signal
-        } else {
-            eprintln!("timeout: unknown status from command: {status}");
-            return 125;
         };
+        for signal in received {
+            monitor.handle_signal(*signal as i32);
+        }
+    }
 
-        if TIMED_OUT.load(Ordering::SeqCst) && !preserve_status {
-            124
-        } else {
-            status
+    monitor.cancel_timeout();
+
+    status = if libc::WIFEXITED(status) {
+        libc::WEXITSTATUS(status)
+    } else if libc::WIFSIGNALED(status) {
+        let signal = libc::WTERMSIG(status);
+        if libc::WCOREDUMP(status) {
+            eprintln!("timeout: monitored command dumped core");
+            return 125;
+        }
+        if !monitor.timed_out && disable_core_dumps() {
+            unsafe { libc::signal(signal, libc::SIG_DFL) };
+            unblock_signal(signal);
+            unsafe { libc::raise(signal) };
+        }
+        // Child killed by timeout after the time limit is reported as killed, not as timed out
+        if monitor.timed_out && monitor.sent_kill && signal == libc::SIGKILL {
+            preserve_status = true;
         }
+        128 + signal
+    } else {
+        eprintln!("timeout: unknown status from command: {status}");
+        return 125;
+    };
+
+    if monitor.timed_out && !preserve_status {
+        124
+    } else {
+        status
     }
 }
 
 /// Exit code:
-///     124 - Process timed out.
+///     124 - Process timed out. With `--preserve-status`, exit status of the utility is
+///           returned instead. If the utility was killed by SIGKILL sent by timeout after the
+///           time limit (`-s KILL` or `-k`), 137 is returned.
 ///     125 - An error other than the two described below occurred.
 ///     126 - The utility specified by utility was found but could not be executed.
 ///     127 - The utility specified by utility could not be found.
@@ -490,3 +798,76 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let exit_code = timeout(args);
     std::process::exit(exit_code);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration_valid() {
+        assert_eq!(parse_duration("0"), Ok(Duration::ZERO));
+        assert_eq!(parse_duration("5"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_duration("007"), Ok(Duration::from_secs(7)));
+        assert_eq!(parse_duration("1.5"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration(".5"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("0.0"), Ok(Duration::ZERO));
+        assert_eq!(parse_duration("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("2d"), Ok(Duration::from_secs(172800)));
+        assert_eq!(parse_duration("0.5m"), Ok(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_parse_duration_no_digits() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration(".").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration(".s").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration_decimal_point() {
+        assert!(parse_duration("1.").is_err());
+        assert!(parse_duration("1.s").is_err());
+        assert!(parse_duration("1..2").is_err());
+        assert!(parse_duration("1.2.3").is_err());
+        assert!(parse_duration("..5").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration_sign() {
+        assert!(parse_duration("-5").is_err());
+        assert!(parse_duration("+5").is_err());
+        assert!(parse_duration("-0").is_err());
+        assert!(parse_duration("5-").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration_suffix() {
+        assert!(parse_duration("1a").is_err());
+        assert!(parse_duration("1ss").is_err());
+        assert!(parse_duration("1 s").is_err());
+        assert!(parse_duration("1S").is_err());
+        assert!(parse_duration("1e3").is_err());
+        assert!(parse_duration(" 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration_out_of_range() {
+        assert_eq!(
+            parse_duration("99999999999999999999999d"),
+            Err(String::from(
+                "duration is out of range '99999999999999999999999d'"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_duration_error_names_operand() {
+        assert_eq!(
+            parse_duration("1..2"),
+            Err(String::from("invalid duration format '1..2'"))
+        );
+    }
+}
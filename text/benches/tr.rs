@@ -0,0 +1,54 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use std::fs::File;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use plib::testing::{generate, TestDir};
+
+/// Large enough that the transformation loop dominates the start of tr.
+const INPUT_SIZE: usize = 50 * 1024 * 1024;
+
+/// Runs tr with `args` over `input`, discarding its output.
+fn run_tr(args: &[&str], input: &Path) {
+    let status = Command::new(env!("CARGO_BIN_EXE_tr"))
+        .args(args)
+        .stdin(File::open(input).unwrap())
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn transformations(c: &mut Criterion) {
+    let dir = TestDir::new("tr-bench");
+    let input = dir.file("input", generate::bytes(INPUT_SIZE, 1994));
+
+    let mut group = c.benchmark_group("tr");
+    group
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(30))
+        .throughput(Throughput::Bytes(INPUT_SIZE as u64));
+    for (name, args) in [
+        ("translate", &["a-z", "A-Z"][..]),
+        ("delete", &["-d", "a-z"]),
+        ("delete complement", &["-cd", "a-z"]),
+        ("squeeze", &["-s", "a-z"]),
+        ("delete and squeeze", &["-ds", "a-m", "n-z"]),
+    ] {
+        group.bench_function(name, |b| b.iter(|| run_tr(args, &input)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, transformations);
+criterion_main!(benches);
@@ -7,10 +7,11 @@
 // SPDX-License-Identifier: MIT
 //
 
-use std::io::{self, BufRead, Error, ErrorKind, Read};
+use std::io::{self, BufRead, Error, ErrorKind};
 
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
+use plib::io::{InputErrors, InputSources};
 use std::path::PathBuf;
 
 /// Cut - cut out selected fields of each line of a file
@@ -279,75 +280,67 @@ fn cut_fields(line: &str, delim: char, ranges: &Vec<(i32, i32)>, suppress: bool)
 /// # Arguments
 ///
 /// * `args` - A struct containing the command-line arguments.
+/// * `errors` - Where the files that cannot be opened or read are reported.
 ///
 /// # Returns
 ///
-/// A `Result` indicating success or failure. If an error occurs during file processing, it is returned as `Err`.
+/// A `Result` indicating success or failure. If the arguments are invalid, the error is returned as `Err`.
 ///
-fn cut_files(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+fn cut_files(mut args: Args, errors: &mut InputErrors) -> Result<(), Box<dyn std::error::Error>> {
     validate_args(&args).map_err(|err| Box::new(Error::new(ErrorKind::Other, err)))?;
 
-    // open files, or stdin
+    let parse_option;
 
-    let filenames = args.filenames;
-    let filenames_len = filenames.len();
-    let readers: Vec<Box<dyn Read>> =
-        if filenames_len == 0 || (filenames_len == 1 && filenames[0] == PathBuf::from("-")) {
-            vec![Box::new(io::stdin().lock())]
-        } else {
-            let mut bufs: Vec<Box<dyn Read>> = Vec::with_capacity(filenames_len);
-            for file in &filenames {
-                bufs.push(Box::new(std::fs::File::open(file)?))
-            }
-            bufs
-        };
-
-    // Process each file
-    for file in readers {
-        let reader = io::BufReader::new(file);
-
-        let parse_option;
+    if let Some(bytes_list) = &args.bytes {
+        let ranges: Vec<(i32, i32)> =
+            read_range(bytes_list).map_err(|err| Box::new(Error::new(ErrorKind::Other, err)))?;
 
-        if let Some(bytes_list) = &args.bytes {
-            let ranges: Vec<(i32, i32)> = read_range(bytes_list)
-                .map_err(|err| Box::new(Error::new(ErrorKind::Other, err)))?;
+        parse_option = ParseVariat::Bytes(ranges);
+    } else if let Some(characters_list) = &args.characters {
+        let ranges: Vec<(i32, i32)> = read_range(characters_list)
+            .map_err(|err| Box::new(Error::new(ErrorKind::Other, err)))?;
 
-            parse_option = ParseVariat::Bytes(ranges);
-        } else if let Some(characters_list) = &args.characters {
-            let ranges: Vec<(i32, i32)> = read_range(characters_list)
-                .map_err(|err| Box::new(Error::new(ErrorKind::Other, err)))?;
+        parse_option = ParseVariat::Characters(ranges);
+    } else if let Some(fields_list) = &args.fields {
+        let ranges: Vec<(i32, i32)> =
+            read_range(fields_list).map_err(|err| Box::new(Error::new(ErrorKind::Other, err)))?;
+        parse_option = ParseVariat::Fields(ranges);
+    } else {
+        return Err(Box::new(Error::new(ErrorKind::Other, "Invalid arguments")));
+    }
 
-            parse_option = ParseVariat::Characters(ranges);
-        } else if let Some(fields_list) = &args.fields {
-            let ranges: Vec<(i32, i32)> = read_range(fields_list)
-                .map_err(|err| Box::new(Error::new(ErrorKind::Other, err)))?;
-            parse_option = ParseVariat::Fields(ranges);
-        } else {
-            return Err(Box::new(Error::new(ErrorKind::Other, "Invalid arguments")));
+    // Process each file, or stdin
+    for (name, reader) in InputSources::new(std::mem::take(&mut args.filenames)) {
+        if let Err(e) = reader.and_then(|reader| cut_reader(reader, &args, &parse_option)) {
+            errors.report(&name, e);
         }
+    }
+    Ok(())
+}
 
-        for line in reader.lines() {
-            let line = line?;
-            match parse_option.clone() {
-                ParseVariat::Bytes(ranges) => {
-                    let bytes = cut_bytes(line.as_bytes(), args.delimiter, &ranges, args.no_split);
-                    match String::from_utf8(bytes) {
-                        Ok(string) => println!("{}", string),
-                        Err(e) => eprintln!("Conversion error to string: {}", e),
-                    }
-                }
-                ParseVariat::Characters(ranges) => {
-                    println!("{}", cut_characters(&line, args.delimiter, &ranges))
+/// Cuts the lines read from `reader` and prints them.
+fn cut_reader(reader: Box<dyn BufRead>, args: &Args, parse_option: &ParseVariat) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        match parse_option {
+            ParseVariat::Bytes(ranges) => {
+                let bytes = cut_bytes(line.as_bytes(), args.delimiter, ranges, args.no_split);
+                match String::from_utf8(bytes) {
+                    Ok(string) => println!("{}", string),
+                    Err(e) => eprintln!("Conversion error to string: {}", e),
                 }
-                ParseVariat::Fields(ranges) => {
-                    if let Some(delim) = args.delimiter {
-                        let result = cut_fields(&line, delim, &ranges, args.suppress);
-                        if !result.1 {
-                            println!("{}", result.0)
-                        }
-                    } else {
-                        println!("{}", line);
+            }
+            ParseVariat::Characters(ranges) => {
+                println!("{}", cut_characters(&line, args.delimiter, ranges))
+            }
+            ParseVariat::Fields(ranges) => {
+                if let Some(delim) = args.delimiter {
+                    let result = cut_fields(&line, delim, ranges, args.suppress);
+                    if !result.1 {
+                        println!("{}", result.0)
                     }
+                } else {
+                    println!("{}", line);
                 }
             }
         }
@@ -440,11 +433,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
 
     let mut exit_code = 0;
+    let mut errors = InputErrors::new("cut");
 
-    if let Err(err) = cut_files(args) {
+    if let Err(err) = cut_files(args, &mut errors) {
         exit_code = 1;
         eprintln!("{}", err);
     }
+    if errors.any() {
+        exit_code = 1;
+    }
 
     std::process::exit(exit_code)
 }
@@ -9,13 +9,12 @@
 
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
-use libc::{regcomp, regex_t, regexec, regfree, REG_EXTENDED, REG_ICASE, REG_NOMATCH};
+use plib::regex::{Regex, RegexFlags};
 use std::{
     ffi::CString,
     fs::File,
     io::{self, BufRead, BufReader},
     path::{Path, PathBuf},
-    ptr,
 };
 
 /// grep - search a file for a pattern.
@@ -201,10 +200,10 @@ impl Args {
     }
 }
 
-/// Newtype over `Vec[libc::regex_t]`. Provides functionality for matching input data.
+/// Fixed strings or compiled regexes. Provides functionality for matching input data.
 enum Patterns {
     Fixed(Vec<String>, bool, bool),
-    Regex(Vec<regex_t>),
+    Regex(Vec<Regex>),
 }
 
 impl Patterns {
@@ -244,12 +243,12 @@ impl Patterns {
         } else {
             let mut ps = vec![];
 
-            let mut cflags = 0;
+            let mut flags = RegexFlags::BASIC;
             if extended_regexp {
-                cflags |= REG_EXTENDED;
+                flags = flags | RegexFlags::EXTENDED;
             }
             if ignore_case {
-                cflags |= REG_ICASE;
+                flags = flags | RegexFlags::ICASE;
             }
             for mut pattern in patterns {
                 // macOS version of [regcomp](regcomp) from `libc`
@@ -273,15 +272,8 @@ impl Patterns {
                 };
 
                 let c_pattern = CString::new(pattern).map_err(|err| err.to_string())?;
-                let mut regex = unsafe { std::mem::zeroed::<regex_t>() };
-
-                let result = unsafe { regcomp(&mut regex, c_pattern.as_ptr(), cflags) };
-                if result != 0 {
-                    return Err(format!(
-                        "Error compiling regex '{}'",
-                        c_pattern.to_string_lossy()
-                    ));
-                }
+                let error = format!("Error compiling regex '{}'", c_pattern.to_string_lossy());
+                let regex = Regex::with_flags(c_pattern, flags).map_err(|_| error)?;
                 ps.push(regex);
             }
             Ok(Self::Regex(ps))
@@ -314,25 +306,7 @@ impl Patterns {
                     }
                 })
             }
-            Patterns::Regex(patterns) => {
-                let c_input = CString::new(input).unwrap();
-                patterns.iter().any(|p| unsafe {
-                    regexec(p, c_input.as_ptr(), 0, ptr::null_mut(), 0) != REG_NOMATCH
-                })
-            }
-        }
-    }
-}
-
-impl Drop for Patterns {
-    fn drop(&mut self) {
-        match &self {
-            Patterns::Fixed(_, _, _) => {}
-            Patterns::Regex(regexes) => {
-                for regex in regexes {
-                    unsafe { regfree(regex as *const regex_t as *mut regex_t) }
-                }
-            }
+            Patterns::Regex(patterns) => patterns.iter().any(|p| p.matches_bytes(input.as_bytes())),
         }
     }
 }
@@ -8,7 +8,10 @@
 // SPDX-License-Identifier: MIT
 //
 
-use plib::testing::{run_test, TestPlan};
+use plib::testing::{run_test, RunOptions, TestDir, TestFixture, TestPlan};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
 
 fn cut_test(args: &[&str], test_data: &str, expected_output: &str) {
     let str_args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
@@ -354,3 +357,60 @@ fn test_stdin_no_hyphen() {
         "081ca869c86b 41 truefalse/src/false.rs\n",
     );
 }
+
+/// Runs cut with `args` in a directory with the files `a` and `b`.
+fn cut_files_test(args: &[&str], stdin: &str, expected_out: &str, expected_err: &str) {
+    TestFixture::new("cut")
+        .with_file("a", "a1\na2\n")
+        .with_file("b", "b1\n")
+        .run_test(TestPlan {
+            cmd: String::from("cut"),
+            args: args.iter().map(|s| String::from(*s)).collect(),
+            stdin_data: String::from(stdin),
+            expected_out: String::from(expected_out),
+            expected_err: String::from(expected_err),
+            expected_exit_code: if expected_err.is_empty() { 0 } else { 1 },
+        });
+}
+
+#[test]
+fn test_cut_missing_file_does_not_stop_the_others() {
+    cut_files_test(
+        &["-c", "2", "a", "missing", "b"],
+        "",
+        "1\n2\n1\n",
+        "cut: missing: No such file or directory (os error 2)\n",
+    );
+}
+
+#[test]
+fn test_cut_stdin_between_files() {
+    cut_files_test(&["-c", "1", "a", "-", "b"], "s\n", "a\na\ns\nb\n", "");
+}
+
+#[test]
+fn test_cut_repeated_stdin() {
+    cut_files_test(&["-c", "1", "-", "a", "-"], "s\nt\n", "s\nt\na\na\n", "");
+}
+
+#[test]
+fn test_cut_file_name_not_utf8() {
+    let dir = TestDir::new("cut");
+    let name = OsStr::from_bytes(b"in\x80.txt");
+    fs::write(dir.path().join(name), "abcdef\n").unwrap();
+
+    run_test(
+        TestPlan {
+            cmd: String::from("cut"),
+            args: vec![OsString::from("-c"), OsString::from("1-3"), name.to_owned()],
+            stdin_data: String::new(),
+            expected_out: String::from("abc\n"),
+            expected_err: String::from(""),
+            expected_exit_code: 0,
+        }
+        .with_options(RunOptions {
+            cwd: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        }),
+    );
+}
@@ -13,7 +13,7 @@ use plib::testing::{run_test, TestPlan};
 fn expand_test_noargs(test_data: &str, expected_output: &str) {
     run_test(TestPlan {
         cmd: String::from("expand"),
-        args: Vec::new(),
+        args: Vec::<String>::new(),
         stdin_data: String::from(test_data),
         expected_out: String::from(expected_output),
         expected_err: String::from(""),
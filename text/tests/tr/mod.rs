@@ -8,7 +8,12 @@
 // SPDX-License-Identifier: MIT
 //
 
-use plib::testing::{run_test, run_test_u8, TestPlan, TestPlanU8};
+use plib::testing::{
+    generate, run_pipeline, run_streaming_test, run_test, run_test_u8, ExpectedOutput, RunOptions,
+    StdinSource, StreamStep, TestPlan, TestPlanU8,
+};
+use std::path::PathBuf;
+use std::time::Duration;
 
 fn tr_test_binary(args: &[&str], test_data: &[u8], expected_output: &[u8]) {
     let str_args = args
@@ -376,11 +381,20 @@ fn tr_d_space_n() {
  61 74 69 6f 6e 2e 0a
 ";
 
-    let output = "\
-546869732069732061207375697465206f6620527573742d6e617469766520636f726520636f6d6d616e64206c696e65207574696c74696573202863702c206d762c0a61776b2c206d616b652c2076692c202e2e2e29207573696e6720504f5349582e323032342061732074686520626173656c696e652073706563696669636174696f6e2e0a\
-";
-
-    tr_test(&["-d", r" \n"], input, output);
+    run_test(
+        TestPlan {
+            cmd: String::from("tr"),
+            args: vec![String::from("-d"), String::from(r" \n")],
+            stdin_data: String::from(input),
+            expected_out: String::new(),
+            expected_err: String::new(),
+            expected_exit_code: 0,
+        }
+        .with_options(RunOptions {
+            expected_out_file: Some(PathBuf::from("tests/tr/d_space_n.out")),
+            ..Default::default()
+        }),
+    );
 }
 
 #[test]
@@ -793,3 +807,203 @@ Only one string may be given when deleting without squeezing repeats.
 ",
     );
 }
+
+#[test]
+fn tr_every_byte_value_round_trips() {
+    let all_bytes = (0..=u8::MAX).collect::<Vec<_>>();
+    tr_test_binary(&["a", "a"], &all_bytes, &all_bytes);
+}
+
+#[test]
+fn tr_translates_every_byte_value() {
+    let all_bytes = (0..=u8::MAX).collect::<Vec<_>>();
+    let shifted = all_bytes
+        .iter()
+        .map(|b| b.wrapping_add(1))
+        .collect::<Vec<_>>();
+    tr_test_binary(&[r"\000-\377", r"\001-\377\000"], &all_bytes, &shifted);
+}
+
+#[test]
+fn tr_character_classes_in_c_and_utf8_locales() {
+    for locale in ["C", "C.UTF-8"] {
+        run_test(
+            TestPlan {
+                cmd: "tr".to_owned(),
+                args: vec!["[:lower:]".to_owned(), "[:upper:]".to_owned()],
+                stdin_data: "h\u{e9}llo w\u{f6}rld\n".to_owned(),
+                expected_out: "H\u{e9}LLO W\u{f6}RLD\n".to_owned(),
+                expected_err: String::new(),
+                expected_exit_code: 0,
+            }
+            .with_env("LC_ALL", locale),
+        );
+    }
+}
+
+#[test]
+fn tr_squeeze_across_input_chunks() {
+    for (args, expected) in [
+        (vec!["-s", "a"], "xay\n"),
+        (vec!["-s", "a", "b"], "xby\n"),
+        (vec!["-ds", "y", "a"], "xa\n"),
+    ] {
+        run_test(
+            TestPlan {
+                cmd: "tr".to_owned(),
+                args: args.into_iter().map(String::from).collect(),
+                expected_out: expected.to_owned(),
+                expected_err: String::new(),
+                expected_exit_code: 0,
+                ..Default::default()
+            }
+            .with_options(RunOptions {
+                stdin: Some(StdinSource::Generator {
+                    // the run of "a" is split across the two chunks
+                    generate: Box::new(|out| out.write_all(b"xaaaaay\n")),
+                    chunk_size: 3,
+                    delay: Some(Duration::from_millis(50)),
+                }),
+                ..Default::default()
+            }),
+        );
+    }
+}
+
+/// Returns a plan for one command of a pipeline.
+fn stage(cmd: &str, args: &[&str]) -> TestPlan {
+    TestPlan {
+        cmd: cmd.to_owned(),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    }
+}
+
+#[test]
+fn tr_in_a_pipeline() {
+    run_pipeline(vec![
+        TestPlan {
+            stdin_data: "banana\napple\ncherry\napple\n".to_owned(),
+            ..stage("tr", &["a-z", "A-Z"])
+        },
+        stage("sort", &[]),
+        TestPlan {
+            expected_out: "2 APPLE\n1 BANANA\n1 CHERRY\n".to_owned(),
+            ..stage("uniq", &["-c"])
+        },
+    ]);
+}
+
+#[test]
+fn tr_in_a_pipeline_whose_last_command_fails() {
+    run_pipeline(vec![
+        TestPlan {
+            stdin_data: "abc\n".to_owned(),
+            ..stage("tr", &["a", "b"])
+        },
+        TestPlan {
+            expected_exit_code: 1,
+            ..stage("grep", &["a"])
+        },
+    ]);
+}
+
+#[test]
+fn tr_failing_in_a_pipeline() {
+    run_pipeline(vec![
+        TestPlan {
+            cmd: "tr".to_owned(),
+            stdin_data: "abc\n".to_owned(),
+            expected_out: ExpectedOutput::from(""),
+            expected_err: ExpectedOutput::Contains("<STRING1>".to_owned()),
+            expected_exit_code: 2,
+            ..Default::default()
+        },
+        TestPlan {
+            cmd: "wc".to_owned(),
+            args: vec!["-l".to_owned()],
+            expected_out: ExpectedOutput::Regex("^ *0\n$".to_owned()),
+            expected_err: ExpectedOutput::from(""),
+            expected_exit_code: 0,
+            ..Default::default()
+        },
+    ]);
+}
+
+#[test]
+fn tr_writes_each_line_before_the_end_of_its_input() {
+    run_streaming_test(
+        TestPlan {
+            cmd: "tr".to_owned(),
+            args: vec!["a".to_owned(), "b".to_owned()],
+            expected_out: "bbc\nbcb\n".to_owned(),
+            expected_err: String::new(),
+            expected_exit_code: 0,
+            ..Default::default()
+        },
+        vec![
+            // tr holds back the last three bytes of each read, which may be
+            // the start of a multibyte character
+            StreamStep::Write(b"abc\naca".to_vec()),
+            StreamStep::Expect {
+                prefix: "bbc\n".to_owned(),
+                within: Duration::from_secs(5),
+            },
+            StreamStep::Write(b"\n".to_vec()),
+        ],
+    );
+}
+
+#[test]
+fn tr_large_input() {
+    const LINES: usize = 200_000;
+    let expected = String::from_utf8(generate::lines(LINES, 1990))
+        .unwrap()
+        .to_uppercase();
+
+    run_test(
+        TestPlan {
+            cmd: String::from("tr"),
+            args: vec![String::from("a-z"), String::from("A-Z")],
+            expected_out: expected,
+            expected_err: String::new(),
+            expected_exit_code: 0,
+            ..Default::default()
+        }
+        .with_options(RunOptions {
+            // generous, to catch per-byte overhead rather than measure speed
+            timeout: Some(Duration::from_secs(60)),
+            stdin: Some(StdinSource::Generator {
+                generate: Box::new(|out| generate::write_lines(out, LINES, 1990)),
+                chunk_size: plib::BUFSZ,
+                delay: None,
+            }),
+            ..Default::default()
+        }),
+    );
+}
+
+#[test]
+fn tr_ends_quietly_when_its_reader_exits() {
+    let first_line = String::from_utf8(generate::lines(1, 1993)).unwrap();
+    run_pipeline(vec![
+        stage("tr", &["a-z", "A-Z"]).with_options(RunOptions {
+            // far more than a pipe holds, so tr is still writing when head exits
+            stdin: Some(StdinSource::Generator {
+                generate: Box::new(|out| generate::write_lines(out, 100_000, 1993)),
+                chunk_size: plib::BUFSZ,
+                delay: None,
+            }),
+            expect_signal: Some(libc::SIGPIPE),
+            ..Default::default()
+        }),
+        TestPlan {
+            expected_out: first_line.to_uppercase(),
+            ..stage("head", &["-n", "1"])
+        }
+        .into(),
+    ]);
+}
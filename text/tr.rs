@@ -1937,6 +1937,7 @@ mod setup {
 }
 
 mod transformation {
+    use plib::io::ExitOnBrokenPipe;
     use std::error::Error;
     use std::io::{self, ErrorKind, Read, Write};
 
@@ -2036,7 +2037,8 @@ mod transformation {
         // TODO
         // Improve this
         let mut stdin_lock = io::stdin().lock();
-        let mut stdout_lock = io::stdout().lock();
+        // a reader that exits early ends tr quietly, like it would a C utility
+        let mut stdout_lock = ExitOnBrokenPipe::new(io::stdout().lock());
 
         loop {
             let buf = &mut input[leftover_bytes..];
@@ -23,7 +23,7 @@ fn setup_test_env() -> (TempDir, String) {
 fn run_rmdir_test(args: Vec<&str>, expected_exit_code: i32, expected_err_substr: &str) {
     let plan = TestPlan {
         cmd: String::from("rmdir"),
-        args: args.iter().map(|&s| s.into()).collect(),
+        args: args.iter().map(|&s| s.to_string()).collect(),
         stdin_data: String::new(),
         expected_out: String::new(),
         expected_err: String::new(),
@@ -8,7 +8,7 @@
 // SPDX-License-Identifier: MIT
 //
 
-use plib::testing::{run_test, run_test_with_checker, TestPlan};
+use plib::testing::{run_test, Normalizer, RunOptions, TestPlan};
 use std::fs;
 use tempfile::tempdir;
 
@@ -22,7 +22,7 @@ fn unlink_remove_existing_file() {
 
     let test_plan = TestPlan {
         cmd: String::from("unlink"),
-        args: vec![file_path.to_str().unwrap().into()],
+        args: vec![file_path.to_str().unwrap().to_string()],
         stdin_data: String::new(),
         expected_out: String::new(),
         expected_err: String::new(),
@@ -42,7 +42,7 @@ fn unlink_remove_non_existing_file() {
 
     let test_plan = TestPlan {
         cmd: String::from("unlink"),
-        args: vec![file_path.to_str().unwrap().into()],
+        args: vec![file_path.to_str().unwrap().to_string()],
         stdin_data: String::new(),
         expected_out: String::new(),
         expected_err: format!(
@@ -63,21 +63,23 @@ fn unlink_remove_directory() {
     // Create the directory to be unlinked
     fs::create_dir_all(&dir_path).expect("Unable to create test directory");
 
-    let test_plan = TestPlan {
-        cmd: String::from("unlink"),
-        args: vec![dir_path.to_str().unwrap().into()],
-        stdin_data: String::new(),
-        expected_out: String::new(),
-        expected_err: String::new(), // We will check this in the checker function
-        expected_exit_code: 1,       // We expect failure
-    };
-
-    run_test_with_checker(test_plan, |_plan, output| {
-        assert_eq!(output.status.code(), Some(1));
-        assert!(!output.status.success());
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        assert!(stderr.contains("unlink: "));
-    });
+    run_test(
+        TestPlan {
+            cmd: String::from("unlink"),
+            args: vec![dir_path.to_str().unwrap().to_string()],
+            stdin_data: String::new(),
+            expected_out: String::new(),
+            expected_err: String::from("unlink: $DIR/testdir: Is a directory (os error 21)\n"),
+            expected_exit_code: 1,
+        }
+        .with_options(RunOptions {
+            stderr_normalizers: vec![Normalizer::literal(
+                temp_dir.path().to_str().unwrap(),
+                "$DIR",
+            )],
+            ..Default::default()
+        }),
+    );
 
     // Ensure the directory still exists
     assert!(dir_path.exists());
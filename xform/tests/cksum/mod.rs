@@ -12,7 +12,7 @@ use plib::testing::{run_test, TestPlan};
 fn cksum_test(test_data: &str, expected_output: &str) {
     run_test(TestPlan {
         cmd: String::from("cksum"),
-        args: Vec::new(),
+        args: Vec::<String>::new(),
         stdin_data: String::from(test_data),
         expected_out: String::from(expected_output),
         expected_err: String::from(""),
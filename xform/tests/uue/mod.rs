@@ -7,7 +7,7 @@
 // SPDX-License-Identifier: MIT
 //
 
-use plib::testing::{run_test, TestPlan};
+use plib::testing::{run_test, run_test_u8, TestPlan, TestPlanU8};
 use std::{
     fs::{File, Permissions},
     io::Read,
@@ -40,15 +40,15 @@ fn uuencode_test(args: &[&str], expected_output: &str, expected_error: &str) {
     })
 }
 
-fn uudecode_test(args: &[&str], stdin_data: &str, expected_output: &str, expected_error: &str) {
+fn uudecode_test(args: &[&str], stdin_data: &str, expected_output: &[u8], expected_error: &str) {
     let str_args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
 
-    run_test(TestPlan {
+    run_test_u8(TestPlanU8 {
         cmd: String::from("uudecode"),
         args: str_args,
-        stdin_data: String::from(stdin_data),
-        expected_out: String::from(expected_output),
-        expected_err: String::from(expected_error),
+        stdin_data: stdin_data.as_bytes().to_vec(),
+        expected_out: expected_output.to_vec(),
+        expected_err: expected_error.as_bytes().to_vec(),
         expected_exit_code: 0,
     });
 }
@@ -90,7 +90,6 @@ fn uuencode_uudecode_with_historical_encoding_text_file() {
         .unwrap()
         .read_to_end(&mut source_file_content)
         .unwrap();
-    let source_file_content = String::from_utf8_lossy(&source_file_content);
 
     // Decode the encoded file using uudecode
     uudecode_test(&[], &encoded_file_content, &source_file_content, "");
@@ -132,7 +131,6 @@ fn uuencode_uudecode_with_base64_encoding_text_file() {
         .unwrap()
         .read_to_end(&mut source_file_content)
         .unwrap();
-    let source_file_content = String::from_utf8_lossy(&source_file_content);
 
     uudecode_test(&[], &encoded_file_content, &source_file_content, "");
 }
@@ -173,7 +171,6 @@ fn uuencode_uudecode_with_historical_encoding_jpg_file() {
         .unwrap()
         .read_to_end(&mut source_file_content)
         .unwrap();
-    let source_file_content = String::from_utf8_lossy(&source_file_content);
 
     uudecode_test(&[], &encoded_file_content, &source_file_content, "");
 }
@@ -214,7 +211,6 @@ fn uuencode_uudecode_with_base64_encoding_jpg_file() {
         .unwrap()
         .read_to_end(&mut source_file_content)
         .unwrap();
-    let source_file_content = String::from_utf8_lossy(&source_file_content);
 
     uudecode_test(&[], &encoded_file_content, &source_file_content, "");
 }